@@ -0,0 +1,170 @@
+//! Modbus ASCII client transport.
+
+use std::time::Duration;
+
+use modbius_codec::{AsciiConfig, AsciiStream, AsciiTransportError};
+use modbius_core::SlaveId;
+use modbius_traits::ModbusTransport;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A Modbus ASCII master on a serial line or any other stream.
+///
+/// Wrap it in a [Bus](crate::Bus) to share the line between multiple tasks.
+#[derive(Debug)]
+pub struct AsciiTransport<S> {
+    stream: AsciiStream<S>,
+    response_timeout: Duration,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsciiTransport<S> {
+    /// Create a new transport with the default [AsciiConfig] and a response timeout of one second
+    pub fn new(stream: S) -> Self {
+        Self::with_config(stream, AsciiConfig::default(), Duration::from_secs(1))
+    }
+
+    /// Create a new transport with the given configuration.
+    ///
+    /// The response timeout is the maximum time to wait for the start of a response.
+    pub fn with_config(stream: S, config: AsciiConfig, response_timeout: Duration) -> Self {
+        Self {
+            stream: AsciiStream::with_config(stream, config),
+            response_timeout,
+        }
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout
+    }
+
+    pub fn set_response_timeout(&mut self, response_timeout: Duration) {
+        self.response_timeout = response_timeout;
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ModbusTransport for AsciiTransport<S> {
+    type Error = AsciiTransportError;
+
+    /// Send the request to the slave and wait for its response.
+    ///
+    /// Broadcast requests are not answered so 0 is returned right after sending them.
+    async fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        // Anything received until now can't be the response to this request
+        self.stream.discard();
+        self.stream.write_frame(slave, request).await?;
+
+        if slave.is_broadcast() {
+            return Ok(0);
+        }
+
+        let (responder, len) = self
+            .stream
+            .read_frame(response, Some(self.response_timeout))
+            .await?;
+        if responder != slave && !self.stream.config().quirks.any_unit_id {
+            return Err(AsciiTransportError::UnexpectedSlave(responder));
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_codec::AsciiTransportError;
+    use modbius_core::{ascii::AsciiFrameError, SlaveId};
+    use modbius_traits::ModbusTransport;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::AsciiTransport;
+
+    #[tokio::test]
+    async fn transact() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = AsciiTransport::new(local);
+
+        let slave = tokio::spawn(async move {
+            let mut request = [0; 17];
+            remote.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request, b":010300000001FB\r\n");
+            remote.write_all(b"\r\n\0:0103020102F7\r\n").await.unwrap();
+            remote
+        });
+
+        let mut response = [0; 8];
+        let len = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 1, 2]);
+        slave.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn transact_fail_lrc() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = AsciiTransport::new(local);
+
+        let slave = tokio::spawn(async move {
+            let mut request = [0; 17];
+            remote.read_exact(&mut request).await.unwrap();
+            remote.write_all(b":0103020102F6\r\n").await.unwrap();
+            remote
+        });
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut [0; 8])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AsciiTransportError::Frame(AsciiFrameError::Lrc {
+                expected: 0xF7,
+                got: 0xF6
+            })
+        ));
+        slave.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn transact_fail_unexpected_slave() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = AsciiTransport::new(local);
+
+        let slave = tokio::spawn(async move {
+            let mut request = [0; 17];
+            remote.read_exact(&mut request).await.unwrap();
+            remote.write_all(b":0203020102F6\r\n").await.unwrap();
+            remote
+        });
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut [0; 8])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AsciiTransportError::UnexpectedSlave(s) if s == SlaveId::new(2)));
+        slave.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn broadcast() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = AsciiTransport::new(local);
+
+        let len = transport
+            .transact(SlaveId::new_broadcast(), &[6, 0, 1, 0, 3], &mut [0; 8])
+            .await
+            .unwrap();
+        assert_eq!(len, 0);
+
+        let mut request = [0; 17];
+        remote.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b":000600010003F6\r\n");
+    }
+}
@@ -0,0 +1,194 @@
+//! Blocking clients for code without an async runtime.
+//!
+//! A [BlockingClient] drives one of the async transports on a private single threaded tokio runtime, so response
+//! timeouts, exception responses and errors behave exactly like with the async clients. It implements the
+//! [blocking traits](modbius_traits::blocking) which have the same methods as the async ones.
+//!
+//! ```no_run
+//! use modbius_client::BlockingClient;
+//! use modbius_core::{read::ReadHoldingRegisters, SlaveId};
+//! use modbius_traits::blocking::ModbusClient;
+//!
+//! let mut client = BlockingClient::connect_tcp("192.168.0.10:502", SlaveId::new(1))?;
+//! let mut response = [0; 256];
+//! let len = client.call(&ReadHoldingRegisters::new(0, 2).into_data(), &mut response)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! RTU clients on serial ports are available with the `serial` feature.
+
+use std::{
+    future::Future,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+};
+
+use modbius_core::SlaveId;
+use modbius_traits::{blocking, ModbusTransport};
+use tokio::{
+    net::TcpStream,
+    runtime::{self, Runtime},
+};
+
+use crate::tcp::TcpTransport;
+
+/// A blocking client driving an async [ModbusTransport].
+///
+/// See the [module](self) documentation for details.
+#[derive(Debug)]
+pub struct BlockingClient<T> {
+    runtime: Runtime,
+    transport: T,
+    slave: SlaveId,
+}
+
+impl<T: ModbusTransport> BlockingClient<T> {
+    /// Create a client talking to slave over the transport returned by connect.
+    ///
+    /// The future is run on the private runtime of the client because tokio io types have to be created inside
+    /// a runtime.
+    pub fn new<F>(slave: SlaveId, connect: F) -> io::Result<Self>
+    where
+        F: Future<Output = io::Result<T>>,
+    {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()?;
+        let transport = runtime.block_on(connect)?;
+        Ok(Self {
+            runtime,
+            transport,
+            slave,
+        })
+    }
+
+    pub fn set_slave(&mut self, slave: SlaveId) {
+        self.slave = slave;
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+impl BlockingClient<TcpTransport<TcpStream>> {
+    /// Connect to a Modbus TCP server with a response timeout of one second
+    pub fn connect_tcp(addr: impl ToSocketAddrs, slave: SlaveId) -> io::Result<Self> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        Self::new(slave, TcpTransport::connect(&addrs[..]))
+    }
+}
+
+#[cfg(feature = "serial")]
+impl BlockingClient<crate::rtu::RtuTransport<tokio_serial::SerialStream>> {
+    /// Open a serial port with 8 data bits, no parity and 1 stop bit as RTU master.
+    ///
+    /// Use [new](Self::new) to open ports with other settings.
+    pub fn open_rtu(path: &str, baud_rate: u32, slave: SlaveId) -> io::Result<Self> {
+        use modbius_codec::RtuConfig;
+        use tokio_serial::SerialPortBuilderExt;
+
+        Self::new(slave, async move {
+            let stream = tokio_serial::new(path, baud_rate).open_native_async()?;
+            Ok(crate::rtu::RtuTransport::with_config(
+                stream,
+                RtuConfig::for_baud_rate(baud_rate),
+                std::time::Duration::from_secs(1),
+            ))
+        })
+    }
+}
+
+impl<T: ModbusTransport> blocking::ModbusTransport for BlockingClient<T> {
+    type Error = T::Error;
+
+    fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.runtime
+            .block_on(self.transport.transact(slave, request, response))
+    }
+}
+
+impl<T: ModbusTransport> blocking::ModbusClient for BlockingClient<T> {
+    type Error = T::Error;
+
+    fn slave(&self) -> SlaveId {
+        self.slave
+    }
+
+    fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Self::Error> {
+        self.runtime
+            .block_on(self.transport.transact(self.slave, request, response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    use modbius_codec::TcpTransportError;
+    use modbius_core::SlaveId;
+    use modbius_traits::blocking::{ModbusClient, ModbusTransport};
+
+    use super::BlockingClient;
+
+    #[test]
+    fn call() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 12];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0, 1, 0, 0, 0, 6, 7, 3, 0, 0, 0, 1]);
+            stream
+                .write_all(&[0, 1, 0, 0, 0, 5, 7, 3, 2, 1, 2])
+                .unwrap();
+
+            // An exception response to a request for another unit
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0, 2, 0, 0, 0, 6, 9, 3, 0, 0, 0, 1]);
+            stream.write_all(&[0, 2, 0, 0, 0, 3, 9, 0x83, 2]).unwrap();
+
+            // No answer to the last request
+            stream.read_exact(&mut request).unwrap();
+            thread::sleep(Duration::from_millis(200));
+        });
+
+        let mut client = BlockingClient::connect_tcp(addr, SlaveId::new(7)).unwrap();
+        assert_eq!(client.slave(), SlaveId::new(7));
+        let mut response = [0; 8];
+        let len = client.call(&[3, 0, 0, 0, 1], &mut response).unwrap();
+        assert_eq!(&response[..len], &[3, 2, 1, 2]);
+
+        let len = client
+            .transact(SlaveId::new(9), &[3, 0, 0, 0, 1], &mut response)
+            .unwrap();
+        assert_eq!(&response[..len], &[0x83, 2]);
+
+        client
+            .get_mut()
+            .set_response_timeout(Duration::from_millis(50));
+        let err = client.call(&[3, 0, 0, 0, 1], &mut response).unwrap_err();
+        assert!(matches!(err, TcpTransportError::Timeout));
+        server.join().unwrap();
+    }
+}
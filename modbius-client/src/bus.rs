@@ -0,0 +1,609 @@
+//! Arbitration of a shared modbus line between multiple logical clients.
+//!
+//! On a multi-drop line (e.g. RTU over RS-485) only one transaction may be outstanding at a time.
+//! A [Bus] owns the transport of such a line and hands out per [SlaveId] [BusClient] handles which may
+//! be used from different tasks. Access to the line is granted in the following order:
+//!
+//! 1. Requests which were passed over [starvation_limit](BusConfig::starvation_limit) times, oldest first
+//! 2. [Priority::High] requests (writes by default) before [Priority::Normal] requests (background polls)
+//! 3. Requests for slaves which stopped responding after everything else
+//! 4. The slave which was served least recently, then first come first served
+//!
+//! A slave that stopped responding is additionally only given [unresponsive_timeout](BusConfig::unresponsive_timeout)
+//! to answer, so it can't hold the line for long.
+
+use core::fmt::{self, Display, Formatter};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use modbius_core::{PublicModbusFunction, SlaveId};
+use modbius_traits::{ModbusClient, ModbusTransport, TransportError};
+use tokio::sync::oneshot;
+
+/// The priority a request is queued for the line with.
+#[derive(Debug, Clone, Copy, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Requests which have to go out as soon as possible, like writes
+    High,
+    /// Background requests like cyclic polls
+    #[default]
+    Normal,
+}
+
+impl Priority {
+    /// Get the default priority of the given request PDU.
+    ///
+    /// Requests of publicly documented functions writing data get [Priority::High], everything else [Priority::Normal].
+    pub fn for_request(request: &[u8]) -> Self {
+        match request.first().map(|code| PublicModbusFunction::new(*code)) {
+            Some(
+                PublicModbusFunction::WriteSingleCoil
+                | PublicModbusFunction::WriteSingleRegister
+                | PublicModbusFunction::WriteMultipleCoils
+                | PublicModbusFunction::WriteMultipleRegisters
+                | PublicModbusFunction::MaskWriteRegister
+                | PublicModbusFunction::ReadWriteMultipleRegisters
+                | PublicModbusFunction::WriteFileRecord,
+            ) => Self::High,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// Configuration of a [Bus]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusConfig {
+    /// The maximum time a single transaction may hold the line
+    pub timeout: Duration,
+    /// The maximum time a transaction to an unresponsive slave may hold the line
+    pub unresponsive_timeout: Duration,
+    /// The number of consecutive timeouts after which a slave is considered unresponsive.
+    ///
+    /// A single answer of the slave makes it responsive again.
+    pub unresponsive_after: u32,
+    /// The number of times a queued request may be passed over before it is served next regardless of its priority
+    pub starvation_limit: u32,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+            unresponsive_timeout: Duration::from_millis(200),
+            unresponsive_after: 3,
+            starvation_limit: 16,
+        }
+    }
+}
+
+/// The error returned by transactions over a [Bus]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BusError<E> {
+    /// The slave did not answer in the time configured for the bus
+    Timeout,
+    /// The transport of the bus failed
+    Transport(E),
+}
+
+impl<E: TransportError> TransportError for BusError<E> {
+    fn is_timeout(&self) -> bool {
+        match self {
+            Self::Timeout => true,
+            Self::Transport(e) => e.is_timeout(),
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout => true,
+            Self::Transport(e) => e.is_retryable(),
+        }
+    }
+
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Transport(e) if e.is_disconnect())
+    }
+}
+
+impl<E: Display> Display for BusError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "slave did not respond in time"),
+            Self::Transport(e) => write!(f, "transport error: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for BusError<E> {}
+
+/// A shared modbus line. Cloning a bus creates a new reference to the same line.
+pub struct Bus<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Bus<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: ModbusTransport + Send> Bus<T> {
+    /// Create a new bus with the default [BusConfig]
+    pub fn new(transport: T) -> Self {
+        Self::with_config(transport, BusConfig::default())
+    }
+
+    /// Create a new bus with the given configuration
+    pub fn with_config(transport: T, config: BusConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                transport: tokio::sync::Mutex::new(transport),
+                state: Mutex::new(State::default()),
+                config,
+            }),
+        }
+    }
+
+    /// Create a client handle for the slave with the given id
+    pub fn client(&self, slave: SlaveId) -> BusClient<T> {
+        BusClient {
+            shared: Arc::clone(&self.shared),
+            slave,
+        }
+    }
+
+    /// The configuration of this bus
+    pub fn config(&self) -> BusConfig {
+        self.shared.config
+    }
+
+    /// Checks if the given slave is currently considered unresponsive
+    pub fn is_unresponsive(&self, slave: SlaveId) -> bool {
+        self.shared
+            .lock_state()
+            .is_unresponsive(slave, &self.shared.config)
+    }
+}
+
+/// A handle to talk to a single slave over a [Bus].
+///
+/// Handles are cheap to clone and may be used concurrently from different tasks.
+pub struct BusClient<T> {
+    shared: Arc<Shared<T>>,
+    slave: SlaveId,
+}
+
+impl<T> Clone for BusClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            slave: self.slave,
+        }
+    }
+}
+
+impl<T: ModbusTransport + Send> BusClient<T> {
+    /// Send the request PDU with the given priority and write the response PDU to response.
+    pub async fn call_with_priority(
+        &self,
+        priority: Priority,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, BusError<T::Error>> {
+        self.shared
+            .transact(self.slave, priority, request, response)
+            .await
+    }
+}
+
+impl<T: ModbusTransport + Send> ModbusClient for BusClient<T> {
+    type Error = BusError<T::Error>;
+
+    fn slave(&self) -> SlaveId {
+        self.slave
+    }
+
+    /// Send the request PDU with its default [Priority] (see [Priority::for_request])
+    async fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Self::Error> {
+        self.call_with_priority(Priority::for_request(request), request, response)
+            .await
+    }
+}
+
+struct Shared<T> {
+    transport: tokio::sync::Mutex<T>,
+    state: Mutex<State>,
+    config: BusConfig,
+}
+
+impl<T: ModbusTransport + Send> Shared<T> {
+    async fn transact(
+        &self,
+        slave: SlaveId,
+        priority: Priority,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, BusError<T::Error>> {
+        let permit = self.acquire(slave, priority).await;
+        let timeout = if self.lock_state().is_unresponsive(slave, &self.config) {
+            self.config.unresponsive_timeout
+        } else {
+            self.config.timeout
+        };
+
+        // The permit guarantees exclusive access, the mutex is only needed to get a mutable reference
+        let mut transport = self.transport.lock().await;
+        let res = match tokio::time::timeout(timeout, transport.transact(slave, request, response))
+            .await
+        {
+            Ok(Ok(n)) => Ok(n),
+            Ok(Err(e)) => Err(BusError::Transport(e)),
+            Err(_) => Err(BusError::Timeout),
+        };
+        drop(transport);
+
+        permit.finish(res.as_ref().is_err_and(|e| e.is_timeout()));
+        res
+    }
+
+    async fn acquire(&self, slave: SlaveId, priority: Priority) -> Permit<'_, T> {
+        let (ticket, granted) = {
+            let mut state = self.lock_state();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+
+            if state.owner.is_none() && state.waiters.is_empty() {
+                state.grant(ticket, slave);
+                return Permit {
+                    shared: self,
+                    ticket,
+                    slave,
+                };
+            }
+
+            let (wake, granted) = oneshot::channel();
+            state.waiters.push(Waiter {
+                ticket,
+                slave,
+                priority,
+                skipped: 0,
+                wake,
+            });
+            (ticket, granted)
+        };
+
+        // Removes the waiter or passes the line on if this future is dropped while waiting
+        let pending = Permit {
+            shared: self,
+            ticket,
+            slave,
+        };
+        // The sender is only dropped after sending or when the waiter was removed by `pending`
+        let _ = granted.await;
+        pending
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, State> {
+        // The state is never left inconsistent while locked so a poisoned lock can still be used
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Exclusive access to the line, or a queued request for it.
+///
+/// Dropping a permit releases the line or removes the queued request.
+struct Permit<'a, T> {
+    shared: &'a Shared<T>,
+    ticket: u64,
+    slave: SlaveId,
+}
+
+impl<T> Permit<'_, T> {
+    fn finish(self, timed_out: bool) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        let slave = state.slaves.entry(self.slave).or_default();
+        if timed_out {
+            slave.timeouts = slave.timeouts.saturating_add(1);
+        } else {
+            slave.timeouts = 0;
+        }
+        drop(state);
+        // Dropping self releases the line
+    }
+}
+
+impl<T> Drop for Permit<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.owner == Some(self.ticket) {
+            state.owner = None;
+            state.grant_next(&self.shared.config);
+        } else {
+            state.waiters.retain(|w| w.ticket != self.ticket);
+        }
+    }
+}
+
+struct Waiter {
+    ticket: u64,
+    slave: SlaveId,
+    priority: Priority,
+    skipped: u32,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct SlaveState {
+    /// The value of [State::grants] when this slave was last granted the line
+    last_grant: u64,
+    /// Consecutive timeouts of this slave
+    timeouts: u32,
+}
+
+#[derive(Default)]
+struct State {
+    owner: Option<u64>,
+    next_ticket: u64,
+    grants: u64,
+    waiters: Vec<Waiter>,
+    slaves: HashMap<SlaveId, SlaveState>,
+}
+
+impl State {
+    fn is_unresponsive(&self, slave: SlaveId, config: &BusConfig) -> bool {
+        self.slaves
+            .get(&slave)
+            .is_some_and(|s| s.timeouts >= config.unresponsive_after)
+    }
+
+    fn grant(&mut self, ticket: u64, slave: SlaveId) {
+        self.grants += 1;
+        self.owner = Some(ticket);
+        self.slaves.entry(slave).or_default().last_grant = self.grants;
+    }
+
+    fn grant_next(&mut self, config: &BusConfig) {
+        while let Some(idx) = self.select(config) {
+            let waiter = self.waiters.swap_remove(idx);
+            for other in self.waiters.iter_mut() {
+                other.skipped = other.skipped.saturating_add(1);
+            }
+
+            self.grant(waiter.ticket, waiter.slave);
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+            // The waiting future is gone without cleaning up after itself, try the next one
+            self.owner = None;
+        }
+    }
+
+    fn select(&self, config: &BusConfig) -> Option<usize> {
+        let starving = self
+            .waiters
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.skipped >= config.starvation_limit)
+            .min_by_key(|(_, w)| w.ticket);
+
+        if let Some((idx, _)) = starving {
+            return Some(idx);
+        }
+
+        self.waiters
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, w)| {
+                let unresponsive = self.is_unresponsive(w.slave, config);
+                let last_grant = self.slaves.get(&w.slave).map_or(0, |s| s.last_grant);
+                (unresponsive, w.priority, last_grant, w.ticket)
+            })
+            .map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use modbius_core::SlaveId;
+    use modbius_traits::{ModbusClient, ModbusTransport, TransportError};
+
+    use super::{Bus, BusConfig, BusError, Priority};
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct MockError;
+
+    impl TransportError for MockError {
+        fn is_timeout(&self) -> bool {
+            false
+        }
+    }
+
+    /// Records the order of transactions, slave 99 never answers
+    #[derive(Clone, Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<(u8, u8)>>>,
+    }
+
+    impl Recorder {
+        fn log(&self) -> Vec<(u8, u8)> {
+            self.log.lock().unwrap().clone()
+        }
+    }
+
+    impl ModbusTransport for Recorder {
+        type Error = MockError;
+
+        async fn transact(
+            &mut self,
+            slave: SlaveId,
+            request: &[u8],
+            response: &mut [u8],
+        ) -> Result<usize, MockError> {
+            if slave == SlaveId::new(99) {
+                future::pending::<()>().await;
+            }
+            self.log.lock().unwrap().push((slave.into(), request[0]));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            response[0] = request[0];
+            Ok(1)
+        }
+    }
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test]
+    fn priority_for_request() {
+        assert_eq!(Priority::for_request(&[3, 0, 0, 0, 1]), Priority::Normal);
+        assert_eq!(Priority::for_request(&[6, 0, 0, 0, 1]), Priority::High);
+        assert_eq!(Priority::for_request(&[16]), Priority::High);
+        assert_eq!(Priority::for_request(&[]), Priority::Normal);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn single() {
+        let bus = Bus::new(Recorder::default());
+        let mut client = bus.client(SlaveId::new(1));
+        let mut response = [0; 1];
+
+        assert_eq!(client.call(&[3], &mut response).await, Ok(1));
+        assert_eq!(response, [3]);
+        assert_eq!(client.slave(), SlaveId::new(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_before_polls() {
+        let recorder = Recorder::default();
+        let bus = Bus::new(recorder.clone());
+
+        let mut tasks = Vec::new();
+        for (slave, code) in [(1, 3), (2, 3), (3, 3), (4, 6)] {
+            let mut client = bus.client(SlaveId::new(slave));
+            tasks.push(tokio::spawn(async move {
+                client.call(&[code], &mut [0]).await.unwrap();
+            }));
+            settle().await;
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(recorder.log(), [(1, 3), (4, 6), (2, 3), (3, 3)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_robin() {
+        let recorder = Recorder::default();
+        let bus = Bus::new(recorder.clone());
+
+        let mut tasks = Vec::new();
+        for slave in [1, 1, 1, 2, 2, 3] {
+            let mut client = bus.client(SlaveId::new(slave));
+            tasks.push(tokio::spawn(async move {
+                client.call(&[3], &mut [0]).await.unwrap();
+            }));
+            settle().await;
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order: Vec<u8> = recorder.log().into_iter().map(|(slave, _)| slave).collect();
+        assert_eq!(order, [1, 2, 3, 1, 2, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn starvation_limit() {
+        let recorder = Recorder::default();
+        let config = BusConfig {
+            starvation_limit: 2,
+            ..Default::default()
+        };
+        let bus = Bus::with_config(recorder.clone(), config);
+
+        let mut tasks = Vec::new();
+        for (slave, code) in [(1, 3), (2, 3), (3, 6), (4, 6), (5, 6)] {
+            let mut client = bus.client(SlaveId::new(slave));
+            tasks.push(tokio::spawn(async move {
+                client.call(&[code], &mut [0]).await.unwrap();
+            }));
+            settle().await;
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order: Vec<u8> = recorder.log().into_iter().map(|(slave, _)| slave).collect();
+        assert_eq!(order, [1, 3, 4, 2, 5]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unresponsive_slave() {
+        let recorder = Recorder::default();
+        let bus = Bus::new(recorder.clone());
+        let mut dead = bus.client(SlaveId::new(99));
+
+        for _ in 0..bus.config().unresponsive_after {
+            assert_eq!(dead.call(&[3], &mut [0]).await, Err(BusError::Timeout));
+        }
+        assert!(bus.is_unresponsive(SlaveId::new(99)));
+
+        let start = tokio::time::Instant::now();
+        let mut first = bus.client(SlaveId::new(1));
+        let first = tokio::spawn(async move { first.call(&[3], &mut [0]).await });
+        settle().await;
+
+        // Even a write to the unresponsive slave has to wait for the poll of a responsive slave
+        let dead = tokio::spawn(async move { dead.call(&[6], &mut [0]).await });
+        settle().await;
+
+        let mut second = bus.client(SlaveId::new(2));
+        assert_eq!(second.call(&[3], &mut [0]).await, Ok(1));
+        assert_eq!(start.elapsed(), Duration::from_millis(20));
+
+        assert_eq!(first.await.unwrap(), Ok(1));
+        assert_eq!(dead.await.unwrap(), Err(BusError::Timeout));
+        assert_eq!(
+            start.elapsed(),
+            bus.config().unresponsive_timeout + Duration::from_millis(20)
+        );
+        assert_eq!(recorder.log(), [(1, 3), (2, 3)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_waiter() {
+        let recorder = Recorder::default();
+        let bus = Bus::new(recorder.clone());
+
+        let mut first = bus.client(SlaveId::new(1));
+        let first = tokio::spawn(async move { first.call(&[3], &mut [0]).await });
+        settle().await;
+
+        let mut cancelled = bus.client(SlaveId::new(2));
+        let cancelled = tokio::spawn(async move { cancelled.call(&[3], &mut [0]).await });
+        settle().await;
+        cancelled.abort();
+
+        let mut last = bus.client(SlaveId::new(3));
+        assert_eq!(last.call(&[3], &mut [0]).await, Ok(1));
+        assert_eq!(first.await.unwrap(), Ok(1));
+        assert_eq!(recorder.log(), [(1, 3), (3, 3)]);
+    }
+}
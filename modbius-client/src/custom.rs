@@ -0,0 +1,218 @@
+//! Sending user defined functions.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+};
+
+use modbius_core::{custom::CustomFunction, exception::check_response, ModbusError, MAX_PDU_SIZE};
+use modbius_traits::{ModbusClient, TransportError};
+
+/// An error while sending a user defined function
+#[derive(Debug)]
+pub enum CustomError<E> {
+    /// The request could not be encoded, the response could not be decoded or the device answered with an
+    /// exception
+    Modbus(ModbusError),
+    Client(E),
+}
+
+impl<E: TransportError> TransportError for CustomError<E> {
+    fn is_timeout(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_timeout())
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_retryable())
+    }
+
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_disconnect())
+    }
+}
+
+impl<E: Display> Display for CustomError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Modbus(e) => write!(f, "{}", e),
+            Self::Client(e) => write!(f, "client error: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for CustomError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Client(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<ModbusError> for CustomError<E> {
+    fn from(e: ModbusError) -> Self {
+        Self::Modbus(e)
+    }
+}
+
+/// Sending user defined functions with any [ModbusClient].
+///
+/// The trait is implemented for every client, the layout of the function is given by its [CustomFunction]
+/// implementation.
+pub trait CustomClient: ModbusClient {
+    /// Send the request of the user defined function F and parse the response.
+    ///
+    /// The response borrows from the given buffer, which should hold at least
+    /// [MAX_PDU_SIZE] bytes.
+    fn send_custom<'r, F: CustomFunction>(
+        &mut self,
+        request: &F::Request<'_>,
+        response: &'r mut [u8],
+    ) -> impl Future<Output = Result<F::Response<'r>, CustomError<Self::Error>>> + Send;
+}
+
+impl<C: ModbusClient + Send> CustomClient for C {
+    fn send_custom<'r, F: CustomFunction>(
+        &mut self,
+        request: &F::Request<'_>,
+        response: &'r mut [u8],
+    ) -> impl Future<Output = Result<F::Response<'r>, CustomError<Self::Error>>> + Send {
+        // The request is written before the future is created so it doesn't have to be Send
+        let mut pdu = [0; MAX_PDU_SIZE];
+        pdu[0] = F::FUNCTION.into();
+        let written = F::write_request(request, &mut pdu[1..])
+            .map_err(|e| ModbusError::encode(e, Some(F::FUNCTION), 1));
+
+        async move {
+            let len = written? + 1;
+            let len = self
+                .call(&pdu[..len], response)
+                .await
+                .map_err(CustomError::Client)?;
+
+            let data = check_response(F::FUNCTION, &response[..len])?;
+            match F::parse_response(data) {
+                Ok((response, _tail)) => Ok(response),
+                Err(e) => Err(e.in_decode(Some(F::FUNCTION), 1).into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fmt, future::Future};
+
+    use modbius_core::{
+        custom::CustomFunction, util, DecodeError, DecodeErrorKind, ExceptionCode, ModbusError,
+        ModbusFunction, ModbusSerializationError, SlaveId,
+    };
+    use modbius_traits::{ModbusClient, TransportError};
+
+    use super::{CustomClient, CustomError};
+
+    /// Read a block of bytes with the given number
+    struct ReadBlock;
+
+    impl CustomFunction for ReadBlock {
+        const FUNCTION: ModbusFunction = ModbusFunction::new(0x64);
+        type Request<'a> = u16;
+        type Response<'a> = &'a [u8];
+
+        fn parse_request(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+            util::read_u16(data)
+        }
+
+        fn write_request(block: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            out[..2].copy_from_slice(&block.to_be_bytes());
+            Ok(2)
+        }
+
+        fn parse_response(data: &[u8]) -> Result<(&[u8], &[u8]), ModbusSerializationError> {
+            match data.split_first() {
+                Some((len, data)) if data.len() >= *len as usize => {
+                    Ok(data.split_at(*len as usize))
+                }
+                _ => Err(ModbusSerializationError::Invalid),
+            }
+        }
+
+        fn write_response(
+            block: &&[u8],
+            out: &mut [u8],
+        ) -> Result<usize, ModbusSerializationError> {
+            out[0] = block.len() as u8;
+            out[1..block.len() + 1].copy_from_slice(block);
+            Ok(block.len() + 1)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Never;
+
+    impl fmt::Display for Never {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("never")
+        }
+    }
+
+    impl TransportError for Never {
+        fn is_timeout(&self) -> bool {
+            false
+        }
+    }
+
+    /// Answers every request with the configured response after checking the request
+    struct Fixed {
+        request: Vec<u8>,
+        response: Vec<u8>,
+    }
+
+    impl ModbusClient for Fixed {
+        type Error = Never;
+
+        fn slave(&self) -> SlaveId {
+            SlaveId::new(1)
+        }
+
+        fn call(
+            &mut self,
+            request: &[u8],
+            response: &mut [u8],
+        ) -> impl Future<Output = Result<usize, Never>> + Send {
+            assert_eq!(request, self.request);
+            response[..self.response.len()].copy_from_slice(&self.response);
+            async { Ok(self.response.len()) }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_custom() {
+        let mut client = Fixed {
+            request: vec![0x64, 0, 7],
+            response: vec![0x64, 2, 0xAB, 0xCD],
+        };
+        let mut response = [0; 253];
+        let block = client
+            .send_custom::<ReadBlock>(&7, &mut response)
+            .await
+            .unwrap();
+        assert_eq!(block, &[0xAB, 0xCD]);
+
+        client.response = vec![0xE4, 2];
+        assert!(matches!(
+            client.send_custom::<ReadBlock>(&7, &mut response).await,
+            Err(CustomError::Modbus(ModbusError::Exception(e))) if e.code == ExceptionCode::IllegalDataAddress
+        ));
+
+        client.response = vec![0x64, 3, 0];
+        assert!(matches!(
+            client.send_custom::<ReadBlock>(&7, &mut response).await,
+            Err(CustomError::Modbus(ModbusError::Decode(DecodeError {
+                offset: 1,
+                kind: DecodeErrorKind::Invalid,
+                ..
+            })))
+        ));
+    }
+}
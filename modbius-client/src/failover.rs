@@ -0,0 +1,557 @@
+//! Failover between redundant paths to a device.
+//!
+//! A [Failover] client talks to a device over an ordered list of paths, e.g. the two network cards of a PLC or a
+//! primary and a standby gateway. Each path is a [ModbusClient] so any transport may be used.
+//!
+//! Transactions are sent over the active path, at first the first path. If it times out, loses its connection or a
+//! gateway answers that it can't reach the device, the path is marked as down and the transaction is sent over the
+//! next path: healthy paths first in list order, then paths marked as down. The path that answers becomes the
+//! active path.
+//!
+//! A request which failed with an error may still have been executed by the device, so only idempotent requests
+//! (see [is_idempotent]) are sent again over another path after an error. The same holds for
+//! [ExceptionCode::GatewayTargetDeviceFailedToRespond], the gateway forwarded the request and the device may have
+//! executed it before its response was lost. Only [ExceptionCode::GatewayPathUnavailable] means the request never
+//! reached the device, so any request is sent again after it. Other requests return the error but still switch the
+//! active path for the next transaction.
+//!
+//! With [fail_back](FailoverConfig::fail_back) enabled a path earlier in the list than the active path is probed
+//! with the [health check request](FailoverConfig::health_check) once its [check interval](FailoverConfig::check_interval)
+//! has elapsed and becomes active again if it answers. [check_health](Failover::check_health) probes all
+//! paths at once.
+
+use std::time::Duration;
+
+use modbius_core::{read::ReadHoldingRegisters, ExceptionCode, SlaveId, MAX_PDU_SIZE};
+use modbius_traits::{ModbusClient, TransportError};
+use tokio::time::Instant;
+
+use crate::retry::{exception_code, is_idempotent};
+
+/// Configuration of a [Failover] client
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FailoverConfig {
+    /// Switch back to a preferred path once it is healthy again
+    pub fail_back: bool,
+    /// The time after which a path which is down is probed again
+    pub check_interval: Duration,
+    /// The request PDU sent to probe a path, reading holding register 0 by default.
+    ///
+    /// Every response including most exception responses counts as healthy.
+    pub health_check: Vec<u8>,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            fail_back: true,
+            check_interval: Duration::from_secs(10),
+            health_check: ReadHoldingRegisters::new(0, 1).into_data().to_vec(),
+        }
+    }
+}
+
+/// The health of a path
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum PathStatus {
+    /// The last transaction over the path was answered or the path wasn't used yet
+    Healthy,
+    /// The last transaction failed, the path is checked again at the given time
+    Down { check_at: Instant },
+}
+
+#[derive(Debug)]
+struct Path<C> {
+    client: C,
+    status: PathStatus,
+}
+
+/// A client talking to a device over redundant paths.
+///
+/// See the [module](self) documentation for details.
+#[derive(Debug)]
+pub struct Failover<C> {
+    paths: Vec<Path<C>>,
+    active: usize,
+    last_path: Option<usize>,
+    config: FailoverConfig,
+}
+
+/// Checks if the response is an exception of a gateway which couldn't reach the device
+fn is_gateway_failure(request: &[u8], response: &[u8]) -> bool {
+    matches!(
+        exception_code(request, response),
+        Some(
+            ExceptionCode::GatewayPathUnavailable
+                | ExceptionCode::GatewayTargetDeviceFailedToRespond
+        )
+    )
+}
+
+impl<C: ModbusClient + Send> Failover<C> {
+    /// Create a failover client over the paths ordered by preference.
+    ///
+    /// # Panics
+    /// If no path is given.
+    pub fn new(paths: impl IntoIterator<Item = C>, config: FailoverConfig) -> Self {
+        let paths: Vec<_> = paths
+            .into_iter()
+            .map(|client| Path {
+                client,
+                status: PathStatus::Healthy,
+            })
+            .collect();
+        assert!(
+            !paths.is_empty(),
+            "a failover client needs at least one path"
+        );
+
+        Self {
+            paths,
+            active: 0,
+            last_path: None,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &FailoverConfig {
+        &self.config
+    }
+
+    /// The number of paths
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Always false, a failover client has at least one path
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// The index of the path the next transaction is sent over first
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The index of the path which served the last transaction, None if no transaction was answered yet
+    pub fn last_path(&self) -> Option<usize> {
+        self.last_path
+    }
+
+    /// The health of the path with the given index
+    pub fn status(&self, path: usize) -> PathStatus {
+        self.paths[path].status
+    }
+
+    pub fn path(&self, path: usize) -> &C {
+        &self.paths[path].client
+    }
+
+    pub fn path_mut(&mut self, path: usize) -> &mut C {
+        &mut self.paths[path].client
+    }
+
+    pub fn into_paths(self) -> Vec<C> {
+        self.paths.into_iter().map(|path| path.client).collect()
+    }
+
+    /// Send the request like [call](ModbusClient::call) and return the len of the response with the index of the
+    /// path which served it
+    pub async fn call_with_path(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<(usize, usize), C::Error> {
+        if self.config.fail_back {
+            self.fail_back().await;
+        }
+
+        let mut order: Vec<usize> = (0..self.paths.len())
+            .filter(|&idx| idx != self.active)
+            .collect();
+        order.sort_by_key(|&idx| matches!(self.paths[idx].status, PathStatus::Down { .. }));
+        order.insert(0, self.active);
+
+        let last = order.len() - 1;
+        for (attempt, idx) in order.into_iter().enumerate() {
+            let result = self.paths[idx].client.call(request, response).await;
+            let failed = match &result {
+                Ok(len) => is_gateway_failure(request, &response[..*len]),
+                Err(e) => e.is_timeout() || e.is_disconnect(),
+            };
+
+            if failed {
+                self.mark_down(idx);
+            } else if result.is_ok() {
+                self.paths[idx].status = PathStatus::Healthy;
+                self.active = idx;
+                self.last_path = Some(idx);
+            }
+
+            // Only a gateway without a path to the device surely didn't forward the request
+            let unavailable = result.as_ref().is_ok_and(|len| {
+                exception_code(request, &response[..*len])
+                    == Some(ExceptionCode::GatewayPathUnavailable)
+            });
+            let resend = unavailable || is_idempotent(request);
+            if !failed || !resend || attempt == last {
+                return result.map(|len| (len, idx));
+            }
+        }
+
+        unreachable!("a failover client has at least one path")
+    }
+
+    /// Probe every path with the health check request and switch back to a preferred path if enabled.
+    ///
+    /// Returns the number of healthy paths.
+    pub async fn check_health(&mut self) -> usize {
+        let mut healthy = 0;
+        for idx in 0..self.paths.len() {
+            if self.probe(idx).await {
+                healthy += 1;
+            }
+        }
+
+        if self.config.fail_back {
+            if let Some(idx) = self.paths[..self.active]
+                .iter()
+                .position(|path| path.status == PathStatus::Healthy)
+            {
+                self.active = idx;
+            }
+        }
+
+        healthy
+    }
+
+    /// Switch to the first path before the active one which is healthy or answers its due health check
+    async fn fail_back(&mut self) {
+        let now = Instant::now();
+        for idx in 0..self.active {
+            let healthy = match self.paths[idx].status {
+                PathStatus::Healthy => true,
+                PathStatus::Down { check_at } if check_at <= now => self.probe(idx).await,
+                PathStatus::Down { .. } => false,
+            };
+
+            if healthy {
+                self.active = idx;
+                return;
+            }
+        }
+    }
+
+    /// Send the health check request over a path and update its status
+    async fn probe(&mut self, idx: usize) -> bool {
+        let mut response = [0; MAX_PDU_SIZE];
+        let request = &self.config.health_check;
+        let healthy = match self.paths[idx].client.call(request, &mut response).await {
+            Ok(len) => !is_gateway_failure(request, &response[..len]),
+            Err(_) => false,
+        };
+
+        if healthy {
+            self.paths[idx].status = PathStatus::Healthy;
+        } else {
+            self.mark_down(idx);
+        }
+        healthy
+    }
+
+    fn mark_down(&mut self, idx: usize) {
+        self.paths[idx].status = PathStatus::Down {
+            check_at: Instant::now() + self.config.check_interval,
+        };
+
+        if idx == self.active {
+            // Continue with the first healthy path, the failed one if there is none
+            if let Some(next) = self
+                .paths
+                .iter()
+                .position(|path| path.status == PathStatus::Healthy)
+            {
+                self.active = next;
+            }
+        }
+    }
+}
+
+impl<C: ModbusClient + Send> ModbusClient for Failover<C> {
+    type Error = C::Error;
+
+    /// The slave id of the active path
+    fn slave(&self) -> SlaveId {
+        self.paths[self.active].client.slave()
+    }
+
+    async fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Self::Error> {
+        self.call_with_path(request, response)
+            .await
+            .map(|(len, _path)| len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use modbius_core::{ExceptionCode, SlaveId};
+    use modbius_traits::{ModbusClient, TransportError};
+    use tokio::time;
+
+    use super::{Failover, FailoverConfig, PathStatus};
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum MockError {
+        Timeout,
+        Invalid,
+    }
+
+    impl TransportError for MockError {
+        fn is_timeout(&self) -> bool {
+            *self == MockError::Timeout
+        }
+    }
+
+    /// A path answering reads with its index
+    #[derive(Clone)]
+    struct Endpoint {
+        idx: u8,
+        online: Arc<AtomicBool>,
+        /// Answer every request with this exception code if it isn't 0
+        gateway_failure: Arc<AtomicU8>,
+        /// Answer every request with an error which is neither a timeout nor a disconnect
+        invalid: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Endpoint {
+        fn new(idx: u8) -> Self {
+            Self {
+                idx,
+                online: Arc::new(AtomicBool::new(true)),
+                gateway_failure: Arc::new(AtomicU8::new(0)),
+                invalid: Arc::new(AtomicBool::new(false)),
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn set_online(&self, online: bool) {
+            self.online.store(online, Ordering::SeqCst);
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl ModbusClient for Endpoint {
+        type Error = MockError;
+
+        fn slave(&self) -> SlaveId {
+            SlaveId::new(self.idx + 1)
+        }
+
+        async fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, MockError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !self.online.load(Ordering::SeqCst) {
+                return Err(MockError::Timeout);
+            }
+            if self.invalid.load(Ordering::SeqCst) {
+                return Err(MockError::Invalid);
+            }
+
+            let code = self.gateway_failure.load(Ordering::SeqCst);
+            if code != 0 {
+                response[..2].copy_from_slice(&[request[0] | 0x80, code]);
+                return Ok(2);
+            }
+
+            response[..4].copy_from_slice(&[request[0], 2, 0, self.idx]);
+            Ok(4)
+        }
+    }
+
+    const READ: [u8; 5] = [3, 0, 0, 0, 1];
+    const WRITE: [u8; 5] = [6, 0, 0, 0, 1];
+
+    fn failover(fail_back: bool) -> (Vec<Endpoint>, Failover<Endpoint>) {
+        let endpoints = vec![Endpoint::new(0), Endpoint::new(1), Endpoint::new(2)];
+        let config = FailoverConfig {
+            fail_back,
+            check_interval: Duration::from_secs(10),
+            ..FailoverConfig::default()
+        };
+        (endpoints.clone(), Failover::new(endpoints, config))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failover_and_back() {
+        let (endpoints, mut client) = failover(true);
+        let mut response = [0; 8];
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 0))
+        );
+
+        endpoints[0].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 1))
+        );
+        assert_eq!(&response[..4], &[3, 2, 0, 1]);
+        assert_eq!(client.active(), 1);
+        assert_eq!(client.slave(), SlaveId::new(2));
+        assert!(matches!(client.status(0), PathStatus::Down { .. }));
+
+        // The failed path isn't tried again before its check interval
+        client.call(&READ, &mut response).await.unwrap();
+        assert_eq!(endpoints[0].calls(), 2);
+        assert_eq!(client.last_path(), Some(1));
+
+        endpoints[0].set_online(true);
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 0))
+        );
+        // The health check and the request
+        assert_eq!(endpoints[0].calls(), 4);
+        assert_eq!(client.status(0), PathStatus::Healthy);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_fail_back() {
+        let (endpoints, mut client) = failover(false);
+        let mut response = [0; 8];
+        endpoints[0].set_online(false);
+        endpoints[1].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 2))
+        );
+
+        endpoints[0].set_online(true);
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 2))
+        );
+
+        // A down path is still tried once the healthy ones failed
+        endpoints[2].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 0))
+        );
+
+        endpoints[0].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Err(MockError::Timeout)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn error_keeps_status() {
+        let (endpoints, mut client) = failover(false);
+        let mut response = [0; 8];
+        endpoints[0].set_online(false);
+        endpoints[1].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 2))
+        );
+
+        // An error which isn't a path failure neither marks the path healthy nor switches to it
+        endpoints[0].set_online(true);
+        endpoints[0].invalid.store(true, Ordering::SeqCst);
+        endpoints[2].set_online(false);
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Err(MockError::Invalid)
+        );
+        assert!(matches!(client.status(0), PathStatus::Down { .. }));
+        assert_eq!(client.active(), 2);
+        assert_eq!(client.last_path(), Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_are_not_resent() {
+        let (endpoints, mut client) = failover(true);
+        let mut response = [0; 8];
+        endpoints[0].set_online(false);
+        assert_eq!(
+            client.call_with_path(&WRITE, &mut response).await,
+            Err(MockError::Timeout)
+        );
+        assert_eq!(endpoints[1].calls(), 0);
+        assert_eq!(client.active(), 1);
+
+        assert_eq!(
+            client.call_with_path(&WRITE, &mut response).await,
+            Ok((4, 1))
+        );
+
+        // A gateway without a path to the device didn't forward the write
+        endpoints[1].gateway_failure.store(
+            ExceptionCode::GatewayPathUnavailable as u8,
+            Ordering::SeqCst,
+        );
+        assert_eq!(
+            client.call_with_path(&WRITE, &mut response).await,
+            Ok((4, 2))
+        );
+        assert_eq!(endpoints[1].calls(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gateway_target_failed() {
+        let (endpoints, mut client) = failover(true);
+        let mut response = [0; 8];
+        endpoints[0].gateway_failure.store(
+            ExceptionCode::GatewayTargetDeviceFailedToRespond as u8,
+            Ordering::SeqCst,
+        );
+
+        // The device may have executed the write before its response was lost
+        assert_eq!(
+            client.call_with_path(&WRITE, &mut response).await,
+            Ok((2, 0))
+        );
+        assert_eq!(&response[..2], &[0x86, 0x0B]);
+        assert_eq!(endpoints[1].calls(), 0);
+        assert_eq!(client.active(), 1);
+
+        // Reads are sent again over the next path
+        endpoints[1].gateway_failure.store(
+            ExceptionCode::GatewayTargetDeviceFailedToRespond as u8,
+            Ordering::SeqCst,
+        );
+        assert_eq!(
+            client.call_with_path(&READ, &mut response).await,
+            Ok((4, 2))
+        );
+        assert_eq!(endpoints[1].calls(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn check_health() {
+        let (endpoints, mut client) = failover(true);
+        endpoints[0].set_online(false);
+        assert_eq!(client.check_health().await, 2);
+        client.call(&READ, &mut [0; 8]).await.unwrap();
+        assert_eq!(client.last_path(), Some(1));
+
+        endpoints[0].set_online(true);
+        assert_eq!(client.check_health().await, 3);
+        assert_eq!(client.active(), 0);
+    }
+}
@@ -0,0 +1,36 @@
+//! Async modbius client implementations.
+//!
+//! The clients are based on `modbius-core` for parsing and implement the traits from `modbius-traits`.
+
+pub mod ascii;
+pub mod blocking;
+pub mod bus;
+pub mod custom;
+pub mod failover;
+#[cfg(feature = "profile")]
+pub mod plan;
+#[cfg(feature = "profile")]
+pub mod poll;
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod retry;
+pub mod rtu;
+pub mod tcp;
+
+#[cfg(all(test, feature = "profile"))]
+mod mock;
+
+pub use ascii::AsciiTransport;
+pub use blocking::BlockingClient;
+pub use bus::{Bus, BusClient, BusConfig, BusError, Priority};
+pub use custom::{CustomClient, CustomError};
+pub use failover::{Failover, FailoverConfig};
+#[cfg(feature = "profile")]
+pub use plan::{PlanConfig, ReadPlan};
+#[cfg(feature = "profile")]
+pub use poll::{Poller, PollerConfig};
+#[cfg(feature = "profile")]
+pub use profile::{Profile, ProfileClient, ProfileError};
+pub use retry::{Retry, RetryPolicy};
+pub use rtu::RtuTransport;
+pub use tcp::{ReconnectingTcpTransport, TcpTransport};
@@ -0,0 +1,64 @@
+//! Test fixtures shared by the profile, plan and poll tests.
+
+use modbius_core::{Request, SlaveId};
+use modbius_server::DataStore;
+use modbius_traits::{ModbusClient, ModbusHandler, TransportError};
+
+#[derive(Debug)]
+pub(crate) struct MockError;
+
+impl TransportError for MockError {
+    fn is_timeout(&self) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Display for MockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("mock error")
+    }
+}
+
+/// A client calling a data store directly
+pub(crate) struct StoreClient {
+    pub store: DataStore,
+    /// The number of calls made so far
+    pub calls: usize,
+    /// Fail every call with [MockError]
+    pub offline: bool,
+}
+
+impl StoreClient {
+    pub fn new(store: DataStore) -> Self {
+        Self {
+            store,
+            calls: 0,
+            offline: false,
+        }
+    }
+}
+
+impl ModbusClient for StoreClient {
+    type Error = MockError;
+
+    fn slave(&self) -> SlaveId {
+        SlaveId::new(1)
+    }
+
+    async fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, MockError> {
+        self.calls += 1;
+        if self.offline {
+            return Err(MockError);
+        }
+
+        let (parsed, _tail) = Request::from_data(request).unwrap();
+        match self.store.handle(self.slave(), parsed, response).await {
+            Ok(len) => Ok(len),
+            Err(code) => {
+                response[0] = request[0] | 0x80;
+                response[1] = code as u8;
+                Ok(2)
+            }
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Planning the fewest read requests covering a set of points.
+//!
+//! Polling a device point by point costs one round trip per point. A [ReadPlan] coalesces the points of each table
+//! into as few requests as possible, respecting the quantity limits of a request and the ranges a device refuses to
+//! read. The responses are sliced back into the individual points with [PlannedRead::slice].
+
+use std::ops::RangeInclusive;
+
+use modbius_core::{
+    read::{ReadCoils, ReadDiscreteInputs, ReadHoldingRegisters, ReadInputRegisters},
+    registerslice::RegisterSlice,
+    response::{ReadBitsResponse, ReadRegistersResponse},
+    BitState, ModbusSerializationError,
+};
+use serde::Deserialize;
+
+use crate::profile::Function;
+
+/// The registers or bits of a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PointRange {
+    pub function: Function,
+    pub address: u16,
+    pub quantity: u16,
+}
+
+impl PointRange {
+    pub const fn new(function: Function, address: u16, quantity: u16) -> Self {
+        Self {
+            function,
+            address,
+            quantity,
+        }
+    }
+
+    /// The address after the last register or bit
+    pub fn end(self) -> u32 {
+        u32::from(self.address) + u32::from(self.quantity)
+    }
+}
+
+/// Addresses a device answers with [IllegalDataAddress](modbius_core::ExceptionCode::IllegalDataAddress)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForbiddenRange {
+    pub function: Function,
+    pub start: u16,
+    /// The last forbidden address
+    pub end: u16,
+}
+
+impl ForbiddenRange {
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.start..=self.end
+    }
+
+    /// Checks if any address in start..end is forbidden
+    fn overlaps(&self, function: Function, start: u32, end: u32) -> bool {
+        self.function == function && u32::from(self.start) < end && u32::from(self.end) >= start
+    }
+}
+
+/// How points are coalesced into requests
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PlanConfig {
+    /// The maximum number of unused registers or bits between two points read with one request
+    pub max_gap: u16,
+    /// The maximum number of registers read with one request, some devices support less than the spec
+    pub max_registers: u16,
+    /// The maximum number of coils or discrete inputs read with one request
+    pub max_bits: u16,
+    /// Addresses which are never read to bridge a gap
+    pub forbidden: Vec<ForbiddenRange>,
+}
+
+impl Default for PlanConfig {
+    fn default() -> Self {
+        Self {
+            max_gap: 0,
+            max_registers: ReadHoldingRegisters::MAX_QUANTITY,
+            max_bits: ReadCoils::MAX_QUANTITY,
+            forbidden: Vec::new(),
+        }
+    }
+}
+
+impl PlanConfig {
+    fn max_quantity(&self, function: Function) -> u16 {
+        if function.is_bit() {
+            self.max_bits.min(ReadCoils::MAX_QUANTITY)
+        } else {
+            self.max_registers.min(ReadHoldingRegisters::MAX_QUANTITY)
+        }
+    }
+
+    fn is_forbidden(&self, function: Function, start: u32, end: u32) -> bool {
+        self.forbidden
+            .iter()
+            .any(|range| range.overlaps(function, start, end))
+    }
+}
+
+/// One request of a [ReadPlan]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlannedRead {
+    pub function: Function,
+    pub address: u16,
+    pub quantity: u16,
+    /// The indices of the points read by this request
+    pub points: Vec<usize>,
+}
+
+impl PlannedRead {
+    /// The request PDU
+    pub fn request_data(&self) -> [u8; 5] {
+        match self.function {
+            Function::Coil => ReadCoils::new(self.address, self.quantity).into_data(),
+            Function::Discrete => ReadDiscreteInputs::new(self.address, self.quantity).into_data(),
+            Function::Holding => ReadHoldingRegisters::new(self.address, self.quantity).into_data(),
+            Function::Input => ReadInputRegisters::new(self.address, self.quantity).into_data(),
+        }
+    }
+
+    /// Slice the response data following the function code into the data of each point.
+    ///
+    /// The points are the ones the plan was made for, the index of each point is returned with its data.
+    ///
+    /// # Errors
+    /// If the response is shorter than requested [ModbusSerializationError::UnexpectedEOF] is returned.
+    pub fn slice<'a>(
+        &self,
+        points: &[PointRange],
+        response: &'a [u8],
+    ) -> Result<Vec<(usize, PointData<'a>)>, ModbusSerializationError> {
+        let bytes = if self.function.is_bit() {
+            let (bits, _tail) = ReadBitsResponse::from_data(response)?;
+            bits.bytes()
+        } else {
+            let (registers, _tail) = ReadRegistersResponse::from_data(response)?;
+            registers.registers().bytes()
+        };
+
+        let unit = if self.function.is_bit() { 1 } else { 16 };
+        let expected = (usize::from(self.quantity) * unit).div_ceil(8);
+        if bytes.len() < expected {
+            return Err(ModbusSerializationError::UnexpectedEOF {
+                expected,
+                got: bytes.len(),
+            });
+        }
+
+        self.points
+            .iter()
+            .map(|&idx| {
+                let point = points.get(idx).ok_or(ModbusSerializationError::Invalid)?;
+                let offset = usize::from(
+                    point
+                        .address
+                        .checked_sub(self.address)
+                        .ok_or(ModbusSerializationError::Invalid)?,
+                );
+                let data = if self.function.is_bit() {
+                    PointData::Bits(Bits {
+                        bytes,
+                        offset,
+                        len: usize::from(point.quantity),
+                    })
+                } else {
+                    let registers = bytes
+                        .get(offset * 2..(offset + usize::from(point.quantity)) * 2)
+                        .ok_or(ModbusSerializationError::Invalid)?;
+                    PointData::Registers(RegisterSlice::new(registers)?)
+                };
+                Ok((idx, data))
+            })
+            .collect()
+    }
+}
+
+/// The data of one point in a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PointData<'a> {
+    Registers(RegisterSlice<'a>),
+    Bits(Bits<'a>),
+}
+
+/// The bits of a point in a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bits<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    len: usize,
+}
+
+impl<'a> Bits<'a> {
+    pub fn get(self, idx: usize) -> Option<BitState> {
+        if idx >= self.len {
+            return None;
+        }
+        let bit = self.offset + idx;
+        self.bytes
+            .get(bit / 8)
+            .map(|byte| BitState::from(byte & (1 << (bit % 8)) != 0))
+    }
+
+    pub fn len(self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(self) -> impl ExactSizeIterator<Item = BitState> + 'a {
+        (0..self.len).map(move |idx| self.get(idx).unwrap_or_default())
+    }
+}
+
+/// The requests reading a set of points
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ReadPlan {
+    requests: Vec<PlannedRead>,
+}
+
+impl ReadPlan {
+    /// Plan the fewest requests reading all points.
+    ///
+    /// Points of the same table are read together if the gap between them is at most
+    /// [max_gap](PlanConfig::max_gap), contains no forbidden address and the request stays within the quantity
+    /// limit. Every point is read by exactly one request, duplicate and overlapping points are allowed.
+    ///
+    /// # Errors
+    /// A point of 0 registers or bits returns [ModbusSerializationError::Invalid], a point larger than the quantity
+    /// limit [ModbusSerializationError::TooLarge] and a point beyond address 0xFFFF
+    /// [ModbusSerializationError::Overflow].
+    pub fn new(
+        points: &[PointRange],
+        config: &PlanConfig,
+    ) -> Result<Self, ModbusSerializationError> {
+        for point in points {
+            if point.quantity == 0 {
+                return Err(ModbusSerializationError::Invalid);
+            } else if point.quantity > config.max_quantity(point.function) {
+                return Err(ModbusSerializationError::TooLarge);
+            } else if point.end() > 0x10000 {
+                return Err(ModbusSerializationError::Overflow);
+            }
+        }
+
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        order.sort_by_key(|&idx| (points[idx].function as u8, points[idx].address));
+
+        let mut requests: Vec<PlannedRead> = Vec::new();
+        let mut end = 0;
+        for idx in order {
+            let point = points[idx];
+            let max = u32::from(config.max_quantity(point.function));
+
+            if let Some(request) = requests.last_mut() {
+                let start = u32::from(request.address);
+                let new_end = end.max(point.end());
+                let fits = request.function == point.function
+                    && u32::from(point.address) <= end + u32::from(config.max_gap)
+                    && new_end - start <= max
+                    && !config.is_forbidden(point.function, end, u32::from(point.address));
+                if fits {
+                    end = new_end;
+                    request.quantity = (end - start) as u16;
+                    request.points.push(idx);
+                    continue;
+                }
+            }
+
+            end = point.end();
+            requests.push(PlannedRead {
+                function: point.function,
+                address: point.address,
+                quantity: point.quantity,
+                points: vec![idx],
+            });
+        }
+
+        Ok(Self { requests })
+    }
+
+    pub fn requests(&self) -> &[PlannedRead] {
+        &self.requests
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, BitState, ModbusSerializationError};
+
+    use super::{ForbiddenRange, PlanConfig, PointData, PointRange, ReadPlan};
+    use crate::profile::Function;
+
+    fn holding(address: u16, quantity: u16) -> PointRange {
+        PointRange::new(Function::Holding, address, quantity)
+    }
+
+    fn ranges(plan: &ReadPlan) -> Vec<(Function, u16, u16, Vec<usize>)> {
+        plan.requests()
+            .iter()
+            .map(|read| {
+                (
+                    read.function,
+                    read.address,
+                    read.quantity,
+                    read.points.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coalesce() {
+        let points = [
+            holding(10, 2),
+            PointRange::new(Function::Coil, 5, 1),
+            holding(0, 1),
+            holding(1, 4),
+            holding(12, 1),
+            PointRange::new(Function::Coil, 1999, 1),
+            holding(3, 1),
+            PointRange::new(Function::Input, 0, 1),
+        ];
+        let plan = ReadPlan::new(&points, &PlanConfig::default()).unwrap();
+        assert_eq!(
+            ranges(&plan),
+            [
+                (Function::Coil, 5, 1, vec![1]),
+                (Function::Coil, 1999, 1, vec![5]),
+                (Function::Holding, 0, 5, vec![2, 3, 6]),
+                (Function::Holding, 10, 3, vec![0, 4]),
+                (Function::Input, 0, 1, vec![7]),
+            ]
+        );
+
+        let config = PlanConfig {
+            max_gap: 5,
+            ..Default::default()
+        };
+        let plan = ReadPlan::new(&points, &config).unwrap();
+        assert_eq!(plan.len(), 4);
+        assert_eq!(
+            ranges(&plan)[2],
+            (Function::Holding, 0, 13, vec![2, 3, 6, 0, 4])
+        );
+
+        // Coils are limited to 2000 per request
+        let config = PlanConfig {
+            max_gap: 2000,
+            ..Default::default()
+        };
+        let plan = ReadPlan::new(&points, &config).unwrap();
+        assert_eq!(ranges(&plan)[0], (Function::Coil, 5, 1995, vec![1, 5]));
+    }
+
+    #[test]
+    fn limits() {
+        let points: Vec<_> = (0..130).map(|address| holding(address, 1)).collect();
+        let plan = ReadPlan::new(&points, &PlanConfig::default()).unwrap();
+        assert_eq!(plan.requests()[0].quantity, 125);
+        assert_eq!(
+            (plan.requests()[1].address, plan.requests()[1].quantity),
+            (125, 5)
+        );
+
+        let config = PlanConfig {
+            max_registers: 50,
+            ..Default::default()
+        };
+        assert_eq!(ReadPlan::new(&points, &config).unwrap().len(), 3);
+
+        let invalid = |point| ReadPlan::new(&[point], &PlanConfig::default()).unwrap_err();
+        assert_eq!(invalid(holding(0, 0)), ModbusSerializationError::Invalid);
+        assert_eq!(invalid(holding(0, 126)), ModbusSerializationError::TooLarge);
+        assert_eq!(
+            invalid(holding(0xFFFF, 2)),
+            ModbusSerializationError::Overflow
+        );
+    }
+
+    #[test]
+    fn forbidden() {
+        let points = [holding(0, 2), holding(10, 2), holding(20, 2)];
+        let config = PlanConfig {
+            max_gap: 10,
+            forbidden: vec![ForbiddenRange {
+                function: Function::Holding,
+                start: 14,
+                end: 15,
+            }],
+            ..Default::default()
+        };
+        let plan = ReadPlan::new(&points, &config).unwrap();
+        assert_eq!(
+            ranges(&plan),
+            [
+                (Function::Holding, 0, 12, vec![0, 1]),
+                (Function::Holding, 20, 2, vec![2]),
+            ]
+        );
+
+        // Forbidden ranges of other tables don't matter
+        let config = PlanConfig {
+            forbidden: vec![ForbiddenRange {
+                function: Function::Input,
+                start: 14,
+                end: 15,
+            }],
+            ..config
+        };
+        assert_eq!(ReadPlan::new(&points, &config).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn slice() {
+        let points = [holding(2, 1), holding(0, 2), holding(2, 2)];
+        let config = PlanConfig::default();
+        let plan = ReadPlan::new(&points, &config).unwrap();
+        assert_eq!(plan.len(), 1);
+
+        let read = &plan.requests()[0];
+        assert_eq!(read.request_data(), [3, 0, 0, 0, 4]);
+        let response = [8, 0, 1, 0, 2, 0, 3, 0, 4];
+        let sliced = read.slice(&points, &response).unwrap();
+        let registers = |bytes| PointData::Registers(RegisterSlice::new(bytes).unwrap());
+        assert_eq!(
+            sliced,
+            [
+                (1, registers(&[0, 1, 0, 2])),
+                (0, registers(&[0, 3])),
+                (2, registers(&[0, 3, 0, 4])),
+            ]
+        );
+
+        assert_eq!(
+            read.slice(&points, &[6, 0, 1, 0, 2, 0, 3]),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 8,
+                got: 6
+            })
+        );
+    }
+
+    #[test]
+    fn slice_bits() {
+        let points = [
+            PointRange::new(Function::Discrete, 3, 1),
+            PointRange::new(Function::Discrete, 8, 3),
+        ];
+        let config = PlanConfig {
+            max_gap: 8,
+            ..Default::default()
+        };
+        let plan = ReadPlan::new(&points, &config).unwrap();
+        let read = &plan.requests()[0];
+        assert_eq!(read.request_data(), [2, 0, 3, 0, 8]);
+
+        // Bits 3 and 9 are on
+        let sliced = read.slice(&points, &[1, 0b0100_0001]).unwrap();
+        let PointData::Bits(bits) = sliced[0].1 else {
+            panic!("expected bits");
+        };
+        assert_eq!(bits.get(0), Some(BitState::On));
+        assert_eq!(bits.get(1), None);
+
+        let PointData::Bits(bits) = sliced[1].1 else {
+            panic!("expected bits");
+        };
+        assert_eq!(
+            bits.iter().collect::<Vec<_>>(),
+            [BitState::Off, BitState::On, BitState::Off]
+        );
+    }
+}
@@ -0,0 +1,720 @@
+//! Cyclic polling of device points.
+//!
+//! A [Poller] reads the subscribed points of its devices at the interval of each subscription. The points of a
+//! device with the same interval form a group which is read with the fewest requests, see [ReadPlan].
+//!
+//! The latest [Sample] of every point is kept in a [PollCache] with the time it was read and its [Quality].
+//! Whenever the value or the quality of a point changes a [Change] is emitted. Numeric values of subscriptions with
+//! a deadband only count as changed once they moved more than the deadband away from the last emitted value.
+//!
+//! A device which failed to respond [backoff_after](PollerConfig::backoff_after) times in a row is only polled again
+//! after a delay, starting at [backoff_initial](PollerConfig::backoff_initial) and doubling with every further
+//! failure up to [backoff_max](PollerConfig::backoff_max). The points of the device keep their last value with
+//! [Quality::CommFailure] in the meantime.
+//!
+//! Groups are polled one after another. To poll devices on different lines or connections concurrently use one
+//! poller per line.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use modbius_core::{ExceptionCode, MAX_PDU_SIZE};
+use modbius_traits::ModbusClient;
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+use crate::{
+    plan::{PointRange, ReadPlan},
+    profile::{Point, PointError, ProfileClient, Value},
+};
+
+/// Configuration of a [Poller]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PollerConfig {
+    /// The number of failed polls in a row after which a device is backed off
+    pub backoff_after: u32,
+    /// The delay after the first failure which backs off a device
+    pub backoff_initial: Duration,
+    /// The maximum delay between polls of a failing device
+    pub backoff_max: Duration,
+}
+
+impl Default for PollerConfig {
+    fn default() -> Self {
+        Self {
+            backoff_after: 3,
+            backoff_initial: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PollerConfig {
+    /// The delay before the next poll of a device after the given number of failures in a row
+    fn backoff(&self, failures: u32) -> Option<Duration> {
+        let exponent = failures.checked_sub(self.backoff_after)?;
+        let delay = self
+            .backoff_initial
+            .checked_mul(1 << exponent.min(16))
+            .unwrap_or(self.backoff_max);
+        Some(delay.min(self.backoff_max))
+    }
+}
+
+/// The handle of a device added to a [Poller]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceId(usize);
+
+/// The quality of a [Sample]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Quality {
+    /// The value was read in the last poll
+    Good,
+    /// The device answered the last poll with an exception
+    Exception(ExceptionCode),
+    /// The response of the last poll could not be parsed or decoded
+    Invalid,
+    /// The device didn't answer the last poll or is backed off
+    CommFailure,
+}
+
+impl Quality {
+    pub fn is_good(self) -> bool {
+        self == Self::Good
+    }
+}
+
+/// The state of a point after a poll
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// The last value read, kept if the quality turns bad. None if the point was never read successfully.
+    pub value: Option<Value>,
+    pub quality: Quality,
+    /// The time of the poll
+    pub timestamp: SystemTime,
+}
+
+/// The value or the quality of a point changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub device: DeviceId,
+    pub point: String,
+    pub sample: Sample,
+}
+
+/// The last samples of all polled points, shared between a [Poller] and its readers
+#[derive(Debug, Clone, Default)]
+pub struct PollCache {
+    samples: Arc<RwLock<HashMap<DeviceId, HashMap<String, Sample>>>>,
+}
+
+impl PollCache {
+    /// Get the last sample of a point, None if the point wasn't polled yet
+    pub fn get(&self, device: DeviceId, point: &str) -> Option<Sample> {
+        let samples = self.samples.read().unwrap_or_else(|e| e.into_inner());
+        samples.get(&device)?.get(point).cloned()
+    }
+
+    /// Get the last samples of all polled points of a device
+    pub fn device(&self, device: DeviceId) -> HashMap<String, Sample> {
+        let samples = self.samples.read().unwrap_or_else(|e| e.into_inner());
+        samples.get(&device).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    point: Point,
+    deadband: Option<f64>,
+    /// The value of the last emitted change
+    reported: Option<Value>,
+}
+
+impl Subscription {
+    /// Check if the value moved far enough from the last emitted value to be reported
+    fn is_change(&self, value: Option<&Value>) -> bool {
+        match (self.reported.as_ref(), value) {
+            (Some(reported), Some(value)) => {
+                match (self.deadband, reported.as_f64(), value.as_f64()) {
+                    (Some(deadband), Some(reported), Some(value)) => {
+                        (value - reported).abs() > deadband
+                    }
+                    _ => reported != value,
+                }
+            }
+            (reported, value) => reported != value,
+        }
+    }
+}
+
+/// The points of a device polled at the same interval
+#[derive(Debug)]
+struct Group {
+    device: DeviceId,
+    interval: Duration,
+    subscriptions: Vec<Subscription>,
+    ranges: Vec<PointRange>,
+    plan: ReadPlan,
+    due: Instant,
+}
+
+#[derive(Debug)]
+struct Device<C> {
+    client: ProfileClient<C>,
+    /// The number of failed polls in a row
+    failures: u32,
+    /// The device is backed off until this time
+    retry_at: Option<Instant>,
+}
+
+/// A cyclic poller of the points of several devices.
+///
+/// See the [module](self) documentation for details.
+#[derive(Debug)]
+pub struct Poller<C> {
+    config: PollerConfig,
+    devices: Vec<Device<C>>,
+    groups: Vec<Group>,
+    cache: PollCache,
+}
+
+impl<C: ModbusClient + Send> Poller<C> {
+    pub fn new(config: PollerConfig) -> Self {
+        Self {
+            config,
+            devices: Vec::new(),
+            groups: Vec::new(),
+            cache: PollCache::default(),
+        }
+    }
+
+    pub fn config(&self) -> &PollerConfig {
+        &self.config
+    }
+
+    /// The cache of the last samples, clone it to read the samples from other tasks
+    pub fn cache(&self) -> &PollCache {
+        &self.cache
+    }
+
+    /// Add a device without any subscriptions
+    pub fn add_device(&mut self, client: ProfileClient<C>) -> DeviceId {
+        self.devices.push(Device {
+            client,
+            failures: 0,
+            retry_at: None,
+        });
+        DeviceId(self.devices.len() - 1)
+    }
+
+    /// Get the client of a device.
+    ///
+    /// # Panics
+    /// If the device wasn't added to this poller.
+    pub fn device(&self, device: DeviceId) -> &ProfileClient<C> {
+        &self.devices[device.0].client
+    }
+
+    /// Get the client of a device, e.g. to write points between polls.
+    ///
+    /// # Panics
+    /// If the device wasn't added to this poller.
+    pub fn device_mut(&mut self, device: DeviceId) -> &mut ProfileClient<C> {
+        &mut self.devices[device.0].client
+    }
+
+    /// Poll the point with the given name every interval.
+    ///
+    /// Numeric values with a deadband are only reported as changed once they moved more than the deadband.
+    /// A point which is already subscribed is moved to the new interval. Newly created groups are polled
+    /// at the next call to [poll_due](Self::poll_due).
+    ///
+    /// # Errors
+    /// [PointError::UnknownPoint] if the profile of the device has no such point, [PointError::AccessDenied] if
+    /// the point isn't readable and [PointError::Serialization] if the point can't be planned.
+    ///
+    /// # Panics
+    /// If the device wasn't added to this poller or the interval is zero.
+    pub fn subscribe(
+        &mut self,
+        device: DeviceId,
+        name: &str,
+        interval: Duration,
+        deadband: Option<f64>,
+    ) -> Result<(), PointError<C::Error>> {
+        assert!(!interval.is_zero(), "the poll interval must not be zero");
+        let point = self.devices[device.0].client.readable_point(name)?;
+        let same_group = |group: &Group| group.device == device && group.interval == interval;
+
+        // Plan the group with the point before changing anything
+        let mut ranges: Vec<_> = self
+            .groups
+            .iter()
+            .find(|group| same_group(group))
+            .map(|group| {
+                group
+                    .subscriptions
+                    .iter()
+                    .filter(|subscription| subscription.point.name != name)
+                    .map(|subscription| subscription.point.range())
+                    .collect()
+            })
+            .unwrap_or_default();
+        ranges.push(point.range());
+        let plan = ReadPlan::new(&ranges, &self.devices[device.0].client.profile().plan)?;
+
+        self.unsubscribe(device, name);
+        let subscription = Subscription {
+            point,
+            deadband,
+            reported: None,
+        };
+        match self.groups.iter().position(same_group) {
+            Some(idx) => {
+                let group = &mut self.groups[idx];
+                group.subscriptions.push(subscription);
+                group.ranges = ranges;
+                group.plan = plan;
+            }
+            None => self.groups.push(Group {
+                device,
+                interval,
+                subscriptions: vec![subscription],
+                ranges,
+                plan,
+                due: Instant::now(),
+            }),
+        }
+
+        Ok(())
+    }
+
+    /// Stop polling the point with the given name, returns false if it wasn't subscribed.
+    ///
+    /// The last sample stays in the cache.
+    pub fn unsubscribe(&mut self, device: DeviceId, name: &str) -> bool {
+        let Some((group, idx)) = self.groups.iter().enumerate().find_map(|(group, g)| {
+            let idx = g
+                .subscriptions
+                .iter()
+                .position(|s| g.device == device && s.point.name == name)?;
+            Some((group, idx))
+        }) else {
+            return false;
+        };
+
+        let g = &mut self.groups[group];
+        g.subscriptions.remove(idx);
+        g.ranges.remove(idx);
+        if g.subscriptions.is_empty() {
+            self.groups.remove(group);
+        } else {
+            // A subset of a valid plan is always valid
+            g.plan = ReadPlan::new(&g.ranges, &self.devices[device.0].client.profile().plan)
+                .unwrap_or_default();
+        }
+
+        true
+    }
+
+    /// The time the next group is due, None if nothing is subscribed
+    pub fn next_poll(&self) -> Option<Instant> {
+        self.groups.iter().map(|group| self.due(group)).min()
+    }
+
+    fn due(&self, group: &Group) -> Instant {
+        match self.devices[group.device.0].retry_at {
+            Some(retry_at) => group.due.max(retry_at),
+            None => group.due,
+        }
+    }
+
+    /// Poll all groups which are due and send the changes to events.
+    ///
+    /// Changes are dropped if the receiver was closed.
+    pub async fn poll_due(&mut self, events: &mpsc::Sender<Change>) {
+        let now = Instant::now();
+        for idx in 0..self.groups.len() {
+            // A failing device may have been backed off by the poll of another group
+            if self.due(&self.groups[idx]) <= now {
+                self.poll_group(idx, events).await;
+            }
+        }
+    }
+
+    /// Poll the subscribed points until the receiver of events is closed or nothing is subscribed
+    pub async fn run(mut self, events: mpsc::Sender<Change>) {
+        while !events.is_closed() {
+            let Some(next) = self.next_poll() else {
+                return;
+            };
+            time::sleep_until(next).await;
+            self.poll_due(&events).await;
+        }
+    }
+
+    async fn poll_group(&mut self, idx: usize, events: &mpsc::Sender<Change>) {
+        let Self {
+            config,
+            devices,
+            groups,
+            cache,
+        } = self;
+        let group = &mut groups[idx];
+        let device = &mut devices[group.device.0];
+
+        let mut results = vec![Err(Quality::CommFailure); group.subscriptions.len()];
+        let mut failed = false;
+        for read in group.plan.requests() {
+            let mut response = [0; MAX_PDU_SIZE];
+            let quality = match device
+                .client
+                .call(&read.request_data(), &mut response)
+                .await
+            {
+                Ok(data) => match read.slice(&group.ranges, data) {
+                    Ok(data) => {
+                        for (idx, data) in data {
+                            results[idx] = group.subscriptions[idx]
+                                .point
+                                .decode(data)
+                                .map_err(|_| Quality::Invalid);
+                        }
+                        continue;
+                    }
+                    Err(_) => Quality::Invalid,
+                },
+                Err(PointError::Exception(code)) => Quality::Exception(code),
+                Err(PointError::Client(_)) => {
+                    // The device is most likely gone, the remaining points keep the comm failure
+                    failed = true;
+                    break;
+                }
+                Err(_) => Quality::Invalid,
+            };
+
+            for &idx in &read.points {
+                results[idx] = Err(quality);
+            }
+        }
+
+        let now = Instant::now();
+        if failed {
+            device.failures = device.failures.saturating_add(1);
+            device.retry_at = config.backoff(device.failures).map(|delay| now + delay);
+        } else {
+            device.failures = 0;
+            device.retry_at = None;
+        }
+
+        group.due += group.interval;
+        if group.due <= now {
+            // Skip the cycles missed while the poll took too long
+            group.due = now + group.interval;
+        }
+
+        let timestamp = SystemTime::now();
+        let mut changes = Vec::new();
+        {
+            let mut samples = cache.samples.write().unwrap_or_else(|e| e.into_inner());
+            let samples = samples.entry(group.device).or_default();
+            for (subscription, result) in group.subscriptions.iter_mut().zip(results) {
+                let previous = samples.get(&subscription.point.name);
+                let (value, quality) = match result {
+                    Ok(value) => (Some(value), Quality::Good),
+                    Err(quality) => (previous.and_then(|sample| sample.value.clone()), quality),
+                };
+                let changed = match previous {
+                    Some(previous) => {
+                        previous.quality != quality || subscription.is_change(value.as_ref())
+                    }
+                    None => true,
+                };
+
+                let sample = Sample {
+                    value,
+                    quality,
+                    timestamp,
+                };
+                if changed {
+                    subscription.reported = sample.value.clone();
+                    changes.push(Change {
+                        device: group.device,
+                        point: subscription.point.name.clone(),
+                        sample: sample.clone(),
+                    });
+                }
+                samples.insert(subscription.point.name.clone(), sample);
+            }
+        }
+
+        for change in changes {
+            if events.send(change).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::ExceptionCode;
+    use modbius_server::{DataStore, DataStoreConfig};
+    use tokio::{sync::mpsc, time};
+
+    use super::{Change, DeviceId, Poller, PollerConfig, Quality};
+    use crate::{
+        mock::StoreClient,
+        profile::{PointError, Profile, ProfileClient, Value},
+    };
+
+    const PROFILE: &str = r#"
+        name = "Boiler"
+
+        [[points]]
+        name = "temperature"
+        function = "input"
+        address = 0
+        type = "i16"
+        scale = 0.1
+
+        [[points]]
+        name = "pressure"
+        function = "input"
+        address = 1
+        type = "u16"
+
+        [[points]]
+        name = "burner"
+        function = "coil"
+        address = 0
+        type = "bool"
+
+        [[points]]
+        name = "setpoint"
+        function = "holding"
+        address = 0
+        type = "u16"
+        access = "write"
+    "#;
+
+    fn poller(config: PollerConfig) -> (DataStore, Poller<StoreClient>, DeviceId) {
+        let store = DataStore::new(DataStoreConfig {
+            coils: 8,
+            discrete_inputs: 8,
+            holding_registers: 8,
+            input_registers: 8,
+        });
+        let client = StoreClient::new(store.clone());
+        let profile = Profile::from_toml(PROFILE).unwrap();
+        let mut poller = Poller::new(config);
+        let device = poller.add_device(ProfileClient::new(client, profile));
+        (store, poller, device)
+    }
+
+    fn drain(events: &mut mpsc::Receiver<Change>) -> Vec<(String, Option<Value>, Quality)> {
+        let mut changes = Vec::new();
+        while let Ok(change) = events.try_recv() {
+            changes.push((change.point, change.sample.value, change.sample.quality));
+        }
+        changes
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changes_and_deadband() {
+        let (store, mut poller, device) = poller(PollerConfig::default());
+        let (tx, mut rx) = mpsc::channel(16);
+        let second = Duration::from_secs(1);
+        poller
+            .subscribe(device, "temperature", second, Some(0.5))
+            .unwrap();
+        poller.subscribe(device, "pressure", second, None).unwrap();
+        store.write(|tables| tables.input_registers_mut()[..2].copy_from_slice(&[215, 3]));
+
+        poller.poll_due(&tx).await;
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                (
+                    "temperature".to_owned(),
+                    Some(Value::Float(21.5)),
+                    Quality::Good
+                ),
+                (
+                    "pressure".to_owned(),
+                    Some(Value::Unsigned(3)),
+                    Quality::Good
+                ),
+            ]
+        );
+        // Both points are read with one request
+        assert_eq!(poller.device(device).get_ref().calls, 1);
+
+        // Within the deadband of the last reported value
+        store.write(|tables| tables.input_registers_mut()[0] = 219);
+        time::advance(second).await;
+        poller.poll_due(&tx).await;
+        assert!(drain(&mut rx).is_empty());
+        let sample = poller.cache().get(device, "temperature").unwrap();
+        assert_eq!(sample.value, Some(Value::Float(21.900000000000002)));
+
+        store.write(|tables| tables.input_registers_mut()[..2].copy_from_slice(&[221, 4]));
+        time::advance(second).await;
+        poller.poll_due(&tx).await;
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                (
+                    "temperature".to_owned(),
+                    Some(Value::Float(22.1)),
+                    Quality::Good
+                ),
+                (
+                    "pressure".to_owned(),
+                    Some(Value::Unsigned(4)),
+                    Quality::Good
+                ),
+            ]
+        );
+        assert_eq!(poller.device(device).get_ref().calls, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn intervals() {
+        let (_store, mut poller, device) = poller(PollerConfig::default());
+        let (tx, _rx) = mpsc::channel(16);
+        poller
+            .subscribe(device, "temperature", Duration::from_secs(1), None)
+            .unwrap();
+        poller
+            .subscribe(device, "burner", Duration::from_secs(3), None)
+            .unwrap();
+
+        let start = time::Instant::now();
+        let mut calls = Vec::new();
+        while time::Instant::now() - start < Duration::from_secs(6) {
+            time::sleep_until(poller.next_poll().unwrap()).await;
+            poller.poll_due(&tx).await;
+            calls.push(poller.device(device).get_ref().calls);
+        }
+        assert_eq!(calls, vec![2, 3, 4, 6, 7, 8, 10]);
+
+        // Moving a point to another interval removes it from the old group
+        poller
+            .subscribe(device, "burner", Duration::from_secs(1), None)
+            .unwrap();
+        assert_eq!(poller.groups.len(), 1);
+        assert!(poller.unsubscribe(device, "burner"));
+        assert!(!poller.unsubscribe(device, "burner"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff() {
+        let config = PollerConfig {
+            backoff_after: 2,
+            backoff_initial: Duration::from_secs(4),
+            backoff_max: Duration::from_secs(6),
+        };
+        let (store, mut poller, device) = poller(config);
+        let (tx, mut rx) = mpsc::channel(16);
+        let second = Duration::from_secs(1);
+        poller.subscribe(device, "pressure", second, None).unwrap();
+        store.write(|tables| tables.input_registers_mut()[1] = 7);
+        poller.poll_due(&tx).await;
+        assert_eq!(drain(&mut rx).len(), 1);
+
+        poller.device_mut(device).get_mut().offline = true;
+        let start = time::Instant::now();
+        let mut polls = Vec::new();
+        while polls.len() < 5 {
+            time::sleep_until(poller.next_poll().unwrap()).await;
+            poller.poll_due(&tx).await;
+            polls.push((time::Instant::now() - start).as_secs());
+        }
+        // Polled at the interval until the second failure, then after 4, 6 and at most 6 seconds
+        assert_eq!(polls, vec![1, 2, 6, 12, 18]);
+
+        // The last value is kept with the bad quality, reported once
+        assert_eq!(
+            drain(&mut rx),
+            vec![(
+                "pressure".to_owned(),
+                Some(Value::Unsigned(7)),
+                Quality::CommFailure
+            )]
+        );
+
+        poller.device_mut(device).get_mut().offline = false;
+        time::sleep_until(poller.next_poll().unwrap()).await;
+        poller.poll_due(&tx).await;
+        assert_eq!(
+            drain(&mut rx),
+            vec![(
+                "pressure".to_owned(),
+                Some(Value::Unsigned(7)),
+                Quality::Good
+            )]
+        );
+        assert_eq!(poller.next_poll().unwrap() - time::Instant::now(), second);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribe_errors_and_exceptions() {
+        let (_store, mut poller, device) = poller(PollerConfig::default());
+        let (tx, mut rx) = mpsc::channel(16);
+        let second = Duration::from_secs(1);
+        assert!(matches!(
+            poller.subscribe(device, "missing", second, None),
+            Err(PointError::UnknownPoint(_))
+        ));
+        assert!(matches!(
+            poller.subscribe(device, "setpoint", second, None),
+            Err(PointError::AccessDenied)
+        ));
+        assert!(poller.next_poll().is_none());
+
+        // The store only has 8 coils
+        let mut profile = Profile::from_toml(PROFILE).unwrap();
+        profile.points[2].address = 20;
+        let client = StoreClient::new(poller.device(device).get_ref().store.clone());
+        let other = poller.add_device(ProfileClient::new(client, profile));
+        poller.subscribe(other, "burner", second, None).unwrap();
+        poller.poll_due(&tx).await;
+        assert_eq!(
+            drain(&mut rx),
+            vec![(
+                "burner".to_owned(),
+                None,
+                Quality::Exception(ExceptionCode::IllegalDataAddress)
+            )]
+        );
+        assert!(poller.cache().get(device, "burner").is_none());
+        assert!(poller.cache().device(other).contains_key("burner"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_until_closed() {
+        let (_store, mut poller, device) = poller(PollerConfig::default());
+        let (tx, mut rx) = mpsc::channel(16);
+        poller
+            .subscribe(device, "pressure", Duration::from_secs(1), None)
+            .unwrap();
+        let cache = poller.cache().clone();
+        let task = tokio::spawn(poller.run(tx));
+
+        let change = rx.recv().await.unwrap();
+        assert_eq!(change.device, device);
+        assert_eq!(change.sample.value, Some(Value::Unsigned(0)));
+        assert!(cache.get(device, "pressure").unwrap().quality.is_good());
+
+        drop(rx);
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,932 @@
+//! Device profiles, register maps loaded at runtime.
+//!
+//! A [Profile] lists the points of a device, the registers and coils with their address, table, data type and
+//! layout. Profiles are written in TOML or JSON so new devices can be described without recompiling:
+//!
+//! ```toml
+//! name = "Energy meter"
+//!
+//! [[points]]
+//! name = "voltage"
+//! function = "input"
+//! address = 10
+//! type = "u32"
+//! order = "CDAB"
+//! scale = 0.1
+//! unit = "V"
+//!
+//! [[points]]
+//! name = "relay"
+//! function = "coil"
+//! address = 0
+//! type = "bool"
+//!
+//! # Read points up to 4 registers apart with one request, but never the registers 100 to 199
+//! [plan]
+//! max_gap = 4
+//! forbidden = [{ function = "holding", start = 100, end = 199 }]
+//! ```
+//!
+//! A [ProfileClient] reads and writes the points of a device by name. Reads of several points are coalesced into
+//! as few requests as possible according to the `[plan]` section of the profile, see [PlanConfig].
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+    sync::Arc,
+};
+
+use modbius_core::{
+    exception::check_response,
+    read::{ReadCoils, ReadDiscreteInputs, ReadHoldingRegisters, ReadInputRegisters},
+    registerslice::RegisterSlice,
+    write::{WriteMultipleRegisters, WriteSingleCoil, WriteSingleRegister},
+    BitState, ExceptionCode, ModbusError, ModbusFunction, ModbusSerializationError,
+    ValidationError, ValidationErrorKind, MAX_PDU_SIZE,
+};
+use modbius_traits::{ModbusClient, TransportError};
+use modbius_types::{fixed::FixedRaw, Bcd, Layout, RegisterValue};
+use serde::Deserialize;
+
+use crate::plan::{PlanConfig, PointData, PointRange, ReadPlan};
+
+/// An error in a device profile
+#[derive(Debug)]
+pub enum ProfileError {
+    /// The profile file could not be read
+    Io(io::Error),
+    /// The profile is not valid TOML or doesn't match the expected structure
+    Toml(toml::de::Error),
+    /// The profile is not valid JSON or doesn't match the expected structure
+    Json(serde_json::Error),
+    /// Two points have the same name
+    DuplicatePoint(String),
+    /// The description of a point is contradictory, e.g. a float stored in a coil
+    Invalid { point: String, reason: &'static str },
+    /// The registers of a point can't be accessed with one request
+    Limit {
+        point: String,
+        error: ValidationError,
+    },
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read profile: {}", e),
+            Self::Toml(e) => write!(f, "invalid profile: {}", e),
+            Self::Json(e) => write!(f, "invalid profile: {}", e),
+            Self::DuplicatePoint(name) => write!(f, "point {:?} is defined more than once", name),
+            Self::Invalid { point, reason } => write!(f, "point {:?}: {}", point, reason),
+            Self::Limit { point, error } => {
+                write!(f, "point {:?} exceeds the modbus limits: {}", point, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Toml(e) => Some(e),
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProfileError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ProfileError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for ProfileError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// The table a point is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Function {
+    Coil,
+    Discrete,
+    Holding,
+    Input,
+}
+
+impl Function {
+    /// Checks if the table holds bits instead of registers
+    pub fn is_bit(self) -> bool {
+        matches!(self, Self::Coil | Self::Discrete)
+    }
+
+    /// Checks if the table can be written by a client
+    pub fn is_writable(self) -> bool {
+        matches!(self, Self::Coil | Self::Holding)
+    }
+}
+
+/// The type of the value of a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    /// The only type of coils and discrete inputs
+    Bool,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    /// 4 BCD digits in one register
+    Bcd16,
+    /// 8 BCD digits in two registers
+    Bcd32,
+    /// Packed ASCII with two characters per register, the number of registers is the length of the point
+    String,
+}
+
+impl DataType {
+    /// The number of registers of the type, None for strings
+    pub fn registers(self) -> Option<u16> {
+        match self {
+            Self::Bool | Self::U16 | Self::I16 | Self::Bcd16 => Some(1),
+            Self::U32 | Self::I32 | Self::F32 | Self::Bcd32 => Some(2),
+            Self::U64 | Self::I64 | Self::F64 => Some(4),
+            Self::String => None,
+        }
+    }
+}
+
+/// The layout of multi-register values, see [Layout]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub enum Order {
+    #[default]
+    ABCD,
+    CDAB,
+    BADC,
+    DCBA,
+}
+
+impl Order {
+    pub fn layout(self) -> Layout {
+        match self {
+            Self::ABCD => Layout::ABCD,
+            Self::CDAB => Layout::CDAB,
+            Self::BADC => Layout::BADC,
+            Self::DCBA => Layout::DCBA,
+        }
+    }
+}
+
+/// Whether a point may be read or written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn is_readable(self) -> bool {
+        matches!(self, Self::Read | Self::ReadWrite)
+    }
+
+    pub fn is_writable(self) -> bool {
+        matches!(self, Self::Write | Self::ReadWrite)
+    }
+}
+
+/// A register or coil of a device
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Point {
+    pub name: String,
+    pub function: Function,
+    /// The address of the first register or of the bit
+    pub address: u16,
+    #[serde(rename = "type")]
+    pub data_type: DataType,
+    /// The number of registers of a string
+    pub length: Option<u16>,
+    #[serde(default)]
+    pub order: Order,
+    /// The value is the stored number times scale
+    pub scale: Option<f64>,
+    /// The unit of the value for display, e.g. `kWh`
+    pub unit: Option<String>,
+    /// The access mode, read only for inputs and read write for coils and holding registers if omitted
+    pub access: Option<Access>,
+}
+
+impl Point {
+    /// The access mode including the default of the table
+    pub fn access(&self) -> Access {
+        self.access.unwrap_or(if self.function.is_writable() {
+            Access::ReadWrite
+        } else {
+            Access::Read
+        })
+    }
+
+    /// The registers or bits of the point
+    pub fn range(&self) -> PointRange {
+        PointRange::new(self.function, self.address, self.quantity())
+    }
+
+    /// Decode the value of the point from its data in a response
+    pub fn decode(&self, data: PointData<'_>) -> Result<Value, ModbusSerializationError> {
+        match data {
+            PointData::Bits(bits) if self.data_type == DataType::Bool => bits
+                .get(0)
+                .map(|state| Value::Bool(state.is_on()))
+                .ok_or(ModbusSerializationError::Invalid),
+            PointData::Registers(registers) => decode(self, registers),
+            PointData::Bits(_) => Err(ModbusSerializationError::Invalid),
+        }
+    }
+
+    /// The number of registers or bits of the point
+    pub fn quantity(&self) -> u16 {
+        self.data_type
+            .registers()
+            .unwrap_or_else(|| self.length.unwrap_or(0))
+    }
+
+    /// Check the point against the limits of a single request
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        let invalid = |reason| {
+            Err(ProfileError::Invalid {
+                point: self.name.clone(),
+                reason,
+            })
+        };
+
+        if self.function.is_bit() != (self.data_type == DataType::Bool) {
+            return invalid("coils and discrete inputs have to be of type bool and registers not");
+        } else if self.data_type == DataType::String && self.length.is_none() {
+            return invalid("strings need a length in registers");
+        } else if self.data_type != DataType::String && self.length.is_some() {
+            return invalid("only strings have a length");
+        } else if self.scale.is_some()
+            && matches!(self.data_type, DataType::Bool | DataType::String)
+        {
+            return invalid("only numbers can be scaled");
+        } else if self
+            .scale
+            .is_some_and(|scale| scale == 0.0 || !scale.is_finite())
+        {
+            return invalid("the scale has to be a finite number other than 0");
+        } else if self.access().is_writable() && !self.function.is_writable() {
+            return invalid("discrete inputs and input registers are read only");
+        }
+
+        let quantity = self.quantity();
+        let read = match self.function {
+            Function::Coil => ReadCoils::new(self.address, quantity).validate(),
+            Function::Discrete => ReadDiscreteInputs::new(self.address, quantity).validate(),
+            Function::Holding => ReadHoldingRegisters::new(self.address, quantity).validate(),
+            Function::Input => ReadInputRegisters::new(self.address, quantity).validate(),
+        };
+        // Writes of multiple registers have a lower limit than reads
+        let write = if self.function == Function::Holding
+            && self.access().is_writable()
+            && quantity > WriteMultipleRegisters::MAX_QUANTITY
+        {
+            Err(ValidationError {
+                function: Some(ModbusFunction::new_public(
+                    WriteMultipleRegisters::MODBUS_FUNCTION_CODE,
+                )),
+                offset: 3,
+                kind: ValidationErrorKind::TooLarge,
+            })
+        } else {
+            Ok(())
+        };
+
+        read.and(write).map_err(|error| ProfileError::Limit {
+            point: self.name.clone(),
+            error,
+        })
+    }
+}
+
+/// The points of a device
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub points: Vec<Point>,
+    /// How the points are coalesced into requests
+    #[serde(default)]
+    pub plan: PlanConfig,
+}
+
+impl Profile {
+    /// Parse and validate a TOML profile
+    pub fn from_toml(profile: &str) -> Result<Self, ProfileError> {
+        let profile: Self = toml::from_str(profile)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Parse and validate a JSON profile
+    pub fn from_json(profile: &str) -> Result<Self, ProfileError> {
+        let profile: Self = serde_json::from_str(profile)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Load a profile from a file, files ending with `.json` are parsed as JSON and all others as TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let profile = fs::read_to_string(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Self::from_json(&profile)
+        } else {
+            Self::from_toml(&profile)
+        }
+    }
+
+    /// Check that the point names are unique and every point can be accessed
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        let mut names = HashSet::new();
+        for point in &self.points {
+            if !names.insert(point.name.as_str()) {
+                return Err(ProfileError::DuplicatePoint(point.name.clone()));
+            }
+            point.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn point(&self, name: &str) -> Option<&Point> {
+        self.points.iter().find(|point| point.name == name)
+    }
+}
+
+/// The value of a point
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    /// Floats and all scaled values
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    /// The value of a number as float
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Unsigned(value) => Some(*value as f64),
+            Self::Signed(value) => Some(*value as f64),
+            Self::Float(value) => Some(*value),
+            Self::Bool(_) | Self::String(_) => None,
+        }
+    }
+}
+
+/// An error while reading or writing a point
+#[derive(Debug)]
+pub enum PointError<E> {
+    /// The profile has no point with the name
+    UnknownPoint(String),
+    /// The access mode of the point doesn't allow the operation
+    AccessDenied,
+    /// The value doesn't match the type of the point or is out of range
+    InvalidValue,
+    /// The device answered with an exception
+    Exception(ExceptionCode),
+    /// The response of the device could not be parsed
+    Serialization(ModbusSerializationError),
+    Client(E),
+}
+
+impl<E: TransportError> TransportError for PointError<E> {
+    fn is_timeout(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_timeout())
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_retryable())
+    }
+
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Client(e) if e.is_disconnect())
+    }
+}
+
+impl<E: Display> Display for PointError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPoint(name) => write!(f, "unknown point {:?}", name),
+            Self::AccessDenied => write!(f, "the access mode of the point forbids this operation"),
+            Self::InvalidValue => write!(f, "the value doesn't fit the point"),
+            Self::Exception(code) => write!(f, "exception response: {:?}", code),
+            Self::Serialization(e) => write!(f, "invalid response: {:?}", e),
+            Self::Client(e) => write!(f, "client error: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for PointError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Client(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<ModbusSerializationError> for PointError<E> {
+    fn from(e: ModbusSerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+/// A client reading and writing the points of a device by name
+#[derive(Debug, Clone)]
+pub struct ProfileClient<C> {
+    client: C,
+    profile: Arc<Profile>,
+}
+
+impl<C: ModbusClient + Send> ProfileClient<C> {
+    /// Create a new client, the profile may be shared by the clients of many devices
+    pub fn new(client: C, profile: impl Into<Arc<Profile>>) -> Self {
+        Self {
+            client,
+            profile: profile.into(),
+        }
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.client
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.client
+    }
+
+    pub fn into_inner(self) -> C {
+        self.client
+    }
+
+    fn point(&self, name: &str) -> Result<Point, PointError<C::Error>> {
+        self.profile
+            .point(name)
+            .cloned()
+            .ok_or_else(|| PointError::UnknownPoint(name.to_owned()))
+    }
+
+    /// Get the point with the given name if its access mode allows reading
+    pub(crate) fn readable_point(&self, name: &str) -> Result<Point, PointError<C::Error>> {
+        let point = self.point(name)?;
+        if point.access().is_readable() {
+            Ok(point)
+        } else {
+            Err(PointError::AccessDenied)
+        }
+    }
+
+    /// Read the value of the point with the given name
+    pub async fn read(&mut self, name: &str) -> Result<Value, PointError<C::Error>> {
+        let mut values = self.read_many(&[name]).await?;
+        Ok(values.remove(0))
+    }
+
+    /// Read the values of several points with the fewest requests.
+    ///
+    /// The requests are planned with the [plan configuration](Profile::plan) of the profile, see [ReadPlan].
+    /// The values are returned in the order of the names.
+    pub async fn read_many(&mut self, names: &[&str]) -> Result<Vec<Value>, PointError<C::Error>> {
+        let points = names
+            .iter()
+            .map(|name| self.readable_point(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ranges: Vec<_> = points.iter().map(Point::range).collect();
+        let plan = ReadPlan::new(&ranges, &self.profile.plan)?;
+
+        let mut values = vec![None; points.len()];
+        for read in plan.requests() {
+            let mut response = [0; MAX_PDU_SIZE];
+            let data = self.call(&read.request_data(), &mut response).await?;
+            for (idx, data) in read.slice(&ranges, data)? {
+                values[idx] = Some(points[idx].decode(data)?);
+            }
+        }
+
+        // The plan reads every point
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    /// Write the value to the point with the given name
+    pub async fn write(&mut self, name: &str, value: &Value) -> Result<(), PointError<C::Error>> {
+        let point = self.point(name)?;
+        if !point.access().is_writable() {
+            return Err(PointError::AccessDenied);
+        }
+
+        let mut request = [0; MAX_PDU_SIZE];
+        let len = if point.function == Function::Coil {
+            let Value::Bool(state) = value else {
+                return Err(PointError::InvalidValue);
+            };
+            WriteSingleCoil::new(point.address, BitState::from(*state))
+                .write_to_slice(&mut request)?;
+            5
+        } else {
+            let mut buf = [0; MAX_PDU_SIZE];
+            let registers = encode(&point, value, &mut buf).ok_or(PointError::InvalidValue)?;
+            match registers.get(0) {
+                Some(register) if registers.len() == 1 => {
+                    WriteSingleRegister::new(point.address, register)
+                        .write_to_slice(&mut request)?;
+                    5
+                }
+                _ => {
+                    let write = WriteMultipleRegisters::new(point.address, registers)?;
+                    write.write_to_slice(&mut request)?;
+                    write.data_size()
+                }
+            }
+        };
+
+        self.call(&request[..len], &mut [0; MAX_PDU_SIZE]).await?;
+        Ok(())
+    }
+
+    /// Send the request and return the response data after the function code
+    pub(crate) async fn call<'r>(
+        &mut self,
+        request: &[u8],
+        response: &'r mut [u8],
+    ) -> Result<&'r [u8], PointError<C::Error>> {
+        let len = self
+            .client
+            .call(request, response)
+            .await
+            .map_err(PointError::Client)?;
+        match check_response(ModbusFunction::new(request[0]), &response[..len]) {
+            Ok(data) => Ok(data),
+            Err(ModbusError::Exception(e)) => Err(PointError::Exception(e.code)),
+            Err(_) => Err(ModbusSerializationError::Invalid.into()),
+        }
+    }
+}
+
+/// Decode the value of a register point
+fn decode(point: &Point, registers: RegisterSlice<'_>) -> Result<Value, ModbusSerializationError> {
+    let layout = point.order.layout();
+    let value = match point.data_type {
+        DataType::U16 => Value::Unsigned(u16::decode_with(registers, layout)?.into()),
+        DataType::I16 => Value::Signed(i16::decode_with(registers, layout)?.into()),
+        DataType::U32 => Value::Unsigned(u32::decode_with(registers, layout)?.into()),
+        DataType::I32 => Value::Signed(i32::decode_with(registers, layout)?.into()),
+        DataType::U64 => Value::Unsigned(u64::decode_with(registers, layout)?),
+        DataType::I64 => Value::Signed(i64::decode_with(registers, layout)?),
+        DataType::F32 => Value::Float(f32::decode_with(registers, layout)?.into()),
+        DataType::F64 => Value::Float(f64::decode_with(registers, layout)?),
+        DataType::Bcd16 => Value::Unsigned(Bcd::<1>::decode_with(registers, layout)?.value()),
+        DataType::Bcd32 => Value::Unsigned(Bcd::<2>::decode_with(registers, layout)?.value()),
+        DataType::String => {
+            let size = usize::from(point.quantity()) * 2;
+            let bytes = registers.bytes();
+            let mut bytes = bytes
+                .get(..size)
+                .ok_or(ModbusSerializationError::UnexpectedEOF {
+                    expected: size,
+                    got: bytes.len(),
+                })?
+                .to_vec();
+            layout.reorder(&mut bytes);
+            if !bytes.is_ascii() {
+                return Err(ModbusSerializationError::Invalid);
+            }
+
+            let len = bytes
+                .iter()
+                .rposition(|byte| !matches!(byte, 0 | b' '))
+                .map_or(0, |idx| idx + 1);
+            bytes.truncate(len);
+            Value::String(String::from_utf8(bytes).map_err(|_| ModbusSerializationError::Invalid)?)
+        }
+        DataType::Bool => return Err(ModbusSerializationError::Invalid),
+    };
+
+    Ok(match point.scale {
+        Some(scale) => Value::Float(value.as_f64().unwrap_or_default() * scale),
+        None => value,
+    })
+}
+
+/// Encode the value of a register point into out, None if the value doesn't fit
+fn encode<'b>(point: &Point, value: &Value, out: &'b mut [u8]) -> Option<RegisterSlice<'b>> {
+    let layout = point.order.layout();
+    let scale = point.scale;
+    let registers = match point.data_type {
+        DataType::U16 => integer::<u16>(value, scale)?.encode_with(layout, out),
+        DataType::I16 => integer::<i16>(value, scale)?.encode_with(layout, out),
+        DataType::U32 => integer::<u32>(value, scale)?.encode_with(layout, out),
+        DataType::I32 => integer::<i32>(value, scale)?.encode_with(layout, out),
+        DataType::U64 => integer::<u64>(value, scale)?.encode_with(layout, out),
+        DataType::I64 => integer::<i64>(value, scale)?.encode_with(layout, out),
+        DataType::F32 => ((value.as_f64()? / scale.unwrap_or(1.0)) as f32).encode_with(layout, out),
+        DataType::F64 => (value.as_f64()? / scale.unwrap_or(1.0)).encode_with(layout, out),
+        DataType::Bcd16 => Bcd::<1>::new(integer(value, scale)?)
+            .ok()?
+            .encode_with(layout, out),
+        DataType::Bcd32 => Bcd::<2>::new(integer(value, scale)?)
+            .ok()?
+            .encode_with(layout, out),
+        DataType::String => {
+            let Value::String(s) = value else {
+                return None;
+            };
+            let size = usize::from(point.quantity()) * 2;
+            if !s.is_ascii() || s.len() > size {
+                return None;
+            }
+
+            let bytes = out.get_mut(..size)?;
+            bytes.fill(0);
+            bytes[..s.len()].copy_from_slice(s.as_bytes());
+            layout.reorder(bytes);
+            RegisterSlice::new(bytes)
+        }
+        DataType::Bool => return None,
+    };
+
+    registers.ok()
+}
+
+/// Convert a value to an integer, scaled values and floats are rounded
+fn integer<T>(value: &Value, scale: Option<f64>) -> Option<T>
+where
+    T: FixedRaw + TryFrom<u64> + TryFrom<i64>,
+{
+    match (value, scale) {
+        (Value::Unsigned(value), None) => T::try_from(*value).ok(),
+        (Value::Signed(value), None) => T::try_from(*value).ok(),
+        (value, scale) => T::from_f64(value.as_f64()? / scale.unwrap_or(1.0)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{ExceptionCode, ValidationError, ValidationErrorKind};
+    use modbius_server::{DataStore, DataStoreConfig};
+
+    use crate::mock::StoreClient;
+
+    use super::{Access, DataType, PointError, Profile, ProfileClient, ProfileError, Value};
+
+    const PROFILE: &str = r#"
+        name = "Energy meter"
+
+        [[points]]
+        name = "relay"
+        function = "coil"
+        address = 1
+        type = "bool"
+
+        [[points]]
+        name = "alarm"
+        function = "discrete"
+        address = 3
+        type = "bool"
+
+        [[points]]
+        name = "voltage"
+        function = "input"
+        address = 10
+        type = "u32"
+        order = "CDAB"
+        scale = 0.1
+        unit = "V"
+
+        [[points]]
+        name = "setpoint"
+        function = "holding"
+        address = 0
+        type = "f32"
+
+        [[points]]
+        name = "limit"
+        function = "holding"
+        address = 2
+        type = "i16"
+        scale = 0.01
+
+        [[points]]
+        name = "serial"
+        function = "holding"
+        address = 4
+        type = "string"
+        length = 3
+        access = "read"
+
+        [[points]]
+        name = "code"
+        function = "holding"
+        address = 7
+        type = "bcd16"
+        access = "write"
+    "#;
+
+    fn client() -> (DataStore, ProfileClient<StoreClient>) {
+        let store = DataStore::new(DataStoreConfig {
+            coils: 8,
+            discrete_inputs: 8,
+            holding_registers: 8,
+            input_registers: 16,
+        });
+        let profile = Profile::from_toml(&format!("{}\n[plan]\nmax_gap = 2", PROFILE)).unwrap();
+        let client = StoreClient::new(store.clone());
+        (store, ProfileClient::new(client, profile))
+    }
+
+    #[test]
+    fn parse() {
+        let profile = Profile::from_toml(PROFILE).unwrap();
+        assert_eq!(profile.points.len(), 7);
+        let voltage = profile.point("voltage").unwrap();
+        assert_eq!(voltage.data_type, DataType::U32);
+        assert_eq!(voltage.unit.as_deref(), Some("V"));
+        assert_eq!(voltage.access(), Access::Read);
+        assert_eq!(profile.point("relay").unwrap().access(), Access::ReadWrite);
+        assert_eq!(profile.point("serial").unwrap().quantity(), 3);
+
+        let json = r#"{
+            "name": "Sensor",
+            "points": [
+                { "name": "temperature", "function": "input", "address": 0, "type": "i16", "scale": 0.1 }
+            ]
+        }"#;
+        let profile = Profile::from_json(json).unwrap();
+        assert_eq!(profile.point("temperature").unwrap().scale, Some(0.1));
+    }
+
+    #[test]
+    fn parse_fail() {
+        let duplicate = PROFILE.replace("name = \"alarm\"", "name = \"relay\"");
+        assert!(matches!(
+            Profile::from_toml(&duplicate),
+            Err(ProfileError::DuplicatePoint(name)) if name == "relay"
+        ));
+
+        let mismatch = PROFILE.replace("type = \"bool\"", "type = \"u16\"");
+        assert!(matches!(
+            Profile::from_toml(&mismatch),
+            Err(ProfileError::Invalid { point, .. }) if point == "relay"
+        ));
+
+        let read_only =
+            PROFILE.replace("type = \"u32\"", "type = \"u32\"\naccess = \"read_write\"");
+        assert!(matches!(
+            Profile::from_toml(&read_only),
+            Err(ProfileError::Invalid { point, .. }) if point == "voltage"
+        ));
+
+        let overflow = PROFILE.replace("address = 10", "address = 65535");
+        assert!(matches!(
+            Profile::from_toml(&overflow),
+            Err(ProfileError::Limit { point, error: ValidationError { kind: ValidationErrorKind::Overflow, .. } })
+                if point == "voltage"
+        ));
+
+        let too_long = PROFILE.replace("length = 3", "length = 126");
+        assert!(matches!(
+            Profile::from_toml(&too_long),
+            Err(ProfileError::Limit { point, error: ValidationError { kind: ValidationErrorKind::TooLarge, .. } })
+                if point == "serial"
+        ));
+
+        assert!(matches!(
+            Profile::from_json("{ \"name\": 1 }"),
+            Err(ProfileError::Json(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn read() {
+        let (store, mut client) = client();
+        store.write(|tables| {
+            tables.coils_mut()[1] = true.into();
+            tables.input_registers_mut()[10] = 0x0900;
+            tables.holding_registers_mut()[..2].copy_from_slice(&[0x3FC0, 0]);
+            tables.holding_registers_mut()[2] = 0xFF9C;
+            tables.holding_registers_mut()[4..7].copy_from_slice(&[0x534E, 0x3432, 0]);
+        });
+
+        assert_eq!(client.read("relay").await.unwrap(), Value::Bool(true));
+        assert_eq!(client.read("alarm").await.unwrap(), Value::Bool(false));
+        assert_eq!(client.read("voltage").await.unwrap(), Value::Float(230.4));
+        assert_eq!(client.read("setpoint").await.unwrap(), Value::Float(1.5));
+        assert_eq!(client.read("limit").await.unwrap(), Value::Float(-1.0));
+        assert_eq!(
+            client.read("serial").await.unwrap(),
+            Value::String("SN42".to_owned())
+        );
+
+        assert!(matches!(
+            client.read("code").await,
+            Err(PointError::AccessDenied)
+        ));
+        assert!(matches!(
+            client.read("missing").await,
+            Err(PointError::UnknownPoint(name)) if name == "missing"
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_many() {
+        let (store, mut client) = client();
+        store.write(|tables| {
+            tables.coils_mut()[1] = true.into();
+            tables.holding_registers_mut()[..3].copy_from_slice(&[0x3FC0, 0, 100]);
+            tables.holding_registers_mut()[4..7].copy_from_slice(&[0x4142, 0, 0]);
+        });
+
+        let values = client
+            .read_many(&["serial", "relay", "setpoint", "limit"])
+            .await
+            .unwrap();
+        assert_eq!(
+            values,
+            [
+                Value::String("AB".to_owned()),
+                Value::Bool(true),
+                Value::Float(1.5),
+                Value::Float(1.0),
+            ]
+        );
+        // The holding registers 0 to 6 are read with one request
+        assert_eq!(client.get_ref().calls, 2);
+    }
+
+    #[tokio::test]
+    async fn write() {
+        let (store, mut client) = client();
+
+        client.write("relay", &Value::Bool(true)).await.unwrap();
+        client.write("setpoint", &Value::Float(1.5)).await.unwrap();
+        client.write("limit", &Value::Float(2.5)).await.unwrap();
+        client.write("code", &Value::Unsigned(1234)).await.unwrap();
+        store.read(|tables| {
+            assert!(tables.coils()[1].is_on());
+            assert_eq!(&tables.holding_registers()[..3], &[0x3FC0, 0, 250]);
+            assert_eq!(tables.holding_registers()[7], 0x1234);
+        });
+
+        assert!(matches!(
+            client.write("limit", &Value::Float(400.0)).await,
+            Err(PointError::InvalidValue)
+        ));
+        assert!(matches!(
+            client.write("relay", &Value::Unsigned(1)).await,
+            Err(PointError::InvalidValue)
+        ));
+        assert!(matches!(
+            client.write("serial", &Value::String("X".to_owned())).await,
+            Err(PointError::AccessDenied)
+        ));
+    }
+
+    #[tokio::test]
+    async fn exception() {
+        let (_store, client) = client();
+        let profile = Profile::from_toml(&PROFILE.replace("address = 7", "address = 8")).unwrap();
+        let mut client = ProfileClient::new(client.into_inner(), profile);
+
+        assert!(matches!(
+            client.write("code", &Value::Unsigned(1)).await,
+            Err(PointError::Exception(ExceptionCode::IllegalDataAddress))
+        ));
+    }
+}
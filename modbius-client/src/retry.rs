@@ -0,0 +1,497 @@
+//! Automatic retries of failed transactions.
+//!
+//! [Retry] wraps a [ModbusTransport] or a [ModbusClient] and sends a request again if it may succeed the next time:
+//!
+//! - errors for which [TransportError::is_retryable] returns true, e.g. timeouts
+//! - exception responses with a code for which [is_retryable_exception] returns true, i.e.
+//!   [ExceptionCode::ServerDeviceBusy]. Other exceptions like [ExceptionCode::IllegalDataAddress] are returned
+//!   right away.
+//! - [ExceptionCode::GatewayTargetDeviceFailedToRespond] exception responses, which are treated like errors.
+//!
+//! Between attempts it waits an exponentially growing, randomly shortened delay so devices recovering from a
+//! failure are not flooded by many clients at once.
+//!
+//! A request which failed with an error may still have been executed by the device. Only requests which can be
+//! executed twice safely, see [is_idempotent], are retried after an error. Writes are only retried after errors if
+//! they are sent with [call_idempotent](Retry::call_idempotent) or [transact_idempotent](Retry::transact_idempotent).
+//! A busy device rejected the request, so writes are retried after [ExceptionCode::ServerDeviceBusy]. A gateway
+//! answering with [ExceptionCode::GatewayTargetDeviceFailedToRespond] did forward the request though, the target
+//! may have executed it before its response was lost.
+//!
+//! Wrap a [ReconnectingTcpTransport](crate::tcp::ReconnectingTcpTransport) to also reconnect transparently.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use modbius_core::{
+    exception::check_response, ExceptionCode, ModbusError, ModbusFunction, PublicModbusFunction,
+    SlaveId,
+};
+use modbius_traits::{ModbusClient, ModbusTransport, TransportError};
+use tokio::time;
+
+/// Configuration of [Retry]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt
+    pub max_retries: u32,
+    /// The delay before the first retry
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts, the delay doubles with every retry up to this
+    pub max_backoff: Duration,
+    /// The fraction of the delay which is randomly cut off, between 0 (no jitter) and 1
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// The delay before the given retry, starting at 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let delay = self
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random())
+    }
+}
+
+/// A random number in `[0, 1)`
+fn random() -> f64 {
+    // Every RandomState is seeded differently
+    let hash = RandomState::new().build_hasher().finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Checks if a request rejected with the given exception code may succeed when it is sent again.
+///
+/// The device didn't execute requests answered with these codes, so any request may be sent again.
+pub fn is_retryable_exception(code: ExceptionCode) -> bool {
+    code == ExceptionCode::ServerDeviceBusy
+}
+
+/// Checks if executing the request PDU twice has the same effect as executing it once.
+///
+/// This is the case for the publicly documented functions which only read data.
+pub fn is_idempotent(request: &[u8]) -> bool {
+    matches!(
+        request.first().map(|code| PublicModbusFunction::new(*code)),
+        Some(
+            PublicModbusFunction::ReadCoils
+                | PublicModbusFunction::ReadDiscreteInputs
+                | PublicModbusFunction::ReadHoldingRegisters
+                | PublicModbusFunction::ReadInputRegisters
+                | PublicModbusFunction::ReadExceptionStatus
+                | PublicModbusFunction::GetCommEventCounter
+                | PublicModbusFunction::GetCommEventLog
+                | PublicModbusFunction::ReportServerID
+                | PublicModbusFunction::ReadFileRecord
+                | PublicModbusFunction::ReadFIFOQueue
+        )
+    )
+}
+
+/// Get the code of the response if it is an exception response to the request
+pub(crate) fn exception_code(request: &[u8], response: &[u8]) -> Option<ExceptionCode> {
+    let function = ModbusFunction::new(*request.first()?);
+    match check_response(function, response) {
+        Err(ModbusError::Exception(e)) => Some(e.code),
+        _ => None,
+    }
+}
+
+/// A transport or client retrying failed transactions according to a [RetryPolicy].
+///
+/// See the [module](self) documentation for details.
+#[derive(Debug, Clone)]
+pub struct Retry<T> {
+    inner: T,
+    policy: RetryPolicy,
+}
+
+impl<T> Retry<T> {
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The delay before the next attempt after the given number of retries, None if the result is final
+    fn retry_delay<E: TransportError>(
+        &self,
+        retries: u32,
+        request: &[u8],
+        result: Result<&[u8], &E>,
+        idempotent: bool,
+    ) -> Option<Duration> {
+        let repeatable = idempotent || is_idempotent(request);
+        let retryable = match result {
+            Ok(response) => match exception_code(request, response) {
+                Some(ExceptionCode::GatewayTargetDeviceFailedToRespond) => repeatable,
+                Some(code) => is_retryable_exception(code),
+                None => false,
+            },
+            Err(e) => e.is_retryable() && repeatable,
+        };
+        if !retryable || retries >= self.policy.max_retries {
+            return None;
+        }
+
+        Some(self.policy.backoff(retries + 1))
+    }
+}
+
+impl<T: ModbusTransport + Send> Retry<T> {
+    /// Send the request like [transact](ModbusTransport::transact) and retry it after errors even if it writes
+    pub async fn transact_idempotent(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, T::Error> {
+        self.transact_with(slave, request, response, true).await
+    }
+
+    async fn transact_with(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+        idempotent: bool,
+    ) -> Result<usize, T::Error> {
+        let mut retries = 0;
+        loop {
+            let delay = {
+                let result = self.inner.transact(slave, request, response).await;
+                let checked = result.as_ref().map(|len| &response[..*len]);
+                match self.retry_delay(retries, request, checked, idempotent) {
+                    Some(delay) => delay,
+                    None => return result,
+                }
+            };
+            time::sleep(delay).await;
+            retries += 1;
+        }
+    }
+}
+
+impl<T: ModbusTransport + Send> ModbusTransport for Retry<T> {
+    type Error = T::Error;
+
+    async fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.transact_with(slave, request, response, false).await
+    }
+}
+
+impl<C: ModbusClient + Send> Retry<C> {
+    /// Send the request like [call](ModbusClient::call) and retry it after errors even if it writes
+    pub async fn call_idempotent(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, C::Error> {
+        self.call_with(request, response, true).await
+    }
+
+    async fn call_with(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+        idempotent: bool,
+    ) -> Result<usize, C::Error> {
+        let mut retries = 0;
+        loop {
+            let delay = {
+                let result = self.inner.call(request, response).await;
+                let checked = result.as_ref().map(|len| &response[..*len]);
+                match self.retry_delay(retries, request, checked, idempotent) {
+                    Some(delay) => delay,
+                    None => return result,
+                }
+            };
+            time::sleep(delay).await;
+            retries += 1;
+        }
+    }
+}
+
+impl<C: ModbusClient + Send> ModbusClient for Retry<C> {
+    type Error = C::Error;
+
+    fn slave(&self) -> SlaveId {
+        self.inner.slave()
+    }
+
+    async fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Self::Error> {
+        self.call_with(request, response, false).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::VecDeque, time::Duration};
+
+    use modbius_core::{ExceptionCode, SlaveId};
+    use modbius_traits::{ModbusClient, ModbusTransport, TransportError};
+    use tokio::time::Instant;
+
+    use super::{is_idempotent, Retry, RetryPolicy};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum MockError {
+        Timeout,
+        Fatal,
+    }
+
+    impl TransportError for MockError {
+        fn is_timeout(&self) -> bool {
+            *self == Self::Timeout
+        }
+    }
+
+    /// Answers with the scripted results, the requests are counted
+    struct Scripted {
+        results: VecDeque<Result<Vec<u8>, MockError>>,
+        calls: usize,
+    }
+
+    impl Scripted {
+        fn new(results: impl IntoIterator<Item = Result<Vec<u8>, MockError>>) -> Self {
+            Self {
+                results: results.into_iter().collect(),
+                calls: 0,
+            }
+        }
+    }
+
+    impl ModbusClient for Scripted {
+        type Error = MockError;
+
+        fn slave(&self) -> SlaveId {
+            SlaveId::new(1)
+        }
+
+        async fn call(&mut self, _request: &[u8], response: &mut [u8]) -> Result<usize, MockError> {
+            self.calls += 1;
+            let data = self.results.pop_front().expect("unexpected request")?;
+            response[..data.len()].copy_from_slice(&data);
+            Ok(data.len())
+        }
+    }
+
+    impl ModbusTransport for Scripted {
+        type Error = MockError;
+
+        async fn transact(
+            &mut self,
+            _slave: SlaveId,
+            request: &[u8],
+            response: &mut [u8],
+        ) -> Result<usize, MockError> {
+            self.call(request, response).await
+        }
+    }
+
+    const READ: [u8; 5] = [3, 0, 0, 0, 1];
+    const WRITE: [u8; 5] = [6, 0, 0, 0, 1];
+
+    fn exception(request: [u8; 5], code: ExceptionCode) -> Result<Vec<u8>, MockError> {
+        Ok(vec![request[0] | 0x80, code as u8])
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            jitter: 0.0,
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|retry| policy.backoff(retry).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..100 {
+            let delay = policy.backoff(2);
+            assert!(delay > Duration::from_millis(100) && delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn idempotent() {
+        assert!(is_idempotent(&READ));
+        assert!(is_idempotent(&[4]));
+        assert!(!is_idempotent(&WRITE));
+        assert!(!is_idempotent(&[8, 0, 1]));
+        assert!(!is_idempotent(&[0x41]));
+        assert!(!is_idempotent(&[]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_exceptions() {
+        let mut client = Retry::new(
+            Scripted::new([
+                exception(READ, ExceptionCode::ServerDeviceBusy),
+                exception(READ, ExceptionCode::ServerDeviceBusy),
+                Ok(vec![3, 2, 0, 7]),
+            ]),
+            RetryPolicy::default(),
+        );
+        let mut response = [0; 8];
+        let len = client.call(&READ, &mut response).await.unwrap();
+        assert_eq!(&response[..len], &[3, 2, 0, 7]);
+        assert_eq!(client.get_ref().calls, 3);
+
+        let mut client = Retry::new(
+            Scripted::new([exception(READ, ExceptionCode::IllegalDataAddress)]),
+            RetryPolicy::default(),
+        );
+        let len = client.call(&READ, &mut response).await.unwrap();
+        assert_eq!(&response[..len], &[0x83, 2]);
+        assert_eq!(client.get_ref().calls, 1);
+
+        // The device rejected the write so it is safe to send it again
+        let mut client = Retry::new(
+            Scripted::new([
+                exception(WRITE, ExceptionCode::ServerDeviceBusy),
+                Ok(WRITE.to_vec()),
+            ]),
+            RetryPolicy::default(),
+        );
+        client.call(&WRITE, &mut response).await.unwrap();
+        assert_eq!(client.get_ref().calls, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_gateway_target_failed() {
+        let failed =
+            |request| exception(request, ExceptionCode::GatewayTargetDeviceFailedToRespond);
+        let mut response = [0; 8];
+
+        // The target may have executed the write before its response was lost
+        let mut client = Retry::new(Scripted::new([failed(WRITE)]), RetryPolicy::default());
+        let len = client.call(&WRITE, &mut response).await.unwrap();
+        assert_eq!(&response[..len], &[0x86, 0x0B]);
+        assert_eq!(client.get_ref().calls, 1);
+
+        let mut client = Retry::new(
+            Scripted::new([failed(WRITE), Ok(WRITE.to_vec())]),
+            RetryPolicy::default(),
+        );
+        client.call_idempotent(&WRITE, &mut response).await.unwrap();
+        assert_eq!(client.get_ref().calls, 2);
+
+        let mut client = Retry::new(
+            Scripted::new([failed(READ), Ok(vec![3, 2, 0, 7])]),
+            RetryPolicy::default(),
+        );
+        client.call(&READ, &mut response).await.unwrap();
+        assert_eq!(client.get_ref().calls, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_errors() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+        };
+        let start = Instant::now();
+        let mut client = Retry::new(Scripted::new(vec![Err(MockError::Timeout); 3]), policy);
+        let err = client.call(&READ, &mut [0; 8]).await.unwrap_err();
+        assert_eq!(err, MockError::Timeout);
+        assert_eq!(client.get_ref().calls, 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+
+        let mut client = Retry::new(Scripted::new([Err(MockError::Fatal)]), policy);
+        assert_eq!(client.call(&READ, &mut [0; 8]).await, Err(MockError::Fatal));
+        assert_eq!(client.get_ref().calls, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_after_errors() {
+        let mut client = Retry::new(
+            Scripted::new([Err(MockError::Timeout)]),
+            RetryPolicy::default(),
+        );
+        assert_eq!(
+            client.call(&WRITE, &mut [0; 8]).await,
+            Err(MockError::Timeout)
+        );
+        assert_eq!(client.get_ref().calls, 1);
+
+        let mut client = Retry::new(
+            Scripted::new([Err(MockError::Timeout), Ok(WRITE.to_vec())]),
+            RetryPolicy::default(),
+        );
+        assert_eq!(client.call_idempotent(&WRITE, &mut [0; 8]).await, Ok(5));
+        assert_eq!(client.get_ref().calls, 2);
+
+        let mut transport = Retry::new(
+            Scripted::new([Err(MockError::Timeout), Ok(WRITE.to_vec())]),
+            RetryPolicy::default(),
+        );
+        let slave = SlaveId::new(1);
+        assert_eq!(
+            transport.transact(slave, &WRITE, &mut [0; 8]).await,
+            Err(MockError::Timeout)
+        );
+        assert_eq!(
+            transport
+                .transact_idempotent(slave, &WRITE, &mut [0; 8])
+                .await,
+            Ok(5)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Modbus RTU client transport.
+
+use std::time::Duration;
+
+use modbius_codec::{RtuConfig, RtuStream, RtuTransportError};
+use modbius_core::SlaveId;
+use modbius_traits::ModbusTransport;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A Modbus RTU master on a serial line or any other stream.
+///
+/// Wrap it in a [Bus](crate::Bus) to share the line between multiple tasks.
+#[derive(Debug)]
+pub struct RtuTransport<S> {
+    stream: RtuStream<S>,
+    response_timeout: Duration,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RtuTransport<S> {
+    /// Create a new transport with the default [RtuConfig] and a response timeout of one second
+    pub fn new(stream: S) -> Self {
+        Self::with_config(stream, RtuConfig::default(), Duration::from_secs(1))
+    }
+
+    /// Create a new transport with the given configuration.
+    ///
+    /// The response timeout is the maximum time to wait for the start of a response.
+    pub fn with_config(stream: S, config: RtuConfig, response_timeout: Duration) -> Self {
+        Self {
+            stream: RtuStream::with_config(stream, config),
+            response_timeout,
+        }
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout
+    }
+
+    pub fn set_response_timeout(&mut self, response_timeout: Duration) {
+        self.response_timeout = response_timeout;
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ModbusTransport for RtuTransport<S> {
+    type Error = RtuTransportError;
+
+    /// Send the request to the slave and wait for its response.
+    ///
+    /// Responses of other slaves are rejected with [RtuTransportError::UnexpectedSlave] unless the
+    /// [quirks](RtuConfig::quirks) accept any unit id. The line has to be silent for one frame gap
+    /// before the request is sent, anything received until then is discarded. Broadcast requests
+    /// are not answered so 0 is returned right after sending them.
+    async fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.stream.discard().await?;
+        self.stream.write_frame(slave, request).await?;
+
+        if slave.is_broadcast() {
+            return Ok(0);
+        }
+
+        let (responder, len) = self
+            .stream
+            .read_frame(response, Some(self.response_timeout))
+            .await?;
+        if responder != slave && !self.stream.config().quirks.any_unit_id {
+            return Err(RtuTransportError::UnexpectedSlave(responder));
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_codec::{RtuConfig, RtuTransportError};
+    use modbius_core::{rtu::RtuFrameError, Quirks, SlaveId};
+    use modbius_traits::ModbusTransport;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::RtuTransport;
+
+    #[tokio::test(start_paused = true)]
+    async fn transact() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = RtuTransport::new(local);
+
+        let slave = tokio::spawn(async move {
+            let mut request = [0; 8];
+            remote.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [1, 3, 0, 0, 0, 1, 0x84, 0x0A]);
+            remote
+                .write_all(&[1, 3, 2, 1, 2, 0x38, 0x15])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let mut response = [0; 8];
+        let len = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 1, 2]);
+        slave.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transact_fail_crc() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = RtuTransport::new(local);
+
+        let slave = tokio::spawn(async move {
+            let mut request = [0; 8];
+            remote.read_exact(&mut request).await.unwrap();
+            remote
+                .write_all(&[1, 3, 2, 1, 2, 0x38, 0x16])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut [0; 8])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RtuTransportError::Frame(RtuFrameError::Crc { .. })
+        ));
+        slave.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transact_any_unit_id() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let config = RtuConfig {
+            quirks: Quirks {
+                any_unit_id: true,
+                ..Quirks::STRICT
+            },
+            ..RtuConfig::default()
+        };
+        let mut transport = RtuTransport::with_config(local, config, Duration::from_secs(1));
+
+        let slave = tokio::spawn(async move {
+            for _ in 0..2 {
+                let mut request = [0; 8];
+                remote.read_exact(&mut request).await.unwrap();
+                // A device answering with the default slave id
+                remote
+                    .write_all(&[0xF7, 3, 2, 1, 2, 0xF0, 0x00])
+                    .await
+                    .unwrap();
+            }
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let mut response = [0; 8];
+        let len = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 1, 2]);
+
+        let mut transport = RtuTransport::new(transport.into_inner());
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RtuTransportError::UnexpectedSlave(s) if s == SlaveId::new(0xF7)));
+        slave.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transact_timeout() {
+        let (local, _remote) = tokio::io::duplex(256);
+        let mut transport = RtuTransport::new(local);
+
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut [0; 8])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RtuTransportError::Timeout));
+    }
+}
@@ -0,0 +1,294 @@
+//! Modbus TCP client transports.
+
+use std::{net::SocketAddr, time::Duration};
+
+use modbius_codec::{MbapStream, TcpTransportError};
+use modbius_core::{tcp::MbapHeader, Quirks, SlaveId};
+use modbius_traits::{ModbusTransport, TransportError};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+    time::{self, Instant},
+};
+
+/// A Modbus TCP client on a connection to a server or gateway.
+///
+/// The slave id of a transaction is sent as unit id. Every request gets a new transaction id,
+/// responses to earlier transactions, e.g. after a timeout, are skipped. The unit id of responses is not checked,
+/// the transaction id already identifies them.
+#[derive(Debug)]
+pub struct TcpTransport<S> {
+    stream: MbapStream<S>,
+    transaction_id: u16,
+    response_timeout: Duration,
+}
+
+impl TcpTransport<TcpStream> {
+    /// Connect to a Modbus TCP server with a response timeout of one second
+    pub async fn connect(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> TcpTransport<S> {
+    /// Create a new transport with a response timeout of one second
+    pub fn new(stream: S) -> Self {
+        Self::with_response_timeout(stream, Duration::from_secs(1))
+    }
+
+    /// Create a new transport with the given maximum time to wait for a complete response
+    pub fn with_response_timeout(stream: S, response_timeout: Duration) -> Self {
+        Self {
+            stream: MbapStream::new(stream),
+            transaction_id: 0,
+            response_timeout,
+        }
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout
+    }
+
+    pub fn set_response_timeout(&mut self, response_timeout: Duration) {
+        self.response_timeout = response_timeout;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.stream.quirks()
+    }
+
+    /// Set the deviations from the specification to accept in responses, see [MbapStream::with_quirks]
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.stream.set_quirks(quirks);
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ModbusTransport for TcpTransport<S> {
+    type Error = TcpTransportError;
+
+    /// Send the request to the unit and wait for the response with the same transaction id.
+    ///
+    /// After a [TcpTransportError::Timeout] or an io error the connection should be closed.
+    async fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let header = MbapHeader::new(self.transaction_id, slave, request.len() as u16);
+        self.stream.write_adu(header, request).await?;
+
+        let deadline = Instant::now() + self.response_timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.stream.read_adu(response, Some(timeout)).await {
+                Ok((header, len)) if header.transaction_id == self.transaction_id => {
+                    return Ok(len)
+                }
+                Ok(_) | Err(TcpTransportError::InvalidProtocol(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A Modbus TCP client which connects on demand.
+///
+/// The connection is established by the first transaction and closed after errors for which
+/// [is_disconnect](TransportError::is_disconnect) returns true. The next transaction connects again, so
+/// together with [Retry](crate::retry::Retry) a lost connection is restored transparently.
+#[derive(Debug)]
+pub struct ReconnectingTcpTransport {
+    addr: SocketAddr,
+    response_timeout: Duration,
+    quirks: Quirks,
+    transport: Option<TcpTransport<TcpStream>>,
+}
+
+impl ReconnectingTcpTransport {
+    /// Create a new transport with a response timeout of one second, nothing is connected yet
+    pub fn new(addr: SocketAddr) -> Self {
+        Self::with_response_timeout(addr, Duration::from_secs(1))
+    }
+
+    /// Create a new transport with the given maximum time to connect and to wait for a complete response
+    pub fn with_response_timeout(addr: SocketAddr, response_timeout: Duration) -> Self {
+        Self {
+            addr,
+            response_timeout,
+            quirks: Quirks::STRICT,
+            transport: None,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout
+    }
+
+    pub fn set_response_timeout(&mut self, response_timeout: Duration) {
+        self.response_timeout = response_timeout;
+        if let Some(transport) = &mut self.transport {
+            transport.set_response_timeout(response_timeout);
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the deviations from the specification to accept in responses, see [MbapStream::with_quirks]
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        if let Some(transport) = &mut self.transport {
+            transport.set_quirks(quirks);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Close the connection, the next transaction connects again
+    pub fn disconnect(&mut self) {
+        self.transport = None;
+    }
+
+    async fn connect(&self) -> Result<TcpTransport<TcpStream>, TcpTransportError> {
+        let stream = time::timeout(self.response_timeout, TcpStream::connect(self.addr))
+            .await
+            .map_err(|_| TcpTransportError::Timeout)??;
+        stream.set_nodelay(true)?;
+        let mut transport = TcpTransport::with_response_timeout(stream, self.response_timeout);
+        transport.set_quirks(self.quirks);
+        Ok(transport)
+    }
+}
+
+impl ModbusTransport for ReconnectingTcpTransport {
+    type Error = TcpTransportError;
+
+    async fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        let transport = match &mut self.transport {
+            Some(transport) => transport,
+            None => self.transport.insert(self.connect().await?),
+        };
+
+        let result = transport.transact(slave, request, response).await;
+        if result.as_ref().is_err_and(TransportError::is_disconnect) {
+            self.transport = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_codec::TcpTransportError;
+    use modbius_core::SlaveId;
+    use modbius_traits::{ModbusTransport, TransportError};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{ReconnectingTcpTransport, TcpTransport};
+
+    #[tokio::test(start_paused = true)]
+    async fn transact() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = TcpTransport::new(local);
+
+        let server = tokio::spawn(async move {
+            let mut request = [0; 12];
+            remote.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [0, 1, 0, 0, 0, 6, 7, 3, 0, 0, 0, 1]);
+            // A late response to an earlier transaction is skipped
+            remote
+                .write_all(&[0, 0, 0, 0, 0, 5, 7, 3, 2, 9, 9])
+                .await
+                .unwrap();
+            remote
+                .write_all(&[0, 1, 0, 0, 0, 5, 7, 3, 2, 1, 2])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let mut response = [0; 8];
+        let len = transport
+            .transact(SlaveId::new(7), &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 1, 2]);
+        server.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transact_timeout() {
+        let (local, _remote) = tokio::io::duplex(256);
+        let mut transport = TcpTransport::new(local);
+
+        let err = transport
+            .transact(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut [0; 8])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TcpTransportError::Timeout));
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = ReconnectingTcpTransport::new(listener.local_addr().unwrap());
+        assert!(!transport.is_connected());
+
+        let server = tokio::spawn(async move {
+            // The first connection is closed without an answer
+            let (stream, _) = listener.accept().await.unwrap();
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 12];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [0, 1, 0, 0, 0, 6, 1, 3, 0, 0, 0, 1]);
+            stream
+                .write_all(&[0, 1, 0, 0, 0, 5, 1, 3, 2, 0, 9])
+                .await
+                .unwrap();
+        });
+
+        let slave = SlaveId::new(1);
+        let mut response = [0; 8];
+        let err = transport
+            .transact(slave, &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap_err();
+        assert!(err.is_disconnect());
+        assert!(!transport.is_connected());
+
+        let len = transport
+            .transact(slave, &[3, 0, 0, 0, 1], &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 0, 9]);
+        assert!(transport.is_connected());
+        server.await.unwrap();
+    }
+}
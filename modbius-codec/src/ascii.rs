@@ -0,0 +1,333 @@
+//! Modbus ASCII on tokio streams.
+//!
+//! The spec allows at most one second between two characters of a frame. This interval is configurable as
+//! devices on radio links or behind converters tend to need more. Garbage before a ':' is skipped and a ':' inside
+//! a frame starts a new frame, so the stream resynchronizes on the next frame after transmission errors.
+
+use core::fmt::{self, Display, Formatter};
+use std::{io, time::Duration};
+
+use modbius_core::{
+    ascii::{self, AsciiDecoder, AsciiFrameError},
+    ModbusSerializationError, Quirks, SlaveId,
+};
+use modbius_traits::TransportError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Configuration of an [AsciiStream]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AsciiConfig {
+    /// The maximum time between two characters of a frame
+    pub inter_char_timeout: Duration,
+    /// The deviations from the specification to accept.
+    ///
+    /// The stream itself is strict, [any_unit_id](Quirks::any_unit_id) is respected by the transports built on it.
+    pub quirks: Quirks,
+}
+
+impl Default for AsciiConfig {
+    fn default() -> Self {
+        Self {
+            inter_char_timeout: Duration::from_secs(1),
+            quirks: Quirks::STRICT,
+        }
+    }
+}
+
+/// An error that occurred while reading or writing ASCII frames
+#[derive(Debug)]
+pub enum AsciiTransportError {
+    /// The underlying stream failed or was closed
+    Io(io::Error),
+    /// No frame started or a started frame was not completed in time
+    Timeout,
+    /// A frame was received but it was invalid, e.g. because of a wrong LRC or a non hex character
+    Frame(AsciiFrameError),
+    /// A frame was received from or addressed to an unexpected slave
+    UnexpectedSlave(SlaveId),
+    /// The frame could not be written or did not fit into the given buffer
+    Serialization(ModbusSerializationError),
+}
+
+impl TransportError for AsciiTransportError {
+    fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout)
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::Timeout | Self::Frame(_))
+    }
+}
+
+impl Display for AsciiTransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Timeout => write!(f, "timed out waiting for an ascii frame"),
+            Self::Frame(AsciiFrameError::Lrc { expected, got }) => {
+                write!(
+                    f,
+                    "invalid lrc, expected {:#04X} got {:#04X}",
+                    expected, got
+                )
+            }
+            Self::Frame(AsciiFrameError::Hex) => write!(f, "invalid character in ascii frame"),
+            Self::Frame(AsciiFrameError::Length) => write!(f, "invalid ascii frame length"),
+            Self::UnexpectedSlave(slave) => write!(f, "unexpected slave id {}", u8::from(*slave)),
+            Self::Serialization(e) => write!(f, "serialization error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for AsciiTransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AsciiTransportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<AsciiFrameError> for AsciiTransportError {
+    fn from(e: AsciiFrameError) -> Self {
+        Self::Frame(e)
+    }
+}
+
+impl From<ModbusSerializationError> for AsciiTransportError {
+    fn from(e: ModbusSerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+/// A stream reading and writing Modbus ASCII frames.
+#[derive(Debug)]
+pub struct AsciiStream<S> {
+    stream: S,
+    config: AsciiConfig,
+    decoder: AsciiDecoder,
+    rx: [u8; 64],
+    rx_pos: usize,
+    rx_len: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsciiStream<S> {
+    /// Create a new stream with the default [AsciiConfig]
+    pub fn new(stream: S) -> Self {
+        Self::with_config(stream, AsciiConfig::default())
+    }
+
+    /// Create a new stream with the given configuration
+    pub fn with_config(stream: S, config: AsciiConfig) -> Self {
+        Self {
+            stream,
+            config,
+            decoder: AsciiDecoder::new(),
+            rx: [0; 64],
+            rx_pos: 0,
+            rx_len: 0,
+        }
+    }
+
+    pub fn config(&self) -> AsciiConfig {
+        self.config
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Discard all received but not yet decoded data and any partially received frame
+    pub fn discard(&mut self) {
+        self.decoder.reset();
+        self.rx_pos = 0;
+        self.rx_len = 0;
+    }
+
+    /// Write a frame with the given slave id and PDU and flush the stream
+    pub async fn write_frame(
+        &mut self,
+        slave: SlaveId,
+        pdu: &[u8],
+    ) -> Result<(), AsciiTransportError> {
+        let mut frame = [0; ascii::MAX_FRAME_SIZE];
+        let size = ascii::write_frame(slave, pdu, &mut frame)?;
+        self.stream.write_all(&frame[..size]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read the next frame and write its PDU to pdu.
+    ///
+    /// The slave id of the frame and the number of bytes written to pdu are returned.
+    /// `start_timeout` is the maximum time to wait for the start of a frame, None waits forever.
+    /// Once a frame started each character has to follow in the configured inter character timeout.
+    ///
+    /// # Errors
+    /// An invalid frame is reported as [AsciiTransportError::Frame], the next call continues with the next frame.
+    /// If pdu is too small for the received PDU [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub async fn read_frame(
+        &mut self,
+        pdu: &mut [u8],
+        start_timeout: Option<Duration>,
+    ) -> Result<(SlaveId, usize), AsciiTransportError> {
+        loop {
+            if self.rx_pos == self.rx_len {
+                let timeout = if self.decoder.in_frame() {
+                    Some(self.config.inter_char_timeout)
+                } else {
+                    start_timeout
+                };
+                self.fill(timeout).await?;
+            }
+
+            let byte = self.rx[self.rx_pos];
+            self.rx_pos += 1;
+
+            if let Some((slave, frame)) = self.decoder.feed(byte)? {
+                if pdu.len() < frame.len() {
+                    return Err(ModbusSerializationError::InsufficientBuffer {
+                        expected: frame.len(),
+                        got: pdu.len(),
+                    }
+                    .into());
+                }
+                pdu[..frame.len()].copy_from_slice(frame);
+                return Ok((slave, frame.len()));
+            }
+        }
+    }
+
+    async fn fill(&mut self, timeout: Option<Duration>) -> Result<(), AsciiTransportError> {
+        let read = self.stream.read(&mut self.rx);
+        let n = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read)
+                .await
+                .map_err(|_| AsciiTransportError::Timeout)??,
+            None => read.await?,
+        };
+
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        self.rx_pos = 0;
+        self.rx_len = n;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::{ascii::AsciiFrameError, SlaveId};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{AsciiStream, AsciiTransportError};
+
+    #[tokio::test]
+    async fn write_frame() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = AsciiStream::new(local);
+
+        stream
+            .write_frame(SlaveId::new(0xF7), &[0x03, 0x13, 0x89, 0x00, 0x0A])
+            .await
+            .unwrap();
+
+        let mut frame = [0; 17];
+        remote.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b":F7031389000A60\r\n");
+    }
+
+    #[tokio::test]
+    async fn read_frames_after_errors() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut stream = AsciiStream::new(local);
+
+        remote
+            .write_all(b"noise:010300000001FC\r\n:01G1:010300000001FB\r\n:02030000")
+            .await
+            .unwrap();
+
+        let mut pdu = [0; 8];
+        let err = stream.read_frame(&mut pdu, None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AsciiTransportError::Frame(AsciiFrameError::Lrc {
+                expected: 0xFB,
+                got: 0xFC
+            })
+        ));
+
+        let err = stream.read_frame(&mut pdu, None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AsciiTransportError::Frame(AsciiFrameError::Hex)
+        ));
+
+        let (slave, len) = stream.read_frame(&mut pdu, None).await.unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(&pdu[..len], &[3, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inter_char_timeout() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = AsciiStream::new(local);
+
+        remote.write_all(b":0103").await.unwrap();
+        let start = tokio::time::Instant::now();
+        let err = stream.read_frame(&mut [0; 8], None).await.unwrap_err();
+
+        assert!(matches!(err, AsciiTransportError::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn start_timeout() {
+        let (local, _remote) = tokio::io::duplex(64);
+        let mut stream = AsciiStream::new(local);
+
+        let err = stream
+            .read_frame(&mut [0; 8], Some(Duration::from_millis(300)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AsciiTransportError::Timeout));
+    }
+
+    #[tokio::test]
+    async fn read_frame_fail_insufficient_buffer() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = AsciiStream::new(local);
+
+        remote.write_all(b":010300000001FB\r\n").await.unwrap();
+        let err = stream.read_frame(&mut [0; 4], None).await.unwrap_err();
+        assert!(matches!(err, AsciiTransportError::Serialization(_)));
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let (local, remote) = tokio::io::duplex(64);
+        let mut stream = AsciiStream::new(local);
+
+        drop(remote);
+        let err = stream.read_frame(&mut [0; 8], None).await.unwrap_err();
+        assert!(matches!(err, AsciiTransportError::Io(_)));
+    }
+}
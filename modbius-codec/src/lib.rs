@@ -0,0 +1,12 @@
+//! Async modbius codecs.
+//!
+//! The codecs of `modbius-core` are byte by byte and free of any IO. This crate drives them on tokio streams and
+//! takes care of the timing requirements of the different codecs. Client and server transports are built on top.
+
+pub mod ascii;
+pub mod rtu;
+pub mod tcp;
+
+pub use ascii::{AsciiConfig, AsciiStream, AsciiTransportError};
+pub use rtu::{RtuConfig, RtuStream, RtuTransportError};
+pub use tcp::{MbapStream, TcpTransportError};
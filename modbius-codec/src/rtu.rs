@@ -0,0 +1,371 @@
+//! Modbus RTU on tokio streams.
+//!
+//! RTU frames are delimited by silent intervals of at least 3.5 character times. A frame ends once nothing was
+//! received for the configured frame gap. Serial drivers and USB converters deliver data in chunks, so the gap
+//! should be chosen generously rather than exactly.
+
+use core::fmt::{self, Display, Formatter};
+use std::{io, time::Duration};
+
+use modbius_core::{
+    rtu::{self, RtuFrameError},
+    ModbusSerializationError, Quirks, SlaveId,
+};
+use modbius_traits::TransportError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Configuration of an [RtuStream]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RtuConfig {
+    /// The silent interval after which a frame is considered complete
+    pub frame_gap: Duration,
+    /// The deviations from the specification to accept in received frames
+    pub quirks: Quirks,
+}
+
+impl RtuConfig {
+    /// Get the configuration for a line with the given baud rate.
+    ///
+    /// The frame gap is 3.5 character times of 11 bits, above 19200 baud the fixed 1.75ms the spec recommends.
+    pub fn for_baud_rate(baud_rate: u32) -> Self {
+        let frame_gap = if baud_rate > 19200 {
+            Duration::from_micros(1750)
+        } else {
+            Duration::from_micros((38_500_000 / baud_rate.max(1) as u64) + 1)
+        };
+        Self {
+            frame_gap,
+            quirks: Quirks::STRICT,
+        }
+    }
+}
+
+impl Default for RtuConfig {
+    /// The configuration for 9600 baud
+    fn default() -> Self {
+        Self::for_baud_rate(9600)
+    }
+}
+
+/// An error that occurred while reading or writing RTU frames
+#[derive(Debug)]
+pub enum RtuTransportError {
+    /// The underlying stream failed or was closed
+    Io(io::Error),
+    /// No frame was received in time
+    Timeout,
+    /// A frame was received but it was invalid, e.g. because of a wrong CRC
+    Frame(RtuFrameError),
+    /// A frame was received from or addressed to an unexpected slave
+    UnexpectedSlave(SlaveId),
+    /// The frame could not be written or did not fit into the given buffer
+    Serialization(ModbusSerializationError),
+}
+
+impl TransportError for RtuTransportError {
+    fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout)
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::Timeout | Self::Frame(_))
+    }
+}
+
+impl Display for RtuTransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Timeout => write!(f, "timed out waiting for an rtu frame"),
+            Self::Frame(RtuFrameError::Crc { expected, got }) => {
+                write!(
+                    f,
+                    "invalid crc, expected {:#06X} got {:#06X}",
+                    expected, got
+                )
+            }
+            Self::Frame(RtuFrameError::Length) => write!(f, "invalid rtu frame length"),
+            Self::UnexpectedSlave(slave) => write!(f, "unexpected slave id {}", u8::from(*slave)),
+            Self::Serialization(e) => write!(f, "serialization error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for RtuTransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RtuTransportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<RtuFrameError> for RtuTransportError {
+    fn from(e: RtuFrameError) -> Self {
+        Self::Frame(e)
+    }
+}
+
+impl From<ModbusSerializationError> for RtuTransportError {
+    fn from(e: ModbusSerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+/// A stream reading and writing Modbus RTU frames.
+#[derive(Debug)]
+pub struct RtuStream<S> {
+    stream: S,
+    config: RtuConfig,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RtuStream<S> {
+    /// Create a new stream with the default [RtuConfig]
+    pub fn new(stream: S) -> Self {
+        Self::with_config(stream, RtuConfig::default())
+    }
+
+    /// Create a new stream with the given configuration
+    pub fn with_config(stream: S, config: RtuConfig) -> Self {
+        Self { stream, config }
+    }
+
+    pub fn config(&self) -> RtuConfig {
+        self.config
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Discard everything received until the line is silent for one frame gap.
+    ///
+    /// Masters call this before sending a request, so late responses to earlier requests are not mistaken
+    /// for the response to the new one.
+    pub async fn discard(&mut self) -> Result<(), RtuTransportError> {
+        let mut buf = [0; rtu::MAX_FRAME_SIZE];
+        loop {
+            match tokio::time::timeout(self.config.frame_gap, self.stream.read(&mut buf)).await {
+                Ok(Ok(0)) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Ok(()),
+            }
+        }
+    }
+
+    /// Write a frame with the given slave id and PDU and flush the stream
+    pub async fn write_frame(
+        &mut self,
+        slave: SlaveId,
+        pdu: &[u8],
+    ) -> Result<(), RtuTransportError> {
+        let mut frame = [0; rtu::MAX_FRAME_SIZE];
+        let size = rtu::write_frame(slave, pdu, &mut frame)?;
+        self.stream.write_all(&frame[..size]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read the next frame and write its PDU to pdu.
+    ///
+    /// The slave id of the frame and the number of bytes written to pdu are returned.
+    /// `start_timeout` is the maximum time to wait for the start of a frame, None waits forever.
+    /// The frame ends once the line is silent for the configured frame gap.
+    ///
+    /// # Errors
+    /// An invalid frame is reported as [RtuTransportError::Frame], the next call continues with the next frame.
+    /// If pdu is too small for the received PDU [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub async fn read_frame(
+        &mut self,
+        pdu: &mut [u8],
+        start_timeout: Option<Duration>,
+    ) -> Result<(SlaveId, usize), RtuTransportError> {
+        let mut frame = [0; rtu::MAX_FRAME_SIZE + 1];
+        let mut len = 0;
+        let mut overflow = false;
+
+        loop {
+            let timeout = if len == 0 {
+                start_timeout
+            } else {
+                Some(self.config.frame_gap)
+            };
+
+            // Bytes beyond the maximum frame size are dropped, the frame is invalid anyways
+            if len == frame.len() {
+                len = rtu::MAX_FRAME_SIZE;
+                overflow = true;
+            }
+
+            let read = self.stream.read(&mut frame[len..]);
+            let n = match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                    Ok(n) => n?,
+                    Err(_) if len == 0 => return Err(RtuTransportError::Timeout),
+                    Err(_) => break,
+                },
+                None => read.await?,
+            };
+
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            len += n;
+        }
+
+        if overflow {
+            return Err(RtuFrameError::Length.into());
+        }
+
+        let (slave, data) = rtu::decode_frame_with_quirks(&frame[..len], self.config.quirks)?;
+        if pdu.len() < data.len() {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: data.len(),
+                got: pdu.len(),
+            }
+            .into());
+        }
+
+        pdu[..data.len()].copy_from_slice(data);
+        Ok((slave, data.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::{rtu::RtuFrameError, Quirks, SlaveId};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{RtuConfig, RtuStream, RtuTransportError};
+
+    #[test]
+    fn config_for_baud_rate() {
+        assert_eq!(
+            RtuConfig::for_baud_rate(9600).frame_gap,
+            Duration::from_micros(4011)
+        );
+        assert_eq!(
+            RtuConfig::for_baud_rate(115200).frame_gap,
+            Duration::from_micros(1750)
+        );
+    }
+
+    #[tokio::test]
+    async fn write_frame() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = RtuStream::new(local);
+
+        stream
+            .write_frame(SlaveId::new(1), &[3, 0, 0, 0, 1])
+            .await
+            .unwrap();
+
+        let mut frame = [0; 8];
+        remote.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame, [1, 3, 0, 0, 0, 1, 0x84, 0x0A]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_frames_split_by_gaps() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = RtuStream::new(local);
+
+        tokio::spawn(async move {
+            // A frame arriving in chunks with short pauses
+            remote.write_all(&[1, 3, 0]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            remote.write_all(&[0, 0, 1, 0x84, 0x0A]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            remote
+                .write_all(&[1, 3, 0, 0, 0, 1, 0x84, 0x0B])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            remote
+                .write_all(&[1, 3, 0, 0, 0, 1, 0x84, 0x0A])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let mut pdu = [0; 8];
+        let (slave, len) = stream.read_frame(&mut pdu, None).await.unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(&pdu[..len], &[3, 0, 0, 0, 1]);
+
+        let err = stream.read_frame(&mut pdu, None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            RtuTransportError::Frame(RtuFrameError::Crc { .. })
+        ));
+
+        let (_slave, len) = stream.read_frame(&mut pdu, None).await.unwrap();
+        assert_eq!(&pdu[..len], &[3, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_frame_quirks() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let config = RtuConfig {
+            quirks: Quirks {
+                swapped_crc: true,
+                trailing_bytes: true,
+                ..Quirks::STRICT
+            },
+            ..RtuConfig::default()
+        };
+        let mut stream = RtuStream::with_config(local, config);
+
+        // A swapped CRC followed by a padding byte
+        remote
+            .write_all(&[1, 3, 0, 0, 0, 1, 0x0A, 0x84, 0xFF])
+            .await
+            .unwrap();
+        let mut pdu = [0; 8];
+        let (slave, len) = stream.read_frame(&mut pdu, None).await.unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(&pdu[..len], &[3, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_frame_too_long() {
+        let (local, mut remote) = tokio::io::duplex(1024);
+        let mut stream = RtuStream::new(local);
+
+        remote.write_all(&[1; 300]).await.unwrap();
+        let err = stream.read_frame(&mut [0; 8], None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            RtuTransportError::Frame(RtuFrameError::Length)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_frame_timeout() {
+        let (local, _remote) = tokio::io::duplex(64);
+        let mut stream = RtuStream::new(local);
+
+        let err = stream
+            .read_frame(&mut [0; 8], Some(Duration::from_secs(1)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RtuTransportError::Timeout));
+    }
+}
@@ -0,0 +1,302 @@
+//! Modbus TCP on tokio streams.
+
+use core::fmt::{self, Display, Formatter};
+use std::{future::Future, io, time::Duration};
+
+use modbius_core::{
+    tcp::{self, MbapHeader, MBAP_HEADER_SIZE, MODBUS_PROTOCOL_ID},
+    ModbusSerializationError, Quirks, MAX_PDU_SIZE,
+};
+use modbius_traits::TransportError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// An error that occurred while reading or writing Modbus TCP ADUs
+#[derive(Debug)]
+pub enum TcpTransportError {
+    /// The underlying stream failed or was closed
+    Io(io::Error),
+    /// No complete ADU was received in time.
+    ///
+    /// The ADU may have been received partially so the stream is out of sync and should be closed.
+    Timeout,
+    /// An ADU with a protocol id other than [MODBUS_PROTOCOL_ID] was received and skipped
+    InvalidProtocol(u16),
+    /// The received MBAP header was invalid or the PDU did not fit into the given buffer
+    Serialization(ModbusSerializationError),
+}
+
+impl TransportError for TcpTransportError {
+    fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout)
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.is_disconnect()
+    }
+
+    /// After a timeout the stream may be out of sync
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Io(_) | Self::Timeout)
+    }
+}
+
+impl Display for TcpTransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Timeout => write!(f, "timed out waiting for a modbus tcp adu"),
+            Self::InvalidProtocol(id) => write!(f, "invalid protocol id {}", id),
+            Self::Serialization(e) => write!(f, "serialization error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for TcpTransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TcpTransportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ModbusSerializationError> for TcpTransportError {
+    fn from(e: ModbusSerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+/// A stream reading and writing Modbus TCP ADUs (MBAP header + PDU).
+#[derive(Debug)]
+pub struct MbapStream<S> {
+    stream: S,
+    quirks: Quirks,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> MbapStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self::with_quirks(stream, Quirks::STRICT)
+    }
+
+    /// Create a new stream accepting the deviations enabled in quirks.
+    ///
+    /// With [any_protocol_id](Quirks::any_protocol_id) ADUs of any protocol id are read.
+    pub fn with_quirks(stream: S, quirks: Quirks) -> Self {
+        Self { stream, quirks }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Write an ADU with the given header and PDU and flush the stream.
+    ///
+    /// The length of the header is set from the len of pdu.
+    pub async fn write_adu(
+        &mut self,
+        header: MbapHeader,
+        pdu: &[u8],
+    ) -> Result<(), TcpTransportError> {
+        let mut adu = [0; tcp::MAX_ADU_SIZE];
+        let size = tcp::write_adu(header.transaction_id, header.unit_id, pdu, &mut adu)?;
+        self.stream.write_all(&adu[..size]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read the next ADU and write its PDU to pdu.
+    ///
+    /// The MBAP header and the number of bytes written to pdu are returned.
+    /// The timeout is the maximum time to wait for the whole ADU, None waits forever.
+    ///
+    /// # Errors
+    /// ADUs with a protocol id other than [MODBUS_PROTOCOL_ID] are skipped and reported as
+    /// [TcpTransportError::InvalidProtocol], unless the [quirks](Self::with_quirks) accept any
+    /// protocol id. If pdu is too small for the received PDU
+    /// [ModbusSerializationError::InsufficientBuffer] is returned. In both cases the next ADU may be read.
+    /// Any other error leaves the stream in an unknown state.
+    pub async fn read_adu(
+        &mut self,
+        pdu: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<(MbapHeader, usize), TcpTransportError> {
+        with_timeout(timeout, self.read_adu_inner(pdu)).await
+    }
+
+    async fn read_adu_inner(
+        &mut self,
+        pdu: &mut [u8],
+    ) -> Result<(MbapHeader, usize), TcpTransportError> {
+        let mut header = [0; MBAP_HEADER_SIZE];
+        self.stream.read_exact(&mut header).await?;
+        let (header, _) = MbapHeader::from_data(&header)?;
+
+        // With an invalid length there is no way to find the start of the next ADU
+        if header.length < 2 {
+            return Err(ModbusSerializationError::Invalid.into());
+        } else if header.pdu_len() > MAX_PDU_SIZE {
+            return Err(ModbusSerializationError::TooLarge.into());
+        }
+
+        let mut buf = [0; MAX_PDU_SIZE];
+        let data = &mut buf[..header.pdu_len()];
+        self.stream.read_exact(data).await?;
+
+        if header.protocol_id != MODBUS_PROTOCOL_ID && !self.quirks.any_protocol_id {
+            return Err(TcpTransportError::InvalidProtocol(header.protocol_id));
+        }
+
+        if pdu.len() < data.len() {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: data.len(),
+                got: pdu.len(),
+            }
+            .into());
+        }
+
+        pdu[..data.len()].copy_from_slice(data);
+        Ok((header, data.len()))
+    }
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, TcpTransportError>>,
+) -> Result<T, TcpTransportError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut)
+            .await
+            .map_err(|_| TcpTransportError::Timeout)?,
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::{tcp::MbapHeader, ModbusSerializationError, Quirks, SlaveId};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{MbapStream, TcpTransportError};
+
+    #[tokio::test]
+    async fn write_adu() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = MbapStream::new(local);
+
+        let header = MbapHeader::new(0x1501, SlaveId::new(1), 0);
+        stream.write_adu(header, &[3, 0, 4, 0, 1]).await.unwrap();
+
+        let mut adu = [0; 12];
+        remote.read_exact(&mut adu).await.unwrap();
+        assert_eq!(adu, [0x15, 0x01, 0, 0, 0, 6, 1, 3, 0, 4, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn read_adu_skip_invalid_protocol() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = MbapStream::new(local);
+
+        remote
+            .write_all(&[0, 1, 0, 1, 0, 3, 1, 3, 0, 0, 2, 0, 0, 0, 3, 1, 7, 8])
+            .await
+            .unwrap();
+
+        let mut pdu = [0; 8];
+        let err = stream.read_adu(&mut pdu, None).await.unwrap_err();
+        assert!(matches!(err, TcpTransportError::InvalidProtocol(1)));
+
+        let (header, len) = stream.read_adu(&mut pdu, None).await.unwrap();
+        assert_eq!(header.transaction_id, 2);
+        assert_eq!(header.unit_id, SlaveId::new(1));
+        assert_eq!(&pdu[..len], &[7, 8]);
+    }
+
+    #[tokio::test]
+    async fn read_adu_any_protocol() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let quirks = Quirks {
+            any_protocol_id: true,
+            ..Quirks::STRICT
+        };
+        let mut stream = MbapStream::with_quirks(local, quirks);
+
+        remote
+            .write_all(&[0, 1, 0, 1, 0, 3, 1, 3, 0])
+            .await
+            .unwrap();
+
+        let mut pdu = [0; 8];
+        let (header, len) = stream.read_adu(&mut pdu, None).await.unwrap();
+        assert_eq!(header.protocol_id, 1);
+        assert_eq!(&pdu[..len], &[3, 0]);
+    }
+
+    #[tokio::test]
+    async fn read_adu_fail_length() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = MbapStream::new(local);
+
+        remote.write_all(&[0, 1, 0, 0, 0, 1, 1]).await.unwrap();
+        let err = stream.read_adu(&mut [0; 8], None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            TcpTransportError::Serialization(ModbusSerializationError::Invalid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_adu_fail_insufficient_buffer() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = MbapStream::new(local);
+
+        remote
+            .write_all(&[0, 1, 0, 0, 0, 4, 1, 3, 0, 0])
+            .await
+            .unwrap();
+        let err = stream.read_adu(&mut [0; 2], None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            TcpTransportError::Serialization(ModbusSerializationError::InsufficientBuffer {
+                expected: 3,
+                got: 2
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_adu_timeout() {
+        let (local, mut remote) = tokio::io::duplex(64);
+        let mut stream = MbapStream::new(local);
+
+        remote.write_all(&[0, 1, 0, 0, 0, 6, 1, 3]).await.unwrap();
+        let err = stream
+            .read_adu(&mut [0; 8], Some(Duration::from_secs(1)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TcpTransportError::Timeout));
+    }
+}
@@ -0,0 +1,323 @@
+//! Conversion of the addresses found in device manuals to protocol addresses.
+//!
+//! Manuals rarely list the zero-based addresses transmitted in requests. Most use Modicon references, where the
+//! first digit selects the table and the remaining digits hold the one-based address: 40001 is the first holding
+//! register, 300101 the input register at address 100. Schneider devices use IEC notation like `%MW100`, which is
+//! zero-based. [ModbusAddress] parses all of them and resolves them to a [Table] and the zero-based address used by
+//! the request structures.
+//!
+//! ```
+//! use modbius_core::{address::{Base, Table}, read::ReadHoldingRegisters, ModbusAddress};
+//!
+//! let address: ModbusAddress = "40101".parse().unwrap();
+//! assert_eq!(address.table, Table::HoldingRegisters);
+//! assert_eq!(ReadHoldingRegisters::new(address.addr, 1).addr, 100);
+//!
+//! assert_eq!("%MW100".parse(), Ok(address));
+//! assert_eq!(ModbusAddress::from_offset(Table::HoldingRegisters, "0x64", Base::Zero), Ok(address));
+//! assert_eq!(ModbusAddress::from_offset(Table::HoldingRegisters, "101", Base::One), Ok(address));
+//! ```
+//!
+//! Addresses are formatted as 5-digit references if possible and as 6-digit references otherwise, the alternate
+//! flag (`{:#}`) always formats 6 digits.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{ModbusSerializationError, PublicModbusFunction};
+
+/// The four tables of the modbus data model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+impl Table {
+    /// The leading digit of Modicon references to this table
+    pub const fn prefix(self) -> u8 {
+        match self {
+            Self::Coils => 0,
+            Self::DiscreteInputs => 1,
+            Self::InputRegisters => 3,
+            Self::HoldingRegisters => 4,
+        }
+    }
+
+    /// Get the table of a leading digit of a Modicon reference
+    pub const fn from_prefix(prefix: u8) -> Option<Self> {
+        match prefix {
+            0 => Some(Self::Coils),
+            1 => Some(Self::DiscreteInputs),
+            3 => Some(Self::InputRegisters),
+            4 => Some(Self::HoldingRegisters),
+            _ => None,
+        }
+    }
+
+    /// The prefix of IEC notation for this table, e.g. `%MW` for holding registers
+    pub const fn iec_prefix(self) -> &'static str {
+        match self {
+            Self::Coils => "%M",
+            Self::DiscreteInputs => "%I",
+            Self::InputRegisters => "%IW",
+            Self::HoldingRegisters => "%MW",
+        }
+    }
+
+    /// Checks if the table holds bits instead of registers
+    pub const fn is_bit(self) -> bool {
+        matches!(self, Self::Coils | Self::DiscreteInputs)
+    }
+
+    /// Checks if the table can be written by a client
+    pub const fn is_writable(self) -> bool {
+        matches!(self, Self::Coils | Self::HoldingRegisters)
+    }
+
+    /// The function reading this table
+    pub const fn read_function(self) -> PublicModbusFunction {
+        match self {
+            Self::Coils => PublicModbusFunction::ReadCoils,
+            Self::DiscreteInputs => PublicModbusFunction::ReadDiscreteInputs,
+            Self::InputRegisters => PublicModbusFunction::ReadInputRegisters,
+            Self::HoldingRegisters => PublicModbusFunction::ReadHoldingRegisters,
+        }
+    }
+}
+
+/// Whether the first entity of a table is numbered 0 or 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Base {
+    /// Addresses as transmitted in requests
+    #[default]
+    Zero,
+    /// Addresses as used in Modicon references, one more than the transmitted address
+    One,
+}
+
+/// An entity of the modbus data model, the zero-based address within a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModbusAddress {
+    pub table: Table,
+    /// The zero-based address as used in requests
+    pub addr: u16,
+}
+
+impl ModbusAddress {
+    pub const fn new(table: Table, addr: u16) -> Self {
+        Self { table, addr }
+    }
+
+    /// Parse a 5- or 6-digit Modicon reference like 40001 or 400001.
+    ///
+    /// # Errors
+    /// If s is not a reference of a known table [ModbusSerializationError::Invalid] is returned, 0 is invalid as
+    /// address as references are one-based. If the address exceeds 65536 [ModbusSerializationError::Overflow]
+    /// is returned.
+    pub fn from_modicon(s: &str) -> Result<Self, ModbusSerializationError> {
+        let s = s.trim();
+        if !(5..=6).contains(&s.len()) || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ModbusSerializationError::Invalid);
+        }
+
+        let table = Table::from_prefix(s.as_bytes()[0] - b'0').ok_or(ModbusSerializationError::Invalid)?;
+        let reference = parse_number(&s[1..], 10)?;
+        Self::from_number(table, reference, Base::One)
+    }
+
+    /// Parse IEC notation like `%MW100` (holding registers), `%IW100` (input registers), `%M100` (coils) or
+    /// `%I100` (discrete inputs). The addresses are zero-based.
+    ///
+    /// # Errors
+    /// See [from_modicon](Self::from_modicon).
+    pub fn from_iec(s: &str) -> Result<Self, ModbusSerializationError> {
+        let s = s.trim();
+        // Longer prefixes first as %M is a prefix of %MW
+        let tables = [Table::HoldingRegisters, Table::InputRegisters, Table::Coils, Table::DiscreteInputs];
+        for table in tables {
+            let prefix = table.iec_prefix();
+            let index = match s.get(..prefix.len()) {
+                Some(head) if head.eq_ignore_ascii_case(prefix) => &s[prefix.len()..],
+                _ => continue,
+            };
+            if index.bytes().all(|b| b.is_ascii_digit()) {
+                return Self::from_number(table, parse_number(index, 10)?, Base::Zero);
+            }
+        }
+        Err(ModbusSerializationError::Invalid)
+    }
+
+    /// Parse an address within the given table.
+    ///
+    /// The address is either decimal or hexadecimal, written with a `0x` prefix or a `h` suffix like 0x64 or 64h.
+    ///
+    /// # Errors
+    /// See [from_modicon](Self::from_modicon).
+    pub fn from_offset(table: Table, s: &str, base: Base) -> Result<Self, ModbusSerializationError> {
+        let s = s.trim();
+        let number = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            parse_number(hex, 16)?
+        } else if let Some(hex) = s.strip_suffix('h').or_else(|| s.strip_suffix('H')) {
+            parse_number(hex, 16)?
+        } else {
+            parse_number(s, 10)?
+        };
+        Self::from_number(table, number, base)
+    }
+
+    /// Create an address from a number of the given base
+    ///
+    /// # Errors
+    /// See [from_modicon](Self::from_modicon).
+    pub fn from_number(table: Table, number: u32, base: Base) -> Result<Self, ModbusSerializationError> {
+        let addr = match base {
+            Base::Zero => number,
+            Base::One => number.checked_sub(1).ok_or(ModbusSerializationError::Invalid)?,
+        };
+        match u16::try_from(addr) {
+            Ok(addr) => Ok(Self::new(table, addr)),
+            Err(_) => Err(ModbusSerializationError::Overflow),
+        }
+    }
+
+    /// Get the address in the given base, e.g. for display
+    pub const fn number(self, base: Base) -> u32 {
+        match base {
+            Base::Zero => self.addr as u32,
+            Base::One => self.addr as u32 + 1,
+        }
+    }
+
+    /// Get a value formatting this address in IEC notation
+    pub const fn iec(self) -> Iec {
+        Iec(self)
+    }
+}
+
+impl FromStr for ModbusAddress {
+    type Err = ModbusSerializationError;
+
+    /// Parse a Modicon reference or IEC notation, see [from_modicon](Self::from_modicon) and
+    /// [from_iec](Self::from_iec)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('%') {
+            Self::from_iec(s)
+        } else {
+            Self::from_modicon(s)
+        }
+    }
+}
+
+impl Display for ModbusAddress {
+    /// Format the address as Modicon reference
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let reference = self.number(Base::One);
+        if reference <= 9999 && !f.alternate() {
+            write!(f, "{}{:04}", self.table.prefix(), reference)
+        } else {
+            write!(f, "{}{:05}", self.table.prefix(), reference)
+        }
+    }
+}
+
+/// Formats a [ModbusAddress] in IEC notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Iec(ModbusAddress);
+
+impl Display for Iec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.0.table.iec_prefix(), self.0.addr)
+    }
+}
+
+fn parse_number(s: &str, radix: u32) -> Result<u32, ModbusSerializationError> {
+    // from_str_radix accepts a sign which no address has
+    if s.is_empty() || s.starts_with(['+', '-']) {
+        return Err(ModbusSerializationError::Invalid);
+    }
+
+    u32::from_str_radix(s, radix).map_err(|e| match e.kind() {
+        core::num::IntErrorKind::PosOverflow => ModbusSerializationError::Overflow,
+        _ => ModbusSerializationError::Invalid,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn modicon() {
+        assert_eq!(ModbusAddress::from_modicon("00001"), Ok(ModbusAddress::new(Table::Coils, 0)));
+        assert_eq!(ModbusAddress::from_modicon("10010"), Ok(ModbusAddress::new(Table::DiscreteInputs, 9)));
+        assert_eq!(ModbusAddress::from_modicon("300101"), Ok(ModbusAddress::new(Table::InputRegisters, 100)));
+        assert_eq!(ModbusAddress::from_modicon("465536"), Ok(ModbusAddress::new(Table::HoldingRegisters, 0xFFFF)));
+        assert_eq!(ModbusAddress::from_modicon(" 49999 "), Ok(ModbusAddress::new(Table::HoldingRegisters, 9998)));
+
+        assert_eq!(ModbusAddress::from_modicon("40000"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_modicon("465537"), Err(ModbusSerializationError::Overflow));
+        assert_eq!(ModbusAddress::from_modicon("20001"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_modicon("4001"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_modicon("4000001"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_modicon("4+001"), Err(ModbusSerializationError::Invalid));
+    }
+
+    #[test]
+    fn iec() {
+        assert_eq!(ModbusAddress::from_iec("%MW100"), Ok(ModbusAddress::new(Table::HoldingRegisters, 100)));
+        assert_eq!(ModbusAddress::from_iec("%iw0"), Ok(ModbusAddress::new(Table::InputRegisters, 0)));
+        assert_eq!(ModbusAddress::from_iec("%M7"), Ok(ModbusAddress::new(Table::Coils, 7)));
+        assert_eq!(ModbusAddress::from_iec("%I65535"), Ok(ModbusAddress::new(Table::DiscreteInputs, 0xFFFF)));
+
+        assert_eq!(ModbusAddress::from_iec("%MW"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_iec("%MD10"), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_iec("%MW65536"), Err(ModbusSerializationError::Overflow));
+    }
+
+    #[test]
+    fn offset() {
+        let table = Table::HoldingRegisters;
+        assert_eq!(ModbusAddress::from_offset(table, "100", Base::Zero), Ok(ModbusAddress::new(table, 100)));
+        assert_eq!(ModbusAddress::from_offset(table, "100", Base::One), Ok(ModbusAddress::new(table, 99)));
+        assert_eq!(ModbusAddress::from_offset(table, "0xFFFF", Base::Zero), Ok(ModbusAddress::new(table, 0xFFFF)));
+        assert_eq!(ModbusAddress::from_offset(table, "1Fh", Base::Zero), Ok(ModbusAddress::new(table, 0x1F)));
+        assert_eq!(ModbusAddress::from_offset(table, "0X10000", Base::One), Ok(ModbusAddress::new(table, 0xFFFF)));
+
+        assert_eq!(ModbusAddress::from_offset(table, "0", Base::One), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_offset(table, "0x", Base::Zero), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_offset(table, "-1", Base::Zero), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ModbusAddress::from_offset(table, "65536", Base::Zero), Err(ModbusSerializationError::Overflow));
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("40001".parse(), Ok(ModbusAddress::new(Table::HoldingRegisters, 0)));
+        assert_eq!(" %MW0".parse(), Ok(ModbusAddress::new(Table::HoldingRegisters, 0)));
+        assert_eq!("MW0".parse::<ModbusAddress>(), Err(ModbusSerializationError::Invalid));
+    }
+
+    #[test]
+    fn format() {
+        let address = ModbusAddress::new(Table::Coils, 9);
+        assert_eq!(address.to_string(), "00010");
+        assert_eq!(std::format!("{:#}", address), "000010");
+        assert_eq!(address.iec().to_string(), "%M9");
+
+        let address = ModbusAddress::new(Table::InputRegisters, 9999);
+        assert_eq!(address.to_string(), "310000");
+        assert_eq!(address.iec().to_string(), "%IW9999");
+
+        for s in ["00001", "19999", "310000", "465536"] {
+            assert_eq!(s.parse::<ModbusAddress>().unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Modbus ASCII framing.
+//!
+//! An ASCII frame consists out of a ':', the slave id, the PDU and a LRC checksum each byte encoded as two
+//! uppercase hex characters and a terminating CR LF. See <https://modbus.org/docs/Modbus_over_serial_line_V1_02.pdf>
+//! page 16 and following for more details.
+//!
+//! Frames are decoded byte by byte with an [AsciiDecoder] which skips any garbage before a ':' and restarts
+//! decoding on every ':' so it resynchronizes on the next frame after transmission errors.
+
+use crate::{ModbusSerializationError, SlaveId};
+
+/// The maximum number of characters of an ASCII frame
+pub const MAX_FRAME_SIZE: usize = 513;
+
+/// The maximum number of bytes an ASCII frame decodes to (slave id + PDU + LRC)
+pub const MAX_DECODED_SIZE: usize = 255;
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Calculate the longitudinal redundancy check of the given data.
+///
+/// The LRC is the two's complement of the sum of all bytes ignoring any overflow.
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)).wrapping_neg()
+}
+
+/// Get how many characters a frame containing the given number of PDU bytes needs
+pub const fn frame_size(pdu_len: usize) -> usize {
+    // ':' + (slave id + PDU + LRC) as hex + CR LF
+    1 + (pdu_len + 2) * 2 + 2
+}
+
+/// Write an ASCII frame with the given slave id and PDU to the slice.
+///
+/// On success the number of written characters is returned.
+///
+/// # Errors
+/// If out can't hold the whole frame [ModbusSerializationError::InsufficientBuffer] is returned.
+/// If the PDU is too large to be framed [ModbusSerializationError::TooLarge] is returned.
+pub fn write_frame(slave: SlaveId, pdu: &[u8], out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+    if pdu.len() > MAX_DECODED_SIZE - 2 {
+        return Err(ModbusSerializationError::TooLarge);
+    }
+
+    let size = frame_size(pdu.len());
+    if out.len() < size {
+        return Err(ModbusSerializationError::InsufficientBuffer {
+            expected: size,
+            got: out.len(),
+        });
+    }
+
+    let slave: u8 = slave.into();
+    let checksum = lrc(pdu).wrapping_sub(slave);
+
+    out[0] = b':';
+    let mut idx = 1;
+    for byte in core::iter::once(slave).chain(pdu.iter().copied()).chain(core::iter::once(checksum)) {
+        out[idx] = HEX[(byte >> 4) as usize];
+        out[idx + 1] = HEX[(byte & 0xF) as usize];
+        idx += 2;
+    }
+    out[idx] = b'\r';
+    out[idx + 1] = b'\n';
+
+    Ok(size)
+}
+
+/// An error encountered while decoding an ASCII frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AsciiFrameError {
+    /// A character other than an uppercase or lowercase hex digit was found inside the frame
+    Hex,
+    /// The LRC of the frame did not match its content
+    Lrc {
+        /// The LRC calculated from the frame content
+        expected: u8,
+        /// The LRC transmitted in the frame
+        got: u8,
+    },
+    /// The frame had an odd number of hex digits or was too short or too long
+    Length,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DecoderState {
+    /// Skipping everything until the next ':'
+    Idle,
+    /// Collecting hex digits
+    Data,
+    /// A CR was received, waiting for the LF
+    End,
+}
+
+/// A byte by byte ASCII frame decoder.
+///
+/// The decoder stores at most [MAX_DECODED_SIZE] bytes and does not allocate.
+#[derive(Debug, Clone)]
+pub struct AsciiDecoder {
+    buf: [u8; MAX_DECODED_SIZE],
+    len: usize,
+    high_nibble: Option<u8>,
+    state: DecoderState,
+}
+
+impl Default for AsciiDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsciiDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_DECODED_SIZE],
+            len: 0,
+            high_nibble: None,
+            state: DecoderState::Idle,
+        }
+    }
+
+    /// Discard any partially decoded frame and wait for the next ':'
+    pub fn reset(&mut self) {
+        self.state = DecoderState::Idle;
+    }
+
+    /// Checks if the decoder is inside of a frame (a ':' was received but the frame is not complete)
+    pub fn in_frame(&self) -> bool {
+        self.state != DecoderState::Idle
+    }
+
+    /// Feed the next received character to the decoder.
+    ///
+    /// If the character completes a valid frame its slave id and PDU are returned.
+    ///
+    /// # Errors
+    /// If the character makes the current frame invalid an [AsciiFrameError] is returned. The frame is discarded
+    /// and the decoder waits for the next ':'.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<(SlaveId, &[u8])>, AsciiFrameError> {
+        if byte == b':' {
+            self.state = DecoderState::Data;
+            self.len = 0;
+            self.high_nibble = None;
+            return Ok(None);
+        }
+
+        match self.state {
+            DecoderState::Idle => Ok(None),
+            DecoderState::Data if byte == b'\r' => {
+                self.state = DecoderState::End;
+                Ok(None)
+            }
+            DecoderState::Data => {
+                let nibble = match hex_value(byte) {
+                    Some(nibble) => nibble,
+                    None => return self.fail(AsciiFrameError::Hex),
+                };
+
+                match self.high_nibble.take() {
+                    None => self.high_nibble = Some(nibble),
+                    Some(_) if self.len == MAX_DECODED_SIZE => return self.fail(AsciiFrameError::Length),
+                    Some(high) => {
+                        self.buf[self.len] = high << 4 | nibble;
+                        self.len += 1;
+                    }
+                }
+                Ok(None)
+            }
+            DecoderState::End if byte != b'\n' => self.fail(AsciiFrameError::Hex),
+            DecoderState::End => {
+                self.state = DecoderState::Idle;
+                // Slave id + function code + LRC
+                if self.high_nibble.is_some() || self.len < 3 {
+                    return Err(AsciiFrameError::Length);
+                }
+
+                let (content, checksum) = self.buf[..self.len].split_at(self.len - 1);
+                let expected = lrc(content);
+                if expected != checksum[0] {
+                    return Err(AsciiFrameError::Lrc {
+                        expected,
+                        got: checksum[0],
+                    });
+                }
+
+                Ok(Some((SlaveId::new(content[0]), &content[1..])))
+            }
+        }
+    }
+
+    fn fail<T>(&mut self, err: AsciiFrameError) -> Result<T, AsciiFrameError> {
+        self.state = DecoderState::Idle;
+        Err(err)
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode<'a>(decoder: &'a mut AsciiDecoder, data: &[u8]) -> Result<Option<(SlaveId, &'a [u8])>, AsciiFrameError> {
+        let (last, data) = data.split_last().unwrap();
+        for byte in data {
+            assert_eq!(decoder.feed(*byte), Ok(None));
+        }
+        decoder.feed(*last)
+    }
+
+    #[test]
+    fn lrc_spec() {
+        // Read holding registers example from the serial line spec
+        assert_eq!(lrc(&[0xF7, 0x03, 0x13, 0x89, 0x00, 0x0A]), 0x60);
+        assert_eq!(lrc(&[]), 0);
+    }
+
+    #[test]
+    fn write_frame_spec() {
+        let mut out = [0; 17];
+        let size = write_frame(SlaveId::new(0xF7), &[0x03, 0x13, 0x89, 0x00, 0x0A], &mut out).unwrap();
+
+        assert_eq!(size, 17);
+        assert_eq!(&out, b":F7031389000A60\r\n");
+    }
+
+    #[test]
+    fn write_frame_fail_insufficient_buffer() {
+        let mut out = [0; 16];
+        let err = write_frame(SlaveId::new(0xF7), &[0x03, 0x13, 0x89, 0x00, 0x0A], &mut out).unwrap_err();
+
+        assert_eq!(err, ModbusSerializationError::InsufficientBuffer { expected: 17, got: 16 });
+    }
+
+    #[test]
+    fn write_frame_fail_too_large() {
+        let mut out = [0; MAX_FRAME_SIZE + 2];
+        let err = write_frame(SlaveId::new(1), &[0; 254], &mut out).unwrap_err();
+
+        assert_eq!(err, ModbusSerializationError::TooLarge);
+        assert_eq!(write_frame(SlaveId::new(1), &[0; 253], &mut out), Ok(MAX_FRAME_SIZE));
+    }
+
+    #[test]
+    fn decode_spec() {
+        let mut decoder = AsciiDecoder::new();
+        let (slave, pdu) = decode(&mut decoder, b":F7031389000A60\r\n").unwrap().unwrap();
+
+        assert_eq!(slave, SlaveId::new(0xF7));
+        assert_eq!(pdu, &[0x03, 0x13, 0x89, 0x00, 0x0A]);
+        assert!(!decoder.in_frame());
+    }
+
+    #[test]
+    fn decode_lowercase() {
+        let mut decoder = AsciiDecoder::new();
+        let (slave, pdu) = decode(&mut decoder, b":f7031389000a60\r\n").unwrap().unwrap();
+
+        assert_eq!(slave, SlaveId::new(0xF7));
+        assert_eq!(pdu, &[0x03, 0x13, 0x89, 0x00, 0x0A]);
+    }
+
+    #[test]
+    fn decode_leading_garbage() {
+        let mut decoder = AsciiDecoder::new();
+        let (slave, pdu) = decode(&mut decoder, b"\x00\xFFgarbage\r\n:010300000001FB\r\n").unwrap().unwrap();
+
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(pdu, &[3, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn decode_resync() {
+        let mut decoder = AsciiDecoder::new();
+        let (slave, pdu) = decode(&mut decoder, b":0103:010300000001FB\r\n").unwrap().unwrap();
+
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(pdu, &[3, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn decode_fail_lrc() {
+        let mut decoder = AsciiDecoder::new();
+        let err = decode(&mut decoder, b":010300000001FC\r\n").unwrap_err();
+
+        assert_eq!(err, AsciiFrameError::Lrc { expected: 0xFB, got: 0xFC });
+        assert!(!decoder.in_frame());
+    }
+
+    #[test]
+    fn decode_fail_hex() {
+        let mut decoder = AsciiDecoder::new();
+        let err = decode(&mut decoder, b":01G").unwrap_err();
+
+        assert_eq!(err, AsciiFrameError::Hex);
+        assert!(!decoder.in_frame());
+
+        // Everything up to the next ':' is skipped
+        let (slave, _pdu) = decode(&mut decoder, b"03000000017B\r\n:010300000001FB\r\n").unwrap().unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+    }
+
+    #[test]
+    fn decode_fail_missing_lf() {
+        let mut decoder = AsciiDecoder::new();
+        let err = decode(&mut decoder, b":010300000001FB\r\r").unwrap_err();
+
+        assert_eq!(err, AsciiFrameError::Hex);
+    }
+
+    #[test]
+    fn decode_fail_length() {
+        let mut decoder = AsciiDecoder::new();
+        assert_eq!(decode(&mut decoder, b":0103000000017\r\n"), Err(AsciiFrameError::Length));
+        assert_eq!(decode(&mut decoder, b":01FF\r\n"), Err(AsciiFrameError::Length));
+
+        let mut too_long = [b'0'; MAX_FRAME_SIZE];
+        too_long[0] = b':';
+        assert_eq!(decode(&mut decoder, &too_long), Err(AsciiFrameError::Length));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut out = [0; MAX_FRAME_SIZE];
+        let pdu = [16, 0, 1, 0, 2, 4, 0, 0xA, 1, 2];
+        let size = write_frame(SlaveId::new(17), &pdu, &mut out).unwrap();
+
+        let mut decoder = AsciiDecoder::new();
+        let (slave, decoded) = decode(&mut decoder, &out[..size]).unwrap().unwrap();
+        assert_eq!(slave, SlaveId::new(17));
+        assert_eq!(decoded, &pdu);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{BitState, ModbusSerializationError};
+
+/// A mutable slice of packed bits, e.g. the coil states of a request being built in place.
+///
+/// The first bit is the least significant bit of the first byte. Padding bits after the last bit are kept at 0.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitSliceMut<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> BitSliceMut<'a> {
+    /// Create a slice of len bits packed into bytes, the padding bits of the last byte are cleared.
+    ///
+    /// # Errors
+    /// If bytes doesn't hold exactly len bits [ModbusSerializationError::Ambivalent] is returned.
+    pub fn new(bytes: &'a mut [u8], len: usize) -> Result<Self, ModbusSerializationError> {
+        if bytes.len() != len.div_ceil(8) {
+            return Err(ModbusSerializationError::Ambivalent);
+        }
+
+        if !len.is_multiple_of(8) {
+            bytes[len / 8] &= (1 << (len % 8)) - 1;
+        }
+        Ok(Self { bytes, len })
+    }
+
+    /// # Safety
+    /// bytes has to hold exactly len bits and the padding bits have to be 0, violating this invariant may invoke
+    /// undefined behavior.
+    pub unsafe fn new_unchecked(bytes: &'a mut [u8], len: usize) -> Self {
+        Self { bytes, len }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<BitState> {
+        if idx < self.len {
+            Some(BitState::from(self.bytes[idx / 8] & (1 << (idx % 8)) != 0))
+        } else {
+            None
+        }
+    }
+
+    /// Set the bit at idx.
+    ///
+    /// # Errors
+    /// If idx is out of bounds [ModbusSerializationError::Overflow] is returned.
+    pub fn set(&mut self, idx: usize, state: BitState) -> Result<(), ModbusSerializationError> {
+        if idx >= self.len {
+            return Err(ModbusSerializationError::Overflow);
+        }
+
+        match state {
+            BitState::On => self.bytes[idx / 8] |= 1 << (idx % 8),
+            BitState::Off => self.bytes[idx / 8] &= !(1 << (idx % 8)),
+        }
+        Ok(())
+    }
+
+    /// Set the bits starting at idx to the given states
+    ///
+    /// # Errors
+    /// If the states don't fit [ModbusSerializationError::Overflow] is returned and nothing is written.
+    pub fn copy_from_bools(&mut self, idx: usize, states: &[bool]) -> Result<(), ModbusSerializationError> {
+        if idx.checked_add(states.len()).is_none_or(|end| end > self.len) {
+            return Err(ModbusSerializationError::Overflow);
+        }
+
+        for (offset, state) in states.iter().enumerate() {
+            self.set(idx + offset, BitState::from(*state))?;
+        }
+        Ok(())
+    }
+
+    /// Set all bits to state
+    pub fn fill(&mut self, state: BitState) {
+        let byte = match state {
+            BitState::On => 0xFF,
+            BitState::Off => 0,
+        };
+        self.bytes.fill(byte);
+        if !self.len.is_multiple_of(8) {
+            self.bytes[self.len / 8] &= (1 << (self.len % 8)) - 1;
+        }
+    }
+
+    /// Iterate over the states of all bits
+    pub fn states(&self) -> impl ExactSizeIterator<Item = BitState> + '_ {
+        (0..self.len).map(move |idx| BitState::from(self.bytes[idx / 8] & (1 << (idx % 8)) != 0))
+    }
+
+    /// The number of bits
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The packed bits
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set() {
+        let mut bytes = [0xFF; 2];
+        let mut bits = BitSliceMut::new(&mut bytes, 10).unwrap();
+        assert_eq!(bits.bytes(), &[0xFF, 0x03]);
+
+        bits.set(1, BitState::Off).unwrap();
+        bits.set(9, BitState::Off).unwrap();
+        assert_eq!(bits.get(1), Some(BitState::Off));
+        assert_eq!(bits.get(8), Some(BitState::On));
+        assert_eq!(bits.get(10), None);
+        assert_eq!(bits.set(10, BitState::On), Err(ModbusSerializationError::Overflow));
+        assert_eq!(bits.bytes(), &[0xFD, 0x01]);
+        assert_eq!(bits.states().filter(|s| s.is_on()).count(), 8);
+
+        assert_eq!(BitSliceMut::new(&mut [0; 2], 8), Err(ModbusSerializationError::Ambivalent));
+    }
+
+    #[test]
+    fn copy_from_bools() {
+        // The coils of the write multiple coils example of the modbus spec
+        let states = [true, false, true, true, false, false, true, true, true, false];
+        let mut bytes = [0; 2];
+        let mut bits = BitSliceMut::new(&mut bytes, 10).unwrap();
+
+        bits.copy_from_bools(0, &states).unwrap();
+        assert_eq!(bits.bytes(), &[0xCD, 0x01]);
+        assert_eq!(bits.copy_from_bools(1, &states), Err(ModbusSerializationError::Overflow));
+        assert_eq!(bits.copy_from_bools(usize::MAX, &states), Err(ModbusSerializationError::Overflow));
+
+        bits.fill(BitState::On);
+        assert_eq!(bits.bytes(), &[0xFF, 0x03]);
+        bits.fill(BitState::Off);
+        assert_eq!(bits.bytes(), &[0, 0]);
+    }
+}
@@ -6,8 +6,9 @@ use crate::ModbusSerializationError;
 ///
 /// This enum works similiar to bool but adds some helper functionality
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
 pub enum BitState {
+    #[default]
     Off = 0,
     On = 1,
 }
@@ -18,12 +19,6 @@ impl BitState {
     }
 }
 
-impl Default for BitState {
-    fn default() -> BitState {
-        BitState::Off
-    }
-}
-
 impl TryFrom<u16> for BitState {
     type Error = ModbusSerializationError;
     fn try_from(value: u16) -> Result<Self, Self::Error> {
@@ -35,11 +30,11 @@ impl TryFrom<u16> for BitState {
     }
 }
 
-impl Into<u16> for BitState {
-    fn into(self) -> u16 {
-        match self { 
-            Self::Off => 0,
-            Self::On => 0xFF00,
+impl From<BitState> for u16 {
+    fn from(state: BitState) -> u16 {
+        match state {
+            BitState::Off => 0,
+            BitState::On => 0xFF00,
         }
     }
 }
@@ -54,11 +49,11 @@ impl From<bool> for BitState {
     }
 }
 
-impl Into<bool> for BitState {
-    fn into(self) -> bool {
-        match self {
-            Self::Off => false,
-            Self::On => true,
+impl From<BitState> for bool {
+    fn from(state: BitState) -> bool {
+        match state {
+            BitState::Off => false,
+            BitState::On => true,
         }
     }
 }
@@ -75,6 +70,7 @@ impl Not for BitState {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod bitstate_test {
     use core::convert::TryFrom;
 
@@ -0,0 +1,264 @@
+//! User defined functions.
+//!
+//! The function codes 65-72 and 100-110 are reserved for user defined functions ([ModbusFunction::is_custom]).
+//! Their layout is only known to the application, which describes it by implementing [CustomFunction].
+//! Registering a [CustomCodec] of the function in [CustomFunctions] makes
+//! [Request::from_data_with_custom](crate::Request::from_data_with_custom) check the requests of the function and
+//! split off their tail. Servers answer user defined functions with the `CustomRouter` of modbius-server, clients
+//! send them with `CustomClient::send_custom` of modbius-client.
+//!
+//! ```
+//! use modbius_core::{
+//!     custom::{CustomCodec, CustomFunction, CustomFunctions},
+//!     ModbusFunction, ModbusSerializationError, Quirks, Request,
+//! };
+//!
+//! /// Read a block of a file, the request holds the block number
+//! struct ReadBlock;
+//!
+//! impl CustomFunction for ReadBlock {
+//!     const FUNCTION: ModbusFunction = ModbusFunction::new(0x41);
+//!     type Request<'a> = u16;
+//!     type Response<'a> = &'a [u8];
+//!
+//!     fn parse_request(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+//!         modbius_core::util::read_u16(data)
+//!     }
+//!
+//!     fn write_request(block: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+//!         write(&block.to_be_bytes(), out)
+//!     }
+//!
+//!     fn parse_response(data: &[u8]) -> Result<(&[u8], &[u8]), ModbusSerializationError> {
+//!         Ok((data, &[]))
+//!     }
+//!
+//!     fn write_response(block: &&[u8], out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+//!         write(block, out)
+//!     }
+//! }
+//!
+//! fn write(data: &[u8], out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+//!     if out.len() < data.len() {
+//!         return Err(ModbusSerializationError::InsufficientBuffer { expected: data.len(), got: out.len() });
+//!     }
+//!     out[..data.len()].copy_from_slice(data);
+//!     Ok(data.len())
+//! }
+//!
+//! const CUSTOM: CustomFunctions<'static> = CustomFunctions::new(&[CustomCodec::of::<ReadBlock>()]);
+//!
+//! let (request, tail) = Request::from_data_with_custom(&[0x41, 0, 7, 0xFF], Quirks::STRICT, CUSTOM).unwrap();
+//! assert_eq!(request, Request::Other { function: ModbusFunction::new(0x41), data: &[0, 7] });
+//! assert_eq!(tail, &[0xFF]);
+//! ```
+
+use crate::{ModbusFunction, ModbusSerializationError};
+
+/// The layout of the requests and responses of a user defined function.
+///
+/// All data excludes the function code.
+pub trait CustomFunction {
+    /// The function code, has to be a user defined code
+    const FUNCTION: ModbusFunction;
+
+    type Request<'a>;
+    type Response<'a>;
+
+    /// Parse a request from the given data, the tail is returned alongside the request.
+    ///
+    /// The tail has to be the part of data after the request.
+    fn parse_request<'a>(data: &'a [u8]) -> Result<(Self::Request<'a>, &'a [u8]), ModbusSerializationError>;
+
+    /// Write the request to the slice, on success the number of written bytes is returned
+    fn write_request(request: &Self::Request<'_>, out: &mut [u8]) -> Result<usize, ModbusSerializationError>;
+
+    /// Parse a response from the given data, the tail is returned alongside the response
+    fn parse_response<'a>(data: &'a [u8]) -> Result<(Self::Response<'a>, &'a [u8]), ModbusSerializationError>;
+
+    /// Write the response to the slice, on success the number of written bytes is returned
+    fn write_response(response: &Self::Response<'_>, out: &mut [u8]) -> Result<usize, ModbusSerializationError>;
+}
+
+/// The type erased layout of a [CustomFunction], used to register the function in [CustomFunctions]
+#[derive(Debug, Clone, Copy)]
+pub struct CustomCodec {
+    function: ModbusFunction,
+    request_size: fn(&[u8]) -> Result<usize, ModbusSerializationError>,
+}
+
+impl CustomCodec {
+    /// Create the codec of the given function.
+    ///
+    /// # Panics
+    /// If the code of F is no user defined function code, in a const context this fails to compile.
+    pub const fn of<F: CustomFunction>() -> Self {
+        assert!(F::FUNCTION.is_custom(), "the function code is reserved for public functions");
+        Self { function: F::FUNCTION, request_size: request_size::<F> }
+    }
+
+    pub const fn function(self) -> ModbusFunction {
+        self.function
+    }
+
+    /// Check the request at the start of data and get its size.
+    ///
+    /// # Errors
+    /// The error of [CustomFunction::parse_request] is returned, a tail which is not the end of data is
+    /// [Invalid](ModbusSerializationError::Invalid).
+    pub fn request_size(self, data: &[u8]) -> Result<usize, ModbusSerializationError> {
+        (self.request_size)(data)
+    }
+}
+
+fn request_size<F: CustomFunction>(data: &[u8]) -> Result<usize, ModbusSerializationError> {
+    let (_, tail) = F::parse_request(data)?;
+    // The tail has to be the end of data, an empty tail may be any empty slice
+    match data.len().checked_sub(tail.len()) {
+        Some(size) if tail.is_empty() || data[size..].as_ptr() == tail.as_ptr() => Ok(size),
+        _ => Err(ModbusSerializationError::Invalid),
+    }
+}
+
+/// A registry of user defined functions.
+///
+/// If several codecs are registered for a function the first one is used.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CustomFunctions<'a> {
+    codecs: &'a [CustomCodec],
+}
+
+impl<'a> CustomFunctions<'a> {
+    pub const fn new(codecs: &'a [CustomCodec]) -> Self {
+        Self { codecs }
+    }
+
+    /// Get the codec registered for function
+    pub fn get(self, function: ModbusFunction) -> Option<CustomCodec> {
+        self.codecs.iter().copied().find(|codec| codec.function == function)
+    }
+
+    pub fn codecs(self) -> &'a [CustomCodec] {
+        self.codecs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{util, DecodeError, DecodeErrorKind, ModbusError, Quirks, Request};
+
+    struct Echo;
+
+    impl CustomFunction for Echo {
+        const FUNCTION: ModbusFunction = ModbusFunction::new(100);
+        type Request<'a> = u16;
+        type Response<'a> = u16;
+
+        fn parse_request(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+            util::read_u16(data)
+        }
+
+        fn write_request(request: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            write(*request, out)
+        }
+
+        fn parse_response(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+            util::read_u16(data)
+        }
+
+        fn write_response(response: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            write(*response, out)
+        }
+    }
+
+    fn write(value: u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+        match out.get_mut(..2) {
+            Some(out) => {
+                out.copy_from_slice(&value.to_be_bytes());
+                Ok(2)
+            }
+            None => Err(ModbusSerializationError::InsufficientBuffer { expected: 2, got: out.len() }),
+        }
+    }
+
+    /// Returns a tail which is not part of the data
+    struct Foreign;
+
+    impl CustomFunction for Foreign {
+        const FUNCTION: ModbusFunction = ModbusFunction::new(101);
+        type Request<'a> = ();
+        type Response<'a> = ();
+
+        fn parse_request(_: &[u8]) -> Result<((), &[u8]), ModbusSerializationError> {
+            Ok(((), &[0; 8]))
+        }
+
+        fn write_request(_: &(), _: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            Ok(0)
+        }
+
+        fn parse_response(_: &[u8]) -> Result<((), &[u8]), ModbusSerializationError> {
+            Ok(((), &[0; 8]))
+        }
+
+        fn write_response(_: &(), _: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            Ok(0)
+        }
+    }
+
+    const CUSTOM: CustomFunctions<'static> = CustomFunctions::new(&[CustomCodec::of::<Echo>()]);
+
+    #[test]
+    fn codec() {
+        let codec = CUSTOM.get(ModbusFunction::new(100)).unwrap();
+        assert_eq!(codec.function(), ModbusFunction::new(100));
+        assert_eq!(codec.request_size(&[1, 2, 3]), Ok(2));
+        assert!(codec.request_size(&[1]).is_err());
+        assert!(CUSTOM.get(ModbusFunction::new(101)).is_none());
+    }
+
+    #[test]
+    fn foreign_tail() {
+        let codecs = [CustomCodec::of::<Foreign>()];
+        let codec = codecs[0];
+        assert_eq!(codec.request_size(&[1, 2]), Err(ModbusSerializationError::Invalid));
+        assert_eq!(codec.request_size(&[0; 16]), Err(ModbusSerializationError::Invalid));
+
+        let custom = CustomFunctions::new(&codecs);
+        assert_eq!(
+            Request::from_data_with_custom(&[101, 1, 2], Quirks::STRICT, custom),
+            Err(ModbusError::Decode(DecodeError {
+                function: Some(ModbusFunction::new(101)),
+                offset: 1,
+                kind: DecodeErrorKind::Invalid,
+            }))
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Request::from_data_with_custom(&[100, 1, 2, 3], Quirks::STRICT, CUSTOM),
+            Ok((Request::Other { function: ModbusFunction::new(100), data: &[1, 2] }, &[3][..]))
+        );
+        assert_eq!(
+            Request::from_data_with_custom(&[100, 1], Quirks::STRICT, CUSTOM),
+            Err(ModbusError::Decode(DecodeError {
+                function: Some(ModbusFunction::new(100)),
+                offset: 2,
+                kind: DecodeErrorKind::UnexpectedEof,
+            }))
+        );
+
+        // Unregistered functions and standard requests are parsed as usual
+        assert_eq!(
+            Request::from_data_with_custom(&[101, 1], Quirks::STRICT, CUSTOM),
+            Ok((Request::Other { function: ModbusFunction::new(101), data: &[1] }, &[][..]))
+        );
+        assert!(matches!(
+            Request::from_data_with_custom(&[3, 0, 0, 0, 1], Quirks::STRICT, CUSTOM),
+            Ok((Request::ReadHoldingRegisters(_), _))
+        ));
+    }
+}
@@ -0,0 +1,649 @@
+//! Enron Modbus, the 32-bit register extension used by flow computers.
+//!
+//! Enron (also called Daniel) Modbus devices reserve address ranges for 32-bit values. Within these ranges an
+//! address refers to a whole 32-bit value and quantities count values instead of 16-bit registers, so a read of
+//! 2 values at a float address is answered with 8 bytes. Which ranges hold 32-bit values is described by an
+//! [EnronMap], the conventional layout is [EnronMap::STANDARD].
+//!
+//! Besides the values Enron devices provide an event log and archives:
+//! - The event log is read with read holding registers at the event log address, the quantity is ignored.
+//!   The response holds up to [MAX_EVENTS] [EnronEvent]s. The master acknowledges the events it read by writing
+//!   the coil at the event log address, only then the device drops them.
+//! - Archives, e.g. hourly and daily records, are read with read holding registers at the archive address. The
+//!   quantity is the index of the record to read. A record consists out of 32-bit floats, usually starting with
+//!   the date and time.
+//!
+//! 32-bit values are transmitted most significant byte first, floats as IEEE 754 single precision.
+
+use crate::{
+    pdu::PduReader, util, DecodeErrorKind, ModbusError, ModbusSerializationError, PublicModbusFunction, Quirks,
+    Request, ValidationError, ValidationErrorKind, MAX_PDU_SIZE,
+};
+
+/// The conventional address of the event log
+pub const EVENT_LOG_ADDR: u16 = 32;
+
+/// The conventional address of the hourly archive
+pub const HOURLY_ARCHIVE_ADDR: u16 = 700;
+
+/// The conventional address of the daily archive
+pub const DAILY_ARCHIVE_ADDR: u16 = 701;
+
+/// The maximum number of 32-bit values in a response
+pub const MAX_VALUES: usize = (MAX_PDU_SIZE - 2) / 4;
+
+/// The maximum number of events in a response to an event log read
+pub const MAX_EVENTS: usize = (MAX_PDU_SIZE - 2) / EnronEvent::SIZE;
+
+/// The kind of registers at an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RegisterKind {
+    /// Standard 16-bit registers
+    #[default]
+    Short,
+    /// 32-bit integers
+    Long,
+    /// 32-bit IEEE 754 floats
+    Float,
+}
+
+impl RegisterKind {
+    /// The number of bytes of a single value
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Short => 2,
+            Self::Long | Self::Float => 4,
+        }
+    }
+
+    pub const fn is_32bit(self) -> bool {
+        !matches!(self, Self::Short)
+    }
+}
+
+/// An inclusive range of addresses holding registers of one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnronRange {
+    pub start: u16,
+    pub end: u16,
+    pub kind: RegisterKind,
+}
+
+impl EnronRange {
+    pub const fn new(start: u16, end: u16, kind: RegisterKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    pub const fn contains(self, addr: u16) -> bool {
+        self.start <= addr && addr <= self.end
+    }
+
+    /// The number of addresses in this range
+    pub const fn len(self) -> usize {
+        (self.end as usize + 1).saturating_sub(self.start as usize)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.end < self.start
+    }
+}
+
+/// Describes which addresses of a device hold 32-bit values and where its event log and archives are.
+///
+/// Addresses outside of all ranges hold standard 16-bit registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnronMap<'a> {
+    pub ranges: &'a [EnronRange],
+    /// The address of the event log, None if the device has none
+    pub event_log: Option<u16>,
+    /// The addresses of the archives
+    pub archives: &'a [u16],
+}
+
+impl EnronMap<'static> {
+    /// The conventional layout: longs at 5001-5999, floats at 7001-7999, the event log at 32 and the hourly and
+    /// daily archives at 700 and 701
+    pub const STANDARD: Self = Self {
+        ranges: &[
+            EnronRange::new(5001, 5999, RegisterKind::Long),
+            EnronRange::new(7001, 7999, RegisterKind::Float),
+        ],
+        event_log: Some(EVENT_LOG_ADDR),
+        archives: &[HOURLY_ARCHIVE_ADDR, DAILY_ARCHIVE_ADDR],
+    };
+}
+
+impl<'a> EnronMap<'a> {
+    /// Get the 32-bit range containing addr
+    pub fn range(self, addr: u16) -> Option<EnronRange> {
+        self.ranges.iter().copied().find(|range| range.contains(addr))
+    }
+
+    /// Get the kind of registers at addr
+    pub fn kind(self, addr: u16) -> RegisterKind {
+        self.range(addr).map(|range| range.kind).unwrap_or_default()
+    }
+
+    pub fn is_archive(self, addr: u16) -> bool {
+        self.archives.contains(&addr)
+    }
+}
+
+/// A request to an Enron Modbus device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnronRequest<'a> {
+    /// Read quantity 32-bit values with read holding or read input registers
+    ReadValues {
+        function: PublicModbusFunction,
+        addr: u16,
+        quantity: u16,
+        kind: RegisterKind,
+    },
+    /// Write a single 32-bit value with write single register
+    WriteValue { addr: u16, value: u32 },
+    /// Write 32-bit values with write multiple registers
+    WriteValues { addr: u16, values: EnronValues<'a> },
+    /// Read the pending events of the event log
+    ReadEventLog { addr: u16 },
+    /// Acknowledge the events read last, the device drops them
+    AcknowledgeEventLog { addr: u16 },
+    /// Read the record with the given index of the archive at addr
+    ReadArchive { addr: u16, index: u16 },
+    /// Any request not touching 32-bit values, the event log or archives
+    Standard(Request<'a>),
+}
+
+impl<'a> EnronRequest<'a> {
+    /// Parse a request PDU, including the function code, with the layout of the given map.
+    ///
+    /// # Errors
+    /// The error tells the function and the offset of the offending field like [Request::from_data]. Reads and
+    /// writes of 32-bit values must stay within one range of the map, otherwise a
+    /// [Overflow](ValidationErrorKind::Overflow) is returned. Reading 0 or more than [MAX_VALUES] values returns
+    /// [ZeroQuantity](ValidationErrorKind::ZeroQuantity) or [TooLarge](ValidationErrorKind::TooLarge). Writes whose
+    /// byte count doesn't match the quantity are [Ambivalent](DecodeErrorKind::Ambivalent). For standard requests
+    /// see [Request::from_data].
+    pub fn from_data(data: &'a [u8], map: EnronMap<'_>) -> Result<(Self, &'a [u8]), ModbusError> {
+        Self::from_data_with_quirks(data, map, Quirks::STRICT)
+    }
+
+    /// Parse a request PDU accepting the deviations enabled in quirks, see [from_data](Self::from_data) and
+    /// [Request::from_data_with_quirks].
+    pub fn from_data_with_quirks(
+        data: &'a [u8],
+        map: EnronMap<'_>,
+        quirks: Quirks,
+    ) -> Result<(Self, &'a [u8]), ModbusError> {
+        let standard = || Request::from_data_with_quirks(data, quirks).map(|(req, tail)| (Self::Standard(req), tail));
+        let mut pdu = PduReader::new(data)?;
+        let function = PublicModbusFunction::from(pdu.function());
+        let addr = match pdu.u16() {
+            Ok(addr) => addr,
+            Err(_) => return standard(),
+        };
+
+        let req = match function {
+            PublicModbusFunction::ReadHoldingRegisters if map.event_log == Some(addr) => {
+                pdu.u16()?;
+                Self::ReadEventLog { addr }
+            }
+            PublicModbusFunction::ReadHoldingRegisters if map.is_archive(addr) => {
+                Self::ReadArchive { addr, index: pdu.u16()? }
+            }
+            PublicModbusFunction::WriteSingleCoil if map.event_log == Some(addr) => {
+                pdu.u16()?;
+                Self::AcknowledgeEventLog { addr }
+            }
+            PublicModbusFunction::ReadHoldingRegisters | PublicModbusFunction::ReadInputRegisters => {
+                let range = match map.range(addr) {
+                    Some(range) if range.kind.is_32bit() => range,
+                    _ => return standard(),
+                };
+                let quantity = pdu.u16()?;
+                check_values(&pdu, range, addr, quantity)?;
+                Self::ReadValues { function, addr, quantity, kind: range.kind }
+            }
+            PublicModbusFunction::WriteSingleRegister if map.kind(addr).is_32bit() => {
+                Self::WriteValue { addr, value: pdu.u32()? }
+            }
+            PublicModbusFunction::WriteMultipleRegisters => {
+                let range = match map.range(addr) {
+                    Some(range) if range.kind.is_32bit() => range,
+                    _ => return standard(),
+                };
+                let quantity = pdu.u16()?;
+                let count_offset = pdu.offset();
+                let count = pdu.u8()?;
+                check_values(&pdu, range, addr, quantity)?;
+
+                let size = quantity as usize * 4;
+                if count as usize != size && !quirks.byte_count_mismatch {
+                    return Err(pdu.decode_error(count_offset, DecodeErrorKind::Ambivalent).into());
+                }
+                Self::WriteValues { addr, values: EnronValues { bytes: pdu.bytes(size)? } }
+            }
+            _ => return standard(),
+        };
+        Ok((req, pdu.tail()))
+    }
+
+    /// Write this request to the slice as modbus data.
+    ///
+    /// On success the number of written bytes is returned.
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+        let mut header = [0; 6];
+        let header: &[u8] = match self {
+            Self::ReadValues { function, addr, quantity, .. } => write_header(&mut header, function, addr, quantity),
+            Self::ReadEventLog { addr } => {
+                write_header(&mut header, PublicModbusFunction::ReadHoldingRegisters, addr, 1)
+            }
+            Self::ReadArchive { addr, index } => {
+                write_header(&mut header, PublicModbusFunction::ReadHoldingRegisters, addr, index)
+            }
+            Self::AcknowledgeEventLog { addr } => {
+                write_header(&mut header, PublicModbusFunction::WriteSingleCoil, addr, 0xFF00)
+            }
+            Self::WriteValue { addr, value } => {
+                let value = value.to_be_bytes();
+                let header = write_header(&mut header, PublicModbusFunction::WriteSingleRegister, addr, 0);
+                return write_parts(out, &header[..3], &value);
+            }
+            Self::WriteValues { addr, values } => {
+                let quantity = values.len() as u16;
+                write_header(&mut header, PublicModbusFunction::WriteMultipleRegisters, addr, quantity);
+                header[5] = values.bytes().len() as u8;
+                return write_parts(out, &header, values.bytes());
+            }
+            Self::Standard(req) => return req.write_to_slice(out),
+        };
+        write_parts(out, header, &[])
+    }
+}
+
+/// Check the quantity of a read or write of values, the address is at offset 1 and the quantity at offset 3
+fn check_values(pdu: &PduReader<'_>, range: EnronRange, addr: u16, quantity: u16) -> Result<(), ValidationError> {
+    let (offset, kind) = match quantity as usize {
+        0 => (3, ValidationErrorKind::ZeroQuantity),
+        n if n > MAX_VALUES => (3, ValidationErrorKind::TooLarge),
+        n if addr as usize + n - 1 > range.end as usize => (1, ValidationErrorKind::Overflow),
+        _ => return Ok(()),
+    };
+    Err(ValidationError { function: Some(pdu.function()), offset, kind })
+}
+
+fn read_u32(data: &[u8]) -> Result<(u32, &[u8]), ModbusSerializationError> {
+    match data.get(..4) {
+        Some(bytes) => Ok((u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]), &data[4..])),
+        None => Err(ModbusSerializationError::UnexpectedEOF { expected: 4, got: data.len() }),
+    }
+}
+
+fn write_header(header: &mut [u8; 6], function: PublicModbusFunction, addr: u16, value: u16) -> &[u8] {
+    header[0] = function as u8;
+    header[1..3].copy_from_slice(&addr.to_be_bytes());
+    header[3..5].copy_from_slice(&value.to_be_bytes());
+    &header[..5]
+}
+
+fn write_parts(out: &mut [u8], header: &[u8], data: &[u8]) -> Result<usize, ModbusSerializationError> {
+    let size = header.len() + data.len();
+    if out.len() < size {
+        return Err(ModbusSerializationError::InsufficientBuffer { expected: size, got: out.len() });
+    }
+
+    out[..header.len()].copy_from_slice(header);
+    out[header.len()..size].copy_from_slice(data);
+    Ok(size)
+}
+
+/// A slice of 32-bit values, e.g. the values of a read response or an archive record
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct EnronValues<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> EnronValues<'a> {
+    /// # Errors
+    /// If the len of bytes is not a multiple of 4 [ModbusSerializationError::Invalid] is returned.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, ModbusSerializationError> {
+        if bytes.len().is_multiple_of(4) {
+            Ok(Self { bytes })
+        } else {
+            Err(ModbusSerializationError::Invalid)
+        }
+    }
+
+    /// Parse the values of a read response or an archive record.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusSerializationError> {
+        let (nbytes, data) = match data.split_first() {
+            Some((nbytes, data)) => (*nbytes as usize, data),
+            None => return Err(ModbusSerializationError::UnexpectedEOF { expected: 1, got: 0 }),
+        };
+
+        match data.get(..nbytes) {
+            Some(bytes) => Ok((Self::new(bytes)?, &data[nbytes..])),
+            None => Err(ModbusSerializationError::UnexpectedEOF { expected: nbytes + 1, got: data.len() + 1 }),
+        }
+    }
+
+    pub fn get(self, idx: usize) -> Option<u32> {
+        self.bytes
+            .get(idx * 4..idx * 4 + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Get the value at idx as float
+    pub fn get_f32(self, idx: usize) -> Option<f32> {
+        self.get(idx).map(f32::from_bits)
+    }
+
+    pub fn iter(self) -> impl ExactSizeIterator<Item = u32> + 'a {
+        self.bytes
+            .chunks_exact(4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn len(self) -> usize {
+        self.bytes.len() / 4
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Write a response containing the given values to the slice.
+    ///
+    /// On success the number of written bytes is returned.
+    ///
+    /// # Errors
+    /// If more than [MAX_VALUES] values are given [ModbusSerializationError::TooLarge] is returned.
+    /// If out is too small to hold the response [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn write_values(
+        function: PublicModbusFunction,
+        values: impl ExactSizeIterator<Item = u32>,
+        out: &mut [u8],
+    ) -> Result<usize, ModbusSerializationError> {
+        if values.len() > MAX_VALUES {
+            return Err(ModbusSerializationError::TooLarge);
+        }
+
+        let size = values.len() * 4 + 2;
+        if out.len() < size {
+            return Err(ModbusSerializationError::InsufficientBuffer { expected: size, got: out.len() });
+        }
+
+        out[0] = function as u8;
+        out[1] = (size - 2) as u8;
+        for (idx, value) in values.enumerate() {
+            out[2 + idx * 4..6 + idx * 4].copy_from_slice(&value.to_be_bytes());
+        }
+        Ok(size)
+    }
+}
+
+/// An entry of the event log, e.g. a changed configuration value or an alarm
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EnronEvent {
+    /// Device specific flags describing the event
+    pub status: u16,
+    /// The address of the changed value
+    pub addr: u16,
+    /// The time of the event as HHMMSS
+    pub time: f32,
+    /// The date of the event as MMDDYY
+    pub date: f32,
+    /// The raw 32-bit value before the event, the bits of a float for float addresses
+    pub old_value: u32,
+    /// The raw 32-bit value after the event
+    pub new_value: u32,
+}
+
+impl EnronEvent {
+    /// The size of an encoded event
+    pub const SIZE: usize = 20;
+
+    /// Parse an event from the given data
+    pub fn from_data(data: &[u8]) -> Result<(Self, &[u8]), ModbusSerializationError> {
+        if data.len() < Self::SIZE {
+            return Err(ModbusSerializationError::UnexpectedEOF { expected: Self::SIZE, got: data.len() });
+        }
+
+        let (status, data) = util::read_u16(data)?;
+        let (addr, data) = util::read_u16(data)?;
+        let (time, data) = read_u32(data)?;
+        let (date, data) = read_u32(data)?;
+        let (old_value, data) = read_u32(data)?;
+        let (new_value, data) = read_u32(data)?;
+        let event = Self {
+            status,
+            addr,
+            time: f32::from_bits(time),
+            date: f32::from_bits(date),
+            old_value,
+            new_value,
+        };
+        Ok((event, data))
+    }
+
+    /// Create modbus data of the correct size from this event
+    pub fn into_data(self) -> [u8; Self::SIZE] {
+        let mut data = [0; Self::SIZE];
+        data[0..2].copy_from_slice(&self.status.to_be_bytes());
+        data[2..4].copy_from_slice(&self.addr.to_be_bytes());
+        data[4..8].copy_from_slice(&self.time.to_bits().to_be_bytes());
+        data[8..12].copy_from_slice(&self.date.to_bits().to_be_bytes());
+        data[12..16].copy_from_slice(&self.old_value.to_be_bytes());
+        data[16..20].copy_from_slice(&self.new_value.to_be_bytes());
+        data
+    }
+
+    /// Iterate over the events of an event log response.
+    ///
+    /// The data should not contain the function code and the byte count, see [EnronValues::from_data] to split
+    /// them off. Incomplete trailing events are ignored.
+    pub fn iter(data: &[u8]) -> impl Iterator<Item = Self> + '_ {
+        data.chunks_exact(Self::SIZE).filter_map(|data| Self::from_data(data).ok().map(|(event, _)| event))
+    }
+
+    /// Write an event log response containing the given events to the slice.
+    ///
+    /// On success the number of written bytes is returned.
+    ///
+    /// # Errors
+    /// If more than [MAX_EVENTS] events are given [ModbusSerializationError::TooLarge] is returned.
+    /// If out is too small to hold the response [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn write_events(
+        events: impl ExactSizeIterator<Item = Self>,
+        out: &mut [u8],
+    ) -> Result<usize, ModbusSerializationError> {
+        if events.len() > MAX_EVENTS {
+            return Err(ModbusSerializationError::TooLarge);
+        }
+
+        let size = events.len() * Self::SIZE + 2;
+        if out.len() < size {
+            return Err(ModbusSerializationError::InsufficientBuffer { expected: size, got: out.len() });
+        }
+
+        out[0] = PublicModbusFunction::ReadHoldingRegisters as u8;
+        out[1] = (size - 2) as u8;
+        for (idx, event) in events.enumerate() {
+            out[2 + idx * Self::SIZE..2 + (idx + 1) * Self::SIZE].copy_from_slice(&event.into_data());
+        }
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DecodeError, ModbusFunction};
+
+    const MAP: EnronMap<'static> = EnronMap::STANDARD;
+
+    #[test]
+    fn map() {
+        assert_eq!(MAP.kind(5001), RegisterKind::Long);
+        assert_eq!(MAP.kind(7999), RegisterKind::Float);
+        assert_eq!(MAP.kind(8000), RegisterKind::Short);
+        assert_eq!(MAP.kind(3001).size(), 2);
+        assert_eq!(MAP.range(7002).map(EnronRange::len), Some(999));
+        assert!(MAP.is_archive(HOURLY_ARCHIVE_ADDR));
+    }
+
+    #[test]
+    fn read_values() {
+        let (req, tail) = EnronRequest::from_data(&[3, 0x1B, 0x59, 0, 2, 42], MAP).unwrap();
+        assert_eq!(
+            req,
+            EnronRequest::ReadValues {
+                function: PublicModbusFunction::ReadHoldingRegisters,
+                addr: 7001,
+                quantity: 2,
+                kind: RegisterKind::Float,
+            }
+        );
+        assert_eq!(tail, &[42]);
+
+        // 16-bit registers are standard requests
+        let (req, _tail) = EnronRequest::from_data(&[3, 0x0B, 0xB9, 0, 2], MAP).unwrap();
+        assert!(matches!(req, EnronRequest::Standard(Request::ReadHoldingRegisters(_))));
+
+        // Reads must stay within the range
+        assert_eq!(
+            EnronRequest::from_data(&[4, 0x1F, 0x3F, 0, 2], MAP),
+            Err(ModbusError::Validation(ValidationError {
+                function: Some(ModbusFunction::new(4)),
+                offset: 1,
+                kind: ValidationErrorKind::Overflow,
+            }))
+        );
+        assert_eq!(
+            EnronRequest::from_data(&[3, 0x1B, 0x59, 0, 63], MAP),
+            Err(ModbusError::Validation(ValidationError {
+                function: Some(ModbusFunction::new(3)),
+                offset: 3,
+                kind: ValidationErrorKind::TooLarge,
+            }))
+        );
+    }
+
+    #[test]
+    fn write_values() {
+        let (req, _tail) = EnronRequest::from_data(&[6, 0x13, 0x89, 0, 1, 0xE2, 0x40], MAP).unwrap();
+        assert_eq!(req, EnronRequest::WriteValue { addr: 5001, value: 123456 });
+
+        let data = [16, 0x1B, 0x59, 0, 2, 8, 0x41, 0x20, 0, 0, 0x3F, 0x80, 0, 0];
+        let (req, _tail) = EnronRequest::from_data(&data, MAP).unwrap();
+        match req {
+            EnronRequest::WriteValues { addr, values } => {
+                assert_eq!(addr, 7001);
+                assert_eq!(values.get_f32(0), Some(10.0));
+                assert_eq!(values.get_f32(1), Some(1.0));
+                assert_eq!(values.get(2), None);
+            }
+            _ => panic!("wrong request {:?}", req),
+        }
+
+        let data = [16, 0x1B, 0x59, 0, 2, 4, 0x41, 0x20, 0, 0, 0x3F, 0x80, 0, 0];
+        assert_eq!(
+            EnronRequest::from_data(&data, MAP),
+            Err(ModbusError::Decode(DecodeError {
+                function: Some(ModbusFunction::new(16)),
+                offset: 5,
+                kind: DecodeErrorKind::Ambivalent,
+            }))
+        );
+        assert_eq!(
+            EnronRequest::from_data(&[16, 0x1B, 0x59, 0, 2, 8, 0x41, 0x20], MAP),
+            Err(ModbusError::Decode(DecodeError {
+                function: Some(ModbusFunction::new(16)),
+                offset: 8,
+                kind: DecodeErrorKind::UnexpectedEof,
+            }))
+        );
+        let quirks = Quirks { byte_count_mismatch: true, ..Quirks::STRICT };
+        assert!(EnronRequest::from_data_with_quirks(&data, MAP, quirks).is_ok());
+    }
+
+    #[test]
+    fn event_log_and_archives() {
+        let (req, _tail) = EnronRequest::from_data(&[3, 0, 32, 0, 1], MAP).unwrap();
+        assert_eq!(req, EnronRequest::ReadEventLog { addr: 32 });
+
+        let (req, _tail) = EnronRequest::from_data(&[5, 0, 32, 0xFF, 0], MAP).unwrap();
+        assert_eq!(req, EnronRequest::AcknowledgeEventLog { addr: 32 });
+
+        let (req, _tail) = EnronRequest::from_data(&[3, 0x02, 0xBD, 0, 5], MAP).unwrap();
+        assert_eq!(req, EnronRequest::ReadArchive { addr: 701, index: 5 });
+
+        // The quantity is an index so index 0 is valid
+        let (req, _tail) = EnronRequest::from_data(&[3, 0x02, 0xBC, 0, 0], MAP).unwrap();
+        assert_eq!(req, EnronRequest::ReadArchive { addr: 700, index: 0 });
+    }
+
+    #[test]
+    fn write_to_slice_roundtrip() {
+        let requests: [&[u8]; 7] = [
+            &[3, 0x1B, 0x59, 0, 2],
+            &[6, 0x13, 0x89, 0, 1, 0xE2, 0x40],
+            &[16, 0x1B, 0x59, 0, 1, 4, 0x41, 0x20, 0, 0],
+            &[3, 0, 32, 0, 1],
+            &[5, 0, 32, 0xFF, 0],
+            &[3, 0x02, 0xBD, 0, 5],
+            &[6, 0, 1, 0, 10],
+        ];
+
+        for data in requests {
+            let (req, _tail) = EnronRequest::from_data(data, MAP).unwrap();
+            let mut out = [0; 16];
+            let size = req.write_to_slice(&mut out).unwrap();
+            assert_eq!(&out[..size], data);
+        }
+    }
+
+    #[test]
+    fn values_response() {
+        let mut out = [0; 16];
+        let size = EnronValues::write_values(
+            PublicModbusFunction::ReadHoldingRegisters,
+            [10.0f32.to_bits(), 123456].into_iter(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..size], &[3, 8, 0x41, 0x20, 0, 0, 0, 1, 0xE2, 0x40]);
+
+        let (values, tail) = EnronValues::from_data(&out[1..size]).unwrap();
+        assert!(tail.is_empty());
+        assert!(values.iter().eq([10.0f32.to_bits(), 123456]));
+        assert_eq!(EnronValues::from_data(&[3, 0, 0, 0]), Err(ModbusSerializationError::Invalid));
+    }
+
+    #[test]
+    fn events() {
+        let event = EnronEvent {
+            status: 1,
+            addr: 7001,
+            time: 120000.0,
+            date: 101826.0,
+            old_value: 1,
+            new_value: 2,
+        };
+        let mut out = [0; 64];
+        let size = EnronEvent::write_events([event, event].into_iter(), &mut out).unwrap();
+        assert_eq!(size, 42);
+        assert_eq!(&out[..6], &[3, 40, 0, 1, 0x1B, 0x59]);
+
+        let (values, _tail) = EnronValues::from_data(&out[1..size]).unwrap();
+        let mut events = EnronEvent::iter(values.bytes());
+        assert_eq!(events.next(), Some(event));
+        assert_eq!(events.next(), Some(event));
+        assert_eq!(events.next(), None);
+    }
+}
@@ -1,3 +1,14 @@
+//! The errors of parsing and encoding modbus data.
+//!
+//! The parsers and encoders of the single structures return a [ModbusSerializationError] describing what went
+//! wrong. The PDU level functions like [Request::from_data](crate::Request::from_data) return a [ModbusError] which
+//! separates decode, encode and validation errors and exception responses, and carries the function and the byte
+//! offset within the PDU the error occurred at.
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::{ExceptionCode, ExceptionResponse, ModbusFunction};
+
 /// An error type describing what can happen when parsing Modbus data from bytes 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ModbusSerializationError {
@@ -35,3 +46,318 @@ pub enum ModbusSerializationError {
     /// would write over the 0xFFFF adddress boundary (e.g. giving addr=0xFFFE but 50 registers to write)
     Overflow,
 }
+
+impl Display for ModbusSerializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEOF { expected, got } => write!(f, "expected {} bytes but got {}", expected, got),
+            Self::InsufficientBuffer { expected, got } => {
+                write!(f, "the buffer holds {} bytes but {} are needed", got, expected)
+            }
+            Self::Invalid => f.write_str("invalid value"),
+            Self::TooLarge => f.write_str("value exceeds the modbus limits"),
+            Self::Ambivalent => f.write_str("contradicting values"),
+            Self::Overflow => f.write_str("address range exceeds 0xFFFF"),
+        }
+    }
+}
+
+impl ModbusSerializationError {
+    /// Add the context of a failed decoding, see [ModbusError::decode]
+    pub const fn in_decode(self, function: Option<ModbusFunction>, offset: usize) -> ModbusError {
+        ModbusError::decode(self, function, offset)
+    }
+}
+
+impl From<ValidationError> for ModbusSerializationError {
+    fn from(e: ValidationError) -> Self {
+        match e.kind {
+            ValidationErrorKind::ZeroQuantity => Self::Invalid,
+            ValidationErrorKind::TooLarge => Self::TooLarge,
+            ValidationErrorKind::Overflow => Self::Overflow,
+        }
+    }
+}
+
+/// Why data could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DecodeErrorKind {
+    /// The data ended at the offset of the error
+    UnexpectedEof,
+    /// The bytes at the offset are not a valid value, e.g. a coil value other than 0xFF00 or 0x0000
+    Invalid,
+    /// The value at the offset contradicts another one, e.g. a byte count which doesn't match the quantity
+    Ambivalent,
+}
+
+/// Received data which could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DecodeError {
+    /// The function of the PDU, None if it could not be read
+    pub function: Option<ModbusFunction>,
+    /// The offset of the offending byte within the PDU, the function code is at offset 0
+    pub offset: usize,
+    pub kind: DecodeErrorKind,
+}
+
+/// Why a PDU could not be encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EncodeErrorKind {
+    /// The output buffer ended at the offset of the error but `expected` bytes are needed
+    InsufficientBuffer { expected: usize },
+    /// The value at the offset exceeds the modbus limits
+    TooLarge,
+}
+
+/// A PDU which could not be encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EncodeError {
+    pub function: Option<ModbusFunction>,
+    /// The offset within the PDU at which encoding failed
+    pub offset: usize,
+    pub kind: EncodeErrorKind,
+}
+
+/// Why a well formed request violates the rules of its function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ValidationErrorKind {
+    /// The quantity at the offset is 0
+    ZeroQuantity,
+    /// The quantity at the offset exceeds the maximum of the function
+    TooLarge,
+    /// The addressed range starting at the offset exceeds address 0xFFFF
+    Overflow,
+}
+
+/// A well formed request violating the rules of its function, e.g. a read of too many registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValidationError {
+    pub function: Option<ModbusFunction>,
+    /// The offset of the offending field within the PDU, the function code is at offset 0
+    pub offset: usize,
+    pub kind: ValidationErrorKind,
+}
+
+/// Any error of a modbus transaction with the function and position it occurred at.
+///
+/// In contrast to [ModbusSerializationError], which is returned by the parsers of the single structures, this error
+/// tells which part of a PDU was wrong and is returned by the PDU level functions like
+/// [Request::from_data](crate::Request::from_data).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ModbusError {
+    Decode(DecodeError),
+    Encode(EncodeError),
+    Validation(ValidationError),
+    /// The device answered with an exception response
+    Exception(ExceptionResponse),
+}
+
+impl ModbusError {
+    /// Classify an error of decoding the PDU part starting at offset.
+    ///
+    /// Quantity and address errors are [Validation](Self::Validation) errors, errors of the output buffer
+    /// [Encode](Self::Encode) errors and all others [Decode](Self::Decode) errors. An
+    /// [UnexpectedEOF](ModbusSerializationError::UnexpectedEOF) is located where the data ended.
+    pub const fn decode(e: ModbusSerializationError, function: Option<ModbusFunction>, offset: usize) -> Self {
+        let (offset, kind) = match e {
+            ModbusSerializationError::UnexpectedEOF { got, .. } => (offset + got, DecodeErrorKind::UnexpectedEof),
+            ModbusSerializationError::Invalid => (offset, DecodeErrorKind::Invalid),
+            ModbusSerializationError::Ambivalent => (offset, DecodeErrorKind::Ambivalent),
+            ModbusSerializationError::InsufficientBuffer { expected, got } => {
+                return Self::encode(ModbusSerializationError::InsufficientBuffer { expected, got }, function, offset);
+            }
+            ModbusSerializationError::TooLarge => {
+                return Self::Validation(ValidationError { function, offset, kind: ValidationErrorKind::TooLarge })
+            }
+            ModbusSerializationError::Overflow => {
+                return Self::Validation(ValidationError { function, offset, kind: ValidationErrorKind::Overflow })
+            }
+        };
+        Self::Decode(DecodeError { function, offset, kind })
+    }
+
+    /// Classify an error of encoding the PDU part starting at offset into a buffer.
+    ///
+    /// A too small buffer is located at its end, all other errors at offset.
+    pub const fn encode(e: ModbusSerializationError, function: Option<ModbusFunction>, offset: usize) -> Self {
+        let (offset, kind) = match e {
+            ModbusSerializationError::InsufficientBuffer { expected, got } => {
+                (offset + got, EncodeErrorKind::InsufficientBuffer { expected: offset + expected })
+            }
+            _ => (offset, EncodeErrorKind::TooLarge),
+        };
+        Self::Encode(EncodeError { function, offset, kind })
+    }
+
+    /// The function of the PDU the error occurred in
+    pub const fn function(self) -> Option<ModbusFunction> {
+        match self {
+            Self::Decode(e) => e.function,
+            Self::Encode(e) => e.function,
+            Self::Validation(e) => e.function,
+            Self::Exception(e) => Some(e.function),
+        }
+    }
+
+    /// The offset within the PDU the error occurred at, exceptions are located at the exception code
+    pub const fn offset(self) -> usize {
+        match self {
+            Self::Decode(e) => e.offset,
+            Self::Encode(e) => e.offset,
+            Self::Validation(e) => e.offset,
+            Self::Exception(_) => 1,
+        }
+    }
+}
+
+impl From<DecodeError> for ModbusError {
+    fn from(e: DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<EncodeError> for ModbusError {
+    fn from(e: EncodeError) -> Self {
+        Self::Encode(e)
+    }
+}
+
+impl From<ValidationError> for ModbusError {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+impl From<ExceptionResponse> for ModbusError {
+    fn from(e: ExceptionResponse) -> Self {
+        Self::Exception(e)
+    }
+}
+
+impl From<ModbusError> for ExceptionCode {
+    /// Get the exception a server answers a request with that failed with the error.
+    ///
+    /// Ranges exceeding address 0xFFFF are answered with [ExceptionCode::IllegalDataAddress], encode errors with
+    /// [ExceptionCode::ServerDeviceFailure] and all other malformed requests with
+    /// [ExceptionCode::IllegalDataValue].
+    fn from(e: ModbusError) -> Self {
+        match e {
+            ModbusError::Validation(ValidationError { kind: ValidationErrorKind::Overflow, .. }) => {
+                Self::IllegalDataAddress
+            }
+            ModbusError::Decode(_) | ModbusError::Validation(_) => Self::IllegalDataValue,
+            ModbusError::Encode(_) => Self::ServerDeviceFailure,
+            ModbusError::Exception(e) => e.code,
+        }
+    }
+}
+
+/// Writes " of function <code>" if the function is known
+struct InFunction(Option<ModbusFunction>);
+
+impl Display for InFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(function) => write!(f, " of function {:#04x}", function.0),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            DecodeErrorKind::UnexpectedEof => "unexpected end",
+            DecodeErrorKind::Invalid => "invalid value",
+            DecodeErrorKind::Ambivalent => "contradicting value",
+        };
+        write!(f, "{} at byte {}{}", what, self.offset, InFunction(self.function))
+    }
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EncodeErrorKind::InsufficientBuffer { expected } => write!(
+                f,
+                "buffer of {} bytes too small for {} bytes{}",
+                self.offset,
+                expected,
+                InFunction(self.function)
+            ),
+            EncodeErrorKind::TooLarge => write!(f, "value exceeds the modbus limits{}", InFunction(self.function)),
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            ValidationErrorKind::ZeroQuantity => "zero quantity",
+            ValidationErrorKind::TooLarge => "quantity too large",
+            ValidationErrorKind::Overflow => "address range exceeds 0xFFFF",
+        };
+        write!(f, "{} at byte {}{}", what, self.offset, InFunction(self.function))
+    }
+}
+
+impl Display for ModbusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => Display::fmt(e, f),
+            Self::Encode(e) => Display::fmt(e, f),
+            Self::Validation(e) => Display::fmt(e, f),
+            Self::Exception(e) => write!(f, "exception {:?}{}", e.code, InFunction(Some(e.function))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let function = Some(ModbusFunction::new(16));
+        assert_eq!(
+            ModbusSerializationError::UnexpectedEOF { expected: 4, got: 2 }.in_decode(function, 1),
+            ModbusError::Decode(DecodeError { function, offset: 3, kind: DecodeErrorKind::UnexpectedEof })
+        );
+        assert_eq!(
+            ModbusSerializationError::Overflow.in_decode(function, 1),
+            ModbusError::Validation(ValidationError { function, offset: 1, kind: ValidationErrorKind::Overflow })
+        );
+        assert_eq!(
+            ModbusError::encode(ModbusSerializationError::InsufficientBuffer { expected: 6, got: 2 }, function, 1),
+            ModbusError::Encode(EncodeError {
+                function,
+                offset: 3,
+                kind: EncodeErrorKind::InsufficientBuffer { expected: 7 },
+            })
+        );
+    }
+
+    #[test]
+    fn exception_code() {
+        let validation = |kind| ModbusError::Validation(ValidationError { function: None, offset: 3, kind });
+        assert_eq!(ExceptionCode::from(validation(ValidationErrorKind::Overflow)), ExceptionCode::IllegalDataAddress);
+        assert_eq!(ExceptionCode::from(validation(ValidationErrorKind::TooLarge)), ExceptionCode::IllegalDataValue);
+        assert_eq!(
+            ExceptionCode::from(ModbusError::Exception(ExceptionResponse::new(
+                ModbusFunction::new(3),
+                ExceptionCode::ServerDeviceBusy
+            ))),
+            ExceptionCode::ServerDeviceBusy
+        );
+    }
+
+    #[test]
+    fn display() {
+        let e = DecodeError { function: Some(ModbusFunction::new(5)), offset: 3, kind: DecodeErrorKind::Invalid };
+        assert_eq!(e.to_string(), "invalid value at byte 3 of function 0x05");
+    }
+}
@@ -0,0 +1,190 @@
+//! Modbus exception codes and exception responses.
+//!
+//! A server answers a request it can't process with an exception response. It consists out of the function code
+//! of the request with the highest bit set and an exception code.
+//! See <https://www.modbus.org/docs/Modbus_Application_Protocol_V1_1b3.pdf> page 48 for reference.
+
+use crate::{DecodeError, DecodeErrorKind, ModbusError, ModbusFunction, ModbusSerializationError};
+
+/// The publicly documented modbus exception codes
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExceptionCode {
+    /// The function code is not supported by the server
+    IllegalFunction = 0x01,
+    /// The requested address range is not available on the server
+    IllegalDataAddress = 0x02,
+    /// A value in the request is not allowed, e.g. an invalid quantity
+    IllegalDataValue = 0x03,
+    /// An unrecoverable error occurred while the server processed the request
+    ServerDeviceFailure = 0x04,
+    /// The request was accepted but takes long to process
+    Acknowledge = 0x05,
+    /// The server is busy processing a long running request, the client should retry later
+    ServerDeviceBusy = 0x06,
+    /// The server detected a parity error in its memory while reading a file record
+    MemoryParityError = 0x08,
+    /// A gateway was unable to allocate a path to the target device
+    GatewayPathUnavailable = 0x0A,
+    /// The target device behind a gateway did not respond
+    GatewayTargetDeviceFailedToRespond = 0x0B,
+}
+
+impl TryFrom<u8> for ExceptionCode {
+    type Error = ModbusSerializationError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            0x01 => Ok(Self::IllegalFunction),
+            0x02 => Ok(Self::IllegalDataAddress),
+            0x03 => Ok(Self::IllegalDataValue),
+            0x04 => Ok(Self::ServerDeviceFailure),
+            0x05 => Ok(Self::Acknowledge),
+            0x06 => Ok(Self::ServerDeviceBusy),
+            0x08 => Ok(Self::MemoryParityError),
+            0x0A => Ok(Self::GatewayPathUnavailable),
+            0x0B => Ok(Self::GatewayTargetDeviceFailedToRespond),
+            _ => Err(ModbusSerializationError::Invalid),
+        }
+    }
+}
+
+impl From<ExceptionCode> for u8 {
+    fn from(code: ExceptionCode) -> u8 {
+        code as u8
+    }
+}
+
+impl From<ModbusSerializationError> for ExceptionCode {
+    /// Get the exception a server answers a request with that could not be parsed.
+    ///
+    /// Requests accessing addresses beyond 0xFFFF are answered with [ExceptionCode::IllegalDataAddress]
+    /// and all other malformed requests with [ExceptionCode::IllegalDataValue].
+    fn from(e: ModbusSerializationError) -> Self {
+        match e {
+            ModbusSerializationError::Overflow => Self::IllegalDataAddress,
+            _ => Self::IllegalDataValue,
+        }
+    }
+}
+
+/// An exception response to a request of the given function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExceptionResponse {
+    /// The function of the request, without the exception flag
+    pub function: ModbusFunction,
+    pub code: ExceptionCode,
+}
+
+impl ExceptionResponse {
+    pub const fn new(function: ModbusFunction, code: ExceptionCode) -> Self {
+        Self { function, code }
+    }
+
+    /// Create modbus data of the correct size from this response
+    ///
+    /// The format of the array will be [function | 0x80, code]
+    pub fn into_data(self) -> [u8; 2] {
+        [self.function.0 | 0x80, self.code as u8]
+    }
+
+    /// Write this response to the slice as modbus data
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+        if out.len() < 2 {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: 2,
+                got: out.len(),
+            });
+        }
+
+        out[..2].copy_from_slice(&self.into_data());
+        Ok(2)
+    }
+}
+
+/// Check a response PDU to a request of the given function and get the response data after the function code.
+///
+/// # Errors
+/// Exception responses are returned as [ModbusError::Exception]. Empty responses, responses of other functions and
+/// exception responses with unknown codes are returned as [ModbusError::Decode].
+pub fn check_response(function: ModbusFunction, response: &[u8]) -> Result<&[u8], ModbusError> {
+    let error = |offset, kind| ModbusError::Decode(DecodeError { function: Some(function), offset, kind });
+    match response {
+        [] => Err(error(0, DecodeErrorKind::UnexpectedEof)),
+        [code, data @ ..] if *code == function.0 => Ok(data),
+        [code, exception, ..] if *code == function.0 | 0x80 => match ExceptionCode::try_from(*exception) {
+            Ok(code) => Err(ModbusError::Exception(ExceptionResponse::new(function, code))),
+            Err(_) => Err(error(1, DecodeErrorKind::Invalid)),
+        },
+        [code] if *code == function.0 | 0x80 => Err(error(1, DecodeErrorKind::UnexpectedEof)),
+        _ => Err(error(0, DecodeErrorKind::Invalid)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        DecodeError, DecodeErrorKind, ModbusError, ModbusFunction, ModbusSerializationError, PublicModbusFunction,
+    };
+
+    use super::{check_response, ExceptionCode, ExceptionResponse};
+
+    #[test]
+    fn check() {
+        let function = ModbusFunction::new(3);
+        let error = |offset, kind| Err(ModbusError::Decode(DecodeError { function: Some(function), offset, kind }));
+
+        assert_eq!(check_response(function, &[3, 2, 0, 1]), Ok(&[2, 0, 1][..]));
+        assert_eq!(
+            check_response(function, &[0x83, 2]),
+            Err(ModbusError::Exception(ExceptionResponse::new(function, ExceptionCode::IllegalDataAddress)))
+        );
+        assert_eq!(check_response(function, &[0x83, 7]), error(1, DecodeErrorKind::Invalid));
+        assert_eq!(check_response(function, &[0x83]), error(1, DecodeErrorKind::UnexpectedEof));
+        assert_eq!(check_response(function, &[4, 2, 0, 1]), error(0, DecodeErrorKind::Invalid));
+        assert_eq!(check_response(function, &[]), error(0, DecodeErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn from_u8() {
+        assert_eq!(ExceptionCode::try_from(2), Ok(ExceptionCode::IllegalDataAddress));
+        assert_eq!(ExceptionCode::try_from(0x0B), Ok(ExceptionCode::GatewayTargetDeviceFailedToRespond));
+        assert_eq!(ExceptionCode::try_from(0), Err(ModbusSerializationError::Invalid));
+        assert_eq!(ExceptionCode::try_from(7), Err(ModbusSerializationError::Invalid));
+    }
+
+    #[test]
+    fn roundtrip_all() {
+        for code in 0..=u8::MAX {
+            if let Ok(exception) = ExceptionCode::try_from(code) {
+                assert_eq!(u8::from(exception), code);
+            }
+        }
+    }
+
+    #[test]
+    fn from_serialization_error() {
+        assert_eq!(ExceptionCode::from(ModbusSerializationError::Overflow), ExceptionCode::IllegalDataAddress);
+        assert_eq!(ExceptionCode::from(ModbusSerializationError::TooLarge), ExceptionCode::IllegalDataValue);
+        assert_eq!(
+            ExceptionCode::from(ModbusSerializationError::UnexpectedEOF { expected: 4, got: 2 }),
+            ExceptionCode::IllegalDataValue
+        );
+    }
+
+    #[test]
+    fn write_to_slice() {
+        let response = ExceptionResponse::new(
+            ModbusFunction::new_public(PublicModbusFunction::ReadHoldingRegisters),
+            ExceptionCode::IllegalDataAddress,
+        );
+        let mut out = [0; 3];
+
+        assert_eq!(response.write_to_slice(&mut out), Ok(2));
+        assert_eq!(out, [0x83, 0x02, 0]);
+        assert_eq!(
+            response.write_to_slice(&mut out[..1]),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 2, got: 1 })
+        );
+    }
+}
@@ -11,7 +11,7 @@ use core::{convert::From, mem};
 /// None is returned if data contains less than 1 byte
 pub fn get_function(data: &[u8]) -> (Option<ModbusFunction>, Option<&[u8]>) {
     (
-        data.get(0).map(|byte| ModbusFunction::new(*byte)),
+        data.first().map(|byte| ModbusFunction::new(*byte)),
         data.get(1..),
     )
 }
@@ -104,9 +104,9 @@ impl From<u8> for ModbusFunction {
     }
 }
 
-impl Into<u8> for ModbusFunction {
-    fn into(self) -> u8 {
-        self.0
+impl From<ModbusFunction> for u8 {
+    fn from(function: ModbusFunction) -> u8 {
+        function.0
     }
 }
 
@@ -203,9 +203,9 @@ impl From<u8> for PublicModbusFunction {
     }
 }
 
-impl Into<u8> for PublicModbusFunction {
-    fn into(self) -> u8 {
-        self as u8
+impl From<PublicModbusFunction> for u8 {
+    fn from(public_function: PublicModbusFunction) -> u8 {
+        public_function as u8
     }
 }
 
@@ -12,15 +12,36 @@
 
 pub mod functions;
 pub mod bitstate;
+pub mod bitslice;
 pub mod slaveid;
 pub mod write;
 pub mod read;
 pub mod util;
 pub mod registerslice;
+pub mod ascii;
+pub mod rtu;
+pub mod rtuslave;
+pub mod tcp;
+pub mod exception;
+pub mod request;
+pub mod response;
+pub mod readwrite;
+pub mod quirks;
+pub mod enron;
+pub mod custom;
+pub mod address;
 
 mod error;
+mod pdu;
 
 pub use functions::{ModbusFunction, PublicModbusFunction};
 pub use bitstate::BitState; 
 pub use slaveid::SlaveId;
 pub use error::*;
+pub use exception::{ExceptionCode, ExceptionResponse};
+pub use request::Request;
+pub use quirks::Quirks;
+pub use address::ModbusAddress;
+
+/// The maximum size of a modbus PDU (function code + data)
+pub const MAX_PDU_SIZE: usize = 253;
@@ -0,0 +1,87 @@
+//! Reading the fields of a PDU while keeping track of their offsets, so errors tell where they occurred.
+
+use crate::{DecodeError, DecodeErrorKind, ModbusFunction, ValidationError, ValidationErrorKind};
+
+/// A cursor over a PDU, the function code is at offset 0 and reading starts after it
+pub(crate) struct PduReader<'a> {
+    pdu: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PduReader<'a> {
+    /// Start reading the PDU after the function code.
+    ///
+    /// # Errors
+    /// An empty PDU is an [UnexpectedEof](DecodeErrorKind::UnexpectedEof) at offset 0.
+    pub fn new(pdu: &'a [u8]) -> Result<Self, DecodeError> {
+        if pdu.is_empty() {
+            return Err(DecodeError { function: None, offset: 0, kind: DecodeErrorKind::UnexpectedEof });
+        }
+        Ok(Self { pdu, offset: 1 })
+    }
+
+    pub fn function(&self) -> ModbusFunction {
+        ModbusFunction::new(self.pdu[0])
+    }
+
+    /// The offset of the next field
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The data after the function code
+    pub fn data(&self) -> &'a [u8] {
+        &self.pdu[1..]
+    }
+
+    /// The data which was not read yet
+    pub fn tail(&self) -> &'a [u8] {
+        &self.pdu[self.offset..]
+    }
+
+    /// Read the next n bytes, if there are less the error is located at the end of the PDU
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        match self.pdu.get(self.offset..self.offset + n) {
+            Some(bytes) => {
+                self.offset += n;
+                Ok(bytes)
+            }
+            None => Err(self.decode_error(self.pdu.len(), DecodeErrorKind::UnexpectedEof)),
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DecodeError> {
+        self.bytes(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.bytes(4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn decode_error(&self, offset: usize, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError { function: Some(self.function()), offset, kind }
+    }
+}
+
+/// Check the quantity of a range with the address at addr_offset and the quantity following it.
+///
+/// The range may end at address 0xFFFF at most.
+pub(crate) const fn check_range(
+    function: ModbusFunction,
+    addr_offset: usize,
+    addr: u16,
+    quantity: u16,
+    max: u16,
+) -> Result<(), ValidationError> {
+    let (offset, kind) = match quantity {
+        0 => (addr_offset + 2, ValidationErrorKind::ZeroQuantity),
+        n if n > max => (addr_offset + 2, ValidationErrorKind::TooLarge),
+        n if addr as u32 + n as u32 > 0x10000 => (addr_offset, ValidationErrorKind::Overflow),
+        _ => return Ok(()),
+    };
+    Err(ValidationError { function: Some(function), offset, kind })
+}
@@ -0,0 +1,48 @@
+//! Leniency towards devices which don't strictly follow the specification.
+//!
+//! All parsers are strict by default. Parsers and transports which have a `with_quirks` variant or a quirks
+//! setting additionally accept the deviations enabled in a [Quirks] value. Only enable the quirks your devices
+//! actually need, every enabled quirk makes it harder to detect corrupted data.
+
+/// The deviations from the specification to accept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Quirks {
+    /// Accept write multiple requests whose byte count disagrees with the quantity.
+    ///
+    /// The quantity is trusted and the byte count ignored, so as many bytes as the quantity needs have to follow.
+    /// Without this quirk such requests are rejected with an [Ambivalent](crate::DecodeErrorKind::Ambivalent)
+    /// decode error.
+    pub byte_count_mismatch: bool,
+    /// Accept RTU frames with the CRC transmitted high byte first
+    pub swapped_crc: bool,
+    /// Accept RTU frames followed by padding bytes, the frame ends at the last position with a valid CRC
+    pub trailing_bytes: bool,
+    /// Accept responses from another slave than the addressed one
+    pub any_unit_id: bool,
+    /// Accept Modbus TCP ADUs with a protocol id other than [MODBUS_PROTOCOL_ID](crate::tcp::MODBUS_PROTOCOL_ID)
+    pub any_protocol_id: bool,
+    /// Accept any coil value in write single coil requests, every value other than 0x0000 switches the coil on
+    pub any_coil_value: bool,
+}
+
+impl Quirks {
+    /// Strictly follow the specification, the default
+    pub const STRICT: Self = Self {
+        byte_count_mismatch: false,
+        swapped_crc: false,
+        trailing_bytes: false,
+        any_unit_id: false,
+        any_protocol_id: false,
+        any_coil_value: false,
+    };
+
+    /// Accept every known deviation
+    pub const LENIENT: Self = Self {
+        byte_count_mismatch: true,
+        swapped_crc: true,
+        trailing_bytes: true,
+        any_unit_id: true,
+        any_protocol_id: true,
+        any_coil_value: true,
+    };
+}
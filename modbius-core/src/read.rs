@@ -5,7 +5,7 @@ use crate::functions::PublicModbusFunction;
 // Also it is inacceptable to require all users to include a (definitely weirdly named) 
 // trait in order to work with these request structures.
 macro_rules! read_req {
-    ($name:ident, $fcode:expr, $entity:literal, $test:ident) => {
+    ($name:ident, $fcode:expr, $max:expr, $entity:literal, $test:ident) => {
         #[doc=concat!("The request structure to read ", $entity)]
         #[doc=concat!("\n")]
         /// used to parse and build modbus data to read quantity entities starting from addr.
@@ -18,12 +18,27 @@ macro_rules! read_req {
         impl $name {
             /// The Modbus function this read requests corresponds to.
             pub const MODBUS_FUNCTION_CODE: PublicModbusFunction = $fcode;
+            #[doc=concat!("The maximum number of ", $entity, " that may be read with one request")]
+            pub const MAX_QUANTITY: u16 = $max;
 
             #[doc=concat!("Create a new request to read quantity ", $entity)]
             pub const fn new(addr: u16, quantity: u16) -> Self {
                 Self { addr, quantity }
             }
 
+            /// Check if this request may be sent as is.
+            ///
+            /// # Errors
+            /// The [ValidationError](crate::ValidationError) is located within the PDU of the request. Reading 0
+            /// entities returns [ZeroQuantity](crate::ValidationErrorKind::ZeroQuantity),
+            #[doc=concat!("more than [MAX_QUANTITY](", stringify!($name), "::MAX_QUANTITY) ")]
+            /// [TooLarge](crate::ValidationErrorKind::TooLarge). [Overflow](crate::ValidationErrorKind::Overflow)
+            /// is returned if the read would go beyond address 0xFFFF.
+            pub fn validate(self) -> Result<(), $crate::ValidationError> {
+                let function = $crate::ModbusFunction::new_public(Self::MODBUS_FUNCTION_CODE);
+                $crate::pdu::check_range(function, 1, self.addr, self.quantity, Self::MAX_QUANTITY)
+            }
+
             /// Parse this request from the given modbus data
             ///
             /// The data should only consist out of the address and quantity as the slave id function
@@ -207,6 +222,26 @@ macro_rules! read_req {
                 assert!(tail.is_empty());
             }
 
+            #[test]
+            fn validate() {
+                assert_eq!($name::new(0, 1).validate(), Ok(()));
+                assert_eq!($name::new(0, $name::MAX_QUANTITY).validate(), Ok(()));
+                assert_eq!($name::new(0xFFFF, 1).validate(), Ok(()));
+                let error = |offset, kind| {
+                    Err($crate::ValidationError {
+                        function: Some($crate::ModbusFunction::new_public($name::MODBUS_FUNCTION_CODE)),
+                        offset,
+                        kind,
+                    })
+                };
+                assert_eq!($name::new(0, 0).validate(), error(3, $crate::ValidationErrorKind::ZeroQuantity));
+                assert_eq!(
+                    $name::new(0, $name::MAX_QUANTITY + 1).validate(),
+                    error(3, $crate::ValidationErrorKind::TooLarge)
+                );
+                assert_eq!($name::new(0xFFFF, 2).validate(), error(1, $crate::ValidationErrorKind::Overflow));
+            }
+
             #[test]
             fn from_data_fail0() {
                 let data = [255, 255];
@@ -398,22 +433,25 @@ macro_rules! read_req {
     };
 }
 
-read_req!(ReadCoils, PublicModbusFunction::ReadCoils, "Coils", coils);
+read_req!(ReadCoils, PublicModbusFunction::ReadCoils, 2000, "Coils", coils);
 read_req!(
     ReadDiscreteInputs,
     PublicModbusFunction::ReadDiscreteInputs,
+    2000,
     "DiscreteInputs",
     discrete_inputs
 );
 read_req!(
     ReadHoldingRegisters,
     PublicModbusFunction::ReadHoldingRegisters,
+    125,
     "HoldingRegisters",
     holding_registers
 );
 read_req!(
     ReadInputRegisters,
     PublicModbusFunction::ReadInputRegisters,
+    125,
     "InputRegisters",
     input_registers
 );
@@ -0,0 +1,175 @@
+//! The combined read/write multiple registers request.
+
+use crate::{read::ReadHoldingRegisters, registerslice::RegisterSlice, util, ModbusSerializationError, PublicModbusFunction};
+
+/// Request structure to write multiple holding registers and read multiple holding registers in one transaction.
+///
+/// The write is performed before the read. The response is the same as the response to a
+/// [ReadHoldingRegisters] request, just with a different function code.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ReadWriteMultipleRegisters<'a> {
+    read: ReadHoldingRegisters,
+    write_addr: u16,
+    registers: RegisterSlice<'a>,
+}
+
+impl<'a> ReadWriteMultipleRegisters<'a> {
+    pub const MODBUS_FUNCTION_CODE: PublicModbusFunction = PublicModbusFunction::ReadWriteMultipleRegisters;
+    /// The maximum number of registers that may be written with one request
+    pub const MAX_WRITE_QUANTITY: u16 = 0x79;
+    /// The minimum size required for a request like Self
+    ///
+    /// The function code is already read for input data so the minimum consists out of
+    /// read address, read quantity, write address, write quantity (2 byte each) + number of following bytes (1 byte)
+    /// + at least one register (2 byte)
+    pub const MIN_INPUT_SIZE: usize = 11;
+    /// The header size of a [ReadWriteMultipleRegisters] request.
+    ///
+    /// The header consists of the function code (1 byte), read address, read quantity, write address,
+    /// write quantity (2 byte each) and the number of following bytes (1 byte).
+    pub const HEADER_SIZE: usize = 10;
+
+    /// Create a new request writing registers at write_addr and then reading the registers described by read.
+    ///
+    /// # Errors
+    /// The read is checked with [ReadHoldingRegisters::validate]. Writing 0 registers returns
+    /// [ModbusSerializationError::Invalid], more than [MAX_WRITE_QUANTITY](ReadWriteMultipleRegisters::MAX_WRITE_QUANTITY)
+    /// [ModbusSerializationError::TooLarge]. [ModbusSerializationError::Overflow] is returned if the write would go
+    /// beyond address 0xFFFF.
+    pub fn new(
+        read: ReadHoldingRegisters,
+        write_addr: u16,
+        registers: RegisterSlice<'a>,
+    ) -> Result<Self, ModbusSerializationError> {
+        read.validate()?;
+        match registers.len() {
+            0 => Err(ModbusSerializationError::Invalid),
+            n if n > Self::MAX_WRITE_QUANTITY as usize => Err(ModbusSerializationError::TooLarge),
+            n if write_addr as usize + n > 0x10000 => Err(ModbusSerializationError::Overflow),
+            _ => Ok(Self {
+                read,
+                write_addr,
+                registers,
+            }),
+        }
+    }
+
+    /// The registers to read after the write
+    pub fn read(self) -> ReadHoldingRegisters {
+        self.read
+    }
+
+    pub fn write_addr(self) -> u16 {
+        self.write_addr
+    }
+
+    /// The values to write starting at [write_addr](ReadWriteMultipleRegisters::write_addr)
+    pub fn registers(self) -> RegisterSlice<'a> {
+        self.registers
+    }
+
+    /// Parse this request from the given modbus data.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusSerializationError> {
+        if data.len() < Self::MIN_INPUT_SIZE {
+            return Err(ModbusSerializationError::UnexpectedEOF {
+                expected: Self::MIN_INPUT_SIZE,
+                got: data.len(),
+            });
+        }
+
+        let (read, data) = unsafe { ReadHoldingRegisters::from_data_unchecked(data) };
+        let (write_addr, data) = unsafe { util::read_u16_unchecked(data) };
+        let (quantity, data) = unsafe { util::read_u16_unchecked(data) };
+        let nbytes = data[0] as usize;
+
+        if nbytes != quantity as usize * 2 {
+            return Err(ModbusSerializationError::Ambivalent);
+        }
+
+        match data.get(1..(nbytes + 1)) {
+            Some(bytes) => Ok((
+                Self::new(read, write_addr, RegisterSlice::new(bytes)?)?,
+                &data[(nbytes + 1)..],
+            )),
+            None => Err(ModbusSerializationError::UnexpectedEOF {
+                expected: nbytes + Self::HEADER_SIZE - 1,
+                got: Self::HEADER_SIZE - 1 + data.len() - 1,
+            }),
+        }
+    }
+
+    /// Get how many bytes this request needs to be encoded
+    pub fn data_size(self) -> usize {
+        self.registers.bytes_len() + Self::HEADER_SIZE
+    }
+
+    /// Write this request to the slice as modbus data
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<(), ModbusSerializationError> {
+        let data_size = self.data_size();
+        if out.len() < data_size {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: data_size,
+                got: out.len(),
+            });
+        }
+
+        out[0] = Self::MODBUS_FUNCTION_CODE as u8;
+        out[1..3].copy_from_slice(&self.read.addr.to_be_bytes());
+        out[3..5].copy_from_slice(&self.read.quantity.to_be_bytes());
+        out[5..7].copy_from_slice(&self.write_addr.to_be_bytes());
+        out[7..9].copy_from_slice(&(self.registers.len() as u16).to_be_bytes());
+        out[9] = self.registers.bytes_len() as u8;
+        out[Self::HEADER_SIZE..data_size].copy_from_slice(self.registers.bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_data_spec() {
+        // Example from the modbus spec
+        let data = [0, 3, 0, 6, 0, 0x0E, 0, 3, 6, 0, 0xFF, 0, 0xFF, 0, 0xFF, 42];
+        let (req, tail) = ReadWriteMultipleRegisters::from_data(&data).unwrap();
+
+        assert_eq!(req.read(), ReadHoldingRegisters::new(3, 6));
+        assert_eq!(req.write_addr(), 0x0E);
+        assert_eq!(req.registers().len(), 3);
+        assert_eq!(req.registers().get(2), Some(0xFF));
+        assert_eq!(tail, &[42]);
+
+        let mut out = [0; 16];
+        req.write_to_slice(&mut out).unwrap();
+        assert_eq!(req.data_size(), 16);
+        assert_eq!(out[0], 23);
+        assert_eq!(&out[1..], &data[..15]);
+    }
+
+    #[test]
+    fn from_data_fail() {
+        assert_eq!(
+            ReadWriteMultipleRegisters::from_data(&[0, 3, 0, 6, 0, 0x0E, 0, 1, 2, 0]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 11, got: 10 })
+        );
+        assert_eq!(
+            ReadWriteMultipleRegisters::from_data(&[0, 3, 0, 6, 0, 0x0E, 0, 1, 4, 0, 1]),
+            Err(ModbusSerializationError::Ambivalent)
+        );
+        assert_eq!(
+            ReadWriteMultipleRegisters::from_data(&[0, 3, 0, 6, 0, 0x0E, 0, 2, 4, 0, 1]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 13, got: 11 })
+        );
+        assert_eq!(
+            ReadWriteMultipleRegisters::from_data(&[0, 3, 0, 0, 0, 0x0E, 0, 1, 2, 0, 1]),
+            Err(ModbusSerializationError::Invalid)
+        );
+        assert_eq!(
+            ReadWriteMultipleRegisters::from_data(&[0, 3, 0, 1, 0xFF, 0xFF, 0, 2, 4, 0, 1, 0, 2]),
+            Err(ModbusSerializationError::Overflow)
+        );
+    }
+}
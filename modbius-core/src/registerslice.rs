@@ -1,4 +1,4 @@
-use crate::ModbusSerializationError;
+use crate::{DecodeError, DecodeErrorKind, ModbusSerializationError};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct RegisterSlice<'a> {
@@ -7,21 +7,26 @@ pub struct RegisterSlice<'a> {
 
 impl<'a> RegisterSlice<'a> {
     pub fn new(bytes: &'a [u8]) -> Result<Self, ModbusSerializationError> {
-        if bytes.len() % 2 == 0 {
+        if bytes.len().is_multiple_of(2) {
             Ok(Self { bytes })
         } else {
             Err(ModbusSerializationError::Invalid)
         }
     }
 
+    /// # Safety
+    /// The len of bytes has to be a multiple of 2, violating this invariant may invoke undefined behavior
+    /// in code relying on it.
     pub unsafe fn new_unchecked(bytes: &'a [u8]) -> Self {
         Self { bytes }
     }
 
     pub fn get(self, idx: usize) -> Option<u16> {
-        (self.bytes.len() >= (idx + 1) * 2).then(|| unsafe { self.get_unchecked(idx) })
+        (idx < self.len()).then(|| unsafe { self.get_unchecked(idx) })
     }
 
+    /// # Safety
+    /// This function invokes undefined behavior if idx is out of bounds (idx >= self.len())
     pub unsafe fn get_unchecked(self, idx: usize) -> u16 {
         let bidx = idx * 2;
         let bytes = self.bytes.get_unchecked(bidx..=(bidx + 1));
@@ -32,6 +37,10 @@ impl<'a> RegisterSlice<'a> {
         self.bytes.len() / 2
     }
 
+    pub fn is_empty(self) -> bool {
+        self.bytes.is_empty()
+    }
+
     pub fn bytes_len(self) -> usize {
         self.bytes.len()
     }
@@ -42,9 +51,193 @@ impl<'a> RegisterSlice<'a> {
 }
 
 impl<'a> TryFrom<&'a [u8]> for RegisterSlice<'a> {
-    //TODO this should be a different error type
-    type Error = ModbusSerializationError;
-    fn try_from(data: &'a [u8]) -> Result<Self, ModbusSerializationError> {
-        Self::new(data)
+    type Error = DecodeError;
+
+    /// Create a register slice, the dangling last byte of data with an odd len is returned as invalid
+    fn try_from(data: &'a [u8]) -> Result<Self, DecodeError> {
+        Self::new(data).map_err(|_| DecodeError {
+            function: None,
+            offset: data.len() - 1,
+            kind: DecodeErrorKind::Invalid,
+        })
+    }
+}
+
+/// A mutable slice of big endian registers, e.g. the register part of a request being built in place.
+///
+/// Values spanning several registers are stored with the high word in the first register.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegisterSliceMut<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl<'a> RegisterSliceMut<'a> {
+    /// # Errors
+    /// If the len of bytes is odd [ModbusSerializationError::Invalid] is returned.
+    pub fn new(bytes: &'a mut [u8]) -> Result<Self, ModbusSerializationError> {
+        if bytes.len().is_multiple_of(2) {
+            Ok(Self { bytes })
+        } else {
+            Err(ModbusSerializationError::Invalid)
+        }
+    }
+
+    /// # Safety
+    /// The len of bytes has to be a multiple of 2, violating this invariant may invoke undefined behavior
+    /// in code relying on it.
+    pub unsafe fn new_unchecked(bytes: &'a mut [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u16> {
+        self.as_slice().get(idx)
+    }
+
+    /// Set the register at idx.
+    ///
+    /// # Errors
+    /// If idx is out of bounds [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn set(&mut self, idx: usize, value: u16) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the registers starting at idx to the given values
+    ///
+    /// # Errors
+    /// If the values don't fit [ModbusSerializationError::InsufficientBuffer] is returned and nothing is written.
+    pub fn copy_from_slice(&mut self, idx: usize, values: &[u16]) -> Result<(), ModbusSerializationError> {
+        let bytes = self.bytes_at(idx, values.len())?;
+        for (register, value) in bytes.chunks_exact_mut(2).zip(values) {
+            register.copy_from_slice(&value.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Set the register at idx to the bits of value
+    pub fn set_i16(&mut self, idx: usize, value: i16) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 2 registers starting at idx
+    pub fn set_u32(&mut self, idx: usize, value: u32) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 2 registers starting at idx
+    pub fn set_i32(&mut self, idx: usize, value: i32) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 2 registers starting at idx to an IEEE 754 single precision float
+    pub fn set_f32(&mut self, idx: usize, value: f32) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 4 registers starting at idx
+    pub fn set_u64(&mut self, idx: usize, value: u64) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 4 registers starting at idx
+    pub fn set_i64(&mut self, idx: usize, value: i64) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the 4 registers starting at idx to an IEEE 754 double precision float
+    pub fn set_f64(&mut self, idx: usize, value: f64) -> Result<(), ModbusSerializationError> {
+        self.set_bytes(idx, &value.to_be_bytes())
+    }
+
+    /// Set the registers starting at idx to the given bytes, their len has to be even
+    fn set_bytes(&mut self, idx: usize, bytes: &[u8]) -> Result<(), ModbusSerializationError> {
+        self.bytes_at(idx, bytes.len() / 2)?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn bytes_at(&mut self, idx: usize, len: usize) -> Result<&mut [u8], ModbusSerializationError> {
+        let got = self.bytes.len();
+        // An end beyond usize::MAX is reported as usize::MAX, no buffer is that large anyway
+        match idx.checked_add(len).and_then(|end| end.checked_mul(2)) {
+            Some(end) if end <= got => Ok(&mut self.bytes[idx * 2..end]),
+            end => Err(ModbusSerializationError::InsufficientBuffer { expected: end.unwrap_or(usize::MAX), got }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn as_slice(&self) -> RegisterSlice<'_> {
+        RegisterSlice { bytes: self.bytes }
+    }
+
+    pub fn into_slice(self) -> RegisterSlice<'a> {
+        RegisterSlice { bytes: self.bytes }
     }
-} 
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set() {
+        let mut bytes = [0; 8];
+        let mut registers = RegisterSliceMut::new(&mut bytes).unwrap();
+
+        registers.set(0, 0x1234).unwrap();
+        registers.set_f32(1, 1.0).unwrap();
+        registers.set_i16(3, -2).unwrap();
+        assert_eq!(registers.get(1), Some(0x3F80));
+        assert_eq!(
+            registers.set(4, 1),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 10, got: 8 })
+        );
+        assert_eq!(
+            registers.set_u32(3, 1),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 10, got: 8 })
+        );
+        assert_eq!(registers.into_slice().bytes(), &[0x12, 0x34, 0x3F, 0x80, 0, 0, 0xFF, 0xFE]);
+
+        assert_eq!(RegisterSliceMut::new(&mut [0; 3]), Err(ModbusSerializationError::Invalid));
+    }
+
+    #[test]
+    fn copy_from_slice() {
+        let mut bytes = [0; 6];
+        let mut registers = RegisterSliceMut::new(&mut bytes).unwrap();
+
+        registers.copy_from_slice(1, &[1, 0xABCD]).unwrap();
+        assert!(registers.copy_from_slice(2, &[1, 2]).is_err());
+        assert_eq!(registers.bytes(), &[0, 0, 0, 1, 0xAB, 0xCD]);
+        assert_eq!(registers.as_slice().get(2), Some(0xABCD));
+        assert_eq!(registers.as_slice().get(usize::MAX), None);
+
+        // Indices whose byte offset exceeds usize::MAX don't fit either
+        assert_eq!(
+            registers.set(usize::MAX / 2, 1),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: usize::MAX, got: 6 })
+        );
+        assert_eq!(
+            registers.copy_from_slice(usize::MAX, &[1]),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: usize::MAX, got: 6 })
+        );
+    }
+
+    #[test]
+    fn try_from() {
+        assert_eq!(RegisterSlice::try_from(&[0, 1][..]).map(|registers| registers.get(0)), Ok(Some(1)));
+        assert_eq!(
+            RegisterSlice::try_from(&[0, 1, 2][..]),
+            Err(DecodeError { function: None, offset: 2, kind: DecodeErrorKind::Invalid })
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Unified parsing of request PDUs.
+//!
+//! [Request] maps every supported request structure, servers use it to dispatch a received PDU.
+
+use crate::{
+    read::{ReadCoils, ReadDiscreteInputs, ReadHoldingRegisters, ReadInputRegisters},
+    readwrite::ReadWriteMultipleRegisters,
+    registerslice::RegisterSlice,
+    write::{MaskWriteRegister, WriteMultipleCoils, WriteMultipleRegisters, WriteSingleCoil, WriteSingleRegister},
+    custom::CustomFunctions,
+    pdu::{self, PduReader},
+    BitState, DecodeErrorKind, ModbusError, ModbusFunction, ModbusSerializationError, PublicModbusFunction, Quirks,
+    ValidationError,
+};
+
+/// Any request PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Request<'a> {
+    ReadCoils(ReadCoils),
+    ReadDiscreteInputs(ReadDiscreteInputs),
+    ReadHoldingRegisters(ReadHoldingRegisters),
+    ReadInputRegisters(ReadInputRegisters),
+    WriteSingleCoil(WriteSingleCoil),
+    WriteSingleRegister(WriteSingleRegister),
+    WriteMultipleCoils(WriteMultipleCoils<'a>),
+    WriteMultipleRegisters(WriteMultipleRegisters<'a>),
+    MaskWriteRegister(MaskWriteRegister),
+    ReadWriteMultipleRegisters(ReadWriteMultipleRegisters<'a>),
+    /// A request of a function without a dedicated request structure.
+    ///
+    /// The data contains everything after the function code.
+    Other {
+        function: ModbusFunction,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Request<'a> {
+    /// Parse a request from the given PDU.
+    ///
+    /// In contrast to the from_data functions of the request structures the data has to start with the
+    /// function code. Requests which can never be valid are rejected, for instance reads of more entities than allowed.
+    ///
+    /// # Errors
+    /// The error tells the function of the request and the offset of the offending field, e.g. of the quantity of a
+    /// read of too many registers. Malformed requests are [ModbusError::Decode] errors, requests violating the limits
+    /// of their function, see [ReadHoldingRegisters::validate], [ModbusError::Validation] errors.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusError> {
+        Self::from_data_with_quirks(data, Quirks::STRICT)
+    }
+
+    /// Parse a request from the given PDU accepting the deviations enabled in quirks.
+    ///
+    /// With [byte_count_mismatch](Quirks::byte_count_mismatch) the byte count of write multiple requests is ignored,
+    /// with [any_coil_value](Quirks::any_coil_value) every coil value other than 0x0000 is accepted as on.
+    ///
+    /// # Errors
+    /// See [from_data](Self::from_data).
+    pub fn from_data_with_quirks(data: &'a [u8], quirks: Quirks) -> Result<(Self, &'a [u8]), ModbusError> {
+        let mut pdu = PduReader::new(data)?;
+        let function = pdu.function();
+
+        let req = match PublicModbusFunction::from(function) {
+            PublicModbusFunction::ReadCoils => {
+                let req = ReadCoils::new(pdu.u16()?, pdu.u16()?);
+                req.validate()?;
+                Self::ReadCoils(req)
+            }
+            PublicModbusFunction::ReadDiscreteInputs => {
+                let req = ReadDiscreteInputs::new(pdu.u16()?, pdu.u16()?);
+                req.validate()?;
+                Self::ReadDiscreteInputs(req)
+            }
+            PublicModbusFunction::ReadHoldingRegisters => {
+                let req = ReadHoldingRegisters::new(pdu.u16()?, pdu.u16()?);
+                req.validate()?;
+                Self::ReadHoldingRegisters(req)
+            }
+            PublicModbusFunction::ReadInputRegisters => {
+                let req = ReadInputRegisters::new(pdu.u16()?, pdu.u16()?);
+                req.validate()?;
+                Self::ReadInputRegisters(req)
+            }
+            PublicModbusFunction::WriteSingleCoil => {
+                let addr = pdu.u16()?;
+                let offset = pdu.offset();
+                let state = match pdu.u16()? {
+                    value if quirks.any_coil_value => BitState::from(value != 0),
+                    value => BitState::try_from(value)
+                        .map_err(|_| pdu.decode_error(offset, DecodeErrorKind::Invalid))?,
+                };
+                Self::WriteSingleCoil(WriteSingleCoil::new(addr, state))
+            }
+            PublicModbusFunction::WriteSingleRegister => {
+                Self::WriteSingleRegister(WriteSingleRegister::new(pdu.u16()?, pdu.u16()?))
+            }
+            PublicModbusFunction::WriteMultipleCoils => {
+                let (addr, quantity, bytes) =
+                    read_write_range(&mut pdu, WriteMultipleCoils::MAX_QUANTITY, quirks, |n| n.div_ceil(8))?;
+                let req = WriteMultipleCoils::new(addr, quantity, bytes).map_err(|e| e.in_decode(Some(function), 1))?;
+                Self::WriteMultipleCoils(req)
+            }
+            PublicModbusFunction::WriteMultipleRegisters => {
+                let (addr, _, bytes) =
+                    read_write_range(&mut pdu, WriteMultipleRegisters::MAX_QUANTITY, quirks, |n| n * 2)?;
+                // Registers ending at address 0xFFFF are the only ones rejected by the request itself
+                let req = RegisterSlice::new(bytes)
+                    .and_then(|registers| WriteMultipleRegisters::new(addr, registers))
+                    .map_err(|e| e.in_decode(Some(function), 1))?;
+                Self::WriteMultipleRegisters(req)
+            }
+            PublicModbusFunction::MaskWriteRegister => {
+                Self::MaskWriteRegister(MaskWriteRegister::new(pdu.u16()?, pdu.u16()?, pdu.u16()?))
+            }
+            PublicModbusFunction::ReadWriteMultipleRegisters => {
+                let read = ReadHoldingRegisters::new(pdu.u16()?, pdu.u16()?);
+                // The read range is at the same offsets as in a read request
+                read.validate().map_err(|e| ValidationError { function: Some(function), ..e })?;
+                let max = ReadWriteMultipleRegisters::MAX_WRITE_QUANTITY;
+                let (addr, _, bytes) = read_write_range(&mut pdu, max, quirks, |n| n * 2)?;
+                let req = RegisterSlice::new(bytes)
+                    .and_then(|registers| ReadWriteMultipleRegisters::new(read, addr, registers))
+                    .map_err(|e| e.in_decode(Some(function), 5))?;
+                Self::ReadWriteMultipleRegisters(req)
+            }
+            _ => return Ok((Self::Other { function, data: pdu.data() }, &[])),
+        };
+        Ok((req, pdu.tail()))
+    }
+
+    /// Write this request to the slice as modbus data, see [write_to_slice](Self::write_to_slice).
+    ///
+    /// The error tells the function of the request and the size of the buffer.
+    pub fn encode(self, out: &mut [u8]) -> Result<usize, ModbusError> {
+        self.write_to_slice(out).map_err(|e| ModbusError::encode(e, Some(self.function()), 0))
+    }
+
+    /// Parse a request from the given PDU, checking requests of the user defined functions registered in custom.
+    ///
+    /// Requests of registered functions are returned as [Request::Other] containing exactly the request data,
+    /// the bytes after it are returned as tail. Any other request is parsed by
+    /// [from_data_with_quirks](Self::from_data_with_quirks).
+    ///
+    /// # Errors
+    /// The error of [CustomFunction::parse_request](crate::custom::CustomFunction::parse_request) is returned
+    /// for invalid requests of registered functions, located relative to the data after the function code.
+    pub fn from_data_with_custom(
+        data: &'a [u8],
+        quirks: Quirks,
+        custom: CustomFunctions<'_>,
+    ) -> Result<(Self, &'a [u8]), ModbusError> {
+        let codec = data.first().and_then(|function| custom.get(ModbusFunction::new(*function)));
+        match codec {
+            Some(codec) => {
+                let function = codec.function();
+                let size = codec.request_size(&data[1..]).map_err(|e| e.in_decode(Some(function), 1))?;
+                let (data, tail) = data[1..].split_at(size);
+                Ok((Self::Other { function, data }, tail))
+            }
+            None => Self::from_data_with_quirks(data, quirks),
+        }
+    }
+
+    /// The function of this request
+    pub fn function(&self) -> ModbusFunction {
+        let public = match self {
+            Self::ReadCoils(_) => ReadCoils::MODBUS_FUNCTION_CODE,
+            Self::ReadDiscreteInputs(_) => ReadDiscreteInputs::MODBUS_FUNCTION_CODE,
+            Self::ReadHoldingRegisters(_) => ReadHoldingRegisters::MODBUS_FUNCTION_CODE,
+            Self::ReadInputRegisters(_) => ReadInputRegisters::MODBUS_FUNCTION_CODE,
+            Self::WriteSingleCoil(_) => WriteSingleCoil::MODBUS_FUNCTION_CODE,
+            Self::WriteSingleRegister(_) => WriteSingleRegister::MODBUS_FUNCTION_CODE,
+            Self::WriteMultipleCoils(_) => WriteMultipleCoils::MODBUS_FUNCTION_CODE,
+            Self::WriteMultipleRegisters(_) => WriteMultipleRegisters::MODBUS_FUNCTION_CODE,
+            Self::MaskWriteRegister(_) => MaskWriteRegister::MODBUS_FUNCTION_CODE,
+            Self::ReadWriteMultipleRegisters(_) => ReadWriteMultipleRegisters::MODBUS_FUNCTION_CODE,
+            Self::Other { function, .. } => return *function,
+        };
+        ModbusFunction::new_public(public)
+    }
+
+    /// Write this request to the slice as modbus data.
+    ///
+    /// On success the number of written bytes is returned.
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+        match self {
+            Self::ReadCoils(req) => req.write_to_slice(out).map(|_| 5),
+            Self::ReadDiscreteInputs(req) => req.write_to_slice(out).map(|_| 5),
+            Self::ReadHoldingRegisters(req) => req.write_to_slice(out).map(|_| 5),
+            Self::ReadInputRegisters(req) => req.write_to_slice(out).map(|_| 5),
+            Self::WriteSingleCoil(req) => req.write_to_slice(out).map(|_| 5),
+            Self::WriteSingleRegister(req) => req.write_to_slice(out).map(|_| 5),
+            Self::WriteMultipleCoils(req) => req.write_to_slice(out).map(|_| req.data_size()),
+            Self::WriteMultipleRegisters(req) => req.write_to_slice(out).map(|_| req.data_size()),
+            Self::MaskWriteRegister(req) => req.write_to_slice(out).map(|_| 7),
+            Self::ReadWriteMultipleRegisters(req) => req.write_to_slice(out).map(|_| req.data_size()),
+            Self::Other { function, data } => {
+                let size = data.len() + 1;
+                if out.len() < size {
+                    return Err(ModbusSerializationError::InsufficientBuffer {
+                        expected: size,
+                        got: out.len(),
+                    });
+                }
+
+                out[0] = function.0;
+                out[1..size].copy_from_slice(data);
+                Ok(size)
+            }
+        }
+    }
+}
+
+/// Read address, quantity, byte count and the written bytes of a write multiple request.
+///
+/// The number of written bytes follows from the quantity, with
+/// [byte_count_mismatch](Quirks::byte_count_mismatch) the byte count is ignored.
+fn read_write_range<'a>(
+    pdu: &mut PduReader<'a>,
+    max: u16,
+    quirks: Quirks,
+    nbytes: impl FnOnce(usize) -> usize,
+) -> Result<(u16, u16, &'a [u8]), ModbusError> {
+    let addr_offset = pdu.offset();
+    let addr = pdu.u16()?;
+    let quantity = pdu.u16()?;
+    let count_offset = pdu.offset();
+    let count = pdu.u8()?;
+    pdu::check_range(pdu.function(), addr_offset, addr, quantity, max)?;
+
+    let size = nbytes(quantity as usize);
+    if count as usize != size && !quirks.byte_count_mismatch {
+        return Err(pdu.decode_error(count_offset, DecodeErrorKind::Ambivalent).into());
+    }
+    Ok((addr, quantity, pdu.bytes(size)?))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        BitState, DecodeError, DecodeErrorKind, EncodeError, EncodeErrorKind, ModbusError, ModbusFunction, Quirks,
+        ValidationError, ValidationErrorKind,
+    };
+
+    use super::Request;
+
+    #[test]
+    fn read_holding_registers() {
+        let data = [3, 0, 1, 0, 10, 42];
+        let (req, tail) = Request::from_data(&data).unwrap();
+
+        match req {
+            Request::ReadHoldingRegisters(req) => {
+                assert_eq!(req.addr, 1);
+                assert_eq!(req.quantity, 10);
+            }
+            _ => panic!("wrong request {:?}", req),
+        }
+        assert_eq!(req.function(), ModbusFunction::new(3));
+        assert_eq!(tail, &[42]);
+    }
+
+    #[test]
+    fn write_single_coil() {
+        let (req, tail) = Request::from_data(&[5, 0, 1, 0xFF, 0]).unwrap();
+
+        match req {
+            Request::WriteSingleCoil(req) => {
+                assert_eq!(req.addr, 1);
+                assert_eq!(req.state, BitState::On);
+            }
+            _ => panic!("wrong request {:?}", req),
+        }
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn write_multiple_registers() {
+        let data = [16, 0, 1, 0, 2, 4, 0, 0xA, 1, 2, 42];
+        let (req, tail) = Request::from_data(&data).unwrap();
+
+        match req {
+            Request::WriteMultipleRegisters(req) => {
+                assert_eq!(req.addr(), 1);
+                assert_eq!(req.registers().bytes(), &[0, 0xA, 1, 2]);
+            }
+            _ => panic!("wrong request {:?}", req),
+        }
+        assert_eq!(tail, &[42]);
+    }
+
+    #[test]
+    fn other() {
+        let data = [0x41, 1, 2, 3];
+        let (req, tail) = Request::from_data(&data).unwrap();
+
+        assert_eq!(
+            req,
+            Request::Other {
+                function: ModbusFunction::new(0x41),
+                data: &[1, 2, 3]
+            }
+        );
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn from_data_with_quirks() {
+        let quirks = Quirks { byte_count_mismatch: true, any_coil_value: true, ..Quirks::STRICT };
+
+        // Byte counts of the quantity instead of the number of bytes
+        let data = [16, 0, 1, 0, 2, 2, 0, 0xA, 1, 2, 42];
+        assert!(matches!(
+            Request::from_data(&data),
+            Err(ModbusError::Decode(DecodeError { kind: DecodeErrorKind::Ambivalent, .. }))
+        ));
+        let (req, tail) = Request::from_data_with_quirks(&data, quirks).unwrap();
+        match req {
+            Request::WriteMultipleRegisters(req) => assert_eq!(req.registers().bytes(), &[0, 0xA, 1, 2]),
+            _ => panic!("wrong request {:?}", req),
+        }
+        assert_eq!(tail, &[42]);
+
+        let data = [15, 0, 1, 0, 10, 10, 0xCD, 1];
+        let (req, tail) = Request::from_data_with_quirks(&data, quirks).unwrap();
+        match req {
+            Request::WriteMultipleCoils(req) => assert_eq!(req.bytes(), &[0xCD, 1]),
+            _ => panic!("wrong request {:?}", req),
+        }
+        assert!(tail.is_empty());
+
+        let data = [23, 0, 3, 0, 6, 0, 0x0E, 0, 1, 0, 0, 0xFF];
+        let (req, _tail) = Request::from_data_with_quirks(&data, quirks).unwrap();
+        match req {
+            Request::ReadWriteMultipleRegisters(req) => assert_eq!(req.registers().bytes(), &[0, 0xFF]),
+            _ => panic!("wrong request {:?}", req),
+        }
+
+        // The quantity decides how many bytes have to follow
+        assert_eq!(
+            Request::from_data_with_quirks(&[16, 0, 1, 0, 2, 4, 0, 0xA], quirks),
+            Err(ModbusError::Decode(DecodeError {
+                function: Some(ModbusFunction::new(16)),
+                offset: 8,
+                kind: DecodeErrorKind::UnexpectedEof,
+            }))
+        );
+
+        let (req, _tail) = Request::from_data_with_quirks(&[5, 0, 1, 0, 1], quirks).unwrap();
+        match req {
+            Request::WriteSingleCoil(req) => assert_eq!(req.state, BitState::On),
+            _ => panic!("wrong request {:?}", req),
+        }
+        let (req, _tail) = Request::from_data_with_quirks(&[5, 0, 1, 0, 0], quirks).unwrap();
+        match req {
+            Request::WriteSingleCoil(req) => assert_eq!(req.state, BitState::Off),
+            _ => panic!("wrong request {:?}", req),
+        }
+    }
+
+    #[test]
+    fn write_to_slice_roundtrip() {
+        let requests: [&[u8]; 8] = [
+            &[3, 0, 1, 0, 10],
+            &[2, 0, 1, 0, 10],
+            &[6, 0, 1, 0, 10],
+            &[15, 0, 1, 0, 10, 2, 0xCD, 1],
+            &[16, 0, 1, 0, 2, 4, 0, 0xA, 1, 2],
+            &[22, 0, 4, 0, 0xF2, 0, 0x25],
+            &[23, 0, 3, 0, 6, 0, 0x0E, 0, 1, 2, 0, 0xFF],
+            &[0x64, 1, 2, 3],
+        ];
+
+        for data in requests {
+            let (req, _tail) = Request::from_data(data).unwrap();
+            let mut out = [0; 16];
+            let size = req.write_to_slice(&mut out).unwrap();
+            assert_eq!(&out[..size], data);
+        }
+    }
+
+    #[test]
+    fn from_data_fail() {
+        let decode = |data| Request::from_data(data).unwrap_err();
+        let function = |code| Some(ModbusFunction::new(code));
+
+        assert_eq!(
+            decode(&[]),
+            ModbusError::Decode(DecodeError { function: None, offset: 0, kind: DecodeErrorKind::UnexpectedEof })
+        );
+        assert_eq!(
+            decode(&[3, 0, 1, 0]),
+            ModbusError::Decode(DecodeError { function: function(3), offset: 4, kind: DecodeErrorKind::UnexpectedEof })
+        );
+        assert_eq!(
+            decode(&[3, 0, 1, 0, 0]),
+            ModbusError::Validation(ValidationError {
+                function: function(3),
+                offset: 3,
+                kind: ValidationErrorKind::ZeroQuantity,
+            })
+        );
+        assert_eq!(
+            decode(&[1, 0, 1, 0x07, 0xD1]),
+            ModbusError::Validation(ValidationError {
+                function: function(1),
+                offset: 3,
+                kind: ValidationErrorKind::TooLarge,
+            })
+        );
+        assert_eq!(
+            decode(&[1, 0xFF, 0xFF, 0, 2]),
+            ModbusError::Validation(ValidationError {
+                function: function(1),
+                offset: 1,
+                kind: ValidationErrorKind::Overflow,
+            })
+        );
+        assert_eq!(
+            decode(&[5, 0, 1, 0x12, 0]),
+            ModbusError::Decode(DecodeError { function: function(5), offset: 3, kind: DecodeErrorKind::Invalid })
+        );
+        assert_eq!(
+            decode(&[16, 0, 1, 0, 2, 2, 0, 1]),
+            ModbusError::Decode(DecodeError { function: function(16), offset: 5, kind: DecodeErrorKind::Ambivalent })
+        );
+
+        assert_eq!(
+            decode(&[16, 0xFF, 0xFF, 0, 2, 4, 0, 1, 0, 2]),
+            ModbusError::Validation(ValidationError {
+                function: function(16),
+                offset: 1,
+                kind: ValidationErrorKind::Overflow,
+            })
+        );
+        assert_eq!(
+            decode(&[15, 0, 1, 0, 0, 0]),
+            ModbusError::Validation(ValidationError {
+                function: function(15),
+                offset: 3,
+                kind: ValidationErrorKind::ZeroQuantity,
+            })
+        );
+
+        // The write range of read/write requests
+        assert_eq!(
+            decode(&[23, 0, 3, 0, 6, 0xFF, 0xFF, 0, 2, 4, 0, 1, 0, 2]),
+            ModbusError::Validation(ValidationError {
+                function: function(23),
+                offset: 5,
+                kind: ValidationErrorKind::Overflow,
+            })
+        );
+        assert_eq!(
+            decode(&[23, 0, 3, 0, 0x7E, 0, 0x0E, 0, 1, 2, 0, 0xFF]),
+            ModbusError::Validation(ValidationError {
+                function: function(23),
+                offset: 3,
+                kind: ValidationErrorKind::TooLarge,
+            })
+        );
+    }
+
+    #[test]
+    fn encode_error() {
+        let (req, _tail) = Request::from_data(&[3, 0, 1, 0, 10]).unwrap();
+        assert_eq!(
+            req.encode(&mut [0; 4]),
+            Err(ModbusError::Encode(EncodeError {
+                function: Some(ModbusFunction::new(3)),
+                offset: 4,
+                kind: EncodeErrorKind::InsufficientBuffer { expected: 5 },
+            }))
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Response structures of the read requests.
+//!
+//! Responses to write requests echo (parts of) the request, see for instance
+//! [WriteMultipleRegisters::into_response_data](crate::write::WriteMultipleRegisters::into_response_data).
+
+use crate::{
+    bitslice::BitSliceMut,
+    read::{ReadCoils, ReadHoldingRegisters},
+    registerslice::{RegisterSlice, RegisterSliceMut},
+    BitState, ModbusSerializationError, PublicModbusFunction};
+
+/// The maximum number of data bytes of a read response, 2000 bits or 125 registers
+const MAX_DATA_SIZE: usize = 250;
+
+/// The response to a [ReadCoils](crate::read::ReadCoils) or [ReadDiscreteInputs](crate::read::ReadDiscreteInputs)
+/// request.
+///
+/// The bits are packed into bytes, the first bit is the least significant bit of the first byte.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ReadBitsResponse<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ReadBitsResponse<'a> {
+    /// Parse this response from the given modbus data.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusSerializationError> {
+        let (nbytes, data) = match data.split_first() {
+            Some((nbytes, data)) => (*nbytes as usize, data),
+            None => return Err(ModbusSerializationError::UnexpectedEOF { expected: 1, got: 0 }),
+        };
+
+        if data.len() < nbytes {
+            return Err(ModbusSerializationError::UnexpectedEOF {
+                expected: nbytes + 1,
+                got: data.len() + 1,
+            });
+        }
+
+        let (bytes, tail) = data.split_at(nbytes);
+        Ok((Self { bytes }, tail))
+    }
+
+    /// Get the state of the bit at idx.
+    ///
+    /// The response always contains a multiple of 8 bits, bits after the requested quantity are zero padding.
+    pub fn get(self, idx: usize) -> Option<BitState> {
+        self.bytes
+            .get(idx / 8)
+            .map(|byte| BitState::from(byte & (1 << (idx % 8)) != 0))
+    }
+
+    pub fn bytes(self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Write a response containing the given bits to the slice.
+    ///
+    /// On success the number of written bytes is returned.
+    ///
+    /// # Errors
+    /// If more bits are given than fit into a response [ModbusSerializationError::TooLarge] is returned.
+    /// If out is too small to hold the response [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn write_bits(
+        function: PublicModbusFunction,
+        bits: impl ExactSizeIterator<Item = BitState>,
+        out: &mut [u8],
+    ) -> Result<usize, ModbusSerializationError> {
+        let nbytes = bits.len().div_ceil(8);
+        if nbytes > MAX_DATA_SIZE {
+            return Err(ModbusSerializationError::TooLarge);
+        }
+
+        let size = nbytes + 2;
+        if out.len() < size {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: size,
+                got: out.len(),
+            });
+        }
+
+        out[0] = function as u8;
+        out[1] = nbytes as u8;
+        out[2..size].fill(0);
+        for (idx, bit) in bits.enumerate() {
+            if bit.is_on() {
+                out[2 + idx / 8] |= 1 << (idx % 8);
+            }
+        }
+
+        Ok(size)
+    }
+}
+
+/// The response to a [ReadHoldingRegisters](crate::read::ReadHoldingRegisters) or
+/// [ReadInputRegisters](crate::read::ReadInputRegisters) request.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ReadRegistersResponse<'a> {
+    registers: RegisterSlice<'a>,
+}
+
+impl<'a> ReadRegistersResponse<'a> {
+    /// Parse this response from the given modbus data.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusSerializationError> {
+        let (bits, tail) = ReadBitsResponse::from_data(data)?;
+        let registers = RegisterSlice::new(bits.bytes())?;
+        Ok((Self { registers }, tail))
+    }
+
+    pub fn registers(self) -> RegisterSlice<'a> {
+        self.registers
+    }
+
+    /// Write a response containing the given registers to the slice.
+    ///
+    /// On success the number of written bytes is returned.
+    ///
+    /// # Errors
+    /// If more registers are given than fit into a response [ModbusSerializationError::TooLarge] is returned.
+    /// If out is too small to hold the response [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn write_registers(
+        function: PublicModbusFunction,
+        registers: impl ExactSizeIterator<Item = u16>,
+        out: &mut [u8],
+    ) -> Result<usize, ModbusSerializationError> {
+        let nbytes = registers.len().saturating_mul(2);
+        if nbytes > MAX_DATA_SIZE {
+            return Err(ModbusSerializationError::TooLarge);
+        }
+
+        let size = nbytes + 2;
+        if out.len() < size {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: size,
+                got: out.len(),
+            });
+        }
+
+        out[0] = function as u8;
+        out[1] = nbytes as u8;
+        for (idx, register) in registers.enumerate() {
+            out[2 + idx * 2..4 + idx * 2].copy_from_slice(&register.to_be_bytes());
+        }
+
+        Ok(size)
+    }
+}
+
+/// Reserve a response of quantity entities of size bytes at the start of out and write its header
+fn reserve(
+    function: PublicModbusFunction,
+    quantity: usize,
+    max: u16,
+    size: impl FnOnce(usize) -> usize,
+    out: &mut [u8],
+) -> Result<&mut [u8], ModbusSerializationError> {
+    let nbytes = match quantity {
+        0 => return Err(ModbusSerializationError::Invalid),
+        n if n > max as usize => return Err(ModbusSerializationError::TooLarge),
+        n => size(n),
+    };
+
+    let size = nbytes + 2;
+    let got = out.len();
+    let out = out.get_mut(..size).ok_or(ModbusSerializationError::InsufficientBuffer { expected: size, got })?;
+    out[0] = function as u8;
+    out[1] = nbytes as u8;
+    out[2..].fill(0);
+    Ok(out)
+}
+
+/// Builds a [ReadBitsResponse] in place, the bits are set directly in the output buffer behind the header.
+#[derive(Debug)]
+pub struct ReadBitsResponseBuilder<'a> {
+    out: &'a mut [u8],
+    quantity: usize,
+}
+
+impl<'a> ReadBitsResponseBuilder<'a> {
+    /// Reserve a response holding quantity bits at the start of out, all bits are initialized to [BitState::Off].
+    ///
+    /// # Errors
+    /// If function is neither [ReadCoils](PublicModbusFunction::ReadCoils) nor
+    /// [ReadDiscreteInputs](PublicModbusFunction::ReadDiscreteInputs) or quantity is 0
+    /// [ModbusSerializationError::Invalid] is returned. If quantity exceeds [ReadCoils::MAX_QUANTITY]
+    /// [ModbusSerializationError::TooLarge] is returned. If out is too small to hold the response
+    /// [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn new(
+        function: PublicModbusFunction,
+        quantity: usize,
+        out: &'a mut [u8],
+    ) -> Result<Self, ModbusSerializationError> {
+        if !matches!(function, PublicModbusFunction::ReadCoils | PublicModbusFunction::ReadDiscreteInputs) {
+            return Err(ModbusSerializationError::Invalid);
+        }
+        let out = reserve(function, quantity, ReadCoils::MAX_QUANTITY, |n| n.div_ceil(8), out)?;
+        Ok(Self { out, quantity })
+    }
+
+    pub fn bits(&mut self) -> BitSliceMut<'_> {
+        unsafe { BitSliceMut::new_unchecked(&mut self.out[2..], self.quantity) }
+    }
+
+    /// Finish the response, the written modbus data is returned
+    pub fn finish(self) -> &'a [u8] {
+        self.out
+    }
+}
+
+/// Builds a [ReadRegistersResponse] in place, the registers are set directly in the output buffer behind the header.
+#[derive(Debug)]
+pub struct ReadRegistersResponseBuilder<'a> {
+    out: &'a mut [u8],
+}
+
+impl<'a> ReadRegistersResponseBuilder<'a> {
+    /// Reserve a response holding quantity registers at the start of out, all registers are initialized to 0.
+    ///
+    /// # Errors
+    /// If function is neither [ReadHoldingRegisters](PublicModbusFunction::ReadHoldingRegisters) nor
+    /// [ReadInputRegisters](PublicModbusFunction::ReadInputRegisters) or quantity is 0
+    /// [ModbusSerializationError::Invalid] is returned. If quantity exceeds [ReadHoldingRegisters::MAX_QUANTITY]
+    /// [ModbusSerializationError::TooLarge] is returned. If out is too small to hold the response
+    /// [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn new(
+        function: PublicModbusFunction,
+        quantity: usize,
+        out: &'a mut [u8],
+    ) -> Result<Self, ModbusSerializationError> {
+        if !matches!(function, PublicModbusFunction::ReadHoldingRegisters | PublicModbusFunction::ReadInputRegisters) {
+            return Err(ModbusSerializationError::Invalid);
+        }
+        let out = reserve(function, quantity, ReadHoldingRegisters::MAX_QUANTITY, |n| n * 2, out)?;
+        Ok(Self { out })
+    }
+
+    pub fn registers(&mut self) -> RegisterSliceMut<'_> {
+        unsafe { RegisterSliceMut::new_unchecked(&mut self.out[2..]) }
+    }
+
+    /// Finish the response, the written modbus data is returned
+    pub fn finish(self) -> &'a [u8] {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BitState, ModbusSerializationError, PublicModbusFunction};
+
+    use super::{ReadBitsResponse, ReadBitsResponseBuilder, ReadRegistersResponse, ReadRegistersResponseBuilder};
+
+    #[test]
+    fn bits_from_data_spec() {
+        // Read coils example from the modbus spec
+        let data = [3, 0xCD, 0x6B, 0x05, 42];
+        let (res, tail) = ReadBitsResponse::from_data(&data).unwrap();
+
+        assert_eq!(res.bytes(), &[0xCD, 0x6B, 0x05]);
+        assert_eq!(res.get(0), Some(BitState::On));
+        assert_eq!(res.get(1), Some(BitState::Off));
+        assert_eq!(res.get(7), Some(BitState::On));
+        assert_eq!(res.get(18), Some(BitState::On));
+        assert_eq!(res.get(19), Some(BitState::Off));
+        assert_eq!(res.get(24), None);
+        assert_eq!(tail, &[42]);
+    }
+
+    #[test]
+    fn bits_from_data_fail() {
+        assert_eq!(
+            ReadBitsResponse::from_data(&[]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 1, got: 0 })
+        );
+        assert_eq!(
+            ReadBitsResponse::from_data(&[3, 1, 2]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 4, got: 3 })
+        );
+    }
+
+    #[test]
+    fn write_bits() {
+        let bits = [1, 0, 1, 1, 0, 0, 1, 1, 1, 1].map(|b| BitState::from(b == 1));
+        let mut out = [0xFF; 5];
+        let size = ReadBitsResponse::write_bits(PublicModbusFunction::ReadCoils, bits.into_iter(), &mut out).unwrap();
+
+        assert_eq!(size, 4);
+        assert_eq!(out, [1, 2, 0xCD, 0x03, 0xFF]);
+
+        let (res, _tail) = ReadBitsResponse::from_data(&out[1..size]).unwrap();
+        for (idx, bit) in bits.iter().enumerate() {
+            assert_eq!(res.get(idx), Some(*bit));
+        }
+    }
+
+    #[test]
+    fn write_bits_fail() {
+        let mut out = [0; 3];
+        let err = ReadBitsResponse::write_bits(
+            PublicModbusFunction::ReadDiscreteInputs,
+            [BitState::On; 9].into_iter(),
+            &mut out,
+        )
+        .unwrap_err();
+
+        assert_eq!(err, ModbusSerializationError::InsufficientBuffer { expected: 4, got: 3 });
+    }
+
+    #[test]
+    fn registers_from_data_spec() {
+        // Read holding registers example from the modbus spec
+        let data = [6, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64];
+        let (res, tail) = ReadRegistersResponse::from_data(&data).unwrap();
+
+        assert_eq!(res.registers().len(), 3);
+        assert_eq!(res.registers().get(0), Some(555));
+        assert_eq!(res.registers().get(1), Some(0));
+        assert_eq!(res.registers().get(2), Some(100));
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn registers_from_data_fail_odd() {
+        let err = ReadRegistersResponse::from_data(&[3, 1, 2, 3]).unwrap_err();
+        assert_eq!(err, ModbusSerializationError::Invalid);
+    }
+
+    #[test]
+    fn write_registers() {
+        let mut out = [0; 8];
+        let size = ReadRegistersResponse::write_registers(
+            PublicModbusFunction::ReadHoldingRegisters,
+            [555, 0, 100].into_iter(),
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(size, 8);
+        assert_eq!(out, [3, 6, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+    }
+
+    #[test]
+    fn write_registers_fail() {
+        let mut out = [0; 512];
+        let err = ReadRegistersResponse::write_registers(
+            PublicModbusFunction::ReadInputRegisters,
+            [0; 126].into_iter(),
+            &mut out,
+        )
+        .unwrap_err();
+        assert_eq!(err, ModbusSerializationError::TooLarge);
+    }
+
+    #[test]
+    fn builders() {
+        let mut out = [0xFF; 8];
+        let function = PublicModbusFunction::ReadHoldingRegisters;
+        let mut builder = ReadRegistersResponseBuilder::new(function, 3, &mut out).unwrap();
+        builder.registers().copy_from_slice(0, &[555, 0, 100]).unwrap();
+        assert_eq!(builder.finish(), &[3, 6, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+
+        let mut builder = ReadBitsResponseBuilder::new(PublicModbusFunction::ReadCoils, 10, &mut out).unwrap();
+        builder.bits().set(0, BitState::On).unwrap();
+        builder.bits().set(9, BitState::On).unwrap();
+        assert_eq!(builder.finish(), &[1, 2, 0x01, 0x02]);
+
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(function, 4, &mut out).unwrap_err(),
+            ModbusSerializationError::InsufficientBuffer { expected: 10, got: 8 }
+        );
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(function, 128, &mut [0; 512]).unwrap_err(),
+            ModbusSerializationError::TooLarge
+        );
+    }
+
+    #[test]
+    fn builders_fail() {
+        let mut out = [0; 512];
+        let holding = PublicModbusFunction::ReadHoldingRegisters;
+        let coils = PublicModbusFunction::ReadCoils;
+
+        // 126 registers would need 252 data bytes, more than a PDU holds
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(holding, 126, &mut out).unwrap_err(),
+            ModbusSerializationError::TooLarge
+        );
+        assert_eq!(ReadRegistersResponseBuilder::new(holding, 125, &mut out).unwrap().finish().len(), 252);
+        assert_eq!(
+            ReadBitsResponseBuilder::new(coils, 2001, &mut out).unwrap_err(),
+            ModbusSerializationError::TooLarge
+        );
+        assert_eq!(ReadBitsResponseBuilder::new(coils, 2000, &mut out).unwrap().finish().len(), 252);
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(holding, usize::MAX, &mut out).unwrap_err(),
+            ModbusSerializationError::TooLarge
+        );
+
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(holding, 0, &mut out).unwrap_err(),
+            ModbusSerializationError::Invalid
+        );
+        assert_eq!(ReadBitsResponseBuilder::new(coils, 0, &mut out).unwrap_err(), ModbusSerializationError::Invalid);
+
+        // Only read functions have these responses
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(PublicModbusFunction::WriteMultipleRegisters, 1, &mut out).unwrap_err(),
+            ModbusSerializationError::Invalid
+        );
+        assert_eq!(
+            ReadRegistersResponseBuilder::new(coils, 1, &mut out).unwrap_err(),
+            ModbusSerializationError::Invalid
+        );
+        assert_eq!(
+            ReadBitsResponseBuilder::new(PublicModbusFunction::ReadInputRegisters, 1, &mut out).unwrap_err(),
+            ModbusSerializationError::Invalid
+        );
+    }
+}
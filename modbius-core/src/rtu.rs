@@ -0,0 +1,265 @@
+//! Modbus RTU framing.
+//!
+//! An RTU frame consists out of the slave id, the PDU and a CRC-16 transmitted low byte first.
+//! Frames are delimited by silent intervals on the line, detecting those is up to the transport.
+//! See <https://modbus.org/docs/Modbus_over_serial_line_V1_02.pdf> page 12 and following for more details.
+
+use crate::{ModbusSerializationError, Quirks, SlaveId, MAX_PDU_SIZE};
+
+/// The maximum size of an RTU frame (slave id + PDU + CRC)
+pub const MAX_FRAME_SIZE: usize = 256;
+
+/// The minimum size of an RTU frame (slave id + function code + CRC)
+pub const MIN_FRAME_SIZE: usize = 4;
+
+/// Calculate the Modbus CRC-16 of the given data.
+///
+/// The CRC has to be transmitted low byte first, see [write_frame].
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Get how many bytes a frame containing the given number of PDU bytes needs
+pub const fn frame_size(pdu_len: usize) -> usize {
+    pdu_len + 3
+}
+
+/// Write an RTU frame with the given slave id and PDU to the slice.
+///
+/// On success the number of written bytes is returned.
+///
+/// # Errors
+/// If the PDU is larger than [MAX_PDU_SIZE] [ModbusSerializationError::TooLarge] is returned.
+/// If out can't hold the whole frame [ModbusSerializationError::InsufficientBuffer] is returned.
+pub fn write_frame(slave: SlaveId, pdu: &[u8], out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+    if pdu.len() > MAX_PDU_SIZE {
+        return Err(ModbusSerializationError::TooLarge);
+    }
+
+    let size = frame_size(pdu.len());
+    if out.len() < size {
+        return Err(ModbusSerializationError::InsufficientBuffer {
+            expected: size,
+            got: out.len(),
+        });
+    }
+
+    out[0] = slave.into();
+    out[1..size - 2].copy_from_slice(pdu);
+    let crc = crc16(&out[..size - 2]);
+    out[size - 2..size].copy_from_slice(&crc.to_le_bytes());
+
+    Ok(size)
+}
+
+/// An error encountered while decoding an RTU frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RtuFrameError {
+    /// The CRC of the frame did not match its content
+    Crc {
+        /// The CRC calculated from the frame content
+        expected: u16,
+        /// The CRC transmitted in the frame
+        got: u16,
+    },
+    /// The frame was shorter than [MIN_FRAME_SIZE] or longer than [MAX_FRAME_SIZE]
+    Length,
+}
+
+/// Decode a complete frame and check its CRC.
+///
+/// The slave id and the PDU of the frame are returned.
+pub fn decode_frame(frame: &[u8]) -> Result<(SlaveId, &[u8]), RtuFrameError> {
+    if frame.len() < MIN_FRAME_SIZE || frame.len() > MAX_FRAME_SIZE {
+        return Err(RtuFrameError::Length);
+    }
+
+    let (content, crc) = frame.split_at(frame.len() - 2);
+    let expected = crc16(content);
+    let got = u16::from_le_bytes([crc[0], crc[1]]);
+    if expected != got {
+        return Err(RtuFrameError::Crc { expected, got });
+    }
+
+    Ok((SlaveId::new(content[0]), &content[1..]))
+}
+
+/// Decode a complete frame accepting the deviations enabled in quirks.
+///
+/// With [swapped_crc](Quirks::swapped_crc) CRCs transmitted high byte first are accepted. With
+/// [trailing_bytes](Quirks::trailing_bytes) the frame ends at the last position followed by a valid CRC, everything
+/// after it is ignored.
+///
+/// # Errors
+/// If no valid frame is found the error of decoding the whole frame is returned, see [decode_frame].
+pub fn decode_frame_with_quirks(frame: &[u8], quirks: Quirks) -> Result<(SlaveId, &[u8]), RtuFrameError> {
+    let err = match decode_frame_swapped(frame, quirks.swapped_crc) {
+        Ok(decoded) => return Ok(decoded),
+        Err(e) => e,
+    };
+
+    if quirks.trailing_bytes && frame.len() > MIN_FRAME_SIZE {
+        let end = frame.len().min(MAX_FRAME_SIZE + 1);
+        for size in (MIN_FRAME_SIZE..end).rev() {
+            if let Ok(decoded) = decode_frame_swapped(&frame[..size], quirks.swapped_crc) {
+                return Ok(decoded);
+            }
+        }
+    }
+
+    Err(err)
+}
+
+fn decode_frame_swapped(frame: &[u8], swapped_crc: bool) -> Result<(SlaveId, &[u8]), RtuFrameError> {
+    match decode_frame(frame) {
+        Err(RtuFrameError::Crc { expected, got }) if swapped_crc && expected == got.swap_bytes() => {
+            Ok((SlaveId::new(frame[0]), &frame[1..frame.len() - 2]))
+        }
+        result => result,
+    }
+}
+
+/// Determine the size of an RTU request frame from its first bytes.
+///
+/// Transports which can't detect the silent interval after a frame may use this to tell where a request ends.
+/// The size follows from the function code and, for requests of variable length, the byte count.
+///
+/// # Errors
+/// If more bytes are needed to determine the size [ModbusSerializationError::UnexpectedEOF] is returned with the
+/// number of bytes needed. For functions without a fixed layout, like user defined functions,
+/// [ModbusSerializationError::Invalid] is returned.
+pub fn request_frame_size(start: &[u8]) -> Result<usize, ModbusSerializationError> {
+    // The offset of the byte count and the size of the frame without the counted bytes
+    let (count_at, size) = match start.get(1) {
+        Some(1..=6 | 8) => return Ok(8),
+        Some(7 | 11 | 12 | 17) => return Ok(4),
+        Some(22) => return Ok(10),
+        Some(24) => return Ok(6),
+        Some(15 | 16) => (6, 9),
+        Some(20 | 21) => (2, 5),
+        Some(23) => (10, 13),
+        Some(43) => match start.get(2) {
+            // Read device identification
+            Some(0x0E) => return Ok(7),
+            Some(_) => return Err(ModbusSerializationError::Invalid),
+            None => (2, 0),
+        },
+        Some(_) => return Err(ModbusSerializationError::Invalid),
+        None => (1, 0),
+    };
+
+    match start.get(count_at) {
+        Some(count) if size > 0 => Ok(size + *count as usize),
+        _ => Err(ModbusSerializationError::UnexpectedEOF {
+            expected: count_at + 1,
+            got: start.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crc_spec() {
+        // Read holding registers example, the CRC is transmitted as 0x84 0x0A
+        assert_eq!(crc16(&[1, 3, 0, 0, 0, 1]), 0x0A84);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn write_frame_pdu() {
+        let mut out = [0; 16];
+        let size = write_frame(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut out).unwrap();
+
+        assert_eq!(size, 8);
+        assert_eq!(&out[..size], &[1, 3, 0, 0, 0, 1, 0x84, 0x0A]);
+    }
+
+    #[test]
+    fn write_frame_fail() {
+        let mut out = [0; MAX_FRAME_SIZE + 1];
+        assert_eq!(
+            write_frame(SlaveId::new(1), &[0; MAX_PDU_SIZE + 1], &mut out),
+            Err(ModbusSerializationError::TooLarge)
+        );
+        assert_eq!(
+            write_frame(SlaveId::new(1), &[3, 0, 0, 0, 1], &mut out[..7]),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 8, got: 7 })
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let (slave, pdu) = decode_frame(&[1, 3, 0, 0, 0, 1, 0x84, 0x0A]).unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(pdu, &[3, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn decode_fail() {
+        assert_eq!(
+            decode_frame(&[1, 3, 0, 0, 0, 1, 0x84, 0x0B]),
+            Err(RtuFrameError::Crc {
+                expected: 0x0A84,
+                got: 0x0B84
+            })
+        );
+        assert_eq!(decode_frame(&[1, 3, 0x84]), Err(RtuFrameError::Length));
+        assert_eq!(decode_frame(&[0; MAX_FRAME_SIZE + 1]), Err(RtuFrameError::Length));
+    }
+
+    #[test]
+    fn decode_quirks() {
+        let swapped = [1, 3, 0, 0, 0, 1, 0x0A, 0x84];
+        let padded = [1, 3, 0, 0, 0, 1, 0x84, 0x0A, 0xFF];
+        assert!(matches!(decode_frame(&swapped), Err(RtuFrameError::Crc { .. })));
+        assert!(matches!(decode_frame_with_quirks(&padded, Quirks::STRICT), Err(RtuFrameError::Crc { .. })));
+
+        let quirks = Quirks { swapped_crc: true, ..Quirks::STRICT };
+        assert_eq!(decode_frame_with_quirks(&swapped, quirks), Ok((SlaveId::new(1), &[3, 0, 0, 0, 1][..])));
+        assert!(decode_frame_with_quirks(&padded, quirks).is_err());
+
+        let quirks = Quirks { trailing_bytes: true, ..Quirks::STRICT };
+        assert_eq!(decode_frame_with_quirks(&padded, quirks), Ok((SlaveId::new(1), &[3, 0, 0, 0, 1][..])));
+        assert_eq!(
+            decode_frame_with_quirks(&[1, 3, 0, 0, 0, 1, 0x84, 0x0B, 0], quirks),
+            Err(RtuFrameError::Crc { expected: 0x000A, got: 0x000B })
+        );
+
+        let mut padded = [0xFF; MAX_FRAME_SIZE + 2];
+        padded[..8].copy_from_slice(&[1, 3, 0, 0, 0, 1, 0x84, 0x0A]);
+        assert_eq!(decode_frame_with_quirks(&padded, quirks), Ok((SlaveId::new(1), &[3, 0, 0, 0, 1][..])));
+    }
+
+    #[test]
+    fn request_size() {
+        assert_eq!(request_frame_size(&[1, 3]), Ok(8));
+        assert_eq!(request_frame_size(&[1, 17]), Ok(4));
+        assert_eq!(request_frame_size(&[1, 16, 0, 1, 0, 2, 4]), Ok(13));
+        assert_eq!(request_frame_size(&[1, 23, 0, 1, 0, 2, 0, 3, 0, 1, 2]), Ok(15));
+        assert_eq!(request_frame_size(&[1, 43, 0x0E]), Ok(7));
+        assert_eq!(
+            request_frame_size(&[1, 16, 0, 1]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 7, got: 4 })
+        );
+        assert_eq!(
+            request_frame_size(&[1, 43]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 3, got: 2 })
+        );
+        assert_eq!(
+            request_frame_size(&[1]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 2, got: 1 })
+        );
+        assert_eq!(request_frame_size(&[1, 0x41]), Err(ModbusSerializationError::Invalid));
+        assert_eq!(request_frame_size(&[1, 43, 0x0D]), Err(ModbusSerializationError::Invalid));
+    }
+}
@@ -0,0 +1,316 @@
+//! A poll driven Modbus RTU slave for targets without an async executor.
+//!
+//! The [RtuSlave] implements the RTU reception state machine of the serial line specification.
+//! It is fed with every received byte, e.g. from a UART interrupt, and with the expiry of a timer measuring the
+//! silent interval of 3.5 characters which ends a frame. Once a frame is complete the request is passed to a
+//! handler and the response frame is written to a buffer of the caller, which then transmits it.
+//!
+//! ```
+//! use modbius_core::{rtu::MAX_FRAME_SIZE, rtuslave::RtuSlave, Request, SlaveId};
+//!
+//! let mut slave = RtuSlave::new(SlaveId::new(1));
+//! let mut response = [0; MAX_FRAME_SIZE];
+//! slave.on_timer_expired(&mut response, |_, _, _| unreachable!());
+//!
+//! // Restart the timer after every byte
+//! for byte in [1, 3, 0, 0, 0, 1, 0x84, 0x0A] {
+//!     slave.on_byte(byte);
+//! }
+//!
+//! let len = slave.on_timer_expired(&mut response, |_slave, request, response| match request {
+//!     Request::ReadHoldingRegisters(_) => {
+//!         response[..4].copy_from_slice(&[3, 2, 0x12, 0x34]);
+//!         Ok(4)
+//!     }
+//!     _ => Err(modbius_core::ExceptionCode::IllegalFunction),
+//! });
+//! assert_eq!(&response[..len], &[1, 3, 2, 0x12, 0x34, 0xB5, 0x33]);
+//! ```
+
+use crate::{
+    rtu::{self, MAX_FRAME_SIZE},
+    ExceptionCode, ExceptionResponse, ModbusFunction, Quirks, Request, SlaveId, MAX_PDU_SIZE,
+};
+
+/// The state of an [RtuSlave]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RtuSlaveState {
+    /// The slave waits for a first silent interval to not start receiving in the middle of a frame
+    Initial,
+    /// The slave waits for the first byte of a frame
+    Idle,
+    /// The slave receives a frame
+    Reception,
+    /// More bytes than a frame can hold were received, they are discarded until the frame ends
+    Discard,
+}
+
+/// A Modbus RTU slave driven by byte and timer events.
+///
+/// The slave uses a fixed size buffer for the received frame and never allocates. The caller has to restart a timer
+/// of 3.5 character times after every byte passed to [on_byte](Self::on_byte) and call
+/// [on_timer_expired](Self::on_timer_expired) once it expires. For baud rates above 19200 the specification
+/// recommends a fixed interval of 1750µs.
+///
+/// Frames with CRC errors and frames addressed to other slaves are ignored. Broadcasts are passed to the handler
+/// but never answered.
+#[derive(Debug, Clone)]
+pub struct RtuSlave {
+    slave: SlaveId,
+    quirks: Quirks,
+    state: RtuSlaveState,
+    frame: [u8; MAX_FRAME_SIZE],
+    len: usize,
+}
+
+impl RtuSlave {
+    /// Create a slave in the [Initial](RtuSlaveState::Initial) state.
+    ///
+    /// The caller should start the timer right away, the slave only receives frames after a first silent interval.
+    pub const fn new(slave: SlaveId) -> Self {
+        Self {
+            slave,
+            quirks: Quirks::STRICT,
+            state: RtuSlaveState::Initial,
+            frame: [0; MAX_FRAME_SIZE],
+            len: 0,
+        }
+    }
+
+    pub const fn slave(&self) -> SlaveId {
+        self.slave
+    }
+
+    pub fn set_slave(&mut self, slave: SlaveId) {
+        self.slave = slave;
+    }
+
+    pub const fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the deviations from the specification to accept in received frames and requests
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub const fn state(&self) -> RtuSlaveState {
+        self.state
+    }
+
+    /// Drop a partially received frame and go back to the [Initial](RtuSlaveState::Initial) state, e.g. after a
+    /// framing or parity error of the UART.
+    pub fn reset(&mut self) {
+        self.state = RtuSlaveState::Initial;
+        self.len = 0;
+    }
+
+    /// Handle a received byte.
+    ///
+    /// The silent interval timer has to be restarted after every byte.
+    pub fn on_byte(&mut self, byte: u8) {
+        match self.state {
+            RtuSlaveState::Initial | RtuSlaveState::Discard => (),
+            RtuSlaveState::Idle => {
+                self.frame[0] = byte;
+                self.len = 1;
+                self.state = RtuSlaveState::Reception;
+            }
+            RtuSlaveState::Reception if self.len == MAX_FRAME_SIZE => {
+                self.len = 0;
+                self.state = RtuSlaveState::Discard;
+            }
+            RtuSlaveState::Reception => {
+                self.frame[self.len] = byte;
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Handle the end of a silent interval.
+    ///
+    /// If a complete request addressed to this slave was received it is parsed and passed to handler, which gets
+    /// the addressed slave id, the request and a buffer of [MAX_PDU_SIZE] bytes for the response PDU, just like the
+    /// `ModbusHandler` of modbius-traits. Requests which can't be parsed and requests the handler fails on are
+    /// answered with an exception response.
+    ///
+    /// The len of the response frame written to response is returned, 0 if nothing has to be transmitted.
+    pub fn on_timer_expired<F>(&mut self, response: &mut [u8; MAX_FRAME_SIZE], handler: F) -> usize
+    where
+        F: FnOnce(SlaveId, Request<'_>, &mut [u8]) -> Result<usize, ExceptionCode>,
+    {
+        let state = self.state;
+        let len = self.len;
+        self.state = RtuSlaveState::Idle;
+        self.len = 0;
+        if state != RtuSlaveState::Reception {
+            return 0;
+        }
+
+        let Ok((slave, pdu)) = rtu::decode_frame_with_quirks(&self.frame[..len], self.quirks) else {
+            return 0;
+        };
+        if !self.slave.must_react(slave) {
+            return 0;
+        }
+
+        let function = ModbusFunction::new(pdu[0]);
+        let pdu_buf = &mut response[1..MAX_PDU_SIZE + 1];
+        let result = match Request::from_data_with_quirks(pdu, self.quirks) {
+            Ok((request, _tail)) => handler(slave, request, pdu_buf),
+            Err(e) => Err(ExceptionCode::from(e)),
+        };
+        let pdu_len = match result {
+            Ok(pdu_len) => pdu_len.min(MAX_PDU_SIZE),
+            Err(code) => {
+                pdu_buf[..2].copy_from_slice(&ExceptionResponse::new(function, code).into_data());
+                2
+            }
+        };
+        if slave.is_broadcast() || pdu_len == 0 {
+            return 0;
+        }
+
+        response[0] = slave.into();
+        let crc = rtu::crc16(&response[..pdu_len + 1]);
+        response[pdu_len + 1..pdu_len + 3].copy_from_slice(&crc.to_le_bytes());
+        rtu::frame_size(pdu_len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::read::ReadHoldingRegisters;
+
+    /// Answer read holding registers requests with the register address as value
+    fn handler(_slave: SlaveId, request: Request<'_>, response: &mut [u8]) -> Result<usize, ExceptionCode> {
+        match request {
+            Request::ReadHoldingRegisters(ReadHoldingRegisters { addr, quantity: 1 }) => {
+                response[0] = 3;
+                response[1] = 2;
+                response[2..4].copy_from_slice(&addr.to_be_bytes());
+                Ok(4)
+            }
+            Request::ReadHoldingRegisters(_) => Err(ExceptionCode::IllegalDataAddress),
+            _ => Err(ExceptionCode::IllegalFunction),
+        }
+    }
+
+    fn feed(slave: &mut RtuSlave, slave_id: u8, pdu: &[u8]) {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let len = rtu::write_frame(SlaveId::new(slave_id), pdu, &mut frame).unwrap();
+        for byte in &frame[..len] {
+            slave.on_byte(*byte);
+        }
+    }
+
+    fn ready() -> RtuSlave {
+        let mut slave = RtuSlave::new(SlaveId::new(1));
+        assert_eq!(slave.on_timer_expired(&mut [0; MAX_FRAME_SIZE], handler), 0);
+        assert_eq!(slave.state(), RtuSlaveState::Idle);
+        slave
+    }
+
+    #[test]
+    fn initial_silence() {
+        let mut slave = RtuSlave::new(SlaveId::new(1));
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        // The tail of a frame received while starting up is ignored
+        feed(&mut slave, 1, &[3, 0, 0, 0, 1]);
+        assert_eq!(slave.state(), RtuSlaveState::Initial);
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+        assert_eq!(slave.state(), RtuSlaveState::Idle);
+    }
+
+    #[test]
+    fn respond() {
+        let mut slave = ready();
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        feed(&mut slave, 1, &[3, 0, 0x12, 0, 1]);
+        assert_eq!(slave.state(), RtuSlaveState::Reception);
+        let len = slave.on_timer_expired(&mut response, handler);
+        assert_eq!(&response[..len], &[1, 3, 2, 0, 0x12, 0x38, 0x49]);
+        assert_eq!(slave.state(), RtuSlaveState::Idle);
+
+        feed(&mut slave, 1, &[3, 0, 0, 0, 2]);
+        let len = slave.on_timer_expired(&mut response, handler);
+        assert_eq!(&response[..len], &[1, 0x83, 2, 0xC0, 0xF1]);
+
+        // Requests which can't be parsed
+        feed(&mut slave, 1, &[3, 0, 0, 0, 0]);
+        let len = slave.on_timer_expired(&mut response, handler);
+        assert_eq!(&response[..len], &[1, 0x83, 3, 0x01, 0x31]);
+    }
+
+    #[test]
+    fn ignore() {
+        let mut slave = ready();
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        // Another slave
+        feed(&mut slave, 2, &[3, 0, 0, 0, 1]);
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+
+        // A CRC error
+        for byte in [1, 3, 0, 0, 0, 1, 0x84, 0x0B] {
+            slave.on_byte(byte);
+        }
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+
+        // A broadcast is handled but not answered
+        let mut handled = false;
+        feed(&mut slave, 0, &[6, 0, 1, 0, 3]);
+        let len = slave.on_timer_expired(&mut response, |slave, _, _| {
+            handled = slave.is_broadcast();
+            Ok(5)
+        });
+        assert_eq!(len, 0);
+        assert!(handled);
+
+        // A silent interval without a frame
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut slave = ready();
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        for _ in 0..MAX_FRAME_SIZE + 1 {
+            slave.on_byte(1);
+        }
+        assert_eq!(slave.state(), RtuSlaveState::Discard);
+        slave.on_byte(1);
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+
+        feed(&mut slave, 1, &[3, 0, 0, 0, 1]);
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 7);
+    }
+
+    #[test]
+    fn quirks() {
+        let mut slave = ready();
+        slave.set_quirks(Quirks { swapped_crc: true, ..Quirks::STRICT });
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        for byte in [1, 3, 0, 0, 0, 1, 0x0A, 0x84] {
+            slave.on_byte(byte);
+        }
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 7);
+    }
+
+    #[test]
+    fn reset() {
+        let mut slave = ready();
+        let mut response = [0; MAX_FRAME_SIZE];
+
+        slave.on_byte(1);
+        slave.reset();
+        assert_eq!(slave.state(), RtuSlaveState::Initial);
+        feed(&mut slave, 1, &[3, 0, 0, 0, 1]);
+        assert_eq!(slave.on_timer_expired(&mut response, handler), 0);
+    }
+}
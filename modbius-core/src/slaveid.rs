@@ -8,7 +8,7 @@
 /// None is returned if data contains less than 1 byte
 pub fn get_slaveid(data: &[u8]) -> (Option<SlaveId>, Option<&[u8]>) {
     (
-        data.get(0).map(|byte| SlaveId::new(*byte)),
+        data.first().map(|byte| SlaveId::new(*byte)),
         data.get(1..),
     )
 }
@@ -83,7 +83,7 @@ impl SlaveId {
         get_slaveid(data)
     }
 
-   /// Gets the slave id of the given modbus data.
+    /// Gets the slave id of the given modbus data.
     ///
     /// # Safety
     /// Providing data with less than one byte is undefined behavior
@@ -98,9 +98,9 @@ impl From<u8> for SlaveId {
     }
 }
 
-impl Into<u8> for SlaveId {
-    fn into(self) -> u8 {
-        self.0
+impl From<SlaveId> for u8 {
+    fn from(sid: SlaveId) -> u8 {
+        sid.0
     }
 }
 
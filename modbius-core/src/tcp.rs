@@ -0,0 +1,234 @@
+//! Modbus TCP framing.
+//!
+//! A Modbus TCP ADU consists out of the MBAP header followed by the PDU.
+//! See <https://modbus.org/docs/Modbus_Messaging_Implementation_Guide_V1_0b.pdf> page 5 for reference.
+
+use crate::{util, ModbusSerializationError, Quirks, SlaveId, MAX_PDU_SIZE};
+
+/// The size of the MBAP header
+pub const MBAP_HEADER_SIZE: usize = 7;
+
+/// The maximum size of a Modbus TCP ADU
+pub const MAX_ADU_SIZE: usize = MBAP_HEADER_SIZE + MAX_PDU_SIZE;
+
+/// The protocol id identifying modbus data
+pub const MODBUS_PROTOCOL_ID: u16 = 0;
+
+/// The Modbus Application Protocol header preceding every PDU sent over TCP.
+#[derive(Debug, Clone, Copy, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MbapHeader {
+    /// Identifies the transaction, a server copies it from the request to the response
+    pub transaction_id: u16,
+    /// Always [MODBUS_PROTOCOL_ID] for modbus data
+    pub protocol_id: u16,
+    /// The number of following bytes, the unit id + the PDU
+    pub length: u16,
+    /// The unit id, the slave id of a device behind a gateway
+    pub unit_id: SlaveId,
+}
+
+impl MbapHeader {
+    /// Create a new header for a PDU of the given len
+    pub const fn new(transaction_id: u16, unit_id: SlaveId, pdu_len: u16) -> Self {
+        Self {
+            transaction_id,
+            protocol_id: MODBUS_PROTOCOL_ID,
+            length: pdu_len + 1,
+            unit_id,
+        }
+    }
+
+    /// The len of the PDU following this header
+    pub const fn pdu_len(self) -> usize {
+        self.length.saturating_sub(1) as usize
+    }
+
+    /// Checks if the header describes a modbus PDU of valid size
+    ///
+    /// # Errors
+    /// [ModbusSerializationError::Invalid] is returned if the protocol id is not [MODBUS_PROTOCOL_ID]
+    /// or the length doesn't include at least the unit id and a function code.
+    /// [ModbusSerializationError::TooLarge] is returned if the PDU would be larger than [MAX_PDU_SIZE].
+    pub fn validate(self) -> Result<(), ModbusSerializationError> {
+        self.validate_with_quirks(Quirks::STRICT)
+    }
+
+    /// Checks if the header describes a modbus PDU of valid size accepting the deviations enabled in quirks.
+    ///
+    /// With [any_protocol_id](Quirks::any_protocol_id) the protocol id is not checked.
+    ///
+    /// # Errors
+    /// See [validate](Self::validate).
+    pub fn validate_with_quirks(self, quirks: Quirks) -> Result<(), ModbusSerializationError> {
+        if (self.protocol_id != MODBUS_PROTOCOL_ID && !quirks.any_protocol_id) || self.length < 2 {
+            Err(ModbusSerializationError::Invalid)
+        } else if self.pdu_len() > MAX_PDU_SIZE {
+            Err(ModbusSerializationError::TooLarge)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Parse a header from the given modbus data
+    pub fn from_data(data: &[u8]) -> Result<(Self, &[u8]), ModbusSerializationError> {
+        if data.len() < MBAP_HEADER_SIZE {
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: MBAP_HEADER_SIZE,
+                got: data.len(),
+            })
+        } else {
+            Ok(unsafe { Self::from_data_unchecked(data) })
+        }
+    }
+
+    /// Parse a header from the given modbus data without bounds checks.
+    ///
+    /// # Safety
+    /// This function causes undefined behavior if the len of data is smaller than [MBAP_HEADER_SIZE]
+    pub unsafe fn from_data_unchecked(data: &[u8]) -> (Self, &[u8]) {
+        let (transaction_id, data) = util::read_u16_unchecked(data);
+        let (protocol_id, data) = util::read_u16_unchecked(data);
+        let (length, data) = util::read_u16_unchecked(data);
+        let unit_id = SlaveId::new(*data.get_unchecked(0));
+
+        (
+            Self {
+                transaction_id,
+                protocol_id,
+                length,
+                unit_id,
+            },
+            data.get_unchecked(1..),
+        )
+    }
+
+    /// Create modbus data of the correct size from this header
+    pub fn into_data(self) -> [u8; MBAP_HEADER_SIZE] {
+        let transaction_id = self.transaction_id.to_be_bytes();
+        let protocol_id = self.protocol_id.to_be_bytes();
+        let length = self.length.to_be_bytes();
+        [
+            transaction_id[0],
+            transaction_id[1],
+            protocol_id[0],
+            protocol_id[1],
+            length[0],
+            length[1],
+            self.unit_id.into(),
+        ]
+    }
+
+    /// Write this header to the slice as modbus data
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<(), ModbusSerializationError> {
+        if out.len() < MBAP_HEADER_SIZE {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: MBAP_HEADER_SIZE,
+                got: out.len(),
+            });
+        }
+
+        out[..MBAP_HEADER_SIZE].copy_from_slice(&self.into_data());
+        Ok(())
+    }
+}
+
+/// Write an ADU with the given transaction id, unit id and PDU to the slice.
+///
+/// On success the number of written bytes is returned.
+///
+/// # Errors
+/// If the PDU is larger than [MAX_PDU_SIZE] [ModbusSerializationError::TooLarge] is returned.
+/// If out can't hold the whole ADU [ModbusSerializationError::InsufficientBuffer] is returned.
+pub fn write_adu(
+    transaction_id: u16,
+    unit_id: SlaveId,
+    pdu: &[u8],
+    out: &mut [u8],
+) -> Result<usize, ModbusSerializationError> {
+    if pdu.len() > MAX_PDU_SIZE {
+        return Err(ModbusSerializationError::TooLarge);
+    }
+
+    let size = MBAP_HEADER_SIZE + pdu.len();
+    if out.len() < size {
+        return Err(ModbusSerializationError::InsufficientBuffer {
+            expected: size,
+            got: out.len(),
+        });
+    }
+
+    MbapHeader::new(transaction_id, unit_id, pdu.len() as u16).write_to_slice(out)?;
+    out[MBAP_HEADER_SIZE..size].copy_from_slice(pdu);
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_data() {
+        let data = [0x15, 0x01, 0, 0, 0, 6, 0xFF, 3, 0, 4, 0, 1];
+        let (header, tail) = MbapHeader::from_data(&data).unwrap();
+
+        assert_eq!(header.transaction_id, 0x1501);
+        assert_eq!(header.protocol_id, 0);
+        assert_eq!(header.length, 6);
+        assert_eq!(header.unit_id, SlaveId::new_default_tcp());
+        assert_eq!(header.pdu_len(), 5);
+        assert_eq!(tail, &[3, 0, 4, 0, 1]);
+        assert_eq!(header.validate(), Ok(()));
+    }
+
+    #[test]
+    fn from_data_fail() {
+        let err = MbapHeader::from_data(&[0, 1, 0, 0, 0, 6]).unwrap_err();
+        assert_eq!(err, ModbusSerializationError::UnexpectedEOF { expected: 7, got: 6 });
+    }
+
+    #[test]
+    fn validate() {
+        let header = MbapHeader::new(1, SlaveId::new(1), 0);
+        assert_eq!(header.validate(), Err(ModbusSerializationError::Invalid));
+
+        let header = MbapHeader::new(1, SlaveId::new(1), MAX_PDU_SIZE as u16 + 1);
+        assert_eq!(header.validate(), Err(ModbusSerializationError::TooLarge));
+
+        let mut header = MbapHeader::new(1, SlaveId::new(1), 5);
+        header.protocol_id = 1;
+        assert_eq!(header.validate(), Err(ModbusSerializationError::Invalid));
+        let quirks = Quirks { any_protocol_id: true, ..Quirks::STRICT };
+        assert_eq!(header.validate_with_quirks(quirks), Ok(()));
+    }
+
+    #[test]
+    fn into_data() {
+        let header = MbapHeader::new(0x1501, SlaveId::new(1), 5);
+        assert_eq!(header.into_data(), [0x15, 0x01, 0, 0, 0, 6, 1]);
+
+        let (parsed, _tail) = MbapHeader::from_data(&header.into_data()).unwrap();
+        assert_eq!(parsed, header);
+    }
+
+    #[test]
+    fn write_adu_pdu() {
+        let mut out = [0; 16];
+        let size = write_adu(2, SlaveId::new(17), &[3, 2, 0, 1], &mut out).unwrap();
+
+        assert_eq!(size, 11);
+        assert_eq!(&out[..size], &[0, 2, 0, 0, 0, 5, 17, 3, 2, 0, 1]);
+    }
+
+    #[test]
+    fn write_adu_fail() {
+        let mut out = [0; MAX_ADU_SIZE + 1];
+        assert_eq!(
+            write_adu(2, SlaveId::new(17), &[0; MAX_PDU_SIZE + 1], &mut out),
+            Err(ModbusSerializationError::TooLarge)
+        );
+        assert_eq!(
+            write_adu(2, SlaveId::new(17), &[3, 2, 0, 1], &mut out[..10]),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 11, got: 10 })
+        );
+    }
+}
@@ -6,7 +6,7 @@ use crate::ModbusSerializationError;
 /// If the data slice contains less than 2 bytes a [ModbusSerializationError::UnexpectedEOF] will be returned 
 /// with its expected field set to 2 and its got field set to data.len().
 pub fn read_u16(data: &[u8]) -> Result<(u16, &[u8]), crate::ModbusSerializationError> {
-    if let [Some(dhi), Some(dlo)] = [data.get(0), data.get(1)] {
+    if let [Some(dhi), Some(dlo)] = [data.first(), data.get(1)] {
         Ok((u16::from_be_bytes([*dhi, *dlo]), &data[2..]))
     } else {
         Err(ModbusSerializationError::UnexpectedEOF {
@@ -17,6 +17,9 @@ pub fn read_u16(data: &[u8]) -> Result<(u16, &[u8]), crate::ModbusSerializationE
 }
 
 /// Reads an u16 from the given modbus data without performing bounds checks. The data is considered to be big endian with msB first.
+///
+/// # Safety
+/// This function invokes undefined behavior if the len of data is smaller than 2
 pub unsafe fn read_u16_unchecked(data: &[u8]) -> (u16, &[u8]) {
     let word = u16::from_be_bytes([*data.get_unchecked(0), *data.get_unchecked(1)]);
 
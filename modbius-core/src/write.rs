@@ -1,6 +1,10 @@
 /// Module for write single family of requests.
 mod single;
 mod multiple_registers;
+mod multiple_coils;
+mod mask;
 
 pub use single::*;
 pub use multiple_registers::*;
+pub use multiple_coils::*;
+pub use mask::*;
@@ -0,0 +1,104 @@
+use crate::{util, ModbusSerializationError, PublicModbusFunction};
+
+/// Request structure to modify a holding register with an AND and an OR mask.
+///
+/// The response to this request is an echo of the request.
+#[derive(Debug, Clone, Copy, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaskWriteRegister {
+    pub addr: u16,
+    pub and_mask: u16,
+    pub or_mask: u16,
+}
+
+impl MaskWriteRegister {
+    pub const MODBUS_FUNCTION_CODE: PublicModbusFunction = PublicModbusFunction::MaskWriteRegister;
+
+    pub const fn new(addr: u16, and_mask: u16, or_mask: u16) -> Self {
+        Self { addr, and_mask, or_mask }
+    }
+
+    /// Compute the new content of the register from its current content
+    pub const fn apply(self, current: u16) -> u16 {
+        (current & self.and_mask) | (self.or_mask & !self.and_mask)
+    }
+
+    /// Parse this request from the given modbus data
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &[u8]) -> Result<(Self, &[u8]), ModbusSerializationError> {
+        if data.len() < 6 {
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 6,
+                got: data.len(),
+            })
+        } else {
+            Ok(unsafe { Self::from_data_unchecked(data) })
+        }
+    }
+
+    /// Parse this request from the given modbus data without bounds checks.
+    ///
+    /// # Safety
+    /// This function causes undefined behavior if the len of data is smaller than 6
+    pub unsafe fn from_data_unchecked(data: &[u8]) -> (Self, &[u8]) {
+        let (addr, data) = util::read_u16_unchecked(data);
+        let (and_mask, data) = util::read_u16_unchecked(data);
+        let (or_mask, data) = util::read_u16_unchecked(data);
+
+        (Self::new(addr, and_mask, or_mask), data)
+    }
+
+    /// Create modbus data of the correct size from this request
+    ///
+    /// The format of the array will be [function code, addrhi, addrlo, andhi, andlo, orhi, orlo] in big endian
+    pub fn into_data(self) -> [u8; 7] {
+        let addr = self.addr.to_be_bytes();
+        let and_mask = self.and_mask.to_be_bytes();
+        let or_mask = self.or_mask.to_be_bytes();
+        [
+            Self::MODBUS_FUNCTION_CODE as u8,
+            addr[0],
+            addr[1],
+            and_mask[0],
+            and_mask[1],
+            or_mask[0],
+            or_mask[1],
+        ]
+    }
+
+    /// Write this request to the slice as modbus data
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<(), ModbusSerializationError> {
+        if out.len() < 7 {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: 7,
+                got: out.len(),
+            });
+        }
+
+        out[..7].copy_from_slice(&self.into_data());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_data_spec() {
+        // Example from the modbus spec
+        let data = [0, 4, 0, 0xF2, 0, 0x25, 1];
+        let (req, tail) = MaskWriteRegister::from_data(&data).unwrap();
+
+        assert_eq!(req, MaskWriteRegister::new(4, 0xF2, 0x25));
+        assert_eq!(req.apply(0x12), 0x17);
+        assert_eq!(tail, &[1]);
+        assert_eq!(req.into_data(), [22, 0, 4, 0, 0xF2, 0, 0x25]);
+    }
+
+    #[test]
+    fn from_data_fail() {
+        let err = MaskWriteRegister::from_data(&[0, 4, 0, 0xF2, 0]).unwrap_err();
+        assert_eq!(err, ModbusSerializationError::UnexpectedEOF { expected: 6, got: 5 });
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{bitslice::BitSliceMut, util, BitState, ModbusSerializationError, PublicModbusFunction};
+
+/// Request structure to write multiple coils.
+///
+/// The coil states are packed into bytes, the first coil is the least significant bit of the first byte.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct WriteMultipleCoils<'a> {
+    addr: u16,
+    quantity: u16,
+    bytes: &'a [u8],
+}
+
+impl<'a> WriteMultipleCoils<'a> {
+    pub const MODBUS_FUNCTION_CODE: PublicModbusFunction = PublicModbusFunction::WriteMultipleCoils;
+    /// The maximum number of coils that may be written with one request
+    pub const MAX_QUANTITY: u16 = 0x7B0;
+    /// The minimum size required for a request like Self
+    ///
+    /// The function code is already read for input data so the minimum consists out of
+    /// starting address (2 byte) + quantity (2 byte) + number of following bytes (1 byte) + at least one byte of coils
+    pub const MIN_INPUT_SIZE: usize = 6;
+    /// The header size of a [WriteMultipleCoils] request.
+    ///
+    /// The header consists of:
+    /// function code (1 byte) + starting address (2 byte) + quantity (2 byte) + number of following bytes (1 byte)
+    pub const HEADER_SIZE: usize = 6;
+
+    /// Create a new request to write quantity coils from the packed bytes.
+    ///
+    /// # Errors
+    /// Writing 0 coils returns [ModbusSerializationError::Invalid], more than
+    /// [MAX_QUANTITY](WriteMultipleCoils::MAX_QUANTITY) [ModbusSerializationError::TooLarge].
+    /// If the len of bytes doesn't match the quantity [ModbusSerializationError::Ambivalent] is returned.
+    /// [ModbusSerializationError::Overflow] is returned if the write would go beyond address 0xFFFF.
+    pub fn new(addr: u16, quantity: u16, bytes: &'a [u8]) -> Result<Self, ModbusSerializationError> {
+        match quantity {
+            0 => Err(ModbusSerializationError::Invalid),
+            n if n > Self::MAX_QUANTITY => Err(ModbusSerializationError::TooLarge),
+            n if bytes.len() != (n as usize).div_ceil(8) => Err(ModbusSerializationError::Ambivalent),
+            n if addr as u32 + n as u32 > 0x10000 => Err(ModbusSerializationError::Overflow),
+            _ => Ok(Self { addr, quantity, bytes }),
+        }
+    }
+
+    pub fn addr(self) -> u16 {
+        self.addr
+    }
+
+    pub fn quantity(self) -> u16 {
+        self.quantity
+    }
+
+    /// The packed coil states
+    pub fn bytes(self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Get the state to write to the coil at addr + idx
+    pub fn get(self, idx: usize) -> Option<BitState> {
+        if idx < self.quantity as usize {
+            Some(BitState::from(self.bytes[idx / 8] & (1 << (idx % 8)) != 0))
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the states of all coils to write
+    pub fn states(self) -> impl ExactSizeIterator<Item = BitState> + 'a {
+        (0..self.quantity as usize).map(move |idx| BitState::from(self.bytes[idx / 8] & (1 << (idx % 8)) != 0))
+    }
+
+    /// Parse this request from the given modbus data.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
+    pub fn from_data(data: &'a [u8]) -> Result<(Self, &'a [u8]), ModbusSerializationError> {
+        if data.len() < Self::MIN_INPUT_SIZE {
+            return Err(ModbusSerializationError::UnexpectedEOF {
+                expected: Self::MIN_INPUT_SIZE,
+                got: data.len(),
+            });
+        }
+
+        let (addr, data) = unsafe { util::read_u16_unchecked(data) };
+        let (quantity, data) = unsafe { util::read_u16_unchecked(data) };
+        let nbytes = data[0] as usize;
+
+        match data.get(1..(nbytes + 1)) {
+            Some(bytes) => Ok((Self::new(addr, quantity, bytes)?, &data[(nbytes + 1)..])),
+            None => Err(ModbusSerializationError::UnexpectedEOF {
+                expected: nbytes + Self::HEADER_SIZE - 1,
+                got: Self::HEADER_SIZE - 1 + data.len() - 1,
+            }),
+        }
+    }
+
+    /// Get how many bytes this request needs to be encoded
+    pub fn data_size(self) -> usize {
+        self.bytes.len() + Self::HEADER_SIZE
+    }
+
+    /// Create the modbus data of the response to this request
+    ///
+    /// The format of the array will be [function code, addrhi, addrlo, quantityhi, quantitylo] in big endian
+    pub fn into_response_data(self) -> [u8; 5] {
+        let addr = self.addr.to_be_bytes();
+        let quantity = self.quantity.to_be_bytes();
+        [Self::MODBUS_FUNCTION_CODE as u8, addr[0], addr[1], quantity[0], quantity[1]]
+    }
+
+    /// Write this request to the slice as modbus data
+    pub fn write_to_slice(self, out: &mut [u8]) -> Result<(), ModbusSerializationError> {
+        let data_size = self.data_size();
+        if out.len() < data_size {
+            return Err(ModbusSerializationError::InsufficientBuffer {
+                expected: data_size,
+                got: out.len(),
+            });
+        }
+
+        out[..5].copy_from_slice(&self.into_response_data());
+        out[5] = self.bytes.len() as u8;
+        out[Self::HEADER_SIZE..data_size].copy_from_slice(self.bytes);
+        Ok(())
+    }
+}
+
+/// Builds a [WriteMultipleCoils] request in place.
+///
+/// The header is written to the start of the output buffer on creation, the coil states are set directly behind it
+/// so they are written once and never copied.
+#[derive(Debug)]
+pub struct WriteMultipleCoilsBuilder<'a> {
+    out: &'a mut [u8],
+    quantity: u16,
+}
+
+impl<'a> WriteMultipleCoilsBuilder<'a> {
+    /// Reserve a request writing quantity coils starting at addr at the start of out.
+    ///
+    /// All coils are initialized to [BitState::Off].
+    ///
+    /// # Errors
+    /// The quantity is checked like in [WriteMultipleCoils::new]. If out is too small to hold the request
+    /// [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn new(addr: u16, quantity: u16, out: &'a mut [u8]) -> Result<Self, ModbusSerializationError> {
+        match quantity {
+            0 => return Err(ModbusSerializationError::Invalid),
+            n if n > WriteMultipleCoils::MAX_QUANTITY => return Err(ModbusSerializationError::TooLarge),
+            n if addr as u32 + n as u32 > 0x10000 => return Err(ModbusSerializationError::Overflow),
+            _ => {}
+        }
+
+        let nbytes = (quantity as usize).div_ceil(8);
+        let data_size = WriteMultipleCoils::HEADER_SIZE + nbytes;
+        let got = out.len();
+        let out = out
+            .get_mut(..data_size)
+            .ok_or(ModbusSerializationError::InsufficientBuffer { expected: data_size, got })?;
+
+        out[0] = WriteMultipleCoils::MODBUS_FUNCTION_CODE as u8;
+        out[1..3].copy_from_slice(&addr.to_be_bytes());
+        out[3..5].copy_from_slice(&quantity.to_be_bytes());
+        out[5] = nbytes as u8;
+        out[WriteMultipleCoils::HEADER_SIZE..].fill(0);
+        Ok(Self { out, quantity })
+    }
+
+    /// The states of the coils to write
+    pub fn coils(&mut self) -> BitSliceMut<'_> {
+        unsafe { BitSliceMut::new_unchecked(&mut self.out[WriteMultipleCoils::HEADER_SIZE..], self.quantity as usize) }
+    }
+
+    /// Get how many bytes the request takes
+    pub fn data_size(&self) -> usize {
+        self.out.len()
+    }
+
+    /// Get the request as parsed structure
+    pub fn request(&self) -> WriteMultipleCoils<'_> {
+        WriteMultipleCoils {
+            addr: u16::from_be_bytes([self.out[1], self.out[2]]),
+            quantity: self.quantity,
+            bytes: &self.out[WriteMultipleCoils::HEADER_SIZE..],
+        }
+    }
+
+    /// Finish the request, the written modbus data is returned
+    pub fn finish(self) -> &'a [u8] {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_data_spec() {
+        // Example from the modbus spec, write 10 coils starting at 20
+        let data = [0, 0x13, 0, 0x0A, 2, 0xCD, 0x01, 42];
+        let (req, tail) = WriteMultipleCoils::from_data(&data).unwrap();
+
+        assert_eq!(req.addr(), 0x13);
+        assert_eq!(req.quantity(), 10);
+        assert_eq!(req.bytes(), &[0xCD, 0x01]);
+        assert_eq!(req.get(0), Some(BitState::On));
+        assert_eq!(req.get(1), Some(BitState::Off));
+        assert_eq!(req.get(8), Some(BitState::On));
+        assert_eq!(req.get(9), Some(BitState::Off));
+        assert_eq!(req.get(10), None);
+        assert_eq!(req.states().len(), 10);
+        assert_eq!(req.states().filter(|s| s.is_on()).count(), 6);
+        assert_eq!(tail, &[42]);
+    }
+
+    #[test]
+    fn from_data_fail() {
+        assert_eq!(
+            WriteMultipleCoils::from_data(&[0, 0, 0, 1, 1]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 6, got: 5 })
+        );
+        assert_eq!(
+            WriteMultipleCoils::from_data(&[0, 0, 0, 9, 2, 0]),
+            Err(ModbusSerializationError::UnexpectedEOF { expected: 7, got: 6 })
+        );
+        assert_eq!(
+            WriteMultipleCoils::from_data(&[0, 0, 0, 9, 1, 0]),
+            Err(ModbusSerializationError::Ambivalent)
+        );
+        assert_eq!(
+            WriteMultipleCoils::from_data(&[0, 0, 0, 0, 1, 0]),
+            Err(ModbusSerializationError::Invalid)
+        );
+        assert_eq!(
+            WriteMultipleCoils::from_data(&[0xFF, 0xFF, 0, 2, 1, 0]),
+            Err(ModbusSerializationError::Overflow)
+        );
+    }
+
+    #[test]
+    fn new_fail_too_large() {
+        let bytes = [0; 247];
+        let err = WriteMultipleCoils::new(0, 0x7B1, &bytes).unwrap_err();
+        assert_eq!(err, ModbusSerializationError::TooLarge);
+    }
+
+    #[test]
+    fn write_to_slice() {
+        let req = WriteMultipleCoils::new(0x13, 10, &[0xCD, 0x01]).unwrap();
+        let mut out = [0; 8];
+        req.write_to_slice(&mut out).unwrap();
+
+        assert_eq!(req.data_size(), 8);
+        assert_eq!(out, [15, 0, 0x13, 0, 0x0A, 2, 0xCD, 0x01]);
+        assert_eq!(req.into_response_data(), [15, 0, 0x13, 0, 0x0A]);
+        assert_eq!(
+            req.write_to_slice(&mut out[..7]),
+            Err(ModbusSerializationError::InsufficientBuffer { expected: 8, got: 7 })
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let mut out = [0xFF; 9];
+        let mut builder = WriteMultipleCoilsBuilder::new(0x13, 10, &mut out).unwrap();
+        builder.coils().copy_from_bools(0, &[true, false, true, true, false, false, true, true]).unwrap();
+        builder.coils().set(8, BitState::On).unwrap();
+        assert!(builder.coils().set(10, BitState::On).is_err());
+
+        assert_eq!(builder.data_size(), 8);
+        assert_eq!(builder.request().get(8), Some(BitState::On));
+        assert_eq!(builder.request().states().filter(|s| s.is_on()).count(), 6);
+        assert_eq!(builder.finish(), &[15, 0, 0x13, 0, 0x0A, 2, 0xCD, 0x01]);
+
+        assert_eq!(
+            WriteMultipleCoilsBuilder::new(0x13, 10, &mut out[..7]).unwrap_err(),
+            ModbusSerializationError::InsufficientBuffer { expected: 8, got: 7 }
+        );
+        assert_eq!(WriteMultipleCoilsBuilder::new(0, 0, &mut out).unwrap_err(), ModbusSerializationError::Invalid);
+        assert_eq!(
+            WriteMultipleCoilsBuilder::new(0xFFFF, 2, &mut out).unwrap_err(),
+            ModbusSerializationError::Overflow
+        );
+    }
+}
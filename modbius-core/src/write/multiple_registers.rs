@@ -1,4 +1,7 @@
-use crate::{registerslice::RegisterSlice, util, ModbusSerializationError, PublicModbusFunction};
+use crate::{
+    registerslice::{RegisterSlice, RegisterSliceMut},
+    util, ModbusSerializationError, PublicModbusFunction,
+};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct WriteMultipleRegisters<'a> {
@@ -9,6 +12,8 @@ pub struct WriteMultipleRegisters<'a> {
 impl<'a> WriteMultipleRegisters<'a> {
     pub const MODBUS_FUNCTION_CODE: PublicModbusFunction =
         PublicModbusFunction::WriteMultipleRegisters;
+    /// The maximum number of registers that may be written with one request
+    pub const MAX_QUANTITY: u16 = 123;
     /// The minimum size required for a request like Self
     /// 
     /// Normally the minimum size of a [WriteMultipleRegisters] request consists out of 8 bytes:
@@ -35,7 +40,7 @@ impl<'a> WriteMultipleRegisters<'a> {
     pub fn new(addr: u16, registers: RegisterSlice<'a>) -> Result<Self, ModbusSerializationError> {
         match registers.len() {
             0 => Err(ModbusSerializationError::Invalid),
-            n if n > 123 => Err(ModbusSerializationError::TooLarge),
+            n if n > Self::MAX_QUANTITY as usize => Err(ModbusSerializationError::TooLarge),
             n if addr.overflowing_add(n as u16).1 => Err(ModbusSerializationError::Overflow),
             _ => Ok(unsafe { Self::new_unchecked(addr, registers) }),
         }
@@ -59,6 +64,9 @@ impl<'a> WriteMultipleRegisters<'a> {
         self.registers
     }
 
+    /// Parse this request from the given modbus data.
+    ///
+    /// The data should not contain the function code as it will be already read through other means.
     pub fn from_data(data: &'a [u8]) -> Result<Self, ModbusSerializationError> {
         if data.len() < Self::MIN_INPUT_SIZE {
             Err(ModbusSerializationError::UnexpectedEOF {
@@ -70,6 +78,11 @@ impl<'a> WriteMultipleRegisters<'a> {
         }
     }
 
+    /// Parse this request from the given modbus data without checking the len of the header.
+    ///
+    /// # Safety
+    /// This function invokes undefined behavior if the len of data is smaller than
+    /// [MIN_INPUT_SIZE](WriteMultipleRegisters::MIN_INPUT_SIZE)
     pub unsafe fn from_data_unchecked(data: &'a [u8]) -> Result<Self, ModbusSerializationError> {
         let (addr, data) = util::read_u16_unchecked(data);
 
@@ -98,6 +111,16 @@ impl<'a> WriteMultipleRegisters<'a> {
         self.registers.bytes_len() + Self::HEADER_SIZE
     }
 
+    /// Create the modbus data of the response to this request
+    ///
+    /// The format of the array will be [function code, addrhi, addrlo, quantityhi, quantitylo] in big endian
+    pub fn into_response_data(self) -> [u8; 5] {
+        let addr = self.addr.to_be_bytes();
+        let quantity = (self.registers.len() as u16).to_be_bytes();
+        [Self::MODBUS_FUNCTION_CODE as u8, addr[0], addr[1], quantity[0], quantity[1]]
+    }
+
+    /// Write this request to the slice as modbus data
     pub fn write_to_slice(self, out: &mut [u8]) -> Result<(), ModbusSerializationError> {
         let data_size = self.data_size();
 
@@ -112,6 +135,10 @@ impl<'a> WriteMultipleRegisters<'a> {
         }
     }
 
+    /// Write this request to the slice as modbus data without bounds checking.
+    ///
+    /// # Safety
+    /// This function invokes undefined behavior if the len of out is less than [data_size](WriteMultipleRegisters::data_size)
     pub unsafe fn write_to_slice_unchecked(self, out: &mut [u8]) {
         *out.get_unchecked_mut(0) = Self::MODBUS_FUNCTION_CODE as u8;
 
@@ -132,6 +159,78 @@ impl<'a> WriteMultipleRegisters<'a> {
     }
 }
 
+/// Builds a [WriteMultipleRegisters] request in place.
+///
+/// The header is written to the start of the output buffer on creation, the registers are set directly behind it
+/// so they are written once and never copied.
+///
+/// ```
+/// use modbius_core::write::WriteMultipleRegistersBuilder;
+///
+/// let mut out = [0; 16];
+/// let mut builder = WriteMultipleRegistersBuilder::new(1, 3, &mut out).unwrap();
+/// builder.registers().set(0, 0x000A).unwrap();
+/// builder.registers().set_f32(1, 1.0).unwrap();
+/// assert_eq!(builder.finish(), &[16, 0, 1, 0, 3, 6, 0, 0x0A, 0x3F, 0x80, 0, 0]);
+/// ```
+#[derive(Debug)]
+pub struct WriteMultipleRegistersBuilder<'a> {
+    out: &'a mut [u8],
+}
+
+impl<'a> WriteMultipleRegistersBuilder<'a> {
+    /// Reserve a request writing quantity registers starting at addr at the start of out.
+    ///
+    /// All registers are initialized to 0.
+    ///
+    /// # Errors
+    /// The quantity is checked like in [WriteMultipleRegisters::new]. If out is too small to hold the request
+    /// [ModbusSerializationError::InsufficientBuffer] is returned.
+    pub fn new(addr: u16, quantity: u16, out: &'a mut [u8]) -> Result<Self, ModbusSerializationError> {
+        match quantity {
+            0 => return Err(ModbusSerializationError::Invalid),
+            n if n > WriteMultipleRegisters::MAX_QUANTITY => return Err(ModbusSerializationError::TooLarge),
+            n if addr.overflowing_add(n).1 => return Err(ModbusSerializationError::Overflow),
+            _ => {}
+        }
+
+        let data_size = WriteMultipleRegisters::HEADER_SIZE + quantity as usize * 2;
+        let got = out.len();
+        let out = out
+            .get_mut(..data_size)
+            .ok_or(ModbusSerializationError::InsufficientBuffer { expected: data_size, got })?;
+
+        out[0] = WriteMultipleRegisters::MODBUS_FUNCTION_CODE as u8;
+        out[1..3].copy_from_slice(&addr.to_be_bytes());
+        out[3..5].copy_from_slice(&quantity.to_be_bytes());
+        out[5] = quantity as u8 * 2;
+        out[WriteMultipleRegisters::HEADER_SIZE..].fill(0);
+        Ok(Self { out })
+    }
+
+    /// The registers to write
+    pub fn registers(&mut self) -> RegisterSliceMut<'_> {
+        unsafe { RegisterSliceMut::new_unchecked(&mut self.out[WriteMultipleRegisters::HEADER_SIZE..]) }
+    }
+
+    /// Get how many bytes the request takes
+    pub fn data_size(&self) -> usize {
+        self.out.len()
+    }
+
+    /// Get the request as parsed structure
+    pub fn request(&self) -> WriteMultipleRegisters<'_> {
+        let registers = unsafe { RegisterSlice::new_unchecked(&self.out[WriteMultipleRegisters::HEADER_SIZE..]) };
+        let addr = u16::from_be_bytes([self.out[1], self.out[2]]);
+        unsafe { WriteMultipleRegisters::new_unchecked(addr, registers) }
+    }
+
+    /// Finish the request, the written modbus data is returned
+    pub fn finish(self) -> &'a [u8] {
+        self.out
+    }
+}
+
 #[cfg(test)]
 mod test_write_registers {
     use super::*;
@@ -328,5 +427,45 @@ mod test_write_registers {
         assert_eq!(err, ModbusSerializationError::InsufficientBuffer {expected: req.data_size(), got: 9})
     }
 
+    #[test]
+    fn into_response_data() {
+        let data = [0, 1, 0, 2, 4, 0, 0xA, 1, 2];
+        let req = WriteMultipleRegisters::from_data(&data).unwrap();
+
+        assert_eq!(req.into_response_data(), [16, 0, 1, 0, 2]);
+    }
+
     //TODO check correctness of unchecked versions with correct data
+
+    #[test]
+    fn builder() {
+        let mut out = [0xFF; 12];
+        let mut builder = WriteMultipleRegistersBuilder::new(10, 3, &mut out).unwrap();
+        builder.registers().copy_from_slice(0, &[1, 0xFFFF]).unwrap();
+        builder.registers().set(2, 256).unwrap();
+        assert!(builder.registers().set(3, 0).is_err());
+
+        assert_eq!(builder.data_size(), 12);
+        assert_eq!(builder.request().addr(), 10);
+        assert_eq!(builder.request().registers().get(1), Some(0xFFFF));
+        assert_eq!(builder.finish(), &[16, 0, 10, 0, 3, 6, 0, 1, 255, 255, 1, 0]);
+    }
+
+    #[test]
+    fn builder_fail() {
+        let mut out = [0; 12];
+        assert_eq!(
+            WriteMultipleRegistersBuilder::new(10, 4, &mut out).unwrap_err(),
+            ModbusSerializationError::InsufficientBuffer { expected: 14, got: 12 }
+        );
+        assert_eq!(WriteMultipleRegistersBuilder::new(10, 0, &mut out).unwrap_err(), ModbusSerializationError::Invalid);
+        assert_eq!(
+            WriteMultipleRegistersBuilder::new(0xFFFE, 2, &mut out).unwrap_err(),
+            ModbusSerializationError::Overflow
+        );
+        assert_eq!(
+            WriteMultipleRegistersBuilder::new(0, 124, &mut [0; 260]).unwrap_err(),
+            ModbusSerializationError::TooLarge
+        );
+    }
 }
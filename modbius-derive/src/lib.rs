@@ -0,0 +1,208 @@
+//! Derive macros for modbius.
+//!
+//! Use them through the `derive` feature of `modbius-types`, the generated code refers to that crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, Ident, Lit, LitInt, Type,
+};
+
+/// Implement `modbius_types::map::RegisterMap` for a struct with named fields.
+///
+/// Every field needs a `#[modbus(...)]` attribute with these keys:
+/// - `offset = 10`: The address of the first register of the field, required
+/// - `ty = u32`: The `RegisterValue` the field is stored as, the type of the field if omitted
+/// - `order = CDAB`: The `Layout` of the value, one of ABCD (the default), CDAB, BADC and DCBA
+/// - `scale = 0.1`: The field is a float with the value of the stored integer times scale
+#[proc_macro_derive(ModbusRegisters, attributes(modbus))]
+pub fn derive_modbus_registers(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Field {
+    ident: Ident,
+    ty: Type,
+    offset: LitInt,
+    stored: Type,
+    order: Ident,
+    scale: Option<Lit>,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            Span::call_site(),
+            "ModbusRegisters can only be derived for structs",
+        ));
+    };
+    let Fields::Named(named) = &data.fields else {
+        return Err(Error::new(
+            data.fields.span(),
+            "ModbusRegisters requires named fields",
+        ));
+    };
+
+    let fields = named
+        .named
+        .iter()
+        .map(parse_field)
+        .collect::<Result<Vec<_>, _>>()?;
+    if fields.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "ModbusRegisters requires at least one field",
+        ));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let field_consts = fields.iter().map(|field| {
+        let Field { offset, stored, .. } = field;
+        quote! {
+            ::modbius_types::map::Field::new(
+                #offset,
+                <#stored as ::modbius_types::RegisterValue>::REGISTERS,
+            )
+        }
+    });
+
+    let decodes = fields.iter().enumerate().map(|(idx, field)| {
+        let Field {
+            ident,
+            ty,
+            stored,
+            order,
+            scale,
+            ..
+        } = field;
+        let raw = quote! {
+            <#stored as ::modbius_types::RegisterValue>::decode_with(
+                ::modbius_types::map::field_registers(blocks, Self::FIELDS[#idx])?,
+                ::modbius_types::Layout::#order,
+            )?
+        };
+        let value = match scale {
+            Some(scale) => quote! {
+                (::modbius_types::fixed::FixedRaw::to_f64(#raw) * (#scale as f64)) as #ty
+            },
+            None => raw,
+        };
+        quote! { #ident: #value }
+    });
+
+    let encodes = fields.iter().enumerate().map(|(idx, field)| {
+        let Field {
+            ident,
+            stored,
+            order,
+            scale,
+            ..
+        } = field;
+        let raw = match scale {
+            Some(scale) => quote! {
+                &<#stored as ::modbius_types::fixed::FixedRaw>::from_f64(
+                    self.#ident as f64 / (#scale as f64),
+                )
+                .ok_or(::modbius_types::__core::ModbusSerializationError::TooLarge)?
+            },
+            None => quote! { &self.#ident },
+        };
+        quote! {
+            <#stored as ::modbius_types::RegisterValue>::encode_with(
+                #raw,
+                ::modbius_types::Layout::#order,
+                ::modbius_types::map::field_buf(Self::FIELDS, out, Self::FIELDS[#idx]),
+            )?;
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::modbius_types::map::RegisterMap for #name #ty_generics #where_clause {
+            const FIELDS: &'static [::modbius_types::map::Field] = &[#(#field_consts),*];
+
+            fn decode_blocks(
+                blocks: &[::modbius_types::map::Block<'_>],
+            ) -> ::core::result::Result<Self, ::modbius_types::__core::ModbusSerializationError> {
+                ::core::result::Result::Ok(Self {
+                    #(#decodes),*
+                })
+            }
+
+            fn encode<'b>(
+                &self,
+                out: &'b mut [u8],
+            ) -> ::core::result::Result<
+                ::modbius_types::map::WriteRequests<'b>,
+                ::modbius_types::__core::ModbusSerializationError,
+            > {
+                ::modbius_types::map::clear(Self::FIELDS, out)?;
+                #(#encodes)*
+                ::core::result::Result::Ok(::modbius_types::map::WriteRequests::new(Self::FIELDS, out))
+            }
+        }
+    })
+}
+
+fn parse_field(field: &syn::Field) -> Result<Field, Error> {
+    let ident = field
+        .ident
+        .clone()
+        .ok_or_else(|| Error::new(field.span(), "ModbusRegisters requires named fields"))?;
+
+    let mut offset = None;
+    let mut stored = None;
+    let mut order = None;
+    let mut scale = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("modbus"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("offset") {
+                offset = Some(meta.value()?.parse::<LitInt>()?);
+            } else if meta.path.is_ident("ty") {
+                stored = Some(meta.value()?.parse::<Type>()?);
+            } else if meta.path.is_ident("order") {
+                let ident = meta.value()?.parse::<Ident>()?;
+                if !["ABCD", "CDAB", "BADC", "DCBA"].contains(&ident.to_string().as_str()) {
+                    return Err(Error::new(
+                        ident.span(),
+                        "the order has to be one of ABCD, CDAB, BADC and DCBA",
+                    ));
+                }
+                order = Some(ident);
+            } else if meta.path.is_ident("scale") {
+                let lit = meta.value()?.parse::<Lit>()?;
+                if !matches!(lit, Lit::Int(_) | Lit::Float(_)) {
+                    return Err(Error::new(lit.span(), "the scale has to be a number"));
+                }
+                scale = Some(lit);
+            } else {
+                return Err(meta.error("expected offset, ty, order or scale"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let offset = offset.ok_or_else(|| {
+        Error::new(
+            ident.span(),
+            "missing register address, add #[modbus(offset = ...)]",
+        )
+    })?;
+    Ok(Field {
+        stored: stored.unwrap_or_else(|| field.ty.clone()),
+        ty: field.ty.clone(),
+        offset,
+        order: order.unwrap_or_else(|| Ident::new("ABCD", Span::call_site())),
+        scale,
+        ident,
+    })
+}
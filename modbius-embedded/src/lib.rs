@@ -0,0 +1,29 @@
+#![no_std]
+//! Allocation free modbus servers for microcontrollers.
+//!
+//! The servers run on any stream implementing the [embedded-io-async](embedded_io_async) `Read` and `Write`
+//! traits, e.g. the UARTs and TCP sockets of embassy, and pass requests to the same
+//! [ModbusHandler] as the std server. The frame buffers are provided by the caller so they may live in a static.
+//!
+//! - [RtuServer]: A Modbus RTU slave on a serial line
+//! - [TcpServer]: A Modbus TCP server on a single connection
+//!
+//! ```ignore
+//! let mut uart = BufferedUart::new(/* ... */);
+//! let server = RtuServer::new(SlaveId::new(1), Registers::default());
+//! let mut rx = [0; modbius_core::rtu::MAX_FRAME_SIZE];
+//! let mut tx = [0; modbius_core::rtu::MAX_FRAME_SIZE];
+//! server.serve(&mut uart, &mut rx, &mut tx).await?;
+//! ```
+
+#[cfg(test)]
+extern crate std;
+
+pub mod rtu;
+pub mod tcp;
+
+#[cfg(test)]
+mod pipe;
+
+pub use rtu::RtuServer;
+pub use tcp::TcpServer;
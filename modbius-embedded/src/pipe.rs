@@ -0,0 +1,29 @@
+//! An in-memory pipe implementing the embedded-io-async traits for tests.
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+/// One end of a [tokio::io::duplex] pipe
+pub struct Pipe(pub DuplexStream);
+
+/// Create a connected pair of pipes
+pub fn pipe() -> (Pipe, DuplexStream) {
+    let (local, remote) = tokio::io::duplex(512);
+    (Pipe(local), remote)
+}
+
+impl ErrorType for Pipe {
+    type Error = ErrorKind;
+}
+
+impl Read for Pipe {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+        self.0.read(buf).await.map_err(|_| ErrorKind::Other)
+    }
+}
+
+impl Write for Pipe {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        self.0.write(buf).await.map_err(|_| ErrorKind::Other)
+    }
+}
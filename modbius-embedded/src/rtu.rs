@@ -0,0 +1,234 @@
+//! Modbus RTU slave.
+
+use embedded_io_async::{Read, Write};
+use modbius_core::{
+    rtu::{self, MAX_FRAME_SIZE},
+    ModbusSerializationError, Quirks, SlaveId, MAX_PDU_SIZE,
+};
+use modbius_traits::ModbusHandler;
+
+/// The result of looking for a request at the start of the receive buffer
+enum Frame {
+    /// A valid frame of the given size
+    Complete(usize),
+    /// More bytes are needed
+    Incomplete,
+    /// No valid frame starts at the first byte
+    Invalid,
+}
+
+/// Look for a request frame at the start of received
+fn find_frame(received: &[u8]) -> Frame {
+    match rtu::request_frame_size(received) {
+        Ok(size) if size > MAX_FRAME_SIZE => Frame::Invalid,
+        Ok(size) if received.len() < size => Frame::Incomplete,
+        Ok(size) => match rtu::decode_frame(&received[..size]) {
+            Ok(_) => Frame::Complete(size),
+            Err(_) => Frame::Invalid,
+        },
+        Err(ModbusSerializationError::UnexpectedEOF { .. }) => Frame::Incomplete,
+        // Functions of unknown size are only accepted if the received bytes form a valid frame
+        Err(_) => match rtu::decode_frame(received) {
+            Ok(_) => Frame::Complete(received.len()),
+            Err(_) => Frame::Invalid,
+        },
+    }
+}
+
+/// A Modbus RTU slave passing every request addressed to it to a [ModbusHandler].
+///
+/// Microcontrollers can't always detect the silent interval ending a frame, so the end of a request is determined
+/// from its function code and byte count, see [request_frame_size](rtu::request_frame_size). Requests of other
+/// functions, like user defined functions, are only recognized if they are received in one piece. After a CRC
+/// error the server drops bytes until a valid frame starts.
+///
+/// Frames addressed to other slaves are ignored. Broadcasts are passed to the handler but never answered.
+#[derive(Debug)]
+pub struct RtuServer<H> {
+    slave: SlaveId,
+    handler: H,
+    quirks: Quirks,
+}
+
+impl<H: ModbusHandler + Sync> RtuServer<H> {
+    pub fn new(slave: SlaveId, handler: H) -> Self {
+        Self {
+            slave,
+            handler,
+            quirks: Quirks::STRICT,
+        }
+    }
+
+    pub fn slave(&self) -> SlaveId {
+        self.slave
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the deviations from the specification to accept in received requests
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Serve requests received on the stream using the given receive and transmit buffers.
+    ///
+    /// This function only returns once the stream is closed or fails. Dropping the returned future stops the server.
+    ///
+    /// # Errors
+    /// Errors of the stream are returned, invalid frames are dropped.
+    pub async fn serve<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        rx: &mut [u8; MAX_FRAME_SIZE],
+        tx: &mut [u8; MAX_FRAME_SIZE],
+    ) -> Result<(), S::Error> {
+        let mut len = 0;
+        loop {
+            match find_frame(&rx[..len]) {
+                Frame::Complete(size) => {
+                    self.respond(stream, &rx[..size], tx).await?;
+                    rx.copy_within(size..len, 0);
+                    len -= size;
+                    continue;
+                }
+                Frame::Invalid => {
+                    rx.copy_within(1..len, 0);
+                    len -= 1;
+                    continue;
+                }
+                Frame::Incomplete => {}
+            }
+
+            let n = stream.read(&mut rx[len..]).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            len += n;
+        }
+    }
+
+    /// Answer a valid request frame if it is addressed to this slave
+    async fn respond<S: Write>(
+        &self,
+        stream: &mut S,
+        frame: &[u8],
+        tx: &mut [u8; MAX_FRAME_SIZE],
+    ) -> Result<(), S::Error> {
+        let Ok((slave, request)) = rtu::decode_frame(frame) else {
+            return Ok(());
+        };
+        if !self.slave.must_react(slave) {
+            return Ok(());
+        }
+
+        let response: &mut [u8; MAX_PDU_SIZE] = (&mut tx[1..MAX_PDU_SIZE + 1])
+            .try_into()
+            .expect("an rtu frame holds the maximum pdu");
+        let len = self
+            .handler
+            .dispatch(slave, request, response, self.quirks)
+            .await;
+        if slave.is_broadcast() || len == 0 {
+            return Ok(());
+        }
+
+        tx[0] = slave.into();
+        let crc = rtu::crc16(&tx[..len + 1]);
+        tx[len + 1..len + 3].copy_from_slice(&crc.to_le_bytes());
+        stream.write_all(&tx[..len + 3]).await?;
+        stream.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{rtu::MAX_FRAME_SIZE, SlaveId};
+    use modbius_server::{DataStore, DataStoreConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::RtuServer;
+    use crate::pipe::pipe;
+
+    #[tokio::test]
+    async fn serve() {
+        let (mut local, mut remote) = pipe();
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        store.write(|tables| tables.holding_registers_mut()[0] = 0x0102);
+        let server = RtuServer::new(SlaveId::new(1), store.clone());
+
+        let client = async {
+            // CRC error, another slave and a broadcast are not answered
+            remote
+                .write_all(&[1, 3, 0, 0, 0, 1, 0x84, 0x0B])
+                .await
+                .unwrap();
+            remote
+                .write_all(&[2, 3, 0, 0, 0, 1, 0x84, 0x39])
+                .await
+                .unwrap();
+            remote
+                .write_all(&[0, 6, 0, 1, 0, 3, 0x99, 0xDA])
+                .await
+                .unwrap();
+            // A request split over several writes
+            remote.write_all(&[1, 3, 0]).await.unwrap();
+            remote.write_all(&[0, 0, 1, 0x84, 0x0A]).await.unwrap();
+
+            let mut response = [0; 7];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [1, 3, 2, 1, 2, 0x38, 0x15]);
+
+            // Exception for an address outside of the store
+            remote
+                .write_all(&[1, 3, 0, 0x0A, 0, 1, 0xA4, 0x08])
+                .await
+                .unwrap();
+            let mut response = [0; 5];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [1, 0x83, 2, 0xC0, 0xF1]);
+            drop(remote);
+        };
+
+        let mut rx = [0; MAX_FRAME_SIZE];
+        let mut tx = [0; MAX_FRAME_SIZE];
+        let (result, ()) = tokio::join!(server.serve(&mut local, &mut rx, &mut tx), client);
+        result.unwrap();
+        store.read(|tables| assert_eq!(tables.holding_registers()[1], 3));
+    }
+
+    #[tokio::test]
+    async fn resync_after_garbage() {
+        let (mut local, mut remote) = pipe();
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        let server = RtuServer::new(SlaveId::new(1), store);
+
+        let client = async {
+            remote.write_all(&[0xFF, 0x10, 1]).await.unwrap();
+            remote
+                .write_all(&[1, 6, 0, 1, 0, 3, 0x98, 0x0B])
+                .await
+                .unwrap();
+            let mut response = [0; 8];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [1, 6, 0, 1, 0, 3, 0x98, 0x0B]);
+            drop(remote);
+        };
+
+        let mut rx = [0; MAX_FRAME_SIZE];
+        let mut tx = [0; MAX_FRAME_SIZE];
+        let (result, ()) = tokio::join!(server.serve(&mut local, &mut rx, &mut tx), client);
+        result.unwrap();
+    }
+}
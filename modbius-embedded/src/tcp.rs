@@ -0,0 +1,188 @@
+//! Modbus TCP server.
+
+use embedded_io_async::{Read, ReadExactError, Write};
+use modbius_core::{
+    tcp::{MbapHeader, MAX_ADU_SIZE, MBAP_HEADER_SIZE},
+    Quirks, MAX_PDU_SIZE,
+};
+use modbius_traits::ModbusHandler;
+
+/// A Modbus TCP server passing every request received on a connection to a [ModbusHandler].
+///
+/// The server serves a single connection at a time, accepting connections is up to the caller, e.g. with an
+/// embassy-net `TcpSocket`. The unit id of a request is passed to the handler as unit.
+#[derive(Debug)]
+pub struct TcpServer<H> {
+    handler: H,
+    quirks: Quirks,
+}
+
+impl<H: ModbusHandler + Sync> TcpServer<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            quirks: Quirks::STRICT,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the deviations from the specification to accept in received headers and requests
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Serve requests received on the connection using the given receive and transmit buffers.
+    ///
+    /// This function returns once the peer closed the connection or sent an invalid MBAP header, after which the
+    /// connection should be closed as it can't be resynchronized.
+    ///
+    /// # Errors
+    /// Errors of the stream are returned.
+    pub async fn serve<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        rx: &mut [u8; MAX_ADU_SIZE],
+        tx: &mut [u8; MAX_ADU_SIZE],
+    ) -> Result<(), S::Error> {
+        loop {
+            let (header, _) = match read_exact(stream, &mut rx[..MBAP_HEADER_SIZE]).await? {
+                Some(()) => MbapHeader::from_data(&rx[..MBAP_HEADER_SIZE])
+                    .expect("the buffer holds a complete header"),
+                None => return Ok(()),
+            };
+            if header.validate_with_quirks(self.quirks).is_err() {
+                return Ok(());
+            }
+
+            let request = &mut rx[MBAP_HEADER_SIZE..MBAP_HEADER_SIZE + header.pdu_len()];
+            if read_exact(stream, request).await?.is_none() {
+                return Ok(());
+            }
+
+            let response: &mut [u8; MAX_PDU_SIZE] = (&mut tx[MBAP_HEADER_SIZE..])
+                .try_into()
+                .expect("an adu holds the maximum pdu");
+            let len = self
+                .handler
+                .dispatch(header.unit_id, request, response, self.quirks)
+                .await;
+            if header.unit_id.is_broadcast() || len == 0 {
+                continue;
+            }
+
+            let response_header =
+                MbapHeader::new(header.transaction_id, header.unit_id, len as u16);
+            tx[..MBAP_HEADER_SIZE].copy_from_slice(&response_header.into_data());
+            stream.write_all(&tx[..MBAP_HEADER_SIZE + len]).await?;
+            stream.flush().await?;
+        }
+    }
+}
+
+/// Fill buf from the stream, None if the stream was closed before
+async fn read_exact<S: Read>(stream: &mut S, buf: &mut [u8]) -> Result<Option<()>, S::Error> {
+    match stream.read_exact(buf).await {
+        Ok(()) => Ok(Some(())),
+        Err(ReadExactError::UnexpectedEof) => Ok(None),
+        Err(ReadExactError::Other(e)) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{tcp::MAX_ADU_SIZE, Quirks};
+    use modbius_server::{DataStore, DataStoreConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::TcpServer;
+    use crate::pipe::pipe;
+
+    #[tokio::test]
+    async fn serve() {
+        let (mut local, mut remote) = pipe();
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        store.write(|tables| tables.holding_registers_mut()[0] = 0x0102);
+        let server = TcpServer::new(store);
+
+        let client = async {
+            remote
+                .write_all(&[0, 7, 0, 0, 0, 6, 1, 3, 0, 0, 0, 1])
+                .await
+                .unwrap();
+            let mut response = [0; 11];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [0, 7, 0, 0, 0, 5, 1, 3, 2, 1, 2]);
+
+            remote
+                .write_all(&[0, 8, 0, 0, 0, 6, 1, 3, 0, 0x0A, 0, 1])
+                .await
+                .unwrap();
+            let mut response = [0; 9];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [0, 8, 0, 0, 0, 3, 1, 0x83, 2]);
+
+            // An invalid protocol id ends the connection
+            remote
+                .write_all(&[0, 9, 0, 1, 0, 6, 1, 3, 0, 0, 0, 1])
+                .await
+                .unwrap();
+        };
+
+        let mut rx = [0; MAX_ADU_SIZE];
+        let mut tx = [0; MAX_ADU_SIZE];
+        let (result, ()) = tokio::join!(server.serve(&mut local, &mut rx, &mut tx), client);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn quirks() {
+        let (mut local, mut remote) = pipe();
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        let mut server = TcpServer::new(store.clone());
+        server.set_quirks(Quirks {
+            any_protocol_id: true,
+            byte_count_mismatch: true,
+            ..Quirks::STRICT
+        });
+
+        let client = async {
+            // A broadcast is executed but not answered
+            remote
+                .write_all(&[0, 1, 0, 0, 0, 6, 0, 6, 0, 1, 0, 5])
+                .await
+                .unwrap();
+
+            // Protocol id 1 and a byte count of 1 for one register are accepted
+            remote
+                .write_all(&[0, 2, 0, 1, 0, 9, 1, 16, 0, 2, 0, 1, 1, 0, 7])
+                .await
+                .unwrap();
+            let mut response = [0; 12];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [0, 2, 0, 0, 0, 6, 1, 16, 0, 2, 0, 1]);
+            drop(remote);
+        };
+
+        let mut rx = [0; MAX_ADU_SIZE];
+        let mut tx = [0; MAX_ADU_SIZE];
+        let (result, ()) = tokio::join!(server.serve(&mut local, &mut rx, &mut tx), client);
+        result.unwrap();
+        assert_eq!(
+            store.read(|tables| tables.holding_registers()[1..3].to_vec()),
+            [5, 7]
+        );
+    }
+}
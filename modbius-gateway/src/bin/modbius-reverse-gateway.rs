@@ -0,0 +1,52 @@
+//! Run an RTU slave on a serial port answering for Modbus TCP devices.
+//!
+//! Usage: `modbius-reverse-gateway <config.toml>`, see the README for the configuration format.
+
+use std::{env, error::Error, fs, process::ExitCode};
+
+use modbius_codec::RtuConfig;
+use modbius_gateway::{config::Parity, ReverseGateway, ReverseGatewayConfig};
+use modbius_server::RtuServer;
+use tokio_serial::SerialPortBuilderExt;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let Some(path) = env::args_os().nth(1) else {
+        eprintln!("usage: modbius-reverse-gateway <config.toml>");
+        return ExitCode::FAILURE;
+    };
+
+    match run(&path).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("modbius-reverse-gateway: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(path: &std::ffi::OsStr) -> Result<(), Box<dyn Error>> {
+    let config = ReverseGatewayConfig::from_toml(&fs::read_to_string(path)?)?;
+    let gateway = ReverseGateway::with_config(config.upstreams()?, config.upstream_config());
+
+    let parity = match config.parity {
+        Parity::None => tokio_serial::Parity::None,
+        Parity::Even => tokio_serial::Parity::Even,
+        Parity::Odd => tokio_serial::Parity::Odd,
+    };
+    let stream = tokio_serial::new(&config.path, config.baud_rate)
+        .parity(parity)
+        .open_native_async()
+        .map_err(|e| format!("failed to open {}: {}", config.path, e))?;
+
+    let server = RtuServer::for_slaves(
+        gateway.slaves(),
+        gateway,
+        RtuConfig::for_baud_rate(config.baud_rate),
+    );
+    tokio::select! {
+        result = server.serve(stream) => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+//! The configuration files of the gateway binaries.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use modbius_core::SlaveId;
+use serde::Deserialize;
+
+use crate::{Route, RoutingTable, Upstream, UpstreamConfig};
+
+/// An error in the gateway configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration is not valid TOML or doesn't match the expected structure
+    Toml(toml::de::Error),
+    /// A route refers to a port that is not configured
+    UnknownPort(String),
+    /// Two ports have the same name
+    DuplicatePort(String),
+    /// A unit id is routed more than once
+    DuplicateUnit(u8),
+    /// The broadcast id can't be routed, broadcasts are forwarded to every port
+    BroadcastUnit,
+    /// A slave id of the reverse gateway has more than one upstream
+    DuplicateSlave(u8),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Toml(e) => write!(f, "invalid configuration: {}", e),
+            Self::UnknownPort(name) => write!(f, "route to unknown port {:?}", name),
+            Self::DuplicatePort(name) => write!(f, "port {:?} is configured more than once", name),
+            Self::DuplicateUnit(unit) => write!(f, "unit {} is routed more than once", unit),
+            Self::BroadcastUnit => write!(f, "the broadcast unit 0 can't be routed"),
+            Self::DuplicateSlave(slave) => write!(f, "slave {} has more than one upstream", slave),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Toml(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+
+/// The parity of a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    None,
+    /// Even parity is the default of the spec
+    #[default]
+    Even,
+    Odd,
+}
+
+/// A serial port with an RTU line
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortConfig {
+    /// The name routes refer to the port with
+    pub name: String,
+    /// The path of the serial device, e.g. `/dev/ttyUSB0`
+    pub path: String,
+    pub baud_rate: u32,
+    #[serde(default)]
+    pub parity: Parity,
+    /// The maximum time to wait for the response of a slave in milliseconds
+    #[serde(default = "default_response_timeout_ms")]
+    pub response_timeout_ms: u64,
+}
+
+impl PortConfig {
+    pub fn response_timeout(&self) -> Duration {
+        Duration::from_millis(self.response_timeout_ms)
+    }
+}
+
+fn default_response_timeout_ms() -> u64 {
+    1000
+}
+
+/// Routes a unit id to a slave on a port
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    pub unit: u8,
+    /// The name of the port
+    pub port: String,
+    /// The slave id on the port, the unit id if omitted
+    pub slave: Option<u8>,
+}
+
+/// The configuration of a gateway
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// The address to accept Modbus TCP connections on
+    pub listen: SocketAddr,
+    /// The maximum number of concurrent TCP connections
+    pub max_connections: Option<usize>,
+    #[serde(default)]
+    pub ports: Vec<PortConfig>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+impl GatewayConfig {
+    /// Parse and check the configuration
+    pub fn from_toml(config: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(config)?;
+        config.routing_table()?;
+        Ok(config)
+    }
+
+    /// Create the routing table, the ports are referred to by their index in [ports](GatewayConfig::ports)
+    pub fn routing_table(&self) -> Result<RoutingTable, ConfigError> {
+        let mut names = HashSet::new();
+        if let Some(port) = self
+            .ports
+            .iter()
+            .find(|port| !names.insert(port.name.as_str()))
+        {
+            return Err(ConfigError::DuplicatePort(port.name.clone()));
+        }
+
+        let mut table = RoutingTable::new();
+        for route in &self.routes {
+            let port = self
+                .ports
+                .iter()
+                .position(|port| port.name == route.port)
+                .ok_or_else(|| ConfigError::UnknownPort(route.port.clone()))?;
+            let unit = SlaveId::new(route.unit);
+            if unit.is_broadcast() {
+                return Err(ConfigError::BroadcastUnit);
+            }
+
+            let slave = SlaveId::new(route.slave.unwrap_or(route.unit));
+            if table.insert(unit, Route::new(port, slave)).is_some() {
+                return Err(ConfigError::DuplicateUnit(route.unit));
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+/// Maps a slave id of the RTU line to a Modbus TCP device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlaveConfig {
+    pub slave: u8,
+    /// The address of the device
+    pub addr: SocketAddr,
+    /// The unit id of the device, the slave id if omitted
+    pub unit: Option<u8>,
+}
+
+/// The configuration of a reverse gateway answering for TCP devices on a serial port
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReverseGatewayConfig {
+    /// The path of the serial device, e.g. `/dev/ttyUSB0`
+    pub path: String,
+    pub baud_rate: u32,
+    #[serde(default)]
+    pub parity: Parity,
+    /// The maximum time to connect to a device and to wait for its response in milliseconds
+    #[serde(default = "default_response_timeout_ms")]
+    pub response_timeout_ms: u64,
+    /// The maximum age of cached read responses in milliseconds, 0 disables the cache
+    #[serde(default)]
+    pub cache_max_age_ms: u64,
+    #[serde(default)]
+    pub slaves: Vec<SlaveConfig>,
+}
+
+impl ReverseGatewayConfig {
+    /// Parse and check the configuration
+    pub fn from_toml(config: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(config)?;
+        config.upstreams()?;
+        Ok(config)
+    }
+
+    /// The upstream of each slave id
+    pub fn upstreams(&self) -> Result<Vec<(SlaveId, Upstream)>, ConfigError> {
+        let mut slaves = HashSet::new();
+        let mut upstreams = Vec::with_capacity(self.slaves.len());
+        for config in &self.slaves {
+            let slave = SlaveId::new(config.slave);
+            if slave.is_broadcast() {
+                return Err(ConfigError::BroadcastUnit);
+            } else if !slaves.insert(slave) {
+                return Err(ConfigError::DuplicateSlave(config.slave));
+            }
+
+            let unit = SlaveId::new(config.unit.unwrap_or(config.slave));
+            upstreams.push((slave, Upstream::new(config.addr, unit)));
+        }
+
+        Ok(upstreams)
+    }
+
+    pub fn upstream_config(&self) -> UpstreamConfig {
+        UpstreamConfig {
+            response_timeout: Duration::from_millis(self.response_timeout_ms),
+            cache_max_age: Duration::from_millis(self.cache_max_age_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::SlaveId;
+
+    use super::{ConfigError, GatewayConfig, Parity, ReverseGatewayConfig};
+    use crate::{Route, Upstream};
+
+    const CONFIG: &str = r#"
+        listen = "0.0.0.0:502"
+
+        [[ports]]
+        name = "line1"
+        path = "/dev/ttyUSB0"
+        baud_rate = 19200
+
+        [[ports]]
+        name = "line2"
+        path = "/dev/ttyUSB1"
+        baud_rate = 9600
+        parity = "none"
+        response_timeout_ms = 300
+
+        [[routes]]
+        unit = 1
+        port = "line1"
+
+        [[routes]]
+        unit = 10
+        port = "line2"
+        slave = 3
+    "#;
+
+    #[test]
+    fn parse() {
+        let config = GatewayConfig::from_toml(CONFIG).unwrap();
+        assert_eq!(config.listen.port(), 502);
+        assert_eq!(config.max_connections, None);
+        assert_eq!(config.ports[0].parity, Parity::Even);
+        assert_eq!(config.ports[0].response_timeout_ms, 1000);
+        assert_eq!(config.ports[1].parity, Parity::None);
+        assert_eq!(config.ports[1].response_timeout_ms, 300);
+
+        let table = config.routing_table().unwrap();
+        assert_eq!(
+            table.get(SlaveId::new(1)),
+            Some(Route::new(0, SlaveId::new(1)))
+        );
+        assert_eq!(
+            table.get(SlaveId::new(10)),
+            Some(Route::new(1, SlaveId::new(3)))
+        );
+        assert_eq!(table.get(SlaveId::new(3)), None);
+    }
+
+    #[test]
+    fn parse_fail() {
+        let unknown = CONFIG.replace("port = \"line2\"", "port = \"line3\"");
+        assert!(matches!(
+            GatewayConfig::from_toml(&unknown),
+            Err(ConfigError::UnknownPort(name)) if name == "line3"
+        ));
+
+        let duplicate = CONFIG.replace("unit = 10", "unit = 1");
+        assert!(matches!(
+            GatewayConfig::from_toml(&duplicate),
+            Err(ConfigError::DuplicateUnit(1))
+        ));
+
+        let broadcast = CONFIG.replace("unit = 10", "unit = 0");
+        assert!(matches!(
+            GatewayConfig::from_toml(&broadcast),
+            Err(ConfigError::BroadcastUnit)
+        ));
+
+        let typo = CONFIG.replace("baud_rate = 9600", "baudrate = 9600");
+        assert!(matches!(
+            GatewayConfig::from_toml(&typo),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    const REVERSE_CONFIG: &str = r#"
+        path = "/dev/ttyUSB0"
+        baud_rate = 9600
+        cache_max_age_ms = 500
+
+        [[slaves]]
+        slave = 1
+        addr = "192.168.0.10:502"
+
+        [[slaves]]
+        slave = 2
+        addr = "192.168.0.11:502"
+        unit = 255
+    "#;
+
+    #[test]
+    fn parse_reverse() {
+        let config = ReverseGatewayConfig::from_toml(REVERSE_CONFIG).unwrap();
+        assert_eq!(config.parity, Parity::Even);
+        let upstream = config.upstream_config();
+        assert_eq!(upstream.response_timeout, Duration::from_secs(1));
+        assert_eq!(upstream.cache_max_age, Duration::from_millis(500));
+
+        assert_eq!(
+            config.upstreams().unwrap(),
+            [
+                (
+                    SlaveId::new(1),
+                    Upstream::new("192.168.0.10:502".parse().unwrap(), SlaveId::new(1))
+                ),
+                (
+                    SlaveId::new(2),
+                    Upstream::new("192.168.0.11:502".parse().unwrap(), SlaveId::new(255))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_reverse_fail() {
+        let duplicate = REVERSE_CONFIG.replace("slave = 2", "slave = 1");
+        assert!(matches!(
+            ReverseGatewayConfig::from_toml(&duplicate),
+            Err(ConfigError::DuplicateSlave(1))
+        ));
+
+        let broadcast = REVERSE_CONFIG.replace("slave = 2", "slave = 0");
+        assert!(matches!(
+            ReverseGatewayConfig::from_toml(&broadcast),
+            Err(ConfigError::BroadcastUnit)
+        ));
+
+        let invalid = REVERSE_CONFIG.replace("192.168.0.11:502", "192.168.0.11");
+        assert!(matches!(
+            ReverseGatewayConfig::from_toml(&invalid),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+//! Forwarding requests to RTU lines.
+
+use std::collections::HashMap;
+
+use modbius_client::Bus;
+use modbius_core::{ExceptionCode, Request, SlaveId, MAX_PDU_SIZE};
+use modbius_traits::{ModbusClient, ModbusHandler, ModbusTransport};
+
+/// Where the requests for a unit id are forwarded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Route {
+    /// The index of the serial port in the ports of the [Gateway]
+    pub port: usize,
+    /// The slave id of the device on the port
+    pub slave: SlaveId,
+}
+
+impl Route {
+    pub const fn new(port: usize, slave: SlaveId) -> Self {
+        Self { port, slave }
+    }
+}
+
+/// Maps the unit ids of the TCP side to serial ports and slave ids
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoutingTable {
+    routes: HashMap<SlaveId, Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route the given unit, the previous route of the unit is returned
+    pub fn insert(&mut self, unit: SlaveId, route: Route) -> Option<Route> {
+        self.routes.insert(unit, route)
+    }
+
+    pub fn remove(&mut self, unit: SlaveId) -> Option<Route> {
+        self.routes.remove(&unit)
+    }
+
+    pub fn get(&self, unit: SlaveId) -> Option<Route> {
+        self.routes.get(&unit).copied()
+    }
+
+    /// Iterate over all units and their routes in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (SlaveId, Route)> + '_ {
+        self.routes.iter().map(|(unit, route)| (*unit, *route))
+    }
+}
+
+impl FromIterator<(SlaveId, Route)> for RoutingTable {
+    fn from_iter<I: IntoIterator<Item = (SlaveId, Route)>>(iter: I) -> Self {
+        Self {
+            routes: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// A [ModbusHandler] forwarding requests to the slaves on one or more serial ports.
+///
+/// Each port is a [Bus], so requests of concurrent TCP connections are queued onto the line by the bus scheduler.
+/// Requests for units without a route are answered with [ExceptionCode::GatewayPathUnavailable],
+/// requests the slave doesn't answer properly with [ExceptionCode::GatewayTargetDeviceFailedToRespond].
+/// Broadcasts are forwarded as broadcasts to every port and never answered.
+pub struct Gateway<T> {
+    ports: Vec<Bus<T>>,
+    routes: RoutingTable,
+}
+
+impl<T: ModbusTransport + Send> Gateway<T> {
+    /// Create a new gateway forwarding requests to the ports according to the routes
+    pub fn new(ports: Vec<Bus<T>>, routes: RoutingTable) -> Self {
+        Self { ports, routes }
+    }
+
+    pub fn ports(&self) -> &[Bus<T>] {
+        &self.ports
+    }
+
+    pub fn routes(&self) -> &RoutingTable {
+        &self.routes
+    }
+}
+
+impl<T> ModbusHandler for Gateway<T>
+where
+    T: ModbusTransport + Send,
+    T::Error: Send,
+{
+    async fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        let mut pdu = [0; MAX_PDU_SIZE];
+        let len = request.write_to_slice(&mut pdu)?;
+        let pdu = &pdu[..len];
+
+        if unit.is_broadcast() {
+            for port in &self.ports {
+                let _ = port
+                    .client(SlaveId::new_broadcast())
+                    .call(pdu, &mut [])
+                    .await;
+            }
+            return Ok(0);
+        }
+
+        let route = self
+            .routes
+            .get(unit)
+            .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+        let port = self
+            .ports
+            .get(route.port)
+            .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+
+        // The response of the slave is passed on as is, this includes exception responses
+        port.client(route.slave)
+            .call(pdu, response)
+            .await
+            .map_err(|_| ExceptionCode::GatewayTargetDeviceFailedToRespond)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_client::{Bus, RtuTransport};
+    use modbius_core::{ExceptionCode, Request, SlaveId};
+    use modbius_server::{DataStore, DataStoreConfig, RtuServer};
+    use modbius_traits::ModbusHandler;
+    use tokio::io::DuplexStream;
+
+    use super::{Gateway, Route, RoutingTable};
+
+    /// Spawn a line with one RTU slave serving a store
+    fn port(slave: u8, store: DataStore) -> Bus<RtuTransport<DuplexStream>> {
+        let (master, line) = tokio::io::duplex(512);
+        tokio::spawn(async move {
+            let server = RtuServer::new(SlaveId::new(slave), store);
+            let _ = server.serve(line).await;
+        });
+        Bus::new(RtuTransport::with_config(
+            master,
+            Default::default(),
+            Duration::from_millis(100),
+        ))
+    }
+
+    async fn handle<T>(
+        gateway: &Gateway<T>,
+        unit: u8,
+        request: &[u8],
+    ) -> Result<Vec<u8>, ExceptionCode>
+    where
+        T: modbius_traits::ModbusTransport + Send,
+        T::Error: Send,
+    {
+        let (request, _tail) = Request::from_data(request).unwrap();
+        let mut response = [0; 253];
+        let len = gateway
+            .handle(SlaveId::new(unit), request, &mut response)
+            .await?;
+        Ok(response[..len].to_vec())
+    }
+
+    fn store() -> DataStore {
+        DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn forward() {
+        let (first, second) = (store(), store());
+        first.write(|tables| tables.holding_registers_mut()[0] = 1);
+        second.write(|tables| tables.holding_registers_mut()[0] = 2);
+
+        let routes: RoutingTable = [
+            (SlaveId::new(1), Route::new(0, SlaveId::new(1))),
+            (SlaveId::new(20), Route::new(1, SlaveId::new(5))),
+            (SlaveId::new(21), Route::new(1, SlaveId::new(6))),
+            (SlaveId::new(30), Route::new(2, SlaveId::new(1))),
+        ]
+        .into_iter()
+        .collect();
+        let gateway = Gateway::new(vec![port(1, first), port(5, second.clone())], routes);
+
+        assert_eq!(
+            handle(&gateway, 1, &[3, 0, 0, 0, 1]).await,
+            Ok(vec![3, 2, 0, 1])
+        );
+        assert_eq!(
+            handle(&gateway, 20, &[3, 0, 0, 0, 1]).await,
+            Ok(vec![3, 2, 0, 2])
+        );
+        assert_eq!(
+            handle(&gateway, 20, &[6, 0, 1, 0, 7]).await,
+            Ok(vec![6, 0, 1, 0, 7])
+        );
+        assert_eq!(second.read(|tables| tables.holding_registers()[1]), 7);
+
+        // Exceptions of the slave are passed on
+        assert_eq!(
+            handle(&gateway, 20, &[3, 0, 20, 0, 1]).await,
+            Ok(vec![0x83, 2])
+        );
+
+        assert_eq!(
+            handle(&gateway, 21, &[3, 0, 0, 0, 1]).await,
+            Err(ExceptionCode::GatewayTargetDeviceFailedToRespond)
+        );
+        assert_eq!(
+            handle(&gateway, 2, &[3, 0, 0, 0, 1]).await,
+            Err(ExceptionCode::GatewayPathUnavailable)
+        );
+        assert_eq!(
+            handle(&gateway, 30, &[3, 0, 0, 0, 1]).await,
+            Err(ExceptionCode::GatewayPathUnavailable)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn broadcast() {
+        let (first, second) = (store(), store());
+        let routes = RoutingTable::new();
+        let gateway = Gateway::new(
+            vec![port(1, first.clone()), port(5, second.clone())],
+            routes,
+        );
+
+        assert_eq!(handle(&gateway, 0, &[6, 0, 1, 0, 9]).await, Ok(vec![]));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(first.read(|tables| tables.holding_registers()[1]), 9);
+        assert_eq!(second.read(|tables| tables.holding_registers()[1]), 9);
+    }
+}
@@ -0,0 +1,17 @@
+//! Modbus TCP to RTU and RTU to TCP gateways.
+//!
+//! The [Gateway] is a handler for the TCP server of `modbius-server`. It forwards every request to a slave on one
+//! of its serial ports according to a [RoutingTable]. The `modbius-gateway` binary runs a gateway described by a
+//! [GatewayConfig](config::GatewayConfig) on serial ports.
+//!
+//! The [ReverseGateway] goes the other way: it is a handler for the RTU server, answering the requests of a serial
+//! master with Modbus TCP devices. The `modbius-reverse-gateway` binary runs one described by a
+//! [ReverseGatewayConfig](config::ReverseGatewayConfig).
+
+pub mod config;
+pub mod gateway;
+pub mod reverse;
+
+pub use config::{ConfigError, GatewayConfig, ReverseGatewayConfig};
+pub use gateway::{Gateway, Route, RoutingTable};
+pub use reverse::{ReverseGateway, Upstream, UpstreamConfig};
@@ -0,0 +1,72 @@
+//! Run a Modbus TCP to RTU gateway on serial ports.
+//!
+//! Usage: `modbius-gateway <config.toml>`, see the README for the configuration format.
+
+use std::{env, error::Error, fs, process::ExitCode, time::Duration};
+
+use modbius_client::{Bus, BusConfig, RtuTransport};
+use modbius_codec::RtuConfig;
+use modbius_gateway::{config::Parity, Gateway, GatewayConfig};
+use modbius_server::{TcpServer, TcpServerConfig};
+use tokio::net::TcpListener;
+use tokio_serial::SerialPortBuilderExt;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let Some(path) = env::args_os().nth(1) else {
+        eprintln!("usage: modbius-gateway <config.toml>");
+        return ExitCode::FAILURE;
+    };
+
+    match run(&path).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("modbius-gateway: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(path: &std::ffi::OsStr) -> Result<(), Box<dyn Error>> {
+    let config = GatewayConfig::from_toml(&fs::read_to_string(path)?)?;
+    let routes = config.routing_table()?;
+
+    let mut ports = Vec::with_capacity(config.ports.len());
+    for port in &config.ports {
+        let parity = match port.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Even => tokio_serial::Parity::Even,
+            Parity::Odd => tokio_serial::Parity::Odd,
+        };
+        let stream = tokio_serial::new(&port.path, port.baud_rate)
+            .parity(parity)
+            .open_native_async()
+            .map_err(|e| format!("failed to open {}: {}", port.path, e))?;
+
+        let transport = RtuTransport::with_config(
+            stream,
+            RtuConfig::for_baud_rate(port.baud_rate),
+            port.response_timeout(),
+        );
+        // Leave time to receive the response after it started
+        let bus_config = BusConfig {
+            timeout: port.response_timeout() + Duration::from_millis(500),
+            ..Default::default()
+        };
+        ports.push(Bus::with_config(transport, bus_config));
+    }
+
+    let mut server_config = TcpServerConfig::default();
+    if let Some(max_connections) = config.max_connections {
+        server_config.max_connections = max_connections;
+    }
+
+    let listener = TcpListener::bind(config.listen).await?;
+    let server = TcpServer::with_config(Gateway::new(ports, routes), server_config);
+    server
+        .serve(listener, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,399 @@
+//! Answering the requests of an RTU master with Modbus TCP devices.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use modbius_client::TcpTransport;
+use modbius_core::{ExceptionCode, Request, SlaveId, MAX_PDU_SIZE};
+use modbius_traits::{ModbusHandler, ModbusTransport};
+use tokio::net::TcpStream;
+
+/// The Modbus TCP device answering for a slave id of the RTU line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Upstream {
+    pub addr: SocketAddr,
+    /// The unit id the requests are sent with
+    pub unit: SlaveId,
+}
+
+impl Upstream {
+    pub const fn new(addr: SocketAddr, unit: SlaveId) -> Self {
+        Self { addr, unit }
+    }
+}
+
+/// The configuration of a [ReverseGateway]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UpstreamConfig {
+    /// The maximum time to connect to a device and the maximum time to wait for its response
+    pub response_timeout: Duration,
+    /// Read responses younger than this are answered from the cache, zero disables the cache
+    pub cache_max_age: Duration,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            response_timeout: Duration::from_secs(1),
+            cache_max_age: Duration::ZERO,
+        }
+    }
+}
+
+struct Connection {
+    upstream: Upstream,
+    transport: tokio::sync::Mutex<Option<TcpTransport<TcpStream>>>,
+}
+
+struct CachedResponse {
+    received: Instant,
+    pdu: Vec<u8>,
+}
+
+/// A [ModbusHandler] forwarding the requests for each slave id to a Modbus TCP device.
+///
+/// Serve it with an [RtuServer](modbius_server::RtuServer) answering for the [slaves](ReverseGateway::slaves)
+/// so a legacy RTU master can poll TCP devices as if they were serial slaves.
+///
+/// The connections are opened on the first request and reopened after errors. Masters often only wait a short
+/// time for an answer, so read responses can be cached for a configurable max age. A write to a slave clears its
+/// cached responses.
+///
+/// Exception responses of the devices are passed on. A slave id without upstream or a device that can't be
+/// connected to is answered with [ExceptionCode::GatewayPathUnavailable], a device that doesn't respond in time
+/// or closes the connection with [ExceptionCode::GatewayTargetDeviceFailedToRespond].
+/// Broadcast writes are sent to every device one after another and never answered.
+pub struct ReverseGateway {
+    upstreams: HashMap<SlaveId, Connection>,
+    config: UpstreamConfig,
+    cache: Mutex<HashMap<(SlaveId, Vec<u8>), CachedResponse>>,
+}
+
+impl ReverseGateway {
+    /// Create a new gateway with the default [UpstreamConfig]
+    pub fn new(upstreams: impl IntoIterator<Item = (SlaveId, Upstream)>) -> Self {
+        Self::with_config(upstreams, UpstreamConfig::default())
+    }
+
+    /// Create a new gateway forwarding the requests for each slave id to its upstream
+    pub fn with_config(
+        upstreams: impl IntoIterator<Item = (SlaveId, Upstream)>,
+        config: UpstreamConfig,
+    ) -> Self {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|(slave, upstream)| {
+                let connection = Connection {
+                    upstream,
+                    transport: tokio::sync::Mutex::new(None),
+                };
+                (slave, connection)
+            })
+            .collect();
+
+        Self {
+            upstreams,
+            config,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &UpstreamConfig {
+        &self.config
+    }
+
+    /// The upstream of the given slave id
+    pub fn upstream(&self, slave: SlaveId) -> Option<Upstream> {
+        self.upstreams
+            .get(&slave)
+            .map(|connection| connection.upstream)
+    }
+
+    /// All slave ids with an upstream in arbitrary order
+    pub fn slaves(&self) -> Vec<SlaveId> {
+        self.upstreams.keys().copied().collect()
+    }
+
+    /// Drop all cached responses
+    pub fn clear_cache(&self) {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn cached(&self, slave: SlaveId, request: &[u8], response: &mut [u8]) -> Option<usize> {
+        let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let cached = cache.get(&(slave, request.to_vec()))?;
+        if cached.received.elapsed() >= self.config.cache_max_age {
+            return None;
+        }
+
+        let out = response.get_mut(..cached.pdu.len())?;
+        out.copy_from_slice(&cached.pdu);
+        Some(cached.pdu.len())
+    }
+
+    fn store(&self, slave: SlaveId, request: &[u8], response: &[u8]) {
+        let max_age = self.config.cache_max_age;
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.retain(|_, cached| cached.received.elapsed() < max_age);
+        cache.insert(
+            (slave, request.to_vec()),
+            CachedResponse {
+                received: Instant::now(),
+                pdu: response.to_vec(),
+            },
+        );
+    }
+
+    fn invalidate(&self, slave: SlaveId) {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(cached, _), _| *cached != slave);
+    }
+
+    async fn forward(
+        &self,
+        connection: &Connection,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        let timeout = self.config.response_timeout;
+        let mut transport = connection.transport.lock().await;
+        if transport.is_none() {
+            let connect = TcpTransport::connect(connection.upstream.addr);
+            let mut connected = tokio::time::timeout(timeout, connect)
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+            connected.set_response_timeout(timeout);
+            *transport = Some(connected);
+        }
+
+        let Some(connected) = transport.as_mut() else {
+            unreachable!("the connection was just opened")
+        };
+        match connected
+            .transact(connection.upstream.unit, request, response)
+            .await
+        {
+            Ok(len) => Ok(len),
+            Err(_) => {
+                // The stream may be out of sync, a new connection is opened for the next request
+                *transport = None;
+                Err(ExceptionCode::GatewayTargetDeviceFailedToRespond)
+            }
+        }
+    }
+}
+
+impl ModbusHandler for ReverseGateway {
+    async fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        let read = matches!(
+            request,
+            Request::ReadCoils(_)
+                | Request::ReadDiscreteInputs(_)
+                | Request::ReadHoldingRegisters(_)
+                | Request::ReadInputRegisters(_)
+        );
+        let mut pdu = [0; MAX_PDU_SIZE];
+        let len = request.write_to_slice(&mut pdu)?;
+        let pdu = &pdu[..len];
+
+        if unit.is_broadcast() {
+            if !read {
+                self.clear_cache();
+                for connection in self.upstreams.values() {
+                    let _ = self.forward(connection, pdu, &mut [0; MAX_PDU_SIZE]).await;
+                }
+            }
+            return Ok(0);
+        }
+
+        let connection = self
+            .upstreams
+            .get(&unit)
+            .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+
+        let cache = read && !self.config.cache_max_age.is_zero();
+        if cache {
+            if let Some(len) = self.cached(unit, pdu, response) {
+                return Ok(len);
+            }
+        } else if !read {
+            self.invalidate(unit);
+        }
+
+        let len = self.forward(connection, pdu, response).await?;
+        // Exception responses are not cached
+        if cache
+            && response
+                .first()
+                .is_some_and(|function| function & 0x80 == 0)
+        {
+            self.store(unit, pdu, &response[..len]);
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, time::Duration};
+
+    use modbius_core::{ExceptionCode, Request, SlaveId};
+    use modbius_server::{DataStore, DataStoreConfig, TcpServer};
+    use modbius_traits::ModbusHandler;
+    use tokio::net::TcpListener;
+
+    use super::{ReverseGateway, Upstream, UpstreamConfig};
+
+    /// Spawn a TCP server serving a store
+    async fn device(store: DataStore) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = TcpServer::new(store)
+                .serve(listener, std::future::pending())
+                .await;
+        });
+        addr
+    }
+
+    fn store(value: u16) -> DataStore {
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        store.write(|tables| tables.holding_registers_mut()[0] = value);
+        store
+    }
+
+    async fn handle(
+        gateway: &ReverseGateway,
+        slave: u8,
+        request: &[u8],
+    ) -> Result<Vec<u8>, ExceptionCode> {
+        let (request, _tail) = Request::from_data(request).unwrap();
+        let mut response = [0; 253];
+        let len = gateway
+            .handle(SlaveId::new(slave), request, &mut response)
+            .await?;
+        Ok(response[..len].to_vec())
+    }
+
+    #[tokio::test]
+    async fn forward() {
+        let (first, second) = (store(1), store(2));
+        let first_addr = device(first).await;
+        let second_addr = device(second.clone()).await;
+        // Nothing listens on a port that was just released
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let gateway = ReverseGateway::with_config(
+            [
+                (SlaveId::new(1), Upstream::new(first_addr, SlaveId::new(1))),
+                (
+                    SlaveId::new(2),
+                    Upstream::new(second_addr, SlaveId::new(255)),
+                ),
+                (SlaveId::new(3), Upstream::new(closed, SlaveId::new(1))),
+            ],
+            UpstreamConfig {
+                response_timeout: Duration::from_millis(500),
+                ..Default::default()
+            },
+        );
+        let mut slaves = gateway.slaves();
+        slaves.sort();
+        assert_eq!(slaves, [SlaveId::new(1), SlaveId::new(2), SlaveId::new(3)]);
+
+        assert_eq!(
+            handle(&gateway, 1, &[3, 0, 0, 0, 1]).await,
+            Ok(vec![3, 2, 0, 1])
+        );
+        assert_eq!(
+            handle(&gateway, 2, &[3, 0, 0, 0, 1]).await,
+            Ok(vec![3, 2, 0, 2])
+        );
+        assert_eq!(
+            handle(&gateway, 2, &[6, 0, 1, 0, 7]).await,
+            Ok(vec![6, 0, 1, 0, 7])
+        );
+        assert_eq!(second.read(|tables| tables.holding_registers()[1]), 7);
+
+        // Exceptions of the device are passed on
+        assert_eq!(
+            handle(&gateway, 2, &[3, 0, 20, 0, 1]).await,
+            Ok(vec![0x83, 2])
+        );
+
+        assert_eq!(
+            handle(&gateway, 3, &[3, 0, 0, 0, 1]).await,
+            Err(ExceptionCode::GatewayPathUnavailable)
+        );
+        assert_eq!(
+            handle(&gateway, 4, &[3, 0, 0, 0, 1]).await,
+            Err(ExceptionCode::GatewayPathUnavailable)
+        );
+    }
+
+    #[tokio::test]
+    async fn cache() {
+        let store = store(1);
+        let addr = device(store.clone()).await;
+        let gateway = ReverseGateway::with_config(
+            [(SlaveId::new(1), Upstream::new(addr, SlaveId::new(1)))],
+            UpstreamConfig {
+                cache_max_age: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+
+        let read = [3, 0, 0, 0, 1];
+        assert_eq!(handle(&gateway, 1, &read).await, Ok(vec![3, 2, 0, 1]));
+        store.write(|tables| tables.holding_registers_mut()[0] = 2);
+        assert_eq!(handle(&gateway, 1, &read).await, Ok(vec![3, 2, 0, 1]));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(handle(&gateway, 1, &read).await, Ok(vec![3, 2, 0, 2]));
+
+        // Writing through the gateway clears the cache of the slave
+        assert_eq!(
+            handle(&gateway, 1, &[6, 0, 0, 0, 3]).await,
+            Ok(vec![6, 0, 0, 0, 3])
+        );
+        assert_eq!(handle(&gateway, 1, &read).await, Ok(vec![3, 2, 0, 3]));
+    }
+
+    #[tokio::test]
+    async fn broadcast() {
+        let (first, second) = (store(0), store(0));
+        let first_addr = device(first.clone()).await;
+        let second_addr = device(second.clone()).await;
+        let gateway = ReverseGateway::new([
+            (SlaveId::new(1), Upstream::new(first_addr, SlaveId::new(1))),
+            (SlaveId::new(2), Upstream::new(second_addr, SlaveId::new(1))),
+        ]);
+
+        assert_eq!(handle(&gateway, 0, &[6, 0, 1, 0, 9]).await, Ok(vec![]));
+        assert_eq!(first.read(|tables| tables.holding_registers()[1]), 9);
+        assert_eq!(second.read(|tables| tables.holding_registers()[1]), 9);
+    }
+}
@@ -0,0 +1,35 @@
+//! Blocking counterparts of the [client](crate::client) traits for code without an async runtime.
+//!
+//! The traits have the same methods as their async versions and the same contract.
+
+use modbius_core::SlaveId;
+
+use crate::TransportError;
+
+/// A blocking [ModbusTransport](crate::ModbusTransport)
+pub trait ModbusTransport {
+    type Error: TransportError;
+
+    /// Send the request PDU to the given slave and write the response PDU to response.
+    ///
+    /// On success the number of bytes written to response is returned.
+    fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Self::Error>;
+}
+
+/// A blocking [ModbusClient](crate::ModbusClient)
+pub trait ModbusClient {
+    type Error: TransportError;
+
+    /// The slave id of the device this client talks to
+    fn slave(&self) -> SlaveId;
+
+    /// Send the request PDU to the device and write the response PDU to response.
+    ///
+    /// On success the number of bytes written to response is returned.
+    fn call(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, Self::Error>;
+}
@@ -0,0 +1,65 @@
+//! Client side traits.
+//!
+//! A [ModbusTransport] carries requests to any slave reachable over a line or connection,
+//! a [ModbusClient] talks to exactly one device.
+
+use core::future::Future;
+
+use modbius_core::SlaveId;
+
+/// Errors returned by transports and clients.
+///
+/// Generic client code has to know how an error came to be without knowing the concrete error type.
+pub trait TransportError {
+    /// Checks if the error was caused by a device not answering in time
+    fn is_timeout(&self) -> bool;
+
+    /// Checks if the transaction may succeed when it is sent again, e.g. after a timeout or a corrupted frame.
+    ///
+    /// Defaults to [is_timeout](Self::is_timeout).
+    fn is_retryable(&self) -> bool {
+        self.is_timeout()
+    }
+
+    /// Checks if the connection is broken and has to be established again before the next transaction.
+    ///
+    /// Defaults to false for transports without a connection.
+    fn is_disconnect(&self) -> bool {
+        false
+    }
+}
+
+/// A transport able to carry modbus transactions to the slaves on a line or connection.
+///
+/// Only one transaction may be outstanding at a time on most transports which is why `transact` takes `&mut self`.
+pub trait ModbusTransport {
+    type Error: TransportError;
+
+    /// Send the request PDU to the given slave and write the response PDU to response.
+    ///
+    /// The request consists out of the function code followed by the request data. The slave id or any
+    /// framing is added by the transport. On success the number of bytes written to response is returned.
+    fn transact(
+        &mut self,
+        slave: SlaveId,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send;
+}
+
+/// A client talking to a single modbus device.
+pub trait ModbusClient {
+    type Error: TransportError;
+
+    /// The slave id of the device this client talks to
+    fn slave(&self) -> SlaveId;
+
+    /// Send the request PDU to the device and write the response PDU to response.
+    ///
+    /// On success the number of bytes written to response is returned.
+    fn call(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send;
+}
@@ -0,0 +1,13 @@
+#![no_std]
+//! Traits modbius clients, servers and transports are built upon.
+//!
+//! The traits work on raw modbus PDUs (function code + data) and leave parsing to `modbius-core`.
+//! Anything that is written against these traits (bus arbitration, retry policies, gateways...) works
+//! with every transport implementing them.
+
+pub mod blocking;
+pub mod client;
+pub mod server;
+
+pub use client::{ModbusClient, ModbusTransport, TransportError};
+pub use server::ModbusHandler;
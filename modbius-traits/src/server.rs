@@ -0,0 +1,94 @@
+//! Server side traits.
+
+use core::future::Future;
+
+use modbius_core::{
+    ExceptionCode, ExceptionResponse, ModbusError, ModbusFunction, Quirks, Request, SlaveId,
+    MAX_PDU_SIZE,
+};
+
+/// Handles the requests a modbus server receives.
+///
+/// The same handler can be used for every server regardless of its transport.
+pub trait ModbusHandler {
+    /// Handle the request addressed to the given unit and write the response PDU to response.
+    ///
+    /// The response consists out of the function code followed by the response data, on success its len is returned.
+    /// If an exception code is returned the server answers with an exception response instead.
+    /// Returning a len of 0 suppresses the response, e.g. for broadcasts or units that should stay silent.
+    /// The response buffer always holds at least [MAX_PDU_SIZE](modbius_core::MAX_PDU_SIZE) bytes.
+    fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> impl Future<Output = Result<usize, ExceptionCode>> + Send;
+
+    /// Parse a request PDU received for the given unit before it is passed to [handle](Self::handle).
+    ///
+    /// The default parses standard requests with [Request::from_data_with_quirks]. Handlers of protocol variants
+    /// with their own request layouts, like Enron Modbus, override it to receive such requests as
+    /// [Request::Other]. Servers answer requests which can't be parsed with the exception code of the error.
+    fn parse<'a>(
+        &self,
+        unit: SlaveId,
+        request: &'a [u8],
+        quirks: Quirks,
+    ) -> Result<Request<'a>, ModbusError> {
+        let _ = unit;
+        Request::from_data_with_quirks(request, quirks).map(|(request, _tail)| request)
+    }
+
+    /// Parse the request PDU, pass it to the handler and write the response PDU to response.
+    ///
+    /// Servers call this for every request they receive. Requests which can't be parsed and requests the handler
+    /// fails on are answered with an exception response. The len of the response PDU is returned, 0 if no response
+    /// must be sent. Servers don't answer broadcasts regardless of the returned len.
+    fn dispatch(
+        &self,
+        unit: SlaveId,
+        request: &[u8],
+        response: &mut [u8; MAX_PDU_SIZE],
+        quirks: Quirks,
+    ) -> impl Future<Output = usize> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let function = ModbusFunction::new(request.first().copied().unwrap_or_default());
+            let result = match self.parse(unit, request, quirks) {
+                Ok(request) => self.handle(unit, request, response).await,
+                Err(e) => Err(ExceptionCode::from(e)),
+            };
+
+            match result {
+                Ok(len) => len,
+                Err(code) => {
+                    response[..2]
+                        .copy_from_slice(&ExceptionResponse::new(function, code).into_data());
+                    2
+                }
+            }
+        }
+    }
+}
+
+impl<H: ModbusHandler + Sync> ModbusHandler for &H {
+    fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> impl Future<Output = Result<usize, ExceptionCode>> + Send {
+        (**self).handle(unit, request, response)
+    }
+
+    fn parse<'a>(
+        &self,
+        unit: SlaveId,
+        request: &'a [u8],
+        quirks: Quirks,
+    ) -> Result<Request<'a>, ModbusError> {
+        (**self).parse(unit, request, quirks)
+    }
+}
@@ -0,0 +1,126 @@
+//! Packed ASCII strings.
+//!
+//! Each register holds two characters, the first one in the high byte. Strings shorter than the registers are
+//! padded with NUL bytes.
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+use crate::{write_bytes, RegisterValue};
+
+/// An ASCII string of up to 2 * N characters stored in N registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsciiString<const N: usize> {
+    chars: [[u8; 2]; N],
+}
+
+impl<const N: usize> AsciiString<N> {
+    /// Create a new string padded with NUL bytes.
+    ///
+    /// # Errors
+    /// If s contains non ASCII characters [ModbusSerializationError::Invalid] is returned,
+    /// if it has more than 2 * N characters [ModbusSerializationError::TooLarge].
+    pub fn new(s: &str) -> Result<Self, ModbusSerializationError> {
+        if !s.is_ascii() {
+            return Err(ModbusSerializationError::Invalid);
+        }
+
+        let mut chars = [[0; 2]; N];
+        chars
+            .as_flattened_mut()
+            .get_mut(..s.len())
+            .ok_or(ModbusSerializationError::TooLarge)?
+            .copy_from_slice(s.as_bytes());
+        Ok(Self { chars })
+    }
+
+    /// All 2 * N bytes including the padding
+    pub fn as_bytes(&self) -> &[u8] {
+        self.chars.as_flattened()
+    }
+
+    /// The string without trailing NUL bytes and spaces
+    pub fn as_str(&self) -> &str {
+        let bytes = self.as_bytes();
+        let len = bytes
+            .iter()
+            .rposition(|byte| !matches!(byte, 0 | b' '))
+            .map_or(0, |idx| idx + 1);
+        // Only ASCII is ever stored
+        core::str::from_utf8(&bytes[..len]).unwrap_or_default()
+    }
+}
+
+impl<const N: usize> Default for AsciiString<N> {
+    fn default() -> Self {
+        Self { chars: [[0; 2]; N] }
+    }
+}
+
+impl<const N: usize> RegisterValue for AsciiString<N> {
+    const REGISTERS: usize = N;
+
+    /// Decode the characters of the first N registers.
+    ///
+    /// Bytes which are no ASCII characters are reported as [ModbusSerializationError::Invalid].
+    fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+        let bytes = registers.bytes();
+        let bytes = bytes
+            .get(..N * 2)
+            .ok_or(ModbusSerializationError::UnexpectedEOF {
+                expected: N * 2,
+                got: bytes.len(),
+            })?;
+        if !bytes.is_ascii() {
+            return Err(ModbusSerializationError::Invalid);
+        }
+
+        let mut chars = [[0; 2]; N];
+        chars.as_flattened_mut().copy_from_slice(bytes);
+        Ok(Self { chars })
+    }
+
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+        write_bytes(self.as_bytes(), out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use super::AsciiString;
+    use crate::RegisterValue;
+
+    #[test]
+    fn decode() {
+        let registers = RegisterSlice::new(b"SN-42\0  ").unwrap();
+        let s = AsciiString::<4>::decode(registers).unwrap();
+        assert_eq!(s.as_str(), "SN-42");
+        assert_eq!(s.as_bytes(), b"SN-42\0  ");
+        assert_eq!(AsciiString::<1>::decode(registers).unwrap().as_str(), "SN");
+        assert_eq!(AsciiString::<4>::default().as_str(), "");
+
+        let invalid = RegisterSlice::new(&[b'A', 0xC3]).unwrap();
+        assert_eq!(
+            AsciiString::<1>::decode(invalid),
+            Err(ModbusSerializationError::Invalid)
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0; 6];
+        let s = AsciiString::<3>::new("ABC").unwrap();
+        assert_eq!(s.encode(&mut buf).unwrap().bytes(), b"ABC\0\0\0");
+        assert_eq!(AsciiString::<3>::decode(s.encode(&mut buf).unwrap()), Ok(s));
+
+        assert_eq!(
+            AsciiString::<1>::new("ABC"),
+            Err(ModbusSerializationError::TooLarge)
+        );
+        assert_eq!(
+            AsciiString::<2>::new("Ä"),
+            Err(ModbusSerializationError::Invalid)
+        );
+    }
+}
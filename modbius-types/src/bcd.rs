@@ -0,0 +1,122 @@
+//! Binary coded decimals.
+//!
+//! Every register holds four decimal digits, one per nibble, with the most significant digit in the high nibble
+//! of the first register.
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+use crate::{write_bytes, RegisterValue};
+
+/// A decimal number of up to 4 * N digits stored in N registers, N may be 1 to 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Bcd<const N: usize> {
+    value: u64,
+}
+
+impl<const N: usize> Bcd<N> {
+    /// The largest value that fits into N registers
+    pub const MAX: u64 = 10u64.pow(4 * <Self as RegisterValue>::REGISTERS as u32) - 1;
+
+    /// Create a new BCD value.
+    ///
+    /// # Errors
+    /// If value has more than 4 * N digits [ModbusSerializationError::TooLarge] is returned.
+    pub fn new(value: u64) -> Result<Self, ModbusSerializationError> {
+        if value > Self::MAX {
+            Err(ModbusSerializationError::TooLarge)
+        } else {
+            Ok(Self { value })
+        }
+    }
+
+    pub fn value(self) -> u64 {
+        self.value
+    }
+}
+
+impl<const N: usize> RegisterValue for Bcd<N> {
+    const REGISTERS: usize = {
+        assert!(N >= 1 && N <= 4, "a bcd value has 1 to 4 registers");
+        N
+    };
+
+    /// Decode the digits of the first N registers.
+    ///
+    /// A nibble greater than 9 is no digit and reported as [ModbusSerializationError::Invalid].
+    fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+        let bytes = registers.bytes();
+        let bytes = bytes
+            .get(..N * 2)
+            .ok_or(ModbusSerializationError::UnexpectedEOF {
+                expected: N * 2,
+                got: bytes.len(),
+            })?;
+
+        let mut value = 0;
+        for byte in bytes {
+            for digit in [byte >> 4, byte & 0x0F] {
+                if digit > 9 {
+                    return Err(ModbusSerializationError::Invalid);
+                }
+                value = value * 10 + u64::from(digit);
+            }
+        }
+
+        Ok(Self { value })
+    }
+
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..Self::REGISTERS * 2];
+        let mut value = self.value;
+        for byte in bytes.iter_mut().rev() {
+            *byte = (value % 10) as u8 | ((value / 10 % 10) as u8) << 4;
+            value /= 100;
+        }
+
+        write_bytes(bytes, out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use super::Bcd;
+    use crate::RegisterValue;
+
+    #[test]
+    fn decode() {
+        let registers = RegisterSlice::new(&[0x12, 0x34, 0x56, 0x78]).unwrap();
+        assert_eq!(Bcd::<1>::decode(registers).unwrap().value(), 1234);
+        assert_eq!(Bcd::<2>::decode(registers).unwrap().value(), 12345678);
+        assert_eq!(
+            Bcd::<3>::decode(registers),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 6,
+                got: 4
+            })
+        );
+
+        let invalid = RegisterSlice::new(&[0x12, 0x3A]).unwrap();
+        assert_eq!(
+            Bcd::<1>::decode(invalid),
+            Err(ModbusSerializationError::Invalid)
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0; 8];
+        let value = Bcd::<2>::new(907).unwrap();
+        assert_eq!(value.encode(&mut buf).unwrap().bytes(), &[0, 0, 0x09, 0x07]);
+
+        let max = Bcd::<4>::new(Bcd::<4>::MAX).unwrap();
+        assert_eq!(max.encode(&mut buf).unwrap().bytes(), &[0x99; 8]);
+
+        assert_eq!(
+            Bcd::<1>::new(10000),
+            Err(ModbusSerializationError::TooLarge)
+        );
+    }
+}
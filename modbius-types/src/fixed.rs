@@ -0,0 +1,130 @@
+//! Fixed-point numbers.
+//!
+//! Devices often store decimals as integers scaled by a power of ten, e.g. a voltage of 230.4 V as 2304 with one
+//! decimal.
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+use crate::RegisterValue;
+
+/// An integer type a [Fixed] value can be stored as
+pub trait FixedRaw: RegisterValue + Copy {
+    fn to_f64(self) -> f64;
+
+    /// Convert the rounded value, None if it is out of range
+    fn from_f64(value: f64) -> Option<Self>;
+}
+
+macro_rules! impl_fixed_raw {
+    ($($ty:ty),*) => {
+        $(
+            impl FixedRaw for $ty {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn from_f64(value: f64) -> Option<Self> {
+                    // f64::round is not available without std
+                    let rounded = if value < 0.0 { value - 0.5 } else { value + 0.5 };
+                    (rounded > <$ty>::MIN as f64 - 1.0 && rounded < <$ty>::MAX as f64 + 1.0)
+                        .then_some(rounded as $ty)
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_raw!(u16, i16, u32, i32, u64, i64);
+
+/// A decimal number stored as the integer T scaled by 10^DECIMALS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed<T, const DECIMALS: u32> {
+    raw: T,
+}
+
+impl<T: FixedRaw, const DECIMALS: u32> Fixed<T, DECIMALS> {
+    /// The factor between the value and the raw integer
+    pub const SCALE: f64 = {
+        let mut scale = 1.0;
+        let mut i = 0;
+        while i < DECIMALS {
+            scale *= 10.0;
+            i += 1;
+        }
+        scale
+    };
+
+    /// Create a value from the raw integer stored in the registers
+    pub const fn from_raw(raw: T) -> Self {
+        Self { raw }
+    }
+
+    /// Create a value from a float, it is rounded to DECIMALS decimals.
+    ///
+    /// # Errors
+    /// If the scaled value doesn't fit into T [ModbusSerializationError::TooLarge] is returned.
+    pub fn from_f64(value: f64) -> Result<Self, ModbusSerializationError> {
+        T::from_f64(value * Self::SCALE)
+            .map(Self::from_raw)
+            .ok_or(ModbusSerializationError::TooLarge)
+    }
+
+    pub fn raw(self) -> T {
+        self.raw
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.raw.to_f64() / Self::SCALE
+    }
+}
+
+impl<T: FixedRaw, const DECIMALS: u32> RegisterValue for Fixed<T, DECIMALS> {
+    const REGISTERS: usize = T::REGISTERS;
+
+    fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+        T::decode(registers).map(Self::from_raw)
+    }
+
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+        self.raw.encode(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use super::Fixed;
+    use crate::RegisterValue;
+
+    #[test]
+    fn decode() {
+        let registers = RegisterSlice::new(&[0x09, 0x00, 0xFF, 0xFF]).unwrap();
+        let voltage = Fixed::<u16, 1>::decode(registers).unwrap();
+        assert_eq!(voltage.raw(), 2304);
+        assert_eq!(voltage.to_f64(), 230.4);
+
+        let power = Fixed::<i32, 3>::decode(registers).unwrap();
+        assert_eq!(power.to_f64(), 151060.479);
+        assert_eq!(Fixed::<i16, 0>::decode(registers).unwrap().to_f64(), 2304.0);
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0; 4];
+        let value = Fixed::<i16, 2>::from_f64(-12.345).unwrap();
+        assert_eq!(value.raw(), -1235);
+        assert_eq!(value.encode(&mut buf).unwrap().bytes(), &[0xFB, 0x2D]);
+
+        assert_eq!(Fixed::<u32, 1>::from_f64(0.04).unwrap().raw(), 0);
+        assert_eq!(Fixed::<u16, 1>::from_f64(6553.5).unwrap().raw(), u16::MAX);
+        assert_eq!(
+            Fixed::<u16, 1>::from_f64(6553.6),
+            Err(ModbusSerializationError::TooLarge)
+        );
+        assert_eq!(
+            Fixed::<u16, 0>::from_f64(-1.0),
+            Err(ModbusSerializationError::TooLarge)
+        );
+    }
+}
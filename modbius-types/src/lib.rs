@@ -0,0 +1,185 @@
+#![no_std]
+//! Conversions between modbus registers and typed values.
+//!
+//! Every value stored in one or more registers implements [RegisterValue]. Values are decoded directly from a
+//! [RegisterSlice], e.g. the registers of a read response, and encoded into a byte buffer which is returned as
+//! [RegisterSlice] ready to be passed to [WriteMultipleRegisters::new](modbius_core::write::WriteMultipleRegisters::new).
+//! Multi-register values are stored with the high word in the first register as recommended by the spec, the
+//! `_with` methods and [Ordered] values support the other [Layout]s devices use.
+//!
+//! ```
+//! use modbius_core::{response::ReadRegistersResponse, write::WriteMultipleRegisters};
+//! use modbius_types::RegisterValue;
+//!
+//! let (response, _tail) = ReadRegistersResponse::from_data(&[4, 0x41, 0x20, 0, 0]).unwrap();
+//! assert_eq!(f32::decode(response.registers()), Ok(10.0));
+//!
+//! let mut buf = [0; 4];
+//! let request = WriteMultipleRegisters::new(100, 70000u32.encode(&mut buf).unwrap()).unwrap();
+//! assert_eq!(request.registers().bytes(), &[0, 1, 0x11, 0x70]);
+//! ```
+//!
+//! The conversions never allocate, errors are reported as [ModbusSerializationError].
+
+pub mod ascii;
+pub mod bcd;
+pub mod fixed;
+pub mod map;
+pub mod number;
+pub mod order;
+
+pub use ascii::AsciiString;
+pub use bcd::Bcd;
+pub use fixed::Fixed;
+pub use map::RegisterMap;
+#[cfg(feature = "derive")]
+pub use modbius_derive::ModbusRegisters;
+pub use order::{ByteOrder, Layout, Ordered, WordOrder};
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+// The code generated by modbius-derive refers to this crate by name
+extern crate self as modbius_types;
+#[doc(hidden)]
+pub use modbius_core as __core;
+
+/// The maximum size of a value in bytes, the registers of one read request
+pub const MAX_VALUE_SIZE: usize = 250;
+
+/// A value stored in a fixed number of registers
+pub trait RegisterValue: Sized {
+    /// The number of registers the value occupies
+    const REGISTERS: usize;
+
+    /// Decode the value from the first [REGISTERS](RegisterValue::REGISTERS) registers.
+    ///
+    /// Further registers are ignored.
+    ///
+    /// # Errors
+    /// If there are not enough registers [ModbusSerializationError::UnexpectedEOF] is returned.
+    /// Register contents which are no valid value are reported as [ModbusSerializationError::Invalid].
+    fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError>;
+
+    /// Encode the value into out, the registers of the value are returned.
+    ///
+    /// # Errors
+    /// If out can't hold [REGISTERS](RegisterValue::REGISTERS) registers
+    /// [ModbusSerializationError::InsufficientBuffer] is returned.
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<RegisterSlice<'b>, ModbusSerializationError>;
+
+    /// Decode the value starting at the register with the given index
+    fn decode_at(
+        registers: RegisterSlice<'_>,
+        idx: usize,
+    ) -> Result<Self, ModbusSerializationError> {
+        let bytes = registers.bytes();
+        let tail = bytes
+            .get(idx * 2..)
+            .ok_or(ModbusSerializationError::UnexpectedEOF {
+                expected: (idx + Self::REGISTERS) * 2,
+                got: bytes.len(),
+            })?;
+        Self::decode(RegisterSlice::new(tail)?)
+    }
+
+    /// Decode the value stored in the given layout.
+    ///
+    /// # Errors
+    /// Values larger than [MAX_VALUE_SIZE] are reported as [ModbusSerializationError::TooLarge],
+    /// other errors are the same as for [decode](RegisterValue::decode).
+    fn decode_with(
+        registers: RegisterSlice<'_>,
+        layout: Layout,
+    ) -> Result<Self, ModbusSerializationError> {
+        let size = Self::REGISTERS * 2;
+        let mut buf = [0; MAX_VALUE_SIZE];
+        let buf = buf
+            .get_mut(..size)
+            .ok_or(ModbusSerializationError::TooLarge)?;
+        let bytes = registers.bytes();
+        buf.copy_from_slice(
+            bytes
+                .get(..size)
+                .ok_or(ModbusSerializationError::UnexpectedEOF {
+                    expected: size,
+                    got: bytes.len(),
+                })?,
+        );
+
+        layout.reorder(buf);
+        Self::decode(RegisterSlice::new(buf)?)
+    }
+
+    /// Encode the value in the given layout, see [encode](RegisterValue::encode)
+    fn encode_with<'b>(
+        &self,
+        layout: Layout,
+        out: &'b mut [u8],
+    ) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+        let size = self.encode(out)?.bytes_len();
+        let bytes = &mut out[..size];
+        layout.reorder(bytes);
+        RegisterSlice::new(bytes)
+    }
+}
+
+/// Copy the bytes of the first N / 2 registers
+pub(crate) fn read_bytes<const N: usize>(
+    registers: RegisterSlice<'_>,
+) -> Result<[u8; N], ModbusSerializationError> {
+    let bytes = registers.bytes();
+    let bytes = bytes
+        .get(..N)
+        .ok_or(ModbusSerializationError::UnexpectedEOF {
+            expected: N,
+            got: bytes.len(),
+        })?;
+
+    let mut out = [0; N];
+    out.copy_from_slice(bytes);
+    Ok(out)
+}
+
+/// Copy bytes to the start of out and return them as registers
+pub(crate) fn write_bytes<'b>(
+    bytes: &[u8],
+    out: &'b mut [u8],
+) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+    let got = out.len();
+    let out = out
+        .get_mut(..bytes.len())
+        .ok_or(ModbusSerializationError::InsufficientBuffer {
+            expected: bytes.len(),
+            got,
+        })?;
+    out.copy_from_slice(bytes);
+    RegisterSlice::new(out)
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use crate::RegisterValue;
+
+    #[test]
+    fn decode_at() {
+        let registers = RegisterSlice::new(&[0, 1, 0, 2, 0, 3]).unwrap();
+        assert_eq!(u16::decode_at(registers, 2), Ok(3));
+        assert_eq!(u32::decode_at(registers, 1), Ok(0x0002_0003));
+        assert_eq!(
+            u32::decode_at(registers, 2),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 4,
+                got: 2
+            })
+        );
+        assert_eq!(
+            u16::decode_at(registers, 4),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 10,
+                got: 6
+            })
+        );
+    }
+}
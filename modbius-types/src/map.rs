@@ -0,0 +1,429 @@
+//! Register maps, structs whose fields are stored at fixed register addresses.
+//!
+//! [RegisterMap] is usually implemented with `#[derive(ModbusRegisters)]` from `modbius-derive`, re-exported
+//! here with the `derive` feature. Each field is annotated with its register address and optionally the type it
+//! is stored as, its [Layout](crate::Layout) and a scale:
+//!
+//! ```
+//! # use modbius_derive::ModbusRegisters;
+//! use modbius_core::{read::ReadHoldingRegisters, registerslice::RegisterSlice};
+//! use modbius_types::{map::Block, AsciiString, RegisterMap};
+//!
+//! #[derive(ModbusRegisters)]
+//! struct Meter {
+//!     #[modbus(offset = 0)]
+//!     serial: AsciiString<2>,
+//!     // A u32 in word swapped order with a resolution of 0.1 V
+//!     #[modbus(offset = 10, ty = u32, order = CDAB, scale = 0.1)]
+//!     voltage: f64,
+//!     #[modbus(offset = 12, order = CDAB)]
+//!     power: f32,
+//! }
+//!
+//! // The serial number and the measurements are read and written with one request each
+//! assert!(Meter::read_requests().eq([
+//!     ReadHoldingRegisters::new(0, 2),
+//!     ReadHoldingRegisters::new(10, 4),
+//! ]));
+//!
+//! let serial = RegisterSlice::new(b"SN42").unwrap();
+//! let values = RegisterSlice::new(&[0x09, 0x00, 0, 0, 0, 0, 0x3F, 0xC0]).unwrap();
+//! let meter = Meter::decode_blocks(&[Block::new(0, serial), Block::new(10, values)]).unwrap();
+//! assert_eq!(meter.serial.as_str(), "SN42");
+//! assert_eq!(meter.voltage, 230.4);
+//! assert_eq!(meter.power, 1.5);
+//! ```
+
+use modbius_core::{
+    read::ReadHoldingRegisters, registerslice::RegisterSlice, write::WriteMultipleRegisters,
+    ModbusSerializationError,
+};
+
+/// The registers of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Field {
+    /// The address of the first register
+    pub offset: u16,
+    /// The number of registers
+    pub registers: usize,
+}
+
+impl Field {
+    pub const fn new(offset: u16, registers: usize) -> Self {
+        Self { offset, registers }
+    }
+
+    /// The address after the last register
+    pub const fn end(self) -> u32 {
+        self.offset as u32 + self.registers as u32
+    }
+}
+
+/// Registers read starting at an address, e.g. the registers of a read response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Block<'a> {
+    pub addr: u16,
+    pub registers: RegisterSlice<'a>,
+}
+
+impl<'a> Block<'a> {
+    pub const fn new(addr: u16, registers: RegisterSlice<'a>) -> Self {
+        Self { addr, registers }
+    }
+
+    /// The registers of the field if the block contains all of them
+    pub fn get(self, field: Field) -> Option<RegisterSlice<'a>> {
+        let start = usize::from(field.offset.checked_sub(self.addr)?) * 2;
+        let bytes = self
+            .registers
+            .bytes()
+            .get(start..start + field.registers * 2)?;
+        RegisterSlice::new(bytes).ok()
+    }
+}
+
+/// A struct stored in registers at fixed addresses
+pub trait RegisterMap: Sized {
+    /// The registers of all fields in declaration order
+    const FIELDS: &'static [Field];
+
+    /// Decode the struct from registers read in one or more blocks.
+    ///
+    /// # Errors
+    /// If a field is not contained in any block [ModbusSerializationError::UnexpectedEOF] is returned,
+    /// other errors are returned as reported by the fields.
+    fn decode_blocks(blocks: &[Block<'_>]) -> Result<Self, ModbusSerializationError>;
+
+    /// Encode all fields into the requests writing them, see [WriteRequests].
+    ///
+    /// Registers between the fields are never written, the fields are encoded into out back to back.
+    ///
+    /// # Errors
+    /// If out can't hold the registers [ModbusSerializationError::InsufficientBuffer] is returned, if a field has
+    /// more registers than fit into a request [ModbusSerializationError::TooLarge].
+    /// Values which can't be encoded are reported as returned by the fields.
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<WriteRequests<'b>, ModbusSerializationError>;
+
+    /// Decode the struct from the registers starting at addr
+    fn decode(addr: u16, registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+        Self::decode_blocks(&[Block::new(addr, registers)])
+    }
+
+    /// The requests to read all fields, see [ReadRequests]
+    fn read_requests() -> ReadRequests {
+        ReadRequests::new(Self::FIELDS)
+    }
+}
+
+/// An iterator over the fewest [ReadHoldingRegisters] requests reading a set of fields.
+///
+/// Fields are read together if they are adjacent or overlapping and fit into one request. Registers between fields
+/// are never read because devices often answer reads of unmapped registers with an exception.
+#[derive(Debug, Clone)]
+pub struct ReadRequests {
+    runs: Runs,
+}
+
+impl ReadRequests {
+    pub const fn new(fields: &'static [Field]) -> Self {
+        Self {
+            runs: Runs::new(fields, ReadHoldingRegisters::MAX_QUANTITY),
+        }
+    }
+}
+
+impl Iterator for ReadRequests {
+    type Item = ReadHoldingRegisters;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.runs.next()?;
+        Some(ReadHoldingRegisters::new(
+            start as u16,
+            (end - start) as u16,
+        ))
+    }
+}
+
+/// An iterator over the fewest [WriteMultipleRegisters] requests writing a set of encoded fields.
+///
+/// Like [ReadRequests] fields are written together if they are adjacent or overlapping and fit into one request.
+/// Registers between fields are never written as they may hold unrelated data or be rejected by the device.
+#[derive(Debug, Clone)]
+pub struct WriteRequests<'b> {
+    runs: Runs,
+    out: &'b [u8],
+}
+
+impl<'b> WriteRequests<'b> {
+    /// Create the requests writing the fields after they were encoded into out, see [field_buf]
+    #[doc(hidden)]
+    pub fn new(fields: &'static [Field], out: &'b [u8]) -> Self {
+        Self {
+            runs: Runs::new(fields, WriteMultipleRegisters::MAX_QUANTITY),
+            out,
+        }
+    }
+}
+
+impl<'b> Iterator for WriteRequests<'b> {
+    type Item = WriteMultipleRegisters<'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.runs.next()?;
+        let (registers, tail) = self.out.split_at((end - start) as usize * 2);
+        self.out = tail;
+        let registers =
+            RegisterSlice::new(registers).expect("registers have an even number of bytes");
+        Some(
+            WriteMultipleRegisters::new(start as u16, registers)
+                .expect("runs are checked by clear"),
+        )
+    }
+}
+
+/// The address ranges of the fewest requests of at most max registers covering a set of fields
+#[derive(Debug, Clone)]
+struct Runs {
+    fields: &'static [Field],
+    max: u32,
+    last: Option<(u32, u32)>,
+}
+
+impl Runs {
+    const fn new(fields: &'static [Field], max: u16) -> Self {
+        Self {
+            fields,
+            max: max as u32,
+            last: None,
+        }
+    }
+
+    fn valid(&self, field: Field) -> bool {
+        field.registers > 0 && field.registers as u32 <= self.max
+    }
+
+    fn pending(&self, field: Field) -> bool {
+        match self.last {
+            Some((start, end)) => u32::from(field.offset) > start && field.end() > end,
+            None => true,
+        }
+    }
+}
+
+impl Iterator for Runs {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self
+            .fields
+            .iter()
+            .filter(|field| self.valid(**field) && self.pending(**field))
+            .map(|field| u32::from(field.offset))
+            .min()?;
+
+        let mut end = start;
+        loop {
+            let grow = self
+                .fields
+                .iter()
+                .filter(|field| self.valid(**field))
+                .map(|field| (u32::from(field.offset), field.end()))
+                .filter(|&(offset, field_end)| {
+                    offset >= start
+                        && offset <= end
+                        && field_end > end
+                        && field_end - start <= self.max
+                })
+                .map(|(_, field_end)| field_end)
+                .max();
+            match grow {
+                Some(field_end) => end = field_end,
+                None => break,
+            }
+        }
+
+        self.last = Some((start, end));
+        Some((start, end))
+    }
+}
+
+/// Find the registers of a field in the blocks
+#[doc(hidden)]
+pub fn field_registers<'a>(
+    blocks: &[Block<'a>],
+    field: Field,
+) -> Result<RegisterSlice<'a>, ModbusSerializationError> {
+    blocks.iter().find_map(|block| block.get(field)).ok_or(
+        ModbusSerializationError::UnexpectedEOF {
+            expected: field.registers * 2,
+            got: 0,
+        },
+    )
+}
+
+/// Check that the fields can be written and zero the buffer they are encoded into
+#[doc(hidden)]
+pub fn clear(fields: &'static [Field], out: &mut [u8]) -> Result<(), ModbusSerializationError> {
+    let max = WriteMultipleRegisters::MAX_QUANTITY as usize;
+    if fields.iter().any(|field| field.registers > max) {
+        return Err(ModbusSerializationError::TooLarge);
+    }
+
+    let mut size = 0;
+    for (start, end) in Runs::new(fields, WriteMultipleRegisters::MAX_QUANTITY) {
+        if start as u16 > u16::MAX - (end - start) as u16 {
+            return Err(ModbusSerializationError::Overflow);
+        }
+        size += (end - start) as usize * 2;
+    }
+
+    let got = out.len();
+    out.get_mut(..size)
+        .ok_or(ModbusSerializationError::InsufficientBuffer {
+            expected: size,
+            got,
+        })?
+        .fill(0);
+    Ok(())
+}
+
+/// The buffer to encode a field into after [clear]
+#[doc(hidden)]
+pub fn field_buf<'b>(fields: &'static [Field], out: &'b mut [u8], field: Field) -> &'b mut [u8] {
+    let mut pos = 0;
+    for (start, end) in Runs::new(fields, WriteMultipleRegisters::MAX_QUANTITY) {
+        let offset = u32::from(field.offset);
+        if offset >= start && field.end() <= end {
+            return &mut out[pos + (offset - start) as usize * 2..];
+        }
+        pos += (end - start) as usize * 2;
+    }
+    &mut []
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{
+        read::ReadHoldingRegisters, registerslice::RegisterSlice, ModbusSerializationError,
+    };
+
+    use modbius_derive::ModbusRegisters;
+
+    use super::{Block, Field, ReadRequests, RegisterMap};
+    use crate::AsciiString;
+
+    #[derive(Debug, PartialEq, ModbusRegisters)]
+    struct Meter {
+        #[modbus(offset = 0)]
+        serial: AsciiString<2>,
+        #[modbus(offset = 10, ty = u32, order = CDAB, scale = 0.1)]
+        voltage: f64,
+        #[modbus(offset = 12, order = CDAB)]
+        power: f32,
+        #[modbus(offset = 14, ty = i16, scale = 0.01)]
+        power_factor: f32,
+    }
+
+    #[test]
+    fn read_requests() {
+        assert!(Meter::read_requests().eq([
+            ReadHoldingRegisters::new(0, 2),
+            ReadHoldingRegisters::new(10, 5)
+        ]));
+
+        static FIELDS: [Field; 5] = [
+            Field::new(200, 100),
+            Field::new(10, 2),
+            Field::new(11, 2),
+            Field::new(150, 60),
+            Field::new(0, 0),
+        ];
+        assert!(ReadRequests::new(&FIELDS).eq([
+            ReadHoldingRegisters::new(10, 3),
+            ReadHoldingRegisters::new(150, 60),
+            ReadHoldingRegisters::new(200, 100),
+        ]));
+    }
+
+    #[test]
+    fn decode() {
+        let serial = RegisterSlice::new(b"AB12").unwrap();
+        let values = RegisterSlice::new(&[0x09, 0x00, 0, 0, 0, 0, 0x3F, 0xC0, 0xFF, 0x9C]).unwrap();
+        let meter = Meter::decode_blocks(&[Block::new(0, serial), Block::new(10, values)]).unwrap();
+        assert_eq!(meter.serial.as_str(), "AB12");
+        assert_eq!(meter.voltage, 230.4);
+        assert_eq!(meter.power, 1.5);
+        assert_eq!(meter.power_factor, -1.0);
+
+        assert_eq!(
+            Meter::decode(10, values),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 4,
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let meter = Meter {
+            serial: AsciiString::new("X").unwrap(),
+            voltage: 230.4,
+            power: 1.5,
+            power_factor: -1.0,
+        };
+
+        let mut buf = [0xAA; 40];
+        let mut requests = meter.encode(&mut buf).unwrap();
+
+        // The unmapped registers 2 to 9 aren't written
+        let serial = requests.next().unwrap();
+        assert_eq!(serial.addr(), 0);
+        assert_eq!(serial.registers().bytes(), b"X\0\0\0");
+        let values = requests.next().unwrap();
+        assert_eq!(values.addr(), 10);
+        assert_eq!(values.registers().len(), 5);
+        assert!(requests.next().is_none());
+
+        let meter = Meter::decode_blocks(&[
+            Block::new(serial.addr(), serial.registers()),
+            Block::new(values.addr(), values.registers()),
+        ])
+        .unwrap();
+        assert_eq!(meter.serial.as_str(), "X");
+        assert_eq!(meter.voltage, 230.4);
+        assert_eq!(meter.power_factor, -1.0);
+
+        assert_eq!(
+            meter.encode(&mut [0; 12]).map(|requests| requests.count()),
+            Err(ModbusSerializationError::InsufficientBuffer {
+                expected: 14,
+                got: 12
+            })
+        );
+    }
+
+    #[test]
+    fn encode_split() {
+        #[derive(ModbusRegisters)]
+        struct Block {
+            #[modbus(offset = 0)]
+            first: AsciiString<100>,
+            #[modbus(offset = 100)]
+            second: AsciiString<50>,
+        }
+
+        let block = Block {
+            first: AsciiString::new("A").unwrap(),
+            second: AsciiString::new("B").unwrap(),
+        };
+        let mut buf = [0; 300];
+        let mut requests = block.encode(&mut buf).unwrap();
+        let first = requests.next().unwrap();
+        assert_eq!((first.addr(), first.registers().len()), (0, 100));
+        let second = requests.next().unwrap();
+        assert_eq!((second.addr(), second.registers().len()), (100, 50));
+        assert_eq!(second.registers().get(0), Some(0x4200));
+        assert!(requests.next().is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! Integers and floats stored in one or more registers.
+//!
+//! The bytes of a value are stored big endian with the most significant register first.
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+use crate::{read_bytes, write_bytes, RegisterValue};
+
+macro_rules! impl_register_value {
+    ($($ty:ty),*) => {
+        $(
+            impl RegisterValue for $ty {
+                const REGISTERS: usize = core::mem::size_of::<$ty>() / 2;
+
+                fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+                    read_bytes(registers).map(<$ty>::from_be_bytes)
+                }
+
+                fn encode<'b>(
+                    &self,
+                    out: &'b mut [u8],
+                ) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+                    write_bytes(&self.to_be_bytes(), out)
+                }
+            }
+        )*
+    };
+}
+
+impl_register_value!(u16, i16, u32, i32, u64, i64, f32, f64);
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use crate::RegisterValue;
+
+    fn registers(bytes: &[u8]) -> RegisterSlice<'_> {
+        RegisterSlice::new(bytes).unwrap()
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(i16::decode(registers(&[0xFF, 0xFE])), Ok(-2));
+        assert_eq!(
+            u32::decode(registers(&[0x12, 0x34, 0x56, 0x78])),
+            Ok(0x1234_5678)
+        );
+        assert_eq!(
+            i32::decode(registers(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 1])),
+            Ok(-1)
+        );
+        assert_eq!(
+            u64::decode(registers(&[1, 2, 3, 4, 5, 6, 7, 8])),
+            Ok(0x0102_0304_0506_0708)
+        );
+        assert_eq!(i64::decode(registers(&[0xFF; 8])), Ok(-1));
+        assert_eq!(f32::decode(registers(&[0x3F, 0xC0, 0, 0])), Ok(1.5));
+        assert_eq!(
+            f64::decode(registers(&[0x40, 0x09, 0x21, 0xFB, 0x54, 0x44, 0x2D, 0x18])),
+            Ok(core::f64::consts::PI)
+        );
+    }
+
+    #[test]
+    fn decode_fail() {
+        assert_eq!(
+            u32::decode(registers(&[0, 1])),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 4,
+                got: 2
+            })
+        );
+        assert_eq!(
+            f64::decode(registers(&[])),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 8,
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0; 10];
+        assert_eq!(
+            (-2i32).encode(&mut buf).unwrap().bytes(),
+            &[0xFF, 0xFF, 0xFF, 0xFE]
+        );
+        assert_eq!(
+            0x0102_0304_0506_0708u64.encode(&mut buf).unwrap().bytes(),
+            &[1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            (-1.5f32).encode(&mut buf).unwrap().bytes(),
+            &[0xBF, 0xC0, 0, 0]
+        );
+
+        let value = 1234.5f64;
+        assert_eq!(f64::decode(value.encode(&mut buf).unwrap()), Ok(value));
+
+        assert_eq!(
+            1u64.encode(&mut [0; 6]),
+            Err(ModbusSerializationError::InsufficientBuffer {
+                expected: 8,
+                got: 6
+            })
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! The layout of multi-register values.
+//!
+//! The spec only defines registers as big endian 16 bit words, vendors disagree on how larger values are split
+//! across registers. The layouts are named after the bytes of a 32 bit value `0xAABBCCDD` in the order they are
+//! transmitted, [Layout::ABCD] is the layout recommended by the spec. Values of 64 bit follow the same scheme.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    marker::PhantomData,
+};
+
+use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+use crate::RegisterValue;
+
+/// The order of the registers of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum WordOrder {
+    /// The most significant register comes first
+    #[default]
+    HighFirst,
+    /// The least significant register comes first
+    LowFirst,
+}
+
+/// The order of the bytes in each register of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ByteOrder {
+    /// The most significant byte comes first as defined by the spec
+    #[default]
+    BigEndian,
+    /// The bytes of every register are swapped
+    LittleEndian,
+}
+
+/// The word and byte order of a multi-register value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Layout {
+    pub words: WordOrder,
+    pub bytes: ByteOrder,
+}
+
+impl Layout {
+    /// Big endian, the layout recommended by the spec
+    pub const ABCD: Self = Self::new(WordOrder::HighFirst, ByteOrder::BigEndian);
+    /// Word swapped
+    pub const CDAB: Self = Self::new(WordOrder::LowFirst, ByteOrder::BigEndian);
+    /// Byte swapped
+    pub const BADC: Self = Self::new(WordOrder::HighFirst, ByteOrder::LittleEndian);
+    /// Little endian
+    pub const DCBA: Self = Self::new(WordOrder::LowFirst, ByteOrder::LittleEndian);
+    /// Every layout
+    pub const ALL: [Self; 4] = [Self::ABCD, Self::CDAB, Self::BADC, Self::DCBA];
+
+    pub const fn new(words: WordOrder, bytes: ByteOrder) -> Self {
+        Self { words, bytes }
+    }
+
+    /// Convert the bytes of a value between the [ABCD](Layout::ABCD) layout and this layout.
+    ///
+    /// The conversion is its own inverse. A trailing odd byte is left untouched.
+    pub fn reorder(self, bytes: &mut [u8]) {
+        let len = bytes.len() / 2 * 2;
+        let bytes = &mut bytes[..len];
+        if self.words == WordOrder::LowFirst {
+            bytes.reverse();
+            // Reversing all bytes also swapped the bytes of every register
+            swap_bytes(bytes);
+        }
+        if self.bytes == ByteOrder::LittleEndian {
+            swap_bytes(bytes);
+        }
+    }
+}
+
+fn swap_bytes(bytes: &mut [u8]) {
+    for register in bytes.chunks_exact_mut(2) {
+        register.swap(0, 1);
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match (self.words, self.bytes) {
+            (WordOrder::HighFirst, ByteOrder::BigEndian) => "ABCD",
+            (WordOrder::LowFirst, ByteOrder::BigEndian) => "CDAB",
+            (WordOrder::HighFirst, ByteOrder::LittleEndian) => "BADC",
+            (WordOrder::LowFirst, ByteOrder::LittleEndian) => "DCBA",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A layout known at compile time, see [Ordered]
+pub trait Order {
+    const LAYOUT: Layout;
+}
+
+macro_rules! order {
+    ($($name:ident => $layout:ident),*) => {
+        $(
+            #[doc = concat!("The [", stringify!($layout), "](Layout::", stringify!($layout), ") layout")]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+            pub struct $name;
+
+            impl Order for $name {
+                const LAYOUT: Layout = Layout::$layout;
+            }
+        )*
+    };
+}
+
+order!(Abcd => ABCD, Cdab => CDAB, Badc => BADC, Dcba => DCBA);
+
+/// A value which is always decoded and encoded with the layout O.
+///
+/// Declaring the layout in the type of a register map prevents decoding a value with the wrong layout,
+/// e.g. `Ordered<f32, Cdab>` for a word swapped float.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Ordered<T, O> {
+    value: T,
+    order: PhantomData<O>,
+}
+
+impl<T, O: Order> Ordered<T, O> {
+    pub const LAYOUT: Layout = O::LAYOUT;
+
+    pub const fn new(value: T) -> Self {
+        Self {
+            value,
+            order: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: RegisterValue, O: Order> RegisterValue for Ordered<T, O> {
+    const REGISTERS: usize = T::REGISTERS;
+
+    fn decode(registers: RegisterSlice<'_>) -> Result<Self, ModbusSerializationError> {
+        T::decode_with(registers, O::LAYOUT).map(Self::new)
+    }
+
+    fn encode<'b>(&self, out: &'b mut [u8]) -> Result<RegisterSlice<'b>, ModbusSerializationError> {
+        self.value.encode_with(O::LAYOUT, out)
+    }
+}
+
+/// The interpretations of a register pair in one layout
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Candidate {
+    pub layout: Layout,
+    pub u32: u32,
+    pub i32: i32,
+    pub f32: f32,
+}
+
+/// List every interpretation of the first two registers, in the order of [Layout::ALL].
+///
+/// This helps to find out the layout of an unknown device by comparing the candidates with the expected value.
+///
+/// # Errors
+/// If there are less than two registers [ModbusSerializationError::UnexpectedEOF] is returned.
+pub fn candidates(
+    registers: RegisterSlice<'_>,
+) -> Result<[Candidate; 4], ModbusSerializationError> {
+    let mut candidates = [Candidate {
+        layout: Layout::ABCD,
+        u32: 0,
+        i32: 0,
+        f32: 0.0,
+    }; 4];
+
+    for (candidate, layout) in candidates.iter_mut().zip(Layout::ALL) {
+        *candidate = Candidate {
+            layout,
+            u32: u32::decode_with(registers, layout)?,
+            i32: i32::decode_with(registers, layout)?,
+            f32: f32::decode_with(registers, layout)?,
+        };
+    }
+
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{registerslice::RegisterSlice, ModbusSerializationError};
+
+    use super::{candidates, Cdab, Dcba, Layout, Ordered};
+    use crate::RegisterValue;
+
+    #[test]
+    fn reorder() {
+        let cases = [
+            (Layout::ABCD, [0xAA, 0xBB, 0xCC, 0xDD]),
+            (Layout::CDAB, [0xCC, 0xDD, 0xAA, 0xBB]),
+            (Layout::BADC, [0xBB, 0xAA, 0xDD, 0xCC]),
+            (Layout::DCBA, [0xDD, 0xCC, 0xBB, 0xAA]),
+        ];
+        for (layout, expected) in cases {
+            let mut bytes = [0xAA, 0xBB, 0xCC, 0xDD];
+            layout.reorder(&mut bytes);
+            assert_eq!(bytes, expected, "{}", layout);
+            layout.reorder(&mut bytes);
+            assert_eq!(bytes, [0xAA, 0xBB, 0xCC, 0xDD], "{}", layout);
+        }
+
+        let mut bytes = [1, 2, 3, 4, 5, 6, 7, 8];
+        Layout::CDAB.reorder(&mut bytes);
+        assert_eq!(bytes, [7, 8, 5, 6, 3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn decode_with() {
+        let registers = RegisterSlice::new(&[0, 0, 0x3F, 0xC0]).unwrap();
+        assert_eq!(f32::decode_with(registers, Layout::CDAB), Ok(1.5));
+        assert_eq!(
+            u64::decode_with(
+                RegisterSlice::new(&[8, 7, 6, 5, 4, 3, 2, 1]).unwrap(),
+                Layout::DCBA
+            ),
+            Ok(0x0102_0304_0506_0708)
+        );
+        assert_eq!(u32::decode_with(registers, Layout::BADC), Ok(0x0000_C03F));
+        assert_eq!(
+            u64::decode_with(registers, Layout::CDAB),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 8,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn encode_with() {
+        let mut buf = [0; 8];
+        assert_eq!(
+            0x1122_3344u32
+                .encode_with(Layout::CDAB, &mut buf)
+                .unwrap()
+                .bytes(),
+            &[0x33, 0x44, 0x11, 0x22]
+        );
+        assert_eq!(
+            (-2i16).encode_with(Layout::BADC, &mut buf).unwrap().bytes(),
+            &[0xFE, 0xFF]
+        );
+    }
+
+    #[test]
+    fn ordered() {
+        let registers = RegisterSlice::new(&[0, 0, 0x3F, 0xC0]).unwrap();
+        let value = Ordered::<f32, Cdab>::decode(registers).unwrap();
+        assert_eq!(*value.get(), 1.5);
+
+        let mut buf = [0; 4];
+        assert_eq!(value.encode(&mut buf).unwrap(), registers);
+        assert_eq!(
+            Ordered::<u32, Dcba>::new(1)
+                .encode(&mut buf)
+                .unwrap()
+                .bytes(),
+            &[1, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn list_candidates() {
+        let registers = RegisterSlice::new(&[0, 0, 0x3F, 0xC0]).unwrap();
+        let candidates = candidates(registers).unwrap();
+        assert_eq!(candidates.map(|candidate| candidate.layout), Layout::ALL);
+        assert_eq!(candidates[0].u32, 0x3FC0);
+        assert_eq!(candidates[1].f32, 1.5);
+        assert_eq!(candidates[3].i32, 0xC03F_0000u32 as i32);
+
+        assert_eq!(
+            super::candidates(RegisterSlice::new(&[0, 1]).unwrap()),
+            Err(ModbusSerializationError::UnexpectedEOF {
+                expected: 4,
+                got: 2
+            })
+        );
+    }
+}
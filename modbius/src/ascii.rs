@@ -0,0 +1,93 @@
+//! Modbus ASCII slave transport.
+
+use modbius_codec::{AsciiConfig, AsciiStream, AsciiTransportError};
+use modbius_core::SlaveId;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The slave side of a Modbus ASCII line.
+///
+/// Requests for every slave id on the line are received, filtering them is up to the caller.
+#[derive(Debug)]
+pub struct AsciiServerTransport<S> {
+    stream: AsciiStream<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsciiServerTransport<S> {
+    /// Create a new transport with the default [AsciiConfig]
+    pub fn new(stream: S) -> Self {
+        Self::with_config(stream, AsciiConfig::default())
+    }
+
+    /// Create a new transport with the given configuration
+    pub fn with_config(stream: S, config: AsciiConfig) -> Self {
+        Self {
+            stream: AsciiStream::with_config(stream, config),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// Wait for the next request and write its PDU to request.
+    ///
+    /// The slave id the request is addressed to and the len of the PDU are returned.
+    ///
+    /// # Errors
+    /// Invalid frames are reported as [AsciiTransportError::Frame] and incomplete frames as
+    /// [AsciiTransportError::Timeout]. In both cases the transport can be used to read the next request.
+    pub async fn read_request(
+        &mut self,
+        request: &mut [u8],
+    ) -> Result<(SlaveId, usize), AsciiTransportError> {
+        self.stream.read_frame(request, None).await
+    }
+
+    /// Send the response PDU from the given slave
+    pub async fn write_response(
+        &mut self,
+        slave: SlaveId,
+        response: &[u8],
+    ) -> Result<(), AsciiTransportError> {
+        self.stream.write_frame(slave, response).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_codec::AsciiTransportError;
+    use modbius_core::{ascii::AsciiFrameError, SlaveId};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::AsciiServerTransport;
+
+    #[tokio::test]
+    async fn serve() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let mut transport = AsciiServerTransport::new(local);
+
+        remote
+            .write_all(b":01030000\xFF\r\n:010300000001FB\r\n")
+            .await
+            .unwrap();
+
+        let mut request = [0; 8];
+        let err = transport.read_request(&mut request).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AsciiTransportError::Frame(AsciiFrameError::Hex)
+        ));
+
+        let (slave, len) = transport.read_request(&mut request).await.unwrap();
+        assert_eq!(slave, SlaveId::new(1));
+        assert_eq!(&request[..len], &[3, 0, 0, 0, 1]);
+
+        transport
+            .write_response(slave, &[3, 2, 1, 2])
+            .await
+            .unwrap();
+        let mut response = [0; 15];
+        remote.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b":0103020102F7\r\n");
+    }
+}
@@ -0,0 +1,330 @@
+//! Serving user defined functions.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use modbius_core::{
+    custom::{CustomCodec, CustomFunction, CustomFunctions},
+    ExceptionCode, ModbusError, ModbusFunction, Quirks, Request, SlaveId,
+};
+use modbius_traits::ModbusHandler;
+
+type Handler = dyn Fn(SlaveId, &[u8], &mut [u8]) -> Result<usize, ExceptionCode> + Send + Sync;
+
+/// A [ModbusHandler] answering user defined functions with the handlers registered for them.
+///
+/// Requests of registered functions are checked with [Request::from_data_with_custom] and passed to the handler
+/// of the function, which parses them with its [CustomFunction] implementation. Any other request is parsed and
+/// handled by the inner handler.
+///
+/// # Example
+/// ```
+/// use modbius_core::{custom::CustomFunction, util, ModbusFunction, ModbusSerializationError};
+/// use modbius_server::{custom::CustomRouter, DataStore};
+///
+/// /// Get the serial number of the device, the response holds it as u16
+/// struct SerialNumber;
+///
+/// impl CustomFunction for SerialNumber {
+///     const FUNCTION: ModbusFunction = ModbusFunction::new(0x64);
+///     type Request<'a> = ();
+///     type Response<'a> = u16;
+///
+///     fn parse_request(data: &[u8]) -> Result<((), &[u8]), ModbusSerializationError> {
+///         Ok(((), data))
+///     }
+///
+///     fn write_request(_: &(), _: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+///         Ok(0)
+///     }
+///
+///     fn parse_response(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+///         util::read_u16(data)
+///     }
+///
+///     fn write_response(serial: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+///         out[..2].copy_from_slice(&serial.to_be_bytes());
+///         Ok(2)
+///     }
+/// }
+///
+/// let mut router = CustomRouter::new(DataStore::default());
+/// router.insert::<SerialNumber>(|_unit, (), response| Ok(SerialNumber::write_response(&4711, response)?));
+/// ```
+#[derive(Clone)]
+pub struct CustomRouter<H> {
+    inner: H,
+    codecs: Vec<CustomCodec>,
+    handlers: Vec<Arc<Handler>>,
+}
+
+impl<H> CustomRouter<H> {
+    /// Create a router without any user defined functions passing all requests to inner
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            codecs: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Register the handler for the user defined function F.
+    ///
+    /// The handler gets the addressed unit, the parsed request and a buffer for the response data after the
+    /// function code, e.g. to be written with [CustomFunction::write_response]. On success it returns the number
+    /// of bytes written, the function code is added by the router. A handler previously registered for the
+    /// function is replaced.
+    ///
+    /// # Panics
+    /// If the code of F is no user defined function code, see [ModbusFunction::is_custom].
+    pub fn insert<F>(
+        &mut self,
+        handler: impl Fn(SlaveId, F::Request<'_>, &mut [u8]) -> Result<usize, ExceptionCode>
+            + Send
+            + Sync
+            + 'static,
+    ) where
+        F: CustomFunction + 'static,
+    {
+        let codec = CustomCodec::of::<F>();
+        let handler = Arc::new(move |unit: SlaveId, data: &[u8], response: &mut [u8]| {
+            let (request, _tail) = F::parse_request(data)?;
+            handler(unit, request, response)
+        });
+        match self.position(F::FUNCTION) {
+            Some(idx) => self.handlers[idx] = handler,
+            None => {
+                self.codecs.push(codec);
+                self.handlers.push(handler);
+            }
+        }
+    }
+
+    /// Remove the handler of function, false is returned if there was none
+    pub fn remove(&mut self, function: ModbusFunction) -> bool {
+        let Some(idx) = self.position(function) else {
+            return false;
+        };
+        self.codecs.remove(idx);
+        self.handlers.remove(idx);
+        true
+    }
+
+    pub fn contains(&self, function: ModbusFunction) -> bool {
+        self.position(function).is_some()
+    }
+
+    /// The registered user defined functions
+    pub fn functions(&self) -> CustomFunctions<'_> {
+        CustomFunctions::new(&self.codecs)
+    }
+
+    pub fn get_ref(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+
+    fn position(&self, function: ModbusFunction) -> Option<usize> {
+        self.codecs
+            .iter()
+            .position(|codec| codec.function() == function)
+    }
+}
+
+impl<H: Debug> Debug for CustomRouter<H> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomRouter")
+            .field("inner", &self.inner)
+            .field(
+                "functions",
+                &self
+                    .codecs
+                    .iter()
+                    .map(|codec| codec.function())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<H: ModbusHandler + Sync> ModbusHandler for CustomRouter<H> {
+    async fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        let handler = match request {
+            Request::Other { function, data } => self
+                .position(function)
+                .map(|idx| (function, data, &self.handlers[idx])),
+            _ => None,
+        };
+        let Some((function, data, handler)) = handler else {
+            return self.inner.handle(unit, request, response).await;
+        };
+
+        response[0] = function.into();
+        handler(unit, data, &mut response[1..]).map(|len| len + 1)
+    }
+
+    /// Requests of registered functions are parsed with [Request::from_data_with_custom], any other request is
+    /// parsed by the inner handler
+    fn parse<'a>(
+        &self,
+        unit: SlaveId,
+        request: &'a [u8],
+        quirks: Quirks,
+    ) -> Result<Request<'a>, ModbusError> {
+        match request.first() {
+            Some(function) if self.contains(ModbusFunction::new(*function)) => {
+                Request::from_data_with_custom(request, quirks, self.functions())
+                    .map(|(request, _tail)| request)
+            }
+            _ => self.inner.parse(unit, request, quirks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{
+        custom::CustomFunction, util, ExceptionCode, ModbusFunction, ModbusSerializationError,
+        Quirks, SlaveId,
+    };
+    use modbius_traits::ModbusHandler;
+
+    use super::CustomRouter;
+    use crate::DataStore;
+
+    /// Add two registers
+    struct Add;
+
+    impl CustomFunction for Add {
+        const FUNCTION: ModbusFunction = ModbusFunction::new(0x41);
+        type Request<'a> = (u16, u16);
+        type Response<'a> = u16;
+
+        fn parse_request(data: &[u8]) -> Result<((u16, u16), &[u8]), ModbusSerializationError> {
+            let (a, data) = util::read_u16(data)?;
+            let (b, data) = util::read_u16(data)?;
+            Ok(((a, b), data))
+        }
+
+        fn write_request(
+            (a, b): &(u16, u16),
+            out: &mut [u8],
+        ) -> Result<usize, ModbusSerializationError> {
+            out[..2].copy_from_slice(&a.to_be_bytes());
+            out[2..4].copy_from_slice(&b.to_be_bytes());
+            Ok(4)
+        }
+
+        fn parse_response(data: &[u8]) -> Result<(u16, &[u8]), ModbusSerializationError> {
+            util::read_u16(data)
+        }
+
+        fn write_response(sum: &u16, out: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            out[..2].copy_from_slice(&sum.to_be_bytes());
+            Ok(2)
+        }
+    }
+
+    async fn process(
+        router: &CustomRouter<DataStore>,
+        request: &[u8],
+    ) -> Result<Vec<u8>, ExceptionCode> {
+        let unit = SlaveId::new(1);
+        let mut response = [0; 253];
+        let request = router.parse(unit, request, Quirks::STRICT)?;
+        let len = router.handle(unit, request, &mut response).await?;
+        Ok(response[..len].to_vec())
+    }
+
+    #[tokio::test]
+    async fn route() {
+        let mut router = CustomRouter::new(DataStore::default());
+        router.insert::<Add>(|_unit, (a, b), response| {
+            let sum = a.checked_add(b).ok_or(ExceptionCode::IllegalDataValue)?;
+            Ok(Add::write_response(&sum, response)?)
+        });
+
+        assert_eq!(
+            process(&router, &[0x41, 0, 1, 0, 2]).await,
+            Ok(vec![0x41, 0, 3])
+        );
+        assert_eq!(
+            process(&router, &[0x41, 0xFF, 0xFF, 0, 2]).await,
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            process(&router, &[0x41, 0, 1]).await,
+            Err(ExceptionCode::IllegalDataValue)
+        );
+
+        // Other requests are handled by the inner handler
+        assert_eq!(
+            process(&router, &[3, 0, 0, 0, 1]).await,
+            Ok(vec![3, 2, 0, 0])
+        );
+        assert_eq!(
+            process(&router, &[0x42, 0, 1]).await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+
+        // A handler registered again replaces the first one
+        router.insert::<Add>(|_unit, _request, _response| Err(ExceptionCode::ServerDeviceBusy));
+        assert_eq!(router.functions().codecs().len(), 1);
+        assert_eq!(
+            process(&router, &[0x41, 0, 1, 0, 2]).await,
+            Err(ExceptionCode::ServerDeviceBusy)
+        );
+
+        assert!(router.remove(Add::FUNCTION));
+        assert_eq!(
+            process(&router, &[0x41, 0, 1, 0, 2]).await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    /// A public function code
+    struct ReadHolding;
+
+    impl CustomFunction for ReadHolding {
+        const FUNCTION: ModbusFunction = ModbusFunction::new(3);
+        type Request<'a> = ();
+        type Response<'a> = ();
+
+        fn parse_request(data: &[u8]) -> Result<((), &[u8]), ModbusSerializationError> {
+            Ok(((), data))
+        }
+
+        fn write_request(_: &(), _: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            Ok(0)
+        }
+
+        fn parse_response(data: &[u8]) -> Result<((), &[u8]), ModbusSerializationError> {
+            Ok(((), data))
+        }
+
+        fn write_response(_: &(), _: &mut [u8]) -> Result<usize, ModbusSerializationError> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the function code is reserved for public functions")]
+    fn insert_public_function() {
+        let mut router = CustomRouter::new(DataStore::default());
+        router.insert::<ReadHolding>(|_unit, (), _response| Ok(0));
+    }
+}
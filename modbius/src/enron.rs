@@ -0,0 +1,550 @@
+//! An in-memory data model for servers emulating Enron Modbus devices like flow computers.
+//!
+//! See [modbius_core::enron] for the conventions of Enron Modbus.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use modbius_core::{
+    enron::{EnronEvent, EnronMap, EnronRange, EnronRequest, EnronValues, MAX_EVENTS},
+    ExceptionCode, ModbusError, PublicModbusFunction, Quirks, Request, SlaveId, MAX_PDU_SIZE,
+};
+use modbius_traits::ModbusHandler;
+
+use crate::store::{DataStoreConfig, DataTables};
+
+/// The layout of an [EnronStore]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnronConfig {
+    /// The sizes of the standard 16-bit address spaces
+    pub tables: DataStoreConfig,
+    /// The ranges holding 32-bit values
+    pub ranges: Vec<EnronRange>,
+    /// The address of the event log, None if there is none
+    pub event_log: Option<u16>,
+    /// The maximum number of unacknowledged events, the oldest events are dropped once more are recorded
+    pub max_events: usize,
+    /// The address and the maximum number of records of each archive
+    pub archives: Vec<(u16, usize)>,
+}
+
+impl Default for EnronConfig {
+    /// The layout of [EnronMap::STANDARD] keeping 35 days of hourly and daily records
+    fn default() -> Self {
+        let map = EnronMap::STANDARD;
+        Self {
+            tables: DataStoreConfig::default(),
+            ranges: map.ranges.to_vec(),
+            event_log: map.event_log,
+            max_events: 100,
+            archives: vec![(map.archives[0], 35 * 24), (map.archives[1], 35)],
+        }
+    }
+}
+
+/// A circular buffer of archive records.
+///
+/// Records are addressed by an index which wraps around at the capacity, just like in the devices.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnronArchive {
+    addr: u16,
+    capacity: usize,
+    records: Vec<Box<[f32]>>,
+    next: usize,
+}
+
+impl EnronArchive {
+    /// Create an empty archive at addr holding up to capacity records
+    pub fn new(addr: u16, capacity: usize) -> Self {
+        Self {
+            addr,
+            capacity,
+            records: Vec::with_capacity(capacity),
+            next: 0,
+        }
+    }
+
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of stored records
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Store a record, overwriting the oldest one if the archive is full.
+    ///
+    /// The index of the record is returned, None if the capacity is 0.
+    pub fn push(&mut self, record: impl Into<Box<[f32]>>) -> Option<u16> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let idx = self.next;
+        if idx < self.records.len() {
+            self.records[idx] = record.into();
+        } else {
+            self.records.push(record.into());
+        }
+        self.next = (idx + 1) % self.capacity;
+        Some(idx as u16)
+    }
+
+    /// Get the record with the given index
+    pub fn record(&self, idx: u16) -> Option<&[f32]> {
+        self.records.get(idx as usize).map(|record| &record[..])
+    }
+}
+
+/// The data of an [EnronStore]: standard 16-bit tables, 32-bit values, the event log and archives
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnronTables {
+    tables: DataTables,
+    ranges: Vec<EnronRange>,
+    values: Vec<Box<[u32]>>,
+    event_log: Option<u16>,
+    max_events: usize,
+    events: VecDeque<EnronEvent>,
+    /// The number of events returned by the last event log read, they are dropped on acknowledgement
+    read_events: usize,
+    archives: Vec<EnronArchive>,
+    archive_addrs: Vec<u16>,
+}
+
+impl EnronTables {
+    /// Create tables of the configured layout with all values set to zero and empty archives
+    pub fn new(config: EnronConfig) -> Self {
+        Self {
+            tables: DataTables::new(config.tables),
+            values: config
+                .ranges
+                .iter()
+                .map(|range| vec![0; range.len()].into())
+                .collect(),
+            ranges: config.ranges,
+            event_log: config.event_log,
+            max_events: config.max_events,
+            events: VecDeque::new(),
+            read_events: 0,
+            archive_addrs: config.archives.iter().map(|(addr, _)| *addr).collect(),
+            archives: config
+                .archives
+                .into_iter()
+                .map(|(addr, capacity)| EnronArchive::new(addr, capacity))
+                .collect(),
+        }
+    }
+
+    /// The layout of the tables used to parse requests
+    pub fn map(&self) -> EnronMap<'_> {
+        EnronMap {
+            ranges: &self.ranges,
+            event_log: self.event_log,
+            archives: &self.archive_addrs,
+        }
+    }
+
+    /// The standard 16-bit tables
+    pub fn tables(&self) -> &DataTables {
+        &self.tables
+    }
+
+    pub fn tables_mut(&mut self) -> &mut DataTables {
+        &mut self.tables
+    }
+
+    /// Get the raw 32-bit value at addr, None if addr is not within a 32-bit range
+    pub fn value(&self, addr: u16) -> Option<u32> {
+        let (range, values) = self.range(addr)?;
+        values.get((addr - range.start) as usize).copied()
+    }
+
+    pub fn value_mut(&mut self, addr: u16) -> Option<&mut u32> {
+        let idx = self.ranges.iter().position(|range| range.contains(addr))?;
+        let start = self.ranges[idx].start;
+        self.values[idx].get_mut((addr - start) as usize)
+    }
+
+    /// Get the float at addr, None if addr is not within a 32-bit range
+    pub fn float(&self, addr: u16) -> Option<f32> {
+        self.value(addr).map(f32::from_bits)
+    }
+
+    /// Set the float at addr, false is returned if addr is not within a 32-bit range
+    pub fn set_float(&mut self, addr: u16, value: f32) -> bool {
+        self.value_mut(addr).map(|v| *v = value.to_bits()).is_some()
+    }
+
+    /// Record an event, dropping the oldest unacknowledged event if the log is full
+    pub fn record_event(&mut self, event: EnronEvent) {
+        if self.max_events == 0 {
+            return;
+        }
+        if self.events.len() == self.max_events {
+            self.events.pop_front();
+            self.read_events = self.read_events.saturating_sub(1);
+        }
+        self.events.push_back(event);
+    }
+
+    /// The unacknowledged events, oldest first
+    pub fn events(&self) -> impl ExactSizeIterator<Item = &EnronEvent> {
+        self.events.iter()
+    }
+
+    /// Get the archive at addr
+    pub fn archive(&self, addr: u16) -> Option<&EnronArchive> {
+        self.archives.iter().find(|archive| archive.addr == addr)
+    }
+
+    pub fn archive_mut(&mut self, addr: u16) -> Option<&mut EnronArchive> {
+        self.archives
+            .iter_mut()
+            .find(|archive| archive.addr == addr)
+    }
+
+    /// Process a request against the tables and write the response PDU to response.
+    ///
+    /// Writes of 32-bit values are answered like standard writes, with the value instead of a register.
+    /// Standard requests are processed by [DataTables::process].
+    ///
+    /// # Errors
+    /// Reading an archive record which doesn't exist fails with [ExceptionCode::IllegalDataAddress].
+    pub fn process(
+        &mut self,
+        request: EnronRequest<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        match request {
+            EnronRequest::ReadValues {
+                function,
+                addr,
+                quantity,
+                ..
+            } => {
+                let (range, values) = self.range(addr).ok_or(ExceptionCode::IllegalDataAddress)?;
+                let start = (addr - range.start) as usize;
+                let values = values
+                    .get(start..start + quantity as usize)
+                    .ok_or(ExceptionCode::IllegalDataAddress)?;
+                Ok(EnronValues::write_values(
+                    function,
+                    values.iter().copied(),
+                    response,
+                )?)
+            }
+            EnronRequest::WriteValue { addr, value } => {
+                *self
+                    .value_mut(addr)
+                    .ok_or(ExceptionCode::IllegalDataAddress)? = value;
+                Ok(request.write_to_slice(response)?)
+            }
+            EnronRequest::WriteValues { addr, values } => {
+                let idx = self
+                    .ranges
+                    .iter()
+                    .position(|range| range.contains(addr))
+                    .ok_or(ExceptionCode::IllegalDataAddress)?;
+                let start = (addr - self.ranges[idx].start) as usize;
+                let table = self.values[idx]
+                    .get_mut(start..start + values.len())
+                    .ok_or(ExceptionCode::IllegalDataAddress)?;
+                for (value, new) in table.iter_mut().zip(values.iter()) {
+                    *value = new;
+                }
+
+                let mut echo = [0; 5];
+                echo[0] = PublicModbusFunction::WriteMultipleRegisters as u8;
+                echo[1..3].copy_from_slice(&addr.to_be_bytes());
+                echo[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+                write_echo(&echo, response)
+            }
+            EnronRequest::ReadEventLog { .. } => {
+                self.read_events = self.events.len().min(MAX_EVENTS);
+                let events = self.events.iter().take(self.read_events).copied();
+                Ok(EnronEvent::write_events(events, response)?)
+            }
+            EnronRequest::AcknowledgeEventLog { .. } => {
+                self.events.drain(..self.read_events);
+                self.read_events = 0;
+                Ok(request.write_to_slice(response)?)
+            }
+            EnronRequest::ReadArchive { addr, index } => {
+                let record = self
+                    .archive(addr)
+                    .and_then(|archive| archive.record(index))
+                    .ok_or(ExceptionCode::IllegalDataAddress)?;
+                Ok(EnronValues::write_values(
+                    PublicModbusFunction::ReadHoldingRegisters,
+                    record.iter().map(|value| value.to_bits()),
+                    response,
+                )?)
+            }
+            EnronRequest::Standard(request) => self.tables.process(request, response),
+        }
+    }
+
+    fn range(&self, addr: u16) -> Option<(EnronRange, &[u32])> {
+        let idx = self.ranges.iter().position(|range| range.contains(addr))?;
+        Some((self.ranges[idx], &self.values[idx]))
+    }
+}
+
+impl Default for EnronTables {
+    fn default() -> Self {
+        Self::new(EnronConfig::default())
+    }
+}
+
+fn write_echo(data: &[u8], response: &mut [u8]) -> Result<usize, ExceptionCode> {
+    match response.get_mut(..data.len()) {
+        Some(response) => {
+            response.copy_from_slice(data);
+            Ok(data.len())
+        }
+        None => Err(ExceptionCode::ServerDeviceFailure),
+    }
+}
+
+/// An in-memory data model serving Enron Modbus requests.
+///
+/// Like the [DataStore](crate::DataStore) the store answers requests of every unit id and all clones share
+/// the same tables. Requests are parsed with the layout of the tables, so addresses within 32-bit ranges
+/// are served as 32-bit values and reads of the event log and archives return events and records.
+///
+/// # Example
+/// ```
+/// use modbius_server::enron::{EnronConfig, EnronStore};
+///
+/// let store = EnronStore::new(EnronConfig::default());
+/// store.write(|tables| tables.set_float(7001, 1.5));
+/// assert_eq!(store.read(|tables| tables.float(7001)), Some(1.5));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EnronStore {
+    tables: Arc<RwLock<EnronTables>>,
+}
+
+impl EnronStore {
+    /// Create a new store with all values set to zero
+    pub fn new(config: EnronConfig) -> Self {
+        Self::from_tables(EnronTables::new(config))
+    }
+
+    /// Create a new store serving the given tables
+    pub fn from_tables(tables: EnronTables) -> Self {
+        Self {
+            tables: Arc::new(RwLock::new(tables)),
+        }
+    }
+
+    /// Access the tables for reading.
+    ///
+    /// Requests are blocked while f runs, so it should return quickly.
+    pub fn read<R>(&self, f: impl FnOnce(&EnronTables) -> R) -> R {
+        f(&self.tables.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Access the tables for writing.
+    ///
+    /// Requests are blocked while f runs, so it should return quickly.
+    pub fn write<R>(&self, f: impl FnOnce(&mut EnronTables) -> R) -> R {
+        f(&mut self.tables.write().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl ModbusHandler for EnronStore {
+    /// Parse the request with the layout of the tables.
+    ///
+    /// Enron requests can't be represented as standard requests, they are passed on as [Request::Other] and
+    /// parsed again by [handle](Self::handle). As they were already accepted with the quirks given here, handle
+    /// parses them with [Quirks::LENIENT] which only relaxes checks and never changes the parsed request.
+    fn parse<'a>(
+        &self,
+        _unit: SlaveId,
+        request: &'a [u8],
+        quirks: Quirks,
+    ) -> Result<Request<'a>, ModbusError> {
+        let parsed = self.read(|tables| {
+            EnronRequest::from_data_with_quirks(request, tables.map(), quirks).map(|(req, _)| req)
+        })?;
+        match parsed {
+            EnronRequest::Standard(request) => Ok(request),
+            _ => Ok(Request::Other {
+                function: request[0].into(),
+                data: &request[1..],
+            }),
+        }
+    }
+
+    async fn handle(
+        &self,
+        _unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        let Request::Other { function, data } = request else {
+            return self.write(|tables| tables.process(EnronRequest::Standard(request), response));
+        };
+
+        let mut pdu = [0; MAX_PDU_SIZE];
+        let len = (data.len() + 1).min(MAX_PDU_SIZE);
+        pdu[0] = function.into();
+        pdu[1..len].copy_from_slice(&data[..len - 1]);
+        self.write(|tables| {
+            let (request, _) =
+                EnronRequest::from_data_with_quirks(&pdu[..len], tables.map(), Quirks::LENIENT)?;
+            tables.process(request, response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{enron::EnronEvent, ExceptionCode, Quirks, SlaveId};
+    use modbius_traits::ModbusHandler;
+
+    use super::{EnronConfig, EnronStore};
+    use crate::store::DataStoreConfig;
+
+    fn store() -> EnronStore {
+        EnronStore::new(EnronConfig {
+            tables: DataStoreConfig {
+                coils: 10,
+                discrete_inputs: 10,
+                holding_registers: 10,
+                input_registers: 10,
+            },
+            max_events: 3,
+            archives: vec![(700, 2)],
+            ..Default::default()
+        })
+    }
+
+    async fn process(store: &EnronStore, request: &[u8]) -> Result<Vec<u8>, ExceptionCode> {
+        let unit = SlaveId::new(1);
+        let mut response = [0; 253];
+        let request = store.parse(unit, request, Quirks::STRICT)?;
+        let len = store.handle(unit, request, &mut response).await?;
+        Ok(response[..len].to_vec())
+    }
+
+    #[tokio::test]
+    async fn values() {
+        let store = store();
+        store.write(|tables| tables.set_float(7002, 1.0));
+
+        assert_eq!(
+            process(&store, &[3, 0x1B, 0x59, 0, 2]).await,
+            Ok(vec![3, 8, 0, 0, 0, 0, 0x3F, 0x80, 0, 0])
+        );
+        assert_eq!(
+            process(&store, &[6, 0x13, 0x89, 0x12, 0x34, 0x56, 0x78]).await,
+            Ok(vec![6, 0x13, 0x89, 0x12, 0x34, 0x56, 0x78])
+        );
+        assert_eq!(
+            process(&store, &[16, 0x13, 0x8A, 0, 1, 4, 0, 0, 0, 9]).await,
+            Ok(vec![16, 0x13, 0x8A, 0, 1])
+        );
+        assert_eq!(
+            store.read(|tables| (tables.value(5001), tables.value(5002))),
+            (Some(0x12345678), Some(9))
+        );
+
+        // Reads past the end of a range
+        assert_eq!(
+            process(&store, &[4, 0x1F, 0x3F, 0, 2]).await,
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+
+        // Standard registers
+        assert_eq!(
+            process(&store, &[6, 0, 1, 0, 7]).await,
+            Ok(vec![6, 0, 1, 0, 7])
+        );
+        assert_eq!(
+            process(&store, &[3, 0, 1, 0, 1]).await,
+            Ok(vec![3, 2, 0, 7])
+        );
+    }
+
+    #[tokio::test]
+    async fn byte_count_mismatch() {
+        let store = store();
+        let unit = SlaveId::new(1);
+        let quirks = Quirks {
+            byte_count_mismatch: true,
+            ..Quirks::STRICT
+        };
+        let data = [16, 0x13, 0x8A, 0, 1, 2, 0, 0, 0, 9];
+
+        assert!(store.parse(unit, &data, Quirks::STRICT).is_err());
+        let request = store.parse(unit, &data, quirks).unwrap();
+        let mut response = [0; 253];
+        let len = store.handle(unit, request, &mut response).await.unwrap();
+        assert_eq!(&response[..len], &[16, 0x13, 0x8A, 0, 1]);
+        assert_eq!(store.read(|tables| tables.value(5002)), Some(9));
+    }
+
+    #[tokio::test]
+    async fn event_log() {
+        let store = store();
+        store.write(|tables| {
+            for status in 0..4 {
+                tables.record_event(EnronEvent {
+                    status,
+                    ..Default::default()
+                });
+            }
+        });
+
+        // The oldest event was dropped
+        let response = process(&store, &[3, 0, 32, 0, 1]).await.unwrap();
+        assert_eq!(&response[..2], &[3, 60]);
+        assert_eq!(&response[2..4], &[0, 1]);
+
+        store.write(|tables| tables.record_event(EnronEvent::default()));
+        assert_eq!(
+            process(&store, &[5, 0, 32, 0xFF, 0]).await,
+            Ok(vec![5, 0, 32, 0xFF, 0])
+        );
+
+        // Only the events which were read are dropped
+        assert_eq!(store.read(|tables| tables.events().len()), 1);
+    }
+
+    #[tokio::test]
+    async fn archive() {
+        let store = store();
+        store.write(|tables| {
+            let archive = tables.archive_mut(700).unwrap();
+            assert_eq!(archive.push([1.0, 2.0]), Some(0));
+            assert_eq!(archive.push([3.0]), Some(1));
+            assert_eq!(archive.push([0.5]), Some(0));
+        });
+
+        assert_eq!(
+            process(&store, &[3, 0x02, 0xBC, 0, 0]).await,
+            Ok(vec![3, 4, 0x3F, 0, 0, 0])
+        );
+        assert_eq!(
+            process(&store, &[3, 0x02, 0xBC, 0, 1]).await,
+            Ok(vec![3, 4, 0x40, 0x40, 0, 0])
+        );
+        assert_eq!(
+            process(&store, &[3, 0x02, 0xBC, 0, 2]).await,
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+}
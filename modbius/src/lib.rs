@@ -0,0 +1,19 @@
+//! Async modbius server implementations.
+//!
+//! The servers are based on `modbius-core` for parsing and the codecs of `modbius-codec` for framing.
+
+pub mod ascii;
+pub mod custom;
+pub mod enron;
+pub mod router;
+pub mod rtu;
+pub mod store;
+pub mod tcp;
+
+pub use ascii::AsciiServerTransport;
+pub use custom::CustomRouter;
+pub use enron::{EnronConfig, EnronStore, EnronTables};
+pub use router::{UnitRouter, UnknownUnit};
+pub use rtu::{RtuDiagnostics, RtuServer};
+pub use store::{DataStore, DataStoreConfig, DataTables};
+pub use tcp::{TcpServer, TcpServerConfig};
@@ -0,0 +1,296 @@
+//! Hosting several units behind one server.
+
+use std::{collections::HashMap, iter::FromIterator};
+
+use modbius_core::{
+    ExceptionCode, ModbusError, ModbusFunction, Quirks, Request, SlaveId, MAX_PDU_SIZE,
+};
+use modbius_traits::ModbusHandler;
+
+/// How a [UnitRouter] reacts to requests for unit ids without a handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UnknownUnit {
+    /// Don't answer the request at all
+    Silence,
+    /// Answer with [ExceptionCode::GatewayTargetDeviceFailedToRespond]
+    #[default]
+    GatewayTargetDeviceFailedToRespond,
+}
+
+/// A [ModbusHandler] passing each request to the handler registered for its unit id.
+///
+/// Broadcasts ([SlaveId::is_broadcast]) are passed to every registered handler and are never answered.
+/// As reading from several units at once makes no sense, broadcast reads are ignored. Broadcasts are parsed as
+/// standard requests, see [parse](Self::parse).
+///
+/// # Example
+/// ```
+/// use modbius_core::SlaveId;
+/// use modbius_server::{router::UnitRouter, DataStore};
+///
+/// let mut router = UnitRouter::new();
+/// router.insert(SlaveId::new(1), DataStore::default());
+/// router.insert(SlaveId::new(2), DataStore::default());
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnitRouter<H> {
+    units: HashMap<SlaveId, H>,
+    unknown_unit: UnknownUnit,
+}
+
+impl<H> UnitRouter<H> {
+    /// Create a router without any units
+    pub fn new() -> Self {
+        Self::with_unknown_unit(UnknownUnit::default())
+    }
+
+    /// Create a router without any units reacting to unknown units as given
+    pub fn with_unknown_unit(unknown_unit: UnknownUnit) -> Self {
+        Self {
+            units: HashMap::new(),
+            unknown_unit,
+        }
+    }
+
+    pub fn unknown_unit(&self) -> UnknownUnit {
+        self.unknown_unit
+    }
+
+    pub fn set_unknown_unit(&mut self, unknown_unit: UnknownUnit) {
+        self.unknown_unit = unknown_unit;
+    }
+
+    /// Register the handler for the given unit.
+    ///
+    /// The handler previously registered for the unit is returned.
+    ///
+    /// # Panics
+    /// Panics if unit is the broadcast id, broadcasts are passed to all handlers.
+    pub fn insert(&mut self, unit: SlaveId, handler: H) -> Option<H> {
+        assert!(
+            !unit.is_broadcast(),
+            "handlers can't be registered for the broadcast id"
+        );
+        self.units.insert(unit, handler)
+    }
+
+    pub fn remove(&mut self, unit: SlaveId) -> Option<H> {
+        self.units.remove(&unit)
+    }
+
+    pub fn get(&self, unit: SlaveId) -> Option<&H> {
+        self.units.get(&unit)
+    }
+
+    /// Iterate over all units and their handlers in arbitrary order
+    pub fn units(&self) -> impl Iterator<Item = (SlaveId, &H)> {
+        self.units.iter().map(|(unit, handler)| (*unit, handler))
+    }
+}
+
+impl<H> Default for UnitRouter<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H> FromIterator<(SlaveId, H)> for UnitRouter<H> {
+    fn from_iter<I: IntoIterator<Item = (SlaveId, H)>>(iter: I) -> Self {
+        let mut router = Self::new();
+        for (unit, handler) in iter {
+            router.insert(unit, handler);
+        }
+        router
+    }
+}
+
+impl<H: ModbusHandler + Sync> ModbusHandler for UnitRouter<H> {
+    async fn handle(
+        &self,
+        unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        if unit.is_broadcast() {
+            if !is_read(request) {
+                let mut discarded = [0; MAX_PDU_SIZE];
+                for (unit, handler) in &self.units {
+                    let _ = handler.handle(*unit, request, &mut discarded).await;
+                }
+            }
+            return Ok(0);
+        }
+
+        match (self.units.get(&unit), self.unknown_unit) {
+            (Some(handler), _) => handler.handle(unit, request, response).await,
+            (None, UnknownUnit::Silence) => Ok(0),
+            (None, UnknownUnit::GatewayTargetDeviceFailedToRespond) => {
+                Err(ExceptionCode::GatewayTargetDeviceFailedToRespond)
+            }
+        }
+    }
+
+    /// Requests are parsed by the handler of their unit, broadcasts as standard requests.
+    ///
+    /// A broadcast is parsed once for all handlers, so parsers of the handlers are bypassed. Broadcasts only
+    /// handlers understand, like Enron writes of 32 bit registers, are rejected and user defined functions reach
+    /// the handlers as [Request::Other].
+    ///
+    /// Requests for unknown units are never handled, so they aren't parsed either. This way malformed requests
+    /// get the same treatment as any other request for an unknown unit.
+    fn parse<'a>(
+        &self,
+        unit: SlaveId,
+        request: &'a [u8],
+        quirks: Quirks,
+    ) -> Result<Request<'a>, ModbusError> {
+        match self.units.get(&unit) {
+            Some(handler) => handler.parse(unit, request, quirks),
+            None if unit.is_broadcast() => {
+                Request::from_data_with_quirks(request, quirks).map(|(request, _tail)| request)
+            }
+            None => Ok(Request::Other {
+                function: ModbusFunction::new(request.first().copied().unwrap_or_default()),
+                data: request.get(1..).unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+fn is_read(request: Request<'_>) -> bool {
+    matches!(
+        request,
+        Request::ReadCoils(_)
+            | Request::ReadDiscreteInputs(_)
+            | Request::ReadHoldingRegisters(_)
+            | Request::ReadInputRegisters(_)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{ExceptionCode, Quirks, Request, SlaveId};
+    use modbius_traits::ModbusHandler;
+
+    use super::{UnitRouter, UnknownUnit};
+    use crate::{DataStore, DataStoreConfig, EnronConfig, EnronStore};
+
+    fn store() -> DataStore {
+        DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        })
+    }
+
+    async fn handle(
+        router: &UnitRouter<DataStore>,
+        unit: u8,
+        request: &[u8],
+    ) -> Result<Vec<u8>, ExceptionCode> {
+        let (request, _tail) = Request::from_data(request).unwrap();
+        let mut response = [0; 253];
+        let len = router
+            .handle(SlaveId::new(unit), request, &mut response)
+            .await?;
+        Ok(response[..len].to_vec())
+    }
+
+    #[tokio::test]
+    async fn route_by_unit() {
+        let (first, second) = (store(), store());
+        let router: UnitRouter<_> = vec![
+            (SlaveId::new(1), first.clone()),
+            (SlaveId::new(2), second.clone()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            handle(&router, 1, &[6, 0, 1, 0, 11]).await,
+            Ok(vec![6, 0, 1, 0, 11])
+        );
+        assert_eq!(
+            handle(&router, 2, &[6, 0, 1, 0, 22]).await,
+            Ok(vec![6, 0, 1, 0, 22])
+        );
+
+        assert_eq!(first.read(|tables| tables.holding_registers()[1]), 11);
+        assert_eq!(second.read(|tables| tables.holding_registers()[1]), 22);
+        assert_eq!(
+            handle(&router, 2, &[3, 0, 1, 0, 1]).await,
+            Ok(vec![3, 2, 0, 22])
+        );
+    }
+
+    #[tokio::test]
+    async fn broadcast() {
+        let (first, second) = (store(), store());
+        let mut router = UnitRouter::new();
+        router.insert(SlaveId::new(1), first.clone());
+        router.insert(SlaveId::new(2), second.clone());
+
+        assert_eq!(handle(&router, 0, &[6, 0, 3, 0, 5]).await, Ok(vec![]));
+        assert_eq!(first.read(|tables| tables.holding_registers()[3]), 5);
+        assert_eq!(second.read(|tables| tables.holding_registers()[3]), 5);
+
+        // Exceptions of the units aren't answered either
+        assert_eq!(handle(&router, 0, &[6, 0, 30, 0, 5]).await, Ok(vec![]));
+        assert_eq!(handle(&router, 0, &[3, 0, 3, 0, 1]).await, Ok(vec![]));
+    }
+
+    #[test]
+    fn broadcast_parsed_as_standard() {
+        let mut router = UnitRouter::new();
+        router.insert(SlaveId::new(1), EnronStore::new(EnronConfig::default()));
+
+        // Writing a 32 bit Enron register is understood by the unit, a broadcast bypasses its parser
+        let request = [16, 0x13, 0x89, 0, 1, 4, 0x12, 0x34, 0x56, 0x78];
+        assert!(router
+            .parse(SlaveId::new(1), &request, Quirks::STRICT)
+            .is_ok());
+        assert!(router
+            .parse(SlaveId::new(0), &request, Quirks::STRICT)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_unit() {
+        let mut router = UnitRouter::new();
+        router.insert(SlaveId::new(1), store());
+
+        assert_eq!(
+            handle(&router, 3, &[3, 0, 1, 0, 1]).await,
+            Err(ExceptionCode::GatewayTargetDeviceFailedToRespond)
+        );
+
+        router.set_unknown_unit(UnknownUnit::Silence);
+        assert_eq!(handle(&router, 3, &[3, 0, 1, 0, 1]).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn unknown_unit_malformed() {
+        let mut router = UnitRouter::with_unknown_unit(UnknownUnit::Silence);
+        router.insert(SlaveId::new(1), store());
+
+        // A zero quantity is accepted as the request is never handled
+        let request = router
+            .parse(SlaveId::new(3), &[3, 0, 1, 0, 0], Quirks::STRICT)
+            .unwrap();
+        let mut response = [0; 253];
+        assert_eq!(
+            router.handle(SlaveId::new(3), request, &mut response).await,
+            Ok(0)
+        );
+
+        router.set_unknown_unit(UnknownUnit::GatewayTargetDeviceFailedToRespond);
+        assert_eq!(
+            router.handle(SlaveId::new(3), request, &mut response).await,
+            Err(ExceptionCode::GatewayTargetDeviceFailedToRespond)
+        );
+
+        // Malformed requests for known units are still rejected by their handler
+        assert!(router
+            .parse(SlaveId::new(1), &[3, 0, 1, 0, 0], Quirks::STRICT)
+            .is_err());
+    }
+}
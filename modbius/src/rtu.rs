@@ -0,0 +1,240 @@
+//! Modbus RTU slave.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use modbius_codec::{RtuConfig, RtuStream, RtuTransportError};
+use modbius_core::{SlaveId, MAX_PDU_SIZE};
+use modbius_traits::ModbusHandler;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Counters of an [RtuServer] for diagnostics.
+///
+/// The counters correspond to the ones of the diagnostics function of the spec, they wrap around on overflow.
+#[derive(Debug, Default)]
+pub struct RtuDiagnostics {
+    bus_messages: AtomicU64,
+    bus_communication_errors: AtomicU64,
+    slave_messages: AtomicU64,
+    slave_no_responses: AtomicU64,
+}
+
+impl RtuDiagnostics {
+    /// The number of valid frames received on the line, regardless of the slave they were addressed to
+    pub fn bus_messages(&self) -> u64 {
+        self.bus_messages.load(Ordering::Relaxed)
+    }
+
+    /// The number of frames dropped because of a CRC error or an invalid length
+    pub fn bus_communication_errors(&self) -> u64 {
+        self.bus_communication_errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests addressed to this slave, including broadcasts
+    pub fn slave_messages(&self) -> u64 {
+        self.slave_messages.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests addressed to this slave that were not answered, e.g. broadcasts
+    pub fn slave_no_responses(&self) -> u64 {
+        self.slave_no_responses.load(Ordering::Relaxed)
+    }
+
+    /// Reset all counters to zero
+    pub fn clear(&self) {
+        self.bus_messages.store(0, Ordering::Relaxed);
+        self.bus_communication_errors.store(0, Ordering::Relaxed);
+        self.slave_messages.store(0, Ordering::Relaxed);
+        self.slave_no_responses.store(0, Ordering::Relaxed);
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A Modbus RTU slave passing every request addressed to it to a [ModbusHandler].
+///
+/// A server may answer for several slave ids, e.g. to front a number of devices behind a gateway.
+/// Frames addressed to other slaves are ignored. Broadcasts are passed to the handler but never answered.
+/// Frames with CRC errors are dropped and counted in the [RtuDiagnostics].
+pub struct RtuServer<H> {
+    handler: H,
+    slaves: Vec<SlaveId>,
+    config: RtuConfig,
+    diagnostics: RtuDiagnostics,
+}
+
+impl<H: ModbusHandler + Sync> RtuServer<H> {
+    /// Create a new slave with the given id and the default [RtuConfig]
+    pub fn new(slave: SlaveId, handler: H) -> Self {
+        Self::with_config(slave, handler, RtuConfig::default())
+    }
+
+    /// Create a new slave with the given id and configuration
+    pub fn with_config(slave: SlaveId, handler: H, config: RtuConfig) -> Self {
+        Self::for_slaves(vec![slave], handler, config)
+    }
+
+    /// Create a new server answering requests for all of the given slave ids.
+    ///
+    /// The handler receives the slave id a request was addressed to as unit.
+    pub fn for_slaves(slaves: Vec<SlaveId>, handler: H, config: RtuConfig) -> Self {
+        Self {
+            handler,
+            slaves,
+            config,
+            diagnostics: RtuDiagnostics::default(),
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn slaves(&self) -> &[SlaveId] {
+        &self.slaves
+    }
+
+    pub fn config(&self) -> RtuConfig {
+        self.config
+    }
+
+    pub fn diagnostics(&self) -> &RtuDiagnostics {
+        &self.diagnostics
+    }
+
+    /// Serve requests received on the stream.
+    ///
+    /// This function only returns on errors of the stream. Dropping the returned future stops the server.
+    ///
+    /// # Errors
+    /// Any [RtuTransportError::Io] is returned, invalid frames are dropped.
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+    ) -> Result<(), RtuTransportError> {
+        let mut stream = RtuStream::with_config(stream, self.config);
+        let mut request = [0; MAX_PDU_SIZE];
+        let mut response = [0; MAX_PDU_SIZE];
+
+        loop {
+            let (slave, len) = match stream.read_frame(&mut request, None).await {
+                Ok(received) => received,
+                Err(RtuTransportError::Frame(_)) => {
+                    RtuDiagnostics::count(&self.diagnostics.bus_communication_errors);
+                    continue;
+                }
+                // A valid frame with a PDU larger than the maximum, it can't be a request
+                Err(RtuTransportError::Serialization(_)) => {
+                    RtuDiagnostics::count(&self.diagnostics.bus_messages);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            RtuDiagnostics::count(&self.diagnostics.bus_messages);
+            if !self.slaves.iter().any(|own| own.must_react(slave)) {
+                continue;
+            }
+
+            RtuDiagnostics::count(&self.diagnostics.slave_messages);
+            let len = self
+                .handler
+                .dispatch(slave, &request[..len], &mut response, self.config.quirks)
+                .await;
+            if slave.is_broadcast() || len == 0 {
+                RtuDiagnostics::count(&self.diagnostics.slave_no_responses);
+                continue;
+            }
+
+            stream.write_frame(slave, &response[..len]).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use modbius_core::SlaveId;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use super::RtuServer;
+    use crate::{DataStore, DataStoreConfig};
+
+    async fn send(remote: &mut DuplexStream, frame: &[u8]) {
+        remote.write_all(frame).await.unwrap();
+        // Keep the line silent so the frame is complete
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serve() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let store = DataStore::new(DataStoreConfig {
+            holding_registers: 10,
+            ..Default::default()
+        });
+        store.write(|tables| tables.holding_registers_mut()[0] = 0x0102);
+        let server = RtuServer::new(SlaveId::new(1), store.clone());
+
+        let client = async {
+            // CRC error, another slave and a broadcast are not answered
+            send(&mut remote, &[1, 3, 0, 0, 0, 1, 0x84, 0x0B]).await;
+            send(&mut remote, &[2, 3, 0, 0, 0, 1, 0x84, 0x39]).await;
+            send(&mut remote, &[0, 6, 0, 1, 0, 3, 0x99, 0xDA]).await;
+            send(&mut remote, &[1, 3, 0, 0, 0, 1, 0x84, 0x0A]).await;
+
+            let mut response = [0; 7];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [1, 3, 2, 1, 2, 0x38, 0x15]);
+
+            // Exception for an address outside of the store
+            send(&mut remote, &[1, 3, 0, 0x0A, 0, 1, 0xA4, 0x08]).await;
+            let mut response = [0; 5];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, [1, 0x83, 2, 0xC0, 0xF1]);
+        };
+
+        tokio::select! {
+            result = server.serve(local) => panic!("server stopped: {:?}", result),
+            _ = client => {}
+        }
+
+        assert_eq!(store.read(|tables| tables.holding_registers()[1]), 3);
+        let diagnostics = server.diagnostics();
+        assert_eq!(diagnostics.bus_communication_errors(), 1);
+        assert_eq!(diagnostics.bus_messages(), 4);
+        assert_eq!(diagnostics.slave_messages(), 3);
+        assert_eq!(diagnostics.slave_no_responses(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serve_several_slaves() {
+        let (local, mut remote) = tokio::io::duplex(256);
+        let server = RtuServer::for_slaves(
+            vec![SlaveId::new(1), SlaveId::new(2)],
+            DataStore::default(),
+            Default::default(),
+        );
+
+        let client = async {
+            send(&mut remote, &[2, 3, 0, 0, 0, 1, 0x84, 0x39]).await;
+            let mut response = [0; 7];
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(&response[..5], &[2, 3, 2, 0, 0]);
+
+            send(&mut remote, &[3, 3, 0, 0, 0, 1, 0x85, 0xE8]).await;
+            send(&mut remote, &[1, 3, 0, 0, 0, 1, 0x84, 0x0A]).await;
+            remote.read_exact(&mut response).await.unwrap();
+            assert_eq!(&response[..5], &[1, 3, 2, 0, 0]);
+        };
+
+        tokio::select! {
+            result = server.serve(local) => panic!("server stopped: {:?}", result),
+            _ = client => {}
+        }
+        assert_eq!(server.diagnostics().bus_messages(), 3);
+        assert_eq!(server.diagnostics().slave_messages(), 2);
+    }
+}
@@ -0,0 +1,449 @@
+//! A ready made in-memory data model for servers.
+
+use std::{
+    ops::Range,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use modbius_core::{
+    registerslice::RegisterSlice,
+    response::{ReadBitsResponse, ReadRegistersResponse},
+    BitState, ExceptionCode, PublicModbusFunction, Request, SlaveId,
+};
+use modbius_traits::ModbusHandler;
+
+/// The sizes of the address spaces of a [DataStore]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DataStoreConfig {
+    pub coils: usize,
+    pub discrete_inputs: usize,
+    pub holding_registers: usize,
+    pub input_registers: usize,
+}
+
+impl Default for DataStoreConfig {
+    /// Every address space spans all 0x10000 addresses
+    fn default() -> Self {
+        Self {
+            coils: 0x10000,
+            discrete_inputs: 0x10000,
+            holding_registers: 0x10000,
+            input_registers: 0x10000,
+        }
+    }
+}
+
+/// The four address spaces of a [DataStore].
+///
+/// The entity at address n is stored at index n, the sizes are fixed at creation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataTables {
+    coils: Box<[BitState]>,
+    discrete_inputs: Box<[BitState]>,
+    holding_registers: Box<[u16]>,
+    input_registers: Box<[u16]>,
+}
+
+impl DataTables {
+    /// Create tables of the configured sizes with all entities set to zero
+    pub fn new(config: DataStoreConfig) -> Self {
+        Self {
+            coils: vec![BitState::Off; config.coils].into(),
+            discrete_inputs: vec![BitState::Off; config.discrete_inputs].into(),
+            holding_registers: vec![0; config.holding_registers].into(),
+            input_registers: vec![0; config.input_registers].into(),
+        }
+    }
+
+    pub fn coils(&self) -> &[BitState] {
+        &self.coils
+    }
+
+    pub fn coils_mut(&mut self) -> &mut [BitState] {
+        &mut self.coils
+    }
+
+    pub fn discrete_inputs(&self) -> &[BitState] {
+        &self.discrete_inputs
+    }
+
+    pub fn discrete_inputs_mut(&mut self) -> &mut [BitState] {
+        &mut self.discrete_inputs
+    }
+
+    pub fn holding_registers(&self) -> &[u16] {
+        &self.holding_registers
+    }
+
+    pub fn holding_registers_mut(&mut self) -> &mut [u16] {
+        &mut self.holding_registers
+    }
+
+    pub fn input_registers(&self) -> &[u16] {
+        &self.input_registers
+    }
+
+    pub fn input_registers_mut(&mut self) -> &mut [u16] {
+        &mut self.input_registers
+    }
+
+    /// Process a request against the tables and write the response PDU to response.
+    ///
+    /// # Errors
+    /// Requests accessing addresses outside of the tables fail with [ExceptionCode::IllegalDataAddress],
+    /// functions other than 1-6, 15, 16, 22 and 23 with [ExceptionCode::IllegalFunction].
+    pub fn process(
+        &mut self,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        if let Some(result) = self.process_read(request, response) {
+            return result;
+        }
+
+        match request {
+            Request::WriteSingleCoil(req) => {
+                let range = range(self.coils.len(), req.addr, 1)?;
+                self.coils[range.start] = req.state;
+                echo(&req.into_data(), response)
+            }
+            Request::WriteSingleRegister(req) => {
+                let range = range(self.holding_registers.len(), req.addr, 1)?;
+                self.holding_registers[range.start] = req.value;
+                echo(&req.into_data(), response)
+            }
+            Request::WriteMultipleCoils(req) => {
+                let range = range(self.coils.len(), req.addr(), req.quantity() as usize)?;
+                for (coil, state) in self.coils[range].iter_mut().zip(req.states()) {
+                    *coil = state;
+                }
+                echo(&req.into_response_data(), response)
+            }
+            Request::WriteMultipleRegisters(req) => {
+                let registers = req.registers();
+                let range = range(self.holding_registers.len(), req.addr(), registers.len())?;
+                write_registers(&mut self.holding_registers[range], registers);
+                echo(&req.into_response_data(), response)
+            }
+            Request::MaskWriteRegister(req) => {
+                let range = range(self.holding_registers.len(), req.addr, 1)?;
+                let register = &mut self.holding_registers[range.start];
+                *register = req.apply(*register);
+                echo(&req.into_data(), response)
+            }
+            Request::ReadWriteMultipleRegisters(req) => {
+                // Both ranges are checked before writing so a failing request doesn't modify anything
+                let read = req.read();
+                let read_range = range(
+                    self.holding_registers.len(),
+                    read.addr,
+                    read.quantity as usize,
+                )?;
+                let registers = req.registers();
+                let write_range = range(
+                    self.holding_registers.len(),
+                    req.write_addr(),
+                    registers.len(),
+                )?;
+
+                write_registers(&mut self.holding_registers[write_range], registers);
+                Ok(ReadRegistersResponse::write_registers(
+                    PublicModbusFunction::ReadWriteMultipleRegisters,
+                    self.holding_registers[read_range].iter().copied(),
+                    response,
+                )?)
+            }
+            _ => Err(ExceptionCode::IllegalFunction),
+        }
+    }
+
+    /// Process the request if it doesn't modify the tables, None is returned for any other request
+    fn process_read(
+        &self,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Option<Result<usize, ExceptionCode>> {
+        let result = match request {
+            Request::ReadCoils(req) => range(self.coils.len(), req.addr, req.quantity as usize)
+                .and_then(|range| {
+                    let bits = self.coils[range].iter().copied();
+                    Ok(ReadBitsResponse::write_bits(
+                        PublicModbusFunction::ReadCoils,
+                        bits,
+                        response,
+                    )?)
+                }),
+            Request::ReadDiscreteInputs(req) => {
+                range(self.discrete_inputs.len(), req.addr, req.quantity as usize).and_then(
+                    |range| {
+                        let bits = self.discrete_inputs[range].iter().copied();
+                        Ok(ReadBitsResponse::write_bits(
+                            PublicModbusFunction::ReadDiscreteInputs,
+                            bits,
+                            response,
+                        )?)
+                    },
+                )
+            }
+            Request::ReadHoldingRegisters(req) => range(
+                self.holding_registers.len(),
+                req.addr,
+                req.quantity as usize,
+            )
+            .and_then(|range| {
+                let registers = self.holding_registers[range].iter().copied();
+                Ok(ReadRegistersResponse::write_registers(
+                    PublicModbusFunction::ReadHoldingRegisters,
+                    registers,
+                    response,
+                )?)
+            }),
+            Request::ReadInputRegisters(req) => {
+                range(self.input_registers.len(), req.addr, req.quantity as usize).and_then(
+                    |range| {
+                        let registers = self.input_registers[range].iter().copied();
+                        Ok(ReadRegistersResponse::write_registers(
+                            PublicModbusFunction::ReadInputRegisters,
+                            registers,
+                            response,
+                        )?)
+                    },
+                )
+            }
+            _ => return None,
+        };
+
+        Some(result)
+    }
+}
+
+impl Default for DataTables {
+    fn default() -> Self {
+        Self::new(DataStoreConfig::default())
+    }
+}
+
+/// Get the indices of quantity entities starting at addr in a table of the given len
+fn range(len: usize, addr: u16, quantity: usize) -> Result<Range<usize>, ExceptionCode> {
+    let range = addr as usize..addr as usize + quantity;
+    if range.end <= len {
+        Ok(range)
+    } else {
+        Err(ExceptionCode::IllegalDataAddress)
+    }
+}
+
+fn write_registers(table: &mut [u16], registers: RegisterSlice<'_>) {
+    for (idx, register) in table.iter_mut().enumerate() {
+        // The table was sliced to the len of registers
+        *register = registers.get(idx).unwrap_or_default();
+    }
+}
+
+fn echo(data: &[u8], response: &mut [u8]) -> Result<usize, ExceptionCode> {
+    match response.get_mut(..data.len()) {
+        Some(response) => {
+            response.copy_from_slice(data);
+            Ok(data.len())
+        }
+        None => Err(ExceptionCode::ServerDeviceFailure),
+    }
+}
+
+/// An in-memory data model serving coils, discrete inputs, holding and input registers.
+///
+/// The store implements [ModbusHandler] and answers requests of every unit id from its tables,
+/// so it can be passed to any server. Cloning the store is cheap and all clones share the same tables,
+/// which allows the application to access the data while a server is running.
+///
+/// # Example
+/// ```
+/// use modbius_server::store::{DataStore, DataStoreConfig};
+///
+/// let store = DataStore::new(DataStoreConfig {
+///     holding_registers: 100,
+///     ..Default::default()
+/// });
+/// store.write(|tables| tables.holding_registers_mut()[10] = 42);
+/// assert_eq!(store.read(|tables| tables.holding_registers()[10]), 42);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DataStore {
+    tables: Arc<RwLock<DataTables>>,
+}
+
+impl DataStore {
+    /// Create a new store with all entities set to zero
+    pub fn new(config: DataStoreConfig) -> Self {
+        Self::from_tables(DataTables::new(config))
+    }
+
+    /// Create a new store serving the given tables
+    pub fn from_tables(tables: DataTables) -> Self {
+        Self {
+            tables: Arc::new(RwLock::new(tables)),
+        }
+    }
+
+    /// Access the tables for reading.
+    ///
+    /// Requests are blocked while f runs, so it should return quickly.
+    pub fn read<R>(&self, f: impl FnOnce(&DataTables) -> R) -> R {
+        f(&self.tables.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Access the tables for writing.
+    ///
+    /// Requests are blocked while f runs, so it should return quickly.
+    /// All changes made by f become visible to requests at once.
+    pub fn write<R>(&self, f: impl FnOnce(&mut DataTables) -> R) -> R {
+        f(&mut self.tables.write().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl ModbusHandler for DataStore {
+    async fn handle(
+        &self,
+        _unit: SlaveId,
+        request: Request<'_>,
+        response: &mut [u8],
+    ) -> Result<usize, ExceptionCode> {
+        // Reads only take the shared lock so they don't block each other
+        if let Some(result) = self.read(|tables| tables.process_read(request, response)) {
+            return result;
+        }
+
+        self.write(|tables| tables.process(request, response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modbius_core::{BitState, ExceptionCode, Request, SlaveId};
+    use modbius_traits::ModbusHandler;
+
+    use super::{DataStore, DataStoreConfig, DataTables};
+
+    fn config() -> DataStoreConfig {
+        DataStoreConfig {
+            coils: 20,
+            discrete_inputs: 10,
+            holding_registers: 10,
+            input_registers: 5,
+        }
+    }
+
+    fn process(tables: &mut DataTables, request: &[u8]) -> Result<Vec<u8>, ExceptionCode> {
+        let (request, _tail) = Request::from_data(request).unwrap();
+        let mut response = [0; 253];
+        let len = tables.process(request, &mut response)?;
+        Ok(response[..len].to_vec())
+    }
+
+    #[test]
+    fn coils() {
+        let mut tables = DataTables::new(config());
+
+        assert_eq!(
+            process(&mut tables, &[5, 0, 2, 0xFF, 0]),
+            Ok(vec![5, 0, 2, 0xFF, 0])
+        );
+        assert_eq!(
+            process(&mut tables, &[15, 0, 8, 0, 10, 2, 0xCD, 0x01]),
+            Ok(vec![15, 0, 8, 0, 10])
+        );
+        assert_eq!(tables.coils()[2], BitState::On);
+        assert_eq!(tables.coils()[17], BitState::Off);
+
+        assert_eq!(
+            process(&mut tables, &[1, 0, 0, 0, 20]),
+            Ok(vec![1, 3, 0x04, 0xCD, 0x01])
+        );
+    }
+
+    #[test]
+    fn registers() {
+        let mut tables = DataTables::new(config());
+        tables.input_registers_mut()[4] = 7;
+
+        assert_eq!(
+            process(&mut tables, &[6, 0, 1, 0x12, 0x34]),
+            Ok(vec![6, 0, 1, 0x12, 0x34])
+        );
+        assert_eq!(
+            process(&mut tables, &[16, 0, 2, 0, 2, 4, 0, 0x12, 0, 0xFF]),
+            Ok(vec![16, 0, 2, 0, 2])
+        );
+        assert_eq!(
+            process(&mut tables, &[22, 0, 2, 0, 0xF2, 0, 0x25]),
+            Ok(vec![22, 0, 2, 0, 0xF2, 0, 0x25])
+        );
+        assert_eq!(tables.holding_registers()[2], 0x17);
+
+        assert_eq!(
+            process(&mut tables, &[3, 0, 1, 0, 3]),
+            Ok(vec![3, 6, 0x12, 0x34, 0, 0x17, 0, 0xFF])
+        );
+        assert_eq!(process(&mut tables, &[4, 0, 4, 0, 1]), Ok(vec![4, 2, 0, 7]));
+
+        // The write is performed before the read
+        assert_eq!(
+            process(&mut tables, &[23, 0, 3, 0, 2, 0, 4, 0, 1, 2, 0, 9]),
+            Ok(vec![23, 4, 0, 0xFF, 0, 9])
+        );
+    }
+
+    #[test]
+    fn illegal_address() {
+        let mut tables = DataTables::new(config());
+
+        assert_eq!(
+            process(&mut tables, &[2, 0, 5, 0, 6]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            process(&mut tables, &[6, 0, 10, 0, 1]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            process(&mut tables, &[15, 0, 19, 0, 2, 1, 3]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+
+        // A failing read/write request doesn't write
+        assert_eq!(
+            process(&mut tables, &[23, 0, 9, 0, 2, 0, 0, 0, 1, 2, 0, 9]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(tables.holding_registers()[0], 0);
+        assert_eq!(tables.coils()[19], BitState::Off);
+
+        assert_eq!(
+            process(&mut tables, &[8, 0, 0, 0, 0]),
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_with_application() {
+        let store = DataStore::new(config());
+        let handler = store.clone();
+
+        store.write(|tables| tables.holding_registers_mut()[0] = 42);
+
+        let mut response = [0; 253];
+        let (request, _tail) = Request::from_data(&[3, 0, 0, 0, 1]).unwrap();
+        let len = handler
+            .handle(SlaveId::new(1), request, &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response[..len], &[3, 2, 0, 42]);
+
+        let (request, _tail) = Request::from_data(&[6, 0, 1, 0, 7]).unwrap();
+        handler
+            .handle(SlaveId::new(1), request, &mut response)
+            .await
+            .unwrap();
+        assert_eq!(store.read(|tables| tables.holding_registers()[1]), 7);
+    }
+}
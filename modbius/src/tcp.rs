@@ -0,0 +1,512 @@
+//! Modbus TCP server.
+
+use std::{future::Future, io, sync::Arc, time::Duration};
+
+use modbius_codec::{MbapStream, TcpTransportError};
+use modbius_core::{tcp::MbapHeader, Quirks, MAX_PDU_SIZE};
+use modbius_traits::ModbusHandler;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{watch, OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+
+/// Configuration of a [TcpServer]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TcpServerConfig {
+    /// The maximum number of concurrently served connections.
+    ///
+    /// Connections beyond the limit are accepted and closed right away.
+    pub max_connections: usize,
+    /// Connections which did not send a complete request for this long are closed
+    pub idle_timeout: Duration,
+    /// The deviations from the specification to accept in received ADUs and requests
+    pub quirks: Quirks,
+}
+
+impl Default for TcpServerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 32,
+            idle_timeout: Duration::from_secs(60),
+            quirks: Quirks::STRICT,
+        }
+    }
+}
+
+/// An async Modbus TCP server passing every request to a [ModbusHandler].
+///
+/// Requests of a connection are handled one after another, different connections are handled concurrently.
+pub struct TcpServer<H> {
+    handler: Arc<H>,
+    config: TcpServerConfig,
+}
+
+impl<H: ModbusHandler + Send + Sync + 'static> TcpServer<H> {
+    /// Create a new server with the default [TcpServerConfig]
+    pub fn new(handler: H) -> Self {
+        Self::with_config(handler, TcpServerConfig::default())
+    }
+
+    /// Create a new server with the given configuration
+    pub fn with_config(handler: H, config: TcpServerConfig) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            config,
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn config(&self) -> TcpServerConfig {
+        self.config
+    }
+
+    /// Serve all connections accepted by the listener until shutdown completes.
+    ///
+    /// On shutdown no more connections are accepted, requests which are currently processed are answered
+    /// and afterwards all connections are closed. This function returns once all connections are closed.
+    ///
+    /// # Errors
+    /// Errors accepting connections are returned after shutting down all connections, except for errors
+    /// only concerning the connection that was about to be accepted.
+    pub async fn serve(
+        &self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        let (stop, stopped) = watch::channel(false);
+        let limit = Arc::new(Semaphore::new(self.config.max_connections));
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+
+        let result = loop {
+            tokio::select! {
+                _ = &mut shutdown => break Ok(()),
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _peer)) => stream,
+                        Err(e) if is_connection_error(&e) => continue,
+                        Err(e) => break Err(e),
+                    };
+
+                    // Dropping the stream right away closes connections beyond the limit
+                    if let Ok(permit) = Arc::clone(&limit).try_acquire_owned() {
+                        let _ = stream.set_nodelay(true);
+                        connections.spawn(serve_connection(
+                            Arc::clone(&self.handler),
+                            stream,
+                            self.config,
+                            stopped.clone(),
+                            permit,
+                        ));
+                    }
+                }
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            }
+        };
+
+        let _ = stop.send(true);
+        while connections.join_next().await.is_some() {}
+        result
+    }
+}
+
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+    )
+}
+
+async fn serve_connection<H, S>(
+    handler: Arc<H>,
+    stream: S,
+    config: TcpServerConfig,
+    mut stopped: watch::Receiver<bool>,
+    _permit: OwnedSemaphorePermit,
+) where
+    H: ModbusHandler + Sync,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = MbapStream::with_quirks(stream, config.quirks);
+    let mut request = [0; MAX_PDU_SIZE];
+    let mut response = [0; MAX_PDU_SIZE];
+
+    while !*stopped.borrow() {
+        let received = tokio::select! {
+            received = stream.read_adu(&mut request, Some(config.idle_timeout)) => received,
+            _ = stopped.changed() => break,
+        };
+
+        let (header, len) = match received {
+            Ok(received) => received,
+            Err(TcpTransportError::InvalidProtocol(_)) => continue,
+            Err(_) => break,
+        };
+
+        let len = handler
+            .dispatch(
+                header.unit_id,
+                &request[..len],
+                &mut response,
+                config.quirks,
+            )
+            .await;
+        if header.unit_id.is_broadcast() || len == 0 {
+            continue;
+        }
+
+        let header = MbapHeader::new(header.transaction_id, header.unit_id, len as u16);
+        if stream.write_adu(header, &response[..len]).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use modbius_core::{
+        response::ReadRegistersResponse, ExceptionCode, PublicModbusFunction, Quirks, Request,
+        SlaveId,
+    };
+    use modbius_traits::ModbusHandler;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::oneshot,
+    };
+
+    use super::{TcpServer, TcpServerConfig};
+    use crate::{DataStore, UnitRouter, UnknownUnit};
+
+    /// Answers every read of holding registers with the register addresses as values
+    #[derive(Default)]
+    struct Addresses {
+        requests: AtomicUsize,
+    }
+
+    impl ModbusHandler for Addresses {
+        async fn handle(
+            &self,
+            unit: SlaveId,
+            request: Request<'_>,
+            response: &mut [u8],
+        ) -> Result<usize, ExceptionCode> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            match request {
+                Request::ReadHoldingRegisters(req) if unit == SlaveId::new(1) => {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    ReadRegistersResponse::write_registers(
+                        PublicModbusFunction::ReadHoldingRegisters,
+                        req.addr..req.addr + req.quantity,
+                        response,
+                    )
+                    .map_err(ExceptionCode::from)
+                }
+                Request::ReadHoldingRegisters(_) => Err(ExceptionCode::GatewayPathUnavailable),
+                _ => Err(ExceptionCode::IllegalFunction),
+            }
+        }
+    }
+
+    struct Server {
+        addr: std::net::SocketAddr,
+        shutdown: oneshot::Sender<()>,
+        task: tokio::task::JoinHandle<std::io::Result<()>>,
+    }
+
+    async fn start(config: TcpServerConfig) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown, stop) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let server = TcpServer::with_config(Addresses::default(), config);
+            server
+                .serve(listener, async {
+                    let _ = stop.await;
+                })
+                .await
+        });
+
+        Server {
+            addr,
+            shutdown,
+            task,
+        }
+    }
+
+    async fn transact(stream: &mut TcpStream, adu: &[u8], response: &mut [u8]) {
+        stream.write_all(adu).await.unwrap();
+        stream.read_exact(response).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_holding_registers() {
+        let server = start(TcpServerConfig::default()).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        let mut response = [0; 13];
+        transact(
+            &mut stream,
+            &[0, 7, 0, 0, 0, 6, 1, 3, 0, 10, 0, 2],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 7, 0, 0, 0, 7, 1, 3, 4, 0, 10, 0, 11]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn quirks() {
+        let config = TcpServerConfig {
+            quirks: Quirks {
+                any_protocol_id: true,
+                byte_count_mismatch: true,
+                ..Quirks::STRICT
+            },
+            ..Default::default()
+        };
+        let server = start(config).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        let mut response = [0; 11];
+        transact(
+            &mut stream,
+            &[0, 7, 0, 1, 0, 6, 1, 3, 0, 10, 0, 1],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 7, 0, 0, 0, 5, 1, 3, 2, 0, 10]);
+
+        // The request reaches the handler instead of being rejected as invalid
+        let mut response = [0; 9];
+        transact(
+            &mut stream,
+            &[0, 8, 0, 0, 0, 9, 1, 16, 0, 1, 0, 1, 1, 0, 7],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 8, 0, 0, 0, 3, 1, 0x90, 1]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn exceptions() {
+        let server = start(TcpServerConfig::default()).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        let mut response = [0; 9];
+
+        // Unsupported function
+        transact(
+            &mut stream,
+            &[0, 1, 0, 0, 0, 6, 1, 6, 0, 10, 0, 2],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 1, 0, 0, 0, 3, 1, 0x86, 1]);
+
+        // Handler exception
+        transact(
+            &mut stream,
+            &[0, 2, 0, 0, 0, 6, 2, 3, 0, 10, 0, 2],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 2, 0, 0, 0, 3, 2, 0x83, 0x0A]);
+
+        // Invalid quantity
+        transact(
+            &mut stream,
+            &[0, 3, 0, 0, 0, 6, 1, 3, 0, 10, 0, 0],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 3, 0, 0, 0, 3, 1, 0x83, 3]);
+
+        // Beyond the address space
+        transact(
+            &mut stream,
+            &[0, 4, 0, 0, 0, 6, 1, 3, 0xFF, 0xFF, 0, 2],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 4, 0, 0, 0, 3, 1, 0x83, 2]);
+
+        // Truncated request
+        transact(&mut stream, &[0, 5, 0, 0, 0, 4, 1, 3, 0, 10], &mut response).await;
+        assert_eq!(response, [0, 5, 0, 0, 0, 3, 1, 0x83, 3]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_protocol_ignored() {
+        let server = start(TcpServerConfig::default()).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        stream
+            .write_all(&[0, 1, 0, 1, 0, 6, 1, 3, 0, 10, 0, 1])
+            .await
+            .unwrap();
+        let mut response = [0; 11];
+        transact(
+            &mut stream,
+            &[0, 2, 0, 0, 0, 6, 1, 3, 0, 10, 0, 1],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 2, 0, 0, 0, 5, 1, 3, 2, 0, 10]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn silent_unit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut router = UnitRouter::with_unknown_unit(UnknownUnit::Silence);
+        router.insert(SlaveId::new(1), DataStore::default());
+        let server = TcpServer::new(router);
+        let task =
+            tokio::spawn(async move { server.serve(listener, std::future::pending()).await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(&[0, 1, 0, 0, 0, 6, 2, 3, 0, 10, 0, 1])
+            .await
+            .unwrap();
+        // Malformed requests for unknown units aren't answered either
+        stream
+            .write_all(&[0, 3, 0, 0, 0, 6, 2, 3, 0, 10, 0, 0])
+            .await
+            .unwrap();
+        let mut response = [0; 11];
+        transact(
+            &mut stream,
+            &[0, 2, 0, 0, 0, 6, 1, 3, 0, 10, 0, 1],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 2, 0, 0, 0, 5, 1, 3, 2, 0, 0]);
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn broadcast_not_answered() {
+        let server = start(TcpServerConfig::default()).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        // Neither a valid nor a malformed broadcast is answered
+        stream
+            .write_all(&[0, 1, 0, 0, 0, 6, 0, 6, 0, 10, 0, 2])
+            .await
+            .unwrap();
+        stream
+            .write_all(&[0, 2, 0, 0, 0, 4, 0, 3, 0, 10])
+            .await
+            .unwrap();
+        let mut response = [0; 11];
+        transact(
+            &mut stream,
+            &[0, 3, 0, 0, 0, 6, 1, 3, 0, 10, 0, 1],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 3, 0, 0, 0, 5, 1, 3, 2, 0, 10]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let config = TcpServerConfig {
+            idle_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let server = start(config).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn connection_limit() {
+        let config = TcpServerConfig {
+            max_connections: 1,
+            ..Default::default()
+        };
+        let server = start(config).await;
+        let mut first = TcpStream::connect(server.addr).await.unwrap();
+        let mut response = [0; 11];
+        transact(
+            &mut first,
+            &[0, 1, 0, 0, 0, 6, 1, 3, 0, 1, 0, 1],
+            &mut response,
+        )
+        .await;
+
+        let mut second = TcpStream::connect(server.addr).await.unwrap();
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), second.read(&mut buf)).await;
+        assert!(matches!(read.unwrap(), Ok(0) | Err(_)));
+
+        // The first connection is still served
+        transact(
+            &mut first,
+            &[0, 2, 0, 0, 0, 6, 1, 3, 0, 1, 0, 1],
+            &mut response,
+        )
+        .await;
+        assert_eq!(response, [0, 2, 0, 0, 0, 5, 1, 3, 2, 0, 1]);
+
+        let _ = server.shutdown.send(());
+        server.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown() {
+        let server = start(TcpServerConfig::default()).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+
+        // Shut down while the request is processed, it is still answered
+        stream
+            .write_all(&[0, 1, 0, 0, 0, 6, 1, 3, 0, 1, 0, 1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let _ = server.shutdown.send(());
+
+        let mut response = [0; 11];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, [0, 1, 0, 0, 0, 5, 1, 3, 2, 0, 1]);
+        assert_eq!(stream.read(&mut [0; 1]).await.unwrap(), 0);
+
+        server.task.await.unwrap().unwrap();
+        assert!(TcpStream::connect(server.addr).await.is_err());
+    }
+}